@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use isobmff::{marshal::Result, testgen::generate};
+
+/// A file to process, with its name for the output.
+pub struct Input {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Returns the file given as first argument, or all test vectors if there is
+/// none, so that each example also runs on its own, e.g. in CI.
+pub fn inputs() -> Result<Vec<Input>> {
+    match std::env::args_os().nth(1) {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            Ok(vec![Input {
+                name: path.display().to_string(),
+                data: std::fs::read(&path)?,
+            }])
+        }
+        None => Ok(generate()?
+            .into_iter()
+            .map(|vector| Input {
+                name: vector.name,
+                data: vector.data,
+            })
+            .collect()),
+    }
+}
+
+/// Writes the result to the path given as second argument, if any.
+#[allow(dead_code)] // not used by probe
+pub fn output(data: &[u8]) -> Result<()> {
+    if let Some(path) = std::env::args_os().nth(2) {
+        std::fs::write(path, data)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,40 @@
+//! Keeps only the audio tracks of a file, e.g. to turn a recording into a
+//! podcast.
+//!
+//! ```sh
+//! cargo run --example extract_audio --features testgen -- input.mp4 output.m4a
+//! ```
+
+mod common;
+
+use std::io::Cursor;
+
+use isobmff::{
+    export::export_tracks,
+    marshal::{Encode, Result},
+};
+
+fn main() -> Result<()> {
+    for input in common::inputs()? {
+        let file = export_tracks(&input.data, |track| {
+            &track.media.handler.r#type.to_bytes() == b"soun"
+        })?;
+        let movie = file.movie.as_ref().unwrap();
+        if movie.tracks.is_empty() {
+            println!("{}: no audio", input.name);
+            continue;
+        }
+
+        let mut output = Cursor::new(Vec::new());
+        file.encode(&mut output)?;
+        let output = output.into_inner();
+        println!(
+            "{}: {} audio tracks, {} bytes",
+            input.name,
+            movie.tracks.len(),
+            output.len()
+        );
+        common::output(&output)?;
+    }
+    Ok(())
+}
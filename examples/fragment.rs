@@ -0,0 +1,50 @@
+//! Converts a file into fragments of a second, e.g. for streaming with DASH or
+//! HLS.
+//!
+//! ```sh
+//! cargo run --example fragment --features testgen -- input.mp4 output.mp4
+//! ```
+
+mod common;
+
+use std::io::Cursor;
+
+use isobmff::{
+    defragment::{defragment, validate_fragments},
+    fragment::fragment,
+    io::read_box_headers,
+    marshal::{Encode, Error, MovieTime, Result},
+};
+
+fn main() -> Result<()> {
+    for input in common::inputs()? {
+        // fragmenting requires a progressive file
+        let file = defragment(&input.data)?;
+        let timescale = file.movie.as_ref().unwrap().header.timescale;
+        let mut progressive = Cursor::new(Vec::new());
+        file.encode(&mut progressive)?;
+        let progressive = progressive.into_inner();
+
+        let mut output = Cursor::new(Vec::new());
+        fragment(&progressive, MovieTime(timescale as u64), &mut output)?;
+        let output = output.into_inner();
+
+        let issues = validate_fragments(&output)?;
+        if !issues.is_empty() {
+            println!("{}: {issues:?}", input.name);
+            return Err(Error::ValueOutOfRange { field: "fragment" });
+        }
+        let fragment_count = read_box_headers(&output)?
+            .iter()
+            .filter(|header| &header.r#type.to_bytes() == b"moof")
+            .count();
+        println!(
+            "{}: {} fragments, {} bytes",
+            input.name,
+            fragment_count,
+            output.len()
+        );
+        common::output(&output)?;
+    }
+    Ok(())
+}
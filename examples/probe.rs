@@ -0,0 +1,88 @@
+//! Prints the layout, tracks and metadata of a file.
+//!
+//! ```sh
+//! cargo run --example probe --features testgen -- input.mp4
+//! ```
+
+mod common;
+
+use isobmff::{
+    defragment::defragment,
+    faststart::FaststartReport,
+    io::{read_box_data, read_box_headers},
+    marshal::{Decode, Dialect, FileTypeBox, MetadataItem, Result},
+};
+
+fn main() -> Result<()> {
+    for input in common::inputs()? {
+        println!("{}", input.name);
+
+        let mut file_type = None;
+        for header in read_box_headers(&input.data)? {
+            println!(
+                "  {:?} at {}, {} bytes",
+                header.r#type, header.offset, header.size
+            );
+            if &header.r#type.to_bytes() == b"ftyp" {
+                file_type = Some(FileTypeBox::decode(
+                    &mut read_box_data(&input.data, &header)?.as_slice(),
+                )?);
+            }
+        }
+        if let Some(file_type) = &file_type {
+            println!(
+                "  brand {:?}, compatible to {:?}",
+                file_type.major_brand, file_type.compatible_brands
+            );
+        }
+        println!("  dialect {:?}", Dialect::detect(file_type.as_ref()));
+        println!(
+            "  faststart {}",
+            FaststartReport::new(&input.data)?.is_faststart()
+        );
+
+        // includes the samples of the fragments
+        let file = defragment(&input.data)?;
+        let movie = file.movie.as_ref().unwrap();
+        println!(
+            "  duration {} / {}",
+            movie.header.duration.0, movie.header.timescale
+        );
+        for track in &movie.tracks {
+            let sample_table = &track.media.information.sample_table;
+            print!(
+                "  track {} {:?}, {} samples, duration {} / {}",
+                track.header.track_id,
+                track.media.handler.r#type,
+                sample_table.sample_size.sample_count(),
+                track.media.header.duration.0,
+                track.media.header.timescale,
+            );
+            if let Some(visual) = sample_table
+                .description
+                .get(1)
+                .and_then(|entry| entry.visual())
+            {
+                print!(", {}x{}", visual.width, visual.height);
+            }
+            println!();
+        }
+        if let Some(item_list) = movie
+            .user_data
+            .as_ref()
+            .and_then(|user_data| user_data.meta.as_ref())
+            .and_then(|meta| meta.item_list.as_ref())
+        {
+            for key in [
+                MetadataItem::TITLE,
+                MetadataItem::ARTIST,
+                MetadataItem::ALBUM,
+            ] {
+                if let Some(text) = item_list.text(key) {
+                    println!("  {key:?} {text}");
+                }
+            }
+        }
+    }
+    Ok(())
+}
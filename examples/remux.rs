@@ -0,0 +1,42 @@
+//! Rewrites a file of any dialect or layout, fragmented or not, into a
+//! progressive MP4 with the moov in front.
+//!
+//! ```sh
+//! cargo run --example remux --features testgen -- input.mov output.mp4
+//! ```
+
+mod common;
+
+use std::io::Cursor;
+
+use isobmff::{
+    defragment::defragment,
+    faststart::is_faststart,
+    marshal::{Encode, Result},
+};
+
+fn main() -> Result<()> {
+    for input in common::inputs()? {
+        let file = defragment(&input.data)?;
+        let sample_count: u32 = file
+            .movie
+            .iter()
+            .flat_map(|movie| &movie.tracks)
+            .map(|track| track.samples().count() as u32)
+            .sum();
+        let mut output = Cursor::new(Vec::new());
+        file.encode(&mut output)?;
+        let output = output.into_inner();
+
+        println!(
+            "{}: {} -> {} bytes, {} samples, faststart {}",
+            input.name,
+            input.data.len(),
+            output.len(),
+            sample_count,
+            is_faststart(&file)?
+        );
+        common::output(&output)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! Extracts the sync sample of the first video track closest before the
+//! middle of it, as PPM if the video is uncompressed, or as is.
+//!
+//! ```sh
+//! cargo run --example thumbnail --features testgen -- input.mp4 thumbnail.ppm
+//! ```
+
+mod common;
+
+use std::io::Cursor;
+
+use isobmff::{
+    defragment::defragment,
+    demux::Reader,
+    index::SampleIndex,
+    io::RandomAccess,
+    marshal::{Encode, MediaTime, Result, SampleEntry},
+};
+
+fn main() -> Result<()> {
+    for input in common::inputs()? {
+        // the samples of fragments are only in the sample tables afterwards
+        let mut progressive = Cursor::new(Vec::new());
+        defragment(&input.data)?.encode(&mut progressive)?;
+        let progressive = progressive.into_inner();
+        let reader = Reader::new(&progressive)?;
+        let movie = reader.movie();
+        let Some(track) = movie
+            .tracks
+            .iter()
+            .find(|track| &track.media.handler.r#type.to_bytes() == b"vide")
+        else {
+            println!("{}: no video", input.name);
+            continue;
+        };
+
+        let index = SampleIndex::new(movie, progressive.len() as u64);
+        let track_index = index.track(track.header.track_id).unwrap();
+        let middle = MediaTime(track.media.header.duration.0 / 2);
+        let Some(sample_index) = track_index.sync_sample_before(middle) else {
+            println!("{}: no sync sample", input.name);
+            continue;
+        };
+        let sample = track_index.samples[sample_index];
+        let data = progressive.read_range(sample.offset..sample.offset + sample.size as u64)?;
+
+        let entry = track.media.information.sample_table.description.get(
+            track
+                .samples()
+                .nth(sample_index)
+                .unwrap()
+                .sample_description_index,
+        );
+        let image = match entry {
+            // only 8-bit RGB is stored like PPM
+            Some(SampleEntry::Uncompressed(entry))
+                if data.len() == entry.base.width as usize * entry.base.height as usize * 3 =>
+            {
+                let mut image =
+                    format!("P6\n{} {}\n255\n", entry.base.width, entry.base.height).into_bytes();
+                image.extend(data);
+                image
+            }
+            _ => data,
+        };
+        println!(
+            "{}: sample {} at {} / {}, {} bytes",
+            input.name,
+            sample_index + 1,
+            sample.decode_time.0,
+            track_index.timescale,
+            image.len()
+        );
+        common::output(&image)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use isobmff::{
+    edit::EditableFile,
+    marshal::{Decode, Encode, File},
+    registry::validate_hierarchy,
+};
+use libfuzzer_sys::fuzz_target;
+
+// decoding and everything done with the result must return errors instead of
+// panicking
+fuzz_target!(|data: &[u8]| {
+    let _ = validate_hierarchy(data);
+    let _ = EditableFile::decode(&mut &data[..]);
+    if let Ok(file) = File::decode(&mut &data[..]) {
+        let _ = format!("{file:?}");
+        let _ = file.mime_type();
+        let _ = file.encode(&mut Cursor::new(Vec::new()));
+        if let Some(movie) = &file.movie {
+            for track in &movie.tracks {
+                for _ in track.samples().zip(track.sample_offsets()).take(1 << 16) {}
+                let _ = track.codec_string();
+            }
+        }
+    }
+});
@@ -0,0 +1,534 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::{
+    marshal::{
+        checked_cast, CompositionOffsetEntry, Decode, Encode, Error, FourCC, MediaTime, MovieBox,
+        Patch, Result, SampleEntry, SampleSizeBox, SampleTableBox, SampleToChunkEntry,
+        SampleToGroupEntry, SyncSampleBox, TimeToSampleEntry,
+    },
+    mux::MuxSummary,
+};
+
+/// Continues an existing file by writing new samples into a new mdat at its
+/// end, and an updated moov behind that on [AppendSession::close].
+///
+/// Until then the previous moov stays in place, and the new mdat reaches until
+/// the end of the file, so the file remains readable with its previous samples
+/// if the session is never closed, and can be opened again.
+pub struct AppendSession<F: Read + Write + Seek> {
+    file: F,
+    movie: MovieBox,
+    movie_offset: u64,
+    media_data_offset: u64,
+    media_data_limit: Option<u64>,
+    parallel_encode: bool,
+    last_track_id: Option<u32>,
+    /// sample_description_index of the next sample of each track
+    sample_description_indices: Vec<u32>,
+    /// encoder delay of each track, which is hidden on close
+    primings: Vec<Option<MediaTime>>,
+}
+
+impl<F: Read + Write + Seek> AppendSession<F> {
+    pub fn open(mut file: F) -> Result<Self> {
+        let end = file.seek(SeekFrom::End(0))?;
+        let mut offset = 0;
+        let mut movie = None;
+        // mdat left behind by a session which was never closed
+        let mut unfinished_media_data = None;
+        let mut previous_box = None;
+        while offset < end {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut header = [0; 8];
+            file.read_exact(&mut header)?;
+            let mut header = header.as_slice();
+            let size = u32::decode(&mut header)?;
+            let r#type = u32::decode(&mut header)?.to_be_bytes();
+            if size == 0 && &r#type == b"mdat" {
+                unfinished_media_data = Some((offset, previous_box));
+            }
+            let (header_size, size) = match size {
+                0 => (4 + 4, end - offset),
+                1 => {
+                    let mut large_size = [0; 8];
+                    file.read_exact(&mut large_size)?;
+                    (4 + 4 + 8, u64::from_be_bytes(large_size))
+                }
+                _ => (4 + 4, size as u64),
+            };
+            if size < header_size || size > end - offset {
+                return Err(Error::InvalidBoxSize {
+                    r#type: r#type.into(),
+                    size,
+                    remaining: (end - offset) as usize,
+                });
+            }
+            if &r#type == b"moov" {
+                let mut data = vec![0; (size - header_size) as usize];
+                file.read_exact(&mut data)?;
+                movie = Some((offset, MovieBox::decode(&mut data.as_slice())?));
+            }
+            previous_box = Some((offset, r#type, size));
+            offset += size;
+        }
+        let (movie_offset, movie) = movie.ok_or(Error::InvalidBoxQuantity {
+            r#type: "moov",
+            quantity: 0,
+            expected: 1,
+        })?;
+
+        if let Some((offset, previous_box)) = unfinished_media_data {
+            match previous_box {
+                Some((free_offset, r#type, 8)) if &r#type == b"free" => {
+                    end_media_data(&mut file, free_offset)?
+                }
+                _ => Patch::u32_at(offset).apply(&mut file, end - offset)?,
+            }
+        }
+        let media_data_offset = begin_media_data(&mut file)?;
+
+        let sample_description_indices = movie
+            .tracks
+            .iter()
+            .map(|track| {
+                track
+                    .media
+                    .information
+                    .sample_table
+                    .sample_to_chunk
+                    .0
+                    .last()
+                    .map_or(1, |entry| entry.sample_description_index)
+            })
+            .collect();
+        let primings = vec![None; movie.tracks.len()];
+
+        Ok(Self {
+            file,
+            movie,
+            movie_offset,
+            media_data_offset,
+            media_data_limit: None,
+            parallel_encode: false,
+            last_track_id: None,
+            sample_description_indices,
+            primings,
+        })
+    }
+
+    pub fn movie(&self) -> &MovieBox {
+        &self.movie
+    }
+
+    /// Starts a new mdat whenever the current one would exceed `limit` bytes,
+    /// e.g. to keep each of them below 4 GiB.
+    pub fn set_media_data_limit(&mut self, limit: Option<u64>) {
+        self.media_data_limit = limit;
+    }
+
+    /// Encodes the moov on close with [MovieBox::encode_parallel], to finish
+    /// long recordings sooner.
+    pub fn set_parallel_encode(&mut self, parallel_encode: bool) {
+        self.parallel_encode = parallel_encode;
+    }
+
+    /// Adds a sample description to a track, which is used for all following
+    /// samples of it.
+    pub fn add_sample_description(&mut self, track_id: u32, entry: SampleEntry) -> Result<u32> {
+        let index = self.track_index(track_id)?;
+        let sample_description_index = self.movie.tracks[index]
+            .media
+            .information
+            .sample_table
+            .description
+            .push(entry);
+        self.sample_description_indices[index] = sample_description_index;
+        Ok(sample_description_index)
+    }
+
+    /// Switches the following samples of a track to an existing sample
+    /// description.
+    pub fn set_sample_description(
+        &mut self,
+        track_id: u32,
+        sample_description_index: u32,
+    ) -> Result<()> {
+        let index = self.track_index(track_id)?;
+        if self.movie.tracks[index]
+            .media
+            .information
+            .sample_table
+            .description
+            .get(sample_description_index)
+            .is_none()
+        {
+            return Err(Error::ValueOutOfRange {
+                field: "sample_description_index",
+            });
+        }
+        self.sample_description_indices[index] = sample_description_index;
+        Ok(())
+    }
+
+    /// Signals the roll distance for all samples of a track, see
+    /// [SampleTableBox::set_roll_distance].
+    pub fn set_roll_distance(&mut self, track_id: u32, roll_distance: i16) -> Result<()> {
+        let index = self.track_index(track_id)?;
+        self.movie.tracks[index]
+            .media
+            .information
+            .sample_table
+            .set_roll_distance(roll_distance);
+        Ok(())
+    }
+
+    /// Hides the first `priming` media time units of a track, like the encoder
+    /// delay of AAC, behind an edit list which is written on close, see
+    /// [TrackBox::set_priming](crate::marshal::TrackBox::set_priming).
+    pub fn set_priming(&mut self, track_id: u32, priming: Option<MediaTime>) -> Result<()> {
+        let index = self.track_index(track_id)?;
+        self.primings[index] = priming;
+        Ok(())
+    }
+
+    /// Embeds a JPEG or PNG thumbnail, which is written on close.
+    pub fn set_cover_art(&mut self, image: Vec<u8>) -> Result<()> {
+        self.movie.set_cover_art(image)
+    }
+
+    /// Sets an iTunes text tag, like
+    /// [MetadataItem::TITLE](crate::marshal::MetadataItem::TITLE), which is
+    /// written on close.
+    pub fn set_metadata_text(&mut self, key: FourCC, value: &str) {
+        self.movie.item_list_mut().set_text(key, value);
+    }
+
+    pub fn append_sample(
+        &mut self,
+        track_id: u32,
+        data: &[u8],
+        duration: u32,
+        sync: bool,
+    ) -> Result<()> {
+        let index = self.track_index(track_id)?;
+        let track = &mut self.movie.tracks[index];
+
+        let mut offset = self.file.seek(SeekFrom::End(0))?;
+        let media_data_header_size = 4 + 4 + 8;
+        let media_data_size = offset - self.media_data_offset;
+        if self.media_data_limit.is_some_and(|limit| {
+            media_data_size != media_data_header_size
+                && media_data_size + data.len() as u64 > limit
+        }) {
+            end_media_data(&mut self.file, self.media_data_offset)?;
+            self.media_data_offset = begin_media_data(&mut self.file)?;
+            self.last_track_id = None;
+            offset = self.file.stream_position()?;
+        }
+        self.file.write_all(data)?;
+
+        let sample_table = &mut track.media.information.sample_table;
+        push_sample(
+            sample_table,
+            offset,
+            checked_cast(data.len(), "sample_size")?,
+            duration,
+            sync,
+            self.sample_description_indices[index],
+            self.last_track_id == Some(track_id),
+        );
+        self.last_track_id = Some(track_id);
+        Ok(())
+    }
+
+    fn track_index(&self, track_id: u32) -> Result<usize> {
+        self.movie
+            .tracks
+            .iter()
+            .position(|track| track.header.track_id == track_id)
+            .ok_or(Error::InvalidTrackId { track_id })
+    }
+
+    /// Writes the updated moov and retires the previous one. The last edit of
+    /// each track is extended to its new samples, see
+    /// [TrackBox::recompute_duration](crate::marshal::TrackBox::recompute_duration).
+    pub fn close(mut self) -> Result<(F, MuxSummary)> {
+        end_media_data(&mut self.file, self.media_data_offset)?;
+
+        let movie_timescale = self.movie.header.timescale;
+        for (track, priming) in self.movie.tracks.iter_mut().zip(&self.primings) {
+            if let Some(priming) = priming {
+                track.set_priming(*priming, movie_timescale);
+            }
+        }
+        self.movie.recompute_durations();
+        let movie_offset = self.file.seek(SeekFrom::End(0))?;
+        if self.parallel_encode {
+            self.movie.encode_parallel(&mut self.file)?;
+        } else {
+            self.movie.encode(&mut self.file)?;
+        }
+        let file_size = self.file.stream_position()?;
+
+        self.file.seek(SeekFrom::Start(self.movie_offset + 4))?;
+        self.file.write_all(b"free")?;
+        self.file.seek(SeekFrom::End(0))?;
+        let summary = MuxSummary::new(&self.movie, file_size, file_size - movie_offset);
+        Ok((self.file, summary))
+    }
+}
+
+/// Writes the header of a mdat at the end, which reaches until the end of the
+/// file until [end_media_data] sets its size.
+fn begin_media_data(file: &mut (impl Write + Seek)) -> Result<u64> {
+    // reserve room for a large size, as there is no going back once samples
+    // follow, behind a free box which is merged into the header later on
+    let media_data_offset = file.seek(SeekFrom::End(0))?;
+    8u32.encode(file)?; // size
+    file.write_all(b"free")?;
+    0u32.encode(file)?; // size
+    file.write_all(b"mdat")?;
+    Ok(media_data_offset)
+}
+
+fn end_media_data(file: &mut (impl Write + Seek), media_data_offset: u64) -> Result<()> {
+    let end = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(media_data_offset))?;
+    1u32.encode(file)?; // size
+    file.write_all(b"mdat")?;
+    (end - media_data_offset).encode(file)?; // largesize
+    file.seek(SeekFrom::End(0))?;
+    Ok(())
+}
+
+fn push_sample(
+    sample_table: &mut SampleTableBox,
+    offset: u64,
+    size: u32,
+    duration: u32,
+    sync: bool,
+    sample_description_index: u32,
+    contiguous: bool,
+) {
+    let sample_count = sample_table.sample_size.sample_count();
+
+    let time_to_sample = &mut sample_table.time_to_sample.0;
+    match time_to_sample.last_mut() {
+        Some(entry) if entry.sample_delta == duration => entry.sample_count += 1,
+        _ => time_to_sample.push(TimeToSampleEntry {
+            sample_count: 1,
+            sample_delta: duration,
+        }),
+    }
+
+    if let Some(composition_offset) = &mut sample_table.composition_offset {
+        match composition_offset.0.last_mut() {
+            Some(entry) if entry.sample_offset == 0 => entry.sample_count += 1,
+            _ => composition_offset.0.push(CompositionOffsetEntry {
+                sample_count: 1,
+                sample_offset: 0,
+            }),
+        }
+    }
+
+    // a roll group covering all samples so far also covers the new one
+    if let Some(sample_to_group) = &mut sample_table.sample_to_group {
+        if matches!(&sample_to_group.0.to_bytes(), b"roll" | b"prol") {
+            let covered = sample_to_group
+                .1
+                .iter()
+                .map(|entry| entry.sample_count as u64)
+                .sum::<u64>();
+            if covered == sample_count as u64 {
+                match sample_to_group.1.last_mut() {
+                    Some(entry) => entry.sample_count += 1,
+                    None => sample_to_group.1.push(SampleToGroupEntry {
+                        sample_count: 1,
+                        group_description_index: 1,
+                    }),
+                }
+            }
+        }
+    }
+
+    match &mut sample_table.sync_sample {
+        Some(sync_sample) => {
+            if sync {
+                sync_sample.0.push(sample_count + 1);
+            }
+        }
+        // without stss every sample is a sync sample
+        None => {
+            if !sync {
+                sample_table.sync_sample = Some(SyncSampleBox((1..=sample_count).collect()));
+            }
+        }
+    }
+
+    sample_table.sample_size = match std::mem::replace(
+        &mut sample_table.sample_size,
+        SampleSizeBox::PerSample(vec![]),
+    ) {
+        SampleSizeBox::Value {
+            sample_size,
+            sample_count,
+        } if sample_size == size || sample_count == 0 => SampleSizeBox::Value {
+            sample_size: size,
+            sample_count: sample_count + 1,
+        },
+        SampleSizeBox::Value {
+            sample_size,
+            sample_count,
+        } => {
+            let mut samples = vec![sample_size; sample_count as usize];
+            samples.push(size);
+            SampleSizeBox::PerSample(samples)
+        }
+        SampleSizeBox::PerSample(mut samples) => {
+            samples.push(size);
+            SampleSizeBox::PerSample(samples)
+        }
+    };
+
+    let chunk_count = sample_table.chunk_offset.0.len() as u32;
+    let sample_to_chunk = &mut sample_table.sample_to_chunk.0;
+    // a chunk only references a single sample description
+    let contiguous = contiguous
+        && sample_to_chunk
+            .last()
+            .is_some_and(|entry| entry.sample_description_index == sample_description_index);
+    if contiguous && chunk_count != 0 {
+        let entry = sample_to_chunk.last_mut().unwrap();
+        if entry.first_chunk == chunk_count {
+            entry.samples_per_chunk += 1;
+        } else {
+            // the last chunk no longer shares the run of the previous ones
+            let samples_per_chunk = entry.samples_per_chunk + 1;
+            let sample_description_index = entry.sample_description_index;
+            sample_to_chunk.push(SampleToChunkEntry {
+                first_chunk: chunk_count,
+                samples_per_chunk,
+                sample_description_index,
+            });
+        }
+    } else {
+        sample_table.chunk_offset.0.push(offset);
+        if sample_to_chunk.last().is_none_or(|entry| {
+            entry.samples_per_chunk != 1
+                || entry.sample_description_index != sample_description_index
+        }) {
+            sample_to_chunk.push(SampleToChunkEntry {
+                first_chunk: chunk_count + 1,
+                samples_per_chunk: 1,
+                sample_description_index,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use fixed_macro::types::U16F16;
+
+    use super::AppendSession;
+    use crate::marshal::{
+        timed_metadata::{URIBox, URIMetaSampleEntry},
+        Decode, EditBox, EditListBox, EditListEntry, Encode, File, FileTypeBox, FourCC, MovieBox,
+        MovieHeaderBox, MovieTime, SampleEntry, TrackBox,
+    };
+
+    fn append(file: Vec<u8>, sample_count: u32) -> Vec<u8> {
+        let mut session = AppendSession::open(Cursor::new(file)).unwrap();
+        for _ in 0..sample_count {
+            session.append_sample(1, &[0; 4], 10, true).unwrap();
+        }
+        session.close().unwrap().0.into_inner()
+    }
+
+    fn recording() -> Vec<u8> {
+        let mut track = TrackBox::new_timed_metadata(
+            1,
+            100,
+            SampleEntry::URIMeta(URIMetaSampleEntry {
+                data_reference_index: 1,
+                uri: URIBox {
+                    the_uri: "urn:example".to_owned(),
+                },
+                init: None,
+                bit_rate: None,
+            }),
+            None,
+        );
+        track.edit = Some(EditBox {
+            edit_list: Some(EditListBox(vec![
+                EditListEntry {
+                    segment_duration: MovieTime(50),
+                    media_time: -1,
+                    media_rate: U16F16!(1),
+                },
+                EditListEntry {
+                    segment_duration: MovieTime(0),
+                    media_time: 0,
+                    media_rate: U16F16!(1),
+                },
+            ])),
+            extra_boxes: Vec::new(),
+        });
+        let mut file = Cursor::new(Vec::new());
+        FileTypeBox {
+            major_brand: FourCC::from(*b"isom"),
+            minor_version: 0,
+            compatible_brands: vec![FourCC::from(*b"isom")],
+        }
+        .encode(&mut file)
+        .unwrap();
+        MovieBox {
+            header: MovieHeaderBox {
+                timescale: 1000,
+                next_track_id: 2,
+                ..Default::default()
+            },
+            tracks: vec![track],
+            extends: None,
+            user_data: None,
+            extra_boxes: Vec::new(),
+        }
+        .encode(&mut file)
+        .unwrap();
+        file.into_inner()
+    }
+
+    #[test]
+    fn edit_list_covers_appended_samples() {
+        // a recording, which is resumed later on
+        let file = append(recording(), 3);
+        let file = append(file, 2);
+
+        let file = File::decode(&mut file.as_slice()).unwrap();
+        let movie = file.movie.unwrap();
+        let track = &movie.tracks[0];
+        assert_eq!(track.samples().count(), 5);
+        let edit_list = &track.edit.as_ref().unwrap().edit_list.as_ref().unwrap().0;
+        assert_eq!(edit_list[1].segment_duration, MovieTime(500));
+        assert_eq!(track.header.duration, MovieTime(550));
+        assert_eq!(movie.header.duration, MovieTime(550));
+    }
+
+    #[test]
+    fn unclosed_session() {
+        let mut file = Cursor::new(append(recording(), 3));
+        let mut session = AppendSession::open(&mut file).unwrap();
+        session.append_sample(1, &[0; 4], 10, true).unwrap();
+        // the session is never closed, like on a power loss
+        drop(session);
+
+        let decoded = File::decode(&mut file.get_ref().as_slice()).unwrap();
+        assert_eq!(decoded.movie.unwrap().tracks[0].samples().count(), 3);
+
+        let file = append(file.into_inner(), 2);
+        let decoded = File::decode(&mut file.as_slice()).unwrap();
+        assert_eq!(decoded.movie.unwrap().tracks[0].samples().count(), 5);
+    }
+}
@@ -0,0 +1,695 @@
+use std::{io::Cursor, ops::Range};
+
+use crate::{
+    io::{read_box_data, read_box_headers, RandomAccess},
+    marshal::{
+        checked_cast, ChunkOffsetBox, CompositionOffsetBox, CompositionOffsetEntry, Decode,
+        EditBox, EditListBox, EditListEntry, Encode, Error, File, FileTypeBox, MediaDataBox,
+        MediaTime, MetaBox, MovieBox, MovieFragmentBox, Result, SampleSizeBox, SampleTableBox,
+        SampleToChunkBox, SampleToChunkEntry, SyncSampleBox, TimeToSampleBox, TimeToSampleEntry,
+        TrackBox, TrackRunEntry, U16F16,
+    },
+};
+
+/// Consolidates the movie fragments of a fragmented file into the sample
+/// tables of its tracks, and the sample data into a single mdat.
+pub fn defragment(input: &(impl RandomAccess + ?Sized)) -> Result<File> {
+    defragment_with(input, |_, _| Ok(()))
+}
+
+/// Like [defragment], but passes the data of each sample with its track id
+/// through `transform` before it is written, and takes over the new sizes.
+pub fn defragment_with(
+    input: &(impl RandomAccess + ?Sized),
+    mut transform: impl FnMut(u32, &mut Vec<u8>) -> Result<()>,
+) -> Result<File> {
+    let mut file_type = None;
+    let mut movie = None;
+    let mut meta = None;
+    let mut tracks = Vec::new();
+    let mut data = Vec::new();
+
+    for header in read_box_headers(input)? {
+        match &header.r#type.to_bytes() {
+            b"ftyp" => {
+                file_type = Some(FileTypeBox::decode(
+                    &mut read_box_data(input, &header)?.as_slice(),
+                )?)
+            }
+            b"moov" => {
+                let value = MovieBox::decode(&mut read_box_data(input, &header)?.as_slice())?;
+                tracks.clear();
+                for track in &value.tracks {
+                    let mut samples = Samples {
+                        track_id: track.header.track_id,
+                        ..Default::default()
+                    };
+                    samples.extend(
+                        input,
+                        &mut data,
+                        &track.media.information.sample_table,
+                        &mut transform,
+                    )?;
+                    tracks.push(samples);
+                }
+                movie = Some(value);
+            }
+            b"moof" => {
+                let movie = movie.as_ref().ok_or(Error::InvalidBoxQuantity {
+                    r#type: "moov",
+                    quantity: 0,
+                    expected: 1,
+                })?;
+                let fragment =
+                    MovieFragmentBox::decode(&mut read_box_data(input, &header)?.as_slice())?;
+                append_fragment(
+                    input,
+                    &mut data,
+                    movie,
+                    &mut tracks,
+                    &fragment,
+                    header.offset,
+                    &mut transform,
+                )?;
+            }
+            b"meta" => {
+                meta = Some(MetaBox::decode(
+                    &mut read_box_data(input, &header)?.as_slice(),
+                )?)
+            }
+            _ => {}
+        }
+    }
+
+    let file_type = file_type.ok_or(Error::InvalidBoxQuantity {
+        r#type: "ftyp",
+        quantity: 0,
+        expected: 1,
+    })?;
+    let mut movie = movie.ok_or(Error::InvalidBoxQuantity {
+        r#type: "moov",
+        quantity: 0,
+        expected: 1,
+    })?;
+    movie.extends = None;
+    let movie_timescale = movie.header.timescale;
+    for (track, samples) in movie.tracks.iter_mut().zip(&tracks) {
+        samples.apply(&mut track.media.information.sample_table);
+        if samples.start != 0 {
+            delay_edits(track, samples.start, movie_timescale);
+        }
+    }
+    movie.recompute_durations();
+
+    let mut file = File {
+        file_type,
+        movie: Some(movie),
+        media_data: vec![MediaDataBox(data)],
+        meta,
+        extra_boxes: Vec::new(),
+    };
+
+    // chunk offsets are relative to the mdat payload until the size of
+    // everything in front of it is known, which again depends on whether the
+    // offsets still fit into stco
+    let mut header_size = 0;
+    loop {
+        let movie = file.movie.as_mut().unwrap();
+        for (track, samples) in movie.tracks.iter_mut().zip(&tracks) {
+            track.media.information.sample_table.chunk_offset = ChunkOffsetBox(
+                samples
+                    .chunks
+                    .iter()
+                    .map(|&(offset, _, _)| offset + header_size)
+                    .collect(),
+            );
+        }
+
+        let size = media_data_offset(&file)?;
+        if size == header_size {
+            break;
+        }
+        header_size = size;
+    }
+    Ok(file)
+}
+
+/// Returns the stsz for the sample sizes, with a single size if all samples
+/// have the same one.
+pub(crate) fn sample_size_box(sizes: &[u32]) -> SampleSizeBox {
+    match sizes {
+        [sample_size, rest @ ..] if rest.iter().all(|size| size == sample_size) => {
+            SampleSizeBox::Value {
+                sample_size: *sample_size,
+                sample_count: sizes.len() as u32,
+            }
+        }
+        _ => SampleSizeBox::PerSample(sizes.to_vec()),
+    }
+}
+
+/// Something off about the order or timing of the movie fragments, like after
+/// a live recording lost or repeated a fragment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentIssue {
+    /// the mfhd sequence number is not greater than the one of the previous
+    /// moof
+    NonIncreasingSequenceNumber {
+        offset: u64,
+        sequence_number: u32,
+        previous: u32,
+    },
+    /// sequence numbers between the previous moof and this one are missing
+    SkippedSequenceNumbers {
+        offset: u64,
+        sequence_number: u32,
+        previous: u32,
+    },
+    /// the tfdt is after the end of the previous samples of the track
+    Gap {
+        offset: u64,
+        track_id: u32,
+        expected: MediaTime,
+        actual: MediaTime,
+    },
+    /// the tfdt is before the end of the previous samples of the track
+    Overlap {
+        offset: u64,
+        track_id: u32,
+        expected: MediaTime,
+        actual: MediaTime,
+    },
+}
+
+/// Checks that the mfhd sequence numbers increase one by one and that the
+/// tfdt of each track fragment continues where the previous samples of the
+/// track ended, with the offset of the moof in each issue.
+pub fn validate_fragments(input: &(impl RandomAccess + ?Sized)) -> Result<Vec<FragmentIssue>> {
+    let mut issues = Vec::new();
+    let mut movie = None;
+    // end of the samples of each track, in the order of the tracks
+    let mut ends = Vec::new();
+    let mut previous_sequence_number = None;
+
+    for header in read_box_headers(input)? {
+        match &header.r#type.to_bytes() {
+            b"moov" => {
+                let value = MovieBox::decode(&mut read_box_data(input, &header)?.as_slice())?;
+                ends = value
+                    .tracks
+                    .iter()
+                    .map(|track| {
+                        track
+                            .media
+                            .information
+                            .sample_table
+                            .time_to_sample
+                            .0
+                            .iter()
+                            .map(|entry| entry.sample_count as u64 * entry.sample_delta as u64)
+                            .sum::<u64>()
+                    })
+                    .collect();
+                movie = Some(value);
+            }
+            b"moof" => {
+                let movie: &MovieBox = movie.as_ref().ok_or(Error::InvalidBoxQuantity {
+                    r#type: "moov",
+                    quantity: 0,
+                    expected: 1,
+                })?;
+                let fragment =
+                    MovieFragmentBox::decode(&mut read_box_data(input, &header)?.as_slice())?;
+                let offset = header.offset;
+
+                let sequence_number = fragment.header.sequence_number;
+                if let Some(previous) = previous_sequence_number {
+                    if sequence_number <= previous {
+                        issues.push(FragmentIssue::NonIncreasingSequenceNumber {
+                            offset,
+                            sequence_number,
+                            previous,
+                        });
+                    } else if sequence_number != previous + 1 {
+                        issues.push(FragmentIssue::SkippedSequenceNumbers {
+                            offset,
+                            sequence_number,
+                            previous,
+                        });
+                    }
+                }
+                previous_sequence_number = Some(sequence_number);
+
+                for track_fragment in &fragment.track_fragments {
+                    let track_id = track_fragment.header.track_id;
+                    let index = movie
+                        .tracks
+                        .iter()
+                        .position(|track| track.header.track_id == track_id)
+                        .ok_or(Error::InvalidTrackId { track_id })?;
+                    let expected = MediaTime(ends[index]);
+                    let start = match &track_fragment.decode_time {
+                        Some(decode_time) => {
+                            let actual = decode_time.base_media_decode_time;
+                            if actual > expected {
+                                issues.push(FragmentIssue::Gap {
+                                    offset,
+                                    track_id,
+                                    expected,
+                                    actual,
+                                });
+                            } else if actual < expected {
+                                issues.push(FragmentIssue::Overlap {
+                                    offset,
+                                    track_id,
+                                    expected,
+                                    actual,
+                                });
+                            }
+                            actual
+                        }
+                        None => expected,
+                    };
+
+                    let default_sample_duration = track_fragment
+                        .header
+                        .default_sample_duration
+                        .or(movie
+                            .extends
+                            .as_ref()
+                            .and_then(|extends| extends.get(track_id))
+                            .map(|track_extends| track_extends.default_sample_duration))
+                        .unwrap_or_default();
+                    let duration: u64 = track_fragment
+                        .runs
+                        .iter()
+                        .flat_map(|run| &run.entries)
+                        .map(|entry| {
+                            entry.sample_duration.unwrap_or(default_sample_duration) as u64
+                        })
+                        .sum();
+                    ends[index] = start.0 + duration;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(issues)
+}
+
+/// Keeps the samples of a track presented at the same time, now that they
+/// start at 0 instead of `start`, by moving the edits, or by delaying the
+/// track with an empty edit if there are none.
+fn delay_edits(track: &mut TrackBox, start: u64, movie_timescale: u32) {
+    let media_timescale = track.media.header.timescale;
+    let to_movie =
+        |media_time: u64| MediaTime(media_time).to_movie(media_timescale, movie_timescale);
+    let edit = track.edit.get_or_insert_with(|| EditBox {
+        edit_list: None,
+        extra_boxes: Vec::new(),
+    });
+    let edit_list = edit
+        .edit_list
+        .get_or_insert_with(|| EditListBox(Vec::new()));
+    if edit_list.0.is_empty() {
+        let duration: u64 = track
+            .media
+            .information
+            .sample_table
+            .time_to_sample
+            .0
+            .iter()
+            .map(|entry| entry.sample_count as u64 * entry.sample_delta as u64)
+            .sum();
+        edit_list.0 = vec![
+            EditListEntry {
+                segment_duration: to_movie(start),
+                media_time: -1,
+                media_rate: U16F16::ONE,
+            },
+            EditListEntry {
+                segment_duration: to_movie(duration),
+                media_time: 0,
+                media_rate: U16F16::ONE,
+            },
+        ];
+        return;
+    }
+
+    let mut entries = Vec::new();
+    for mut entry in std::mem::take(&mut edit_list.0) {
+        // -1 denotes an empty edit
+        if entry.media_time >= 0 {
+            match (entry.media_time as u64).checked_sub(start) {
+                Some(media_time) => entry.media_time = media_time as i64,
+                None => {
+                    // the beginning of the edit is before the first sample
+                    let empty_duration =
+                        to_movie(start - entry.media_time as u64).min(entry.segment_duration);
+                    entries.push(EditListEntry {
+                        segment_duration: empty_duration,
+                        media_time: -1,
+                        media_rate: U16F16::ONE,
+                    });
+                    entry.segment_duration.0 -= empty_duration.0;
+                    entry.media_time = 0;
+                }
+            }
+        }
+        entries.push(entry);
+    }
+    edit_list.0 = entries;
+}
+
+/// Returns where the payload of the first mdat starts, when it directly
+/// follows ftyp and moov.
+pub(crate) fn media_data_offset(file: &File) -> Result<u64> {
+    let mut output = Cursor::new(Vec::new());
+    file.file_type.encode(&mut output)?;
+    file.movie.encode(&mut output)?;
+    let media_data_header_size = if u32::try_from(4 + 4 + file.media_data[0].0.len()).is_ok() {
+        4 + 4
+    } else {
+        4 + 4 + 8
+    };
+    Ok(output.position() + media_data_header_size)
+}
+
+pub(crate) fn decode_movie(input: &(impl RandomAccess + ?Sized)) -> Result<MovieBox> {
+    let header = read_box_headers(input)?
+        .into_iter()
+        .find(|header| &header.r#type.to_bytes() == b"moov")
+        .ok_or(Error::InvalidBoxQuantity {
+            r#type: "moov",
+            quantity: 0,
+            expected: 1,
+        })?;
+    MovieBox::decode(&mut read_box_data(input, &header)?.as_slice())
+}
+
+fn append_fragment(
+    input: &(impl RandomAccess + ?Sized),
+    data: &mut Vec<u8>,
+    movie: &MovieBox,
+    tracks: &mut [Samples],
+    fragment: &MovieFragmentBox,
+    begin: u64,
+    transform: &mut impl FnMut(u32, &mut Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    // without an explicit base, the first track fragment starts at the moof and
+    // each following one where the data of the previous one ended
+    let mut data_end = begin;
+    for track_fragment in &fragment.track_fragments {
+        let header = &track_fragment.header;
+        let track_id = header.track_id;
+        let index = movie
+            .tracks
+            .iter()
+            .position(|track| track.header.track_id == track_id)
+            .ok_or(Error::InvalidTrackId { track_id })?;
+        let samples = &mut tracks[index];
+
+        let track_extends = movie
+            .extends
+            .as_ref()
+            .and_then(|extends| extends.get(track_id));
+        let default_sample_duration = header
+            .default_sample_duration
+            .or(track_extends.map(|track_extends| track_extends.default_sample_duration))
+            .unwrap_or_default();
+        let default_sample_size = header
+            .default_sample_size
+            .or(track_extends.map(|track_extends| track_extends.default_sample_size))
+            .unwrap_or_default();
+        let default_sample_flags = header
+            .default_sample_flags
+            .or(track_extends.map(|track_extends| track_extends.default_sample_flags))
+            .unwrap_or_default();
+        let sample_description_index = header
+            .sample_description_index
+            .or(track_extends.map(|track_extends| track_extends.default_sample_description_index))
+            .unwrap_or(1);
+
+        if let Some(decode_time) = &track_fragment.decode_time {
+            samples.seek(decode_time.base_media_decode_time.0)?;
+        }
+
+        let base_data_offset = header
+            .base_data_offset
+            .unwrap_or(if header.default_base_is_moof {
+                begin
+            } else {
+                data_end
+            });
+        let mut offset = base_data_offset;
+        for run in &track_fragment.runs {
+            if let Some(data_offset) = run.data_offset {
+                offset = base_data_offset
+                    .checked_add_signed(data_offset as i64)
+                    .ok_or(Error::ValueOutOfRange {
+                        field: "data_offset",
+                    })?;
+            }
+            let run_begin = offset;
+            for (index, entry) in run.entries.iter().enumerate() {
+                let sample_flags = if index == 0 {
+                    run.first_sample_flags.or(entry.sample_flags)
+                } else {
+                    entry.sample_flags
+                }
+                .unwrap_or(default_sample_flags);
+                let sample_size = entry.sample_size.unwrap_or(default_sample_size);
+                samples.push(
+                    entry.sample_duration.unwrap_or(default_sample_duration),
+                    sample_size,
+                    sample_flags & TrackRunEntry::NON_SYNC_SAMPLE == 0,
+                    entry.sample_composition_time_offset.unwrap_or_default(),
+                );
+                offset += sample_size as u64;
+            }
+            samples.push_chunk(
+                input,
+                data,
+                run_begin..offset,
+                run.entries.len() as u32,
+                sample_description_index,
+                transform,
+            )?;
+            data_end = offset;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Samples {
+    track_id: u32,
+    /// decode time of the first sample
+    start: u64,
+    time: u64,
+    durations: Vec<u32>,
+    sizes: Vec<u32>,
+    syncs: Vec<bool>,
+    composition_offsets: Vec<i32>,
+    /// offset into the new mdat payload, sample count and sample description
+    /// index of each chunk
+    chunks: Vec<(u64, u32, u32)>,
+    /// samples that are already part of a chunk
+    chunked_samples: usize,
+}
+
+impl Samples {
+    fn extend(
+        &mut self,
+        input: &(impl RandomAccess + ?Sized),
+        data: &mut Vec<u8>,
+        sample_table: &SampleTableBox,
+        transform: &mut impl FnMut(u32, &mut Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        let durations =
+            sample_table.time_to_sample.0.iter().flat_map(|entry| {
+                std::iter::repeat_n(entry.sample_delta, entry.sample_count as usize)
+            });
+        let mut composition_offsets = sample_table
+            .composition_offset
+            .iter()
+            .flat_map(|composition_offset| &composition_offset.0)
+            .flat_map(|entry| {
+                std::iter::repeat_n(entry.sample_offset, entry.sample_count as usize)
+            });
+        // stss is sorted, so it is walked along with the samples
+        let mut sync_samples = sample_table
+            .sync_sample
+            .as_ref()
+            .map(|sync_sample| sync_sample.0.iter().copied().peekable());
+        for (sample, duration) in durations
+            .take(sample_table.sample_size.sample_count() as usize)
+            .enumerate()
+        {
+            let sample = sample as u32;
+            let sync = match &mut sync_samples {
+                Some(sync_samples) => {
+                    while sync_samples
+                        .next_if(|&sync_sample| sync_sample <= sample)
+                        .is_some()
+                    {}
+                    sync_samples.next_if_eq(&(sample + 1)).is_some()
+                }
+                None => true,
+            };
+            self.push(
+                duration,
+                sample_table.sample_size.sample_size(sample).unwrap_or(0),
+                sync,
+                composition_offsets.next().unwrap_or_default(),
+            );
+        }
+
+        for (offset, samples, sample_description_index) in sample_table.chunks() {
+            let length: u64 = samples
+                .clone()
+                .map(|sample| sample_table.sample_size.sample_size(sample).unwrap_or(0) as u64)
+                .sum();
+            self.push_chunk(
+                input,
+                data,
+                offset..offset + length,
+                samples.len() as u32,
+                sample_description_index,
+                transform,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn seek(&mut self, time: u64) -> Result<()> {
+        let Some(duration) = self.durations.last_mut() else {
+            // the samples start later, which is kept with an edit
+            self.start = time;
+            self.time = time;
+            return Ok(());
+        };
+        // stts can't express gaps, extend the previous sample instead
+        if time > self.time {
+            *duration = duration
+                .checked_add(checked_cast(time - self.time, "base_media_decode_time")?)
+                .ok_or(Error::ValueOutOfRange {
+                    field: "base_media_decode_time",
+                })?;
+            self.time = time;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, duration: u32, size: u32, sync: bool, composition_offset: i32) {
+        self.time += duration as u64;
+        self.durations.push(duration);
+        self.sizes.push(size);
+        self.syncs.push(sync);
+        self.composition_offsets.push(composition_offset);
+    }
+
+    fn push_chunk(
+        &mut self,
+        input: &(impl RandomAccess + ?Sized),
+        data: &mut Vec<u8>,
+        range: Range<u64>,
+        sample_count: u32,
+        sample_description_index: u32,
+        transform: &mut impl FnMut(u32, &mut Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        if sample_count == 0 {
+            return Ok(());
+        }
+        if range.end > input.len()? {
+            return Err(Error::ValueOutOfRange {
+                field: "data_offset",
+            });
+        }
+        let chunk = input.read_range(range)?;
+        self.chunks
+            .push((data.len() as u64, sample_count, sample_description_index));
+
+        let sizes = self
+            .sizes
+            .get_mut(self.chunked_samples..self.chunked_samples + sample_count as usize)
+            .ok_or(Error::ValueOutOfRange {
+                field: "sample_count",
+            })?;
+        self.chunked_samples += sample_count as usize;
+        let mut chunk = chunk.as_slice();
+        let mut sample = Vec::new();
+        for size in sizes {
+            let (sample_data, remaining_chunk) =
+                chunk
+                    .split_at_checked(*size as usize)
+                    .ok_or(Error::ValueOutOfRange {
+                        field: "sample_size",
+                    })?;
+            chunk = remaining_chunk;
+            sample.clear();
+            sample.extend_from_slice(sample_data);
+            transform(self.track_id, &mut sample)?;
+            *size = checked_cast(sample.len(), "sample_size")?;
+            data.extend_from_slice(&sample);
+        }
+        Ok(())
+    }
+
+    fn apply(&self, sample_table: &mut SampleTableBox) {
+        let mut time_to_sample: Vec<TimeToSampleEntry> = Vec::new();
+        for &sample_delta in &self.durations {
+            match time_to_sample.last_mut() {
+                Some(entry) if entry.sample_delta == sample_delta => entry.sample_count += 1,
+                _ => time_to_sample.push(TimeToSampleEntry {
+                    sample_count: 1,
+                    sample_delta,
+                }),
+            }
+        }
+        sample_table.time_to_sample = TimeToSampleBox(time_to_sample);
+
+        let mut composition_offset: Vec<CompositionOffsetEntry> = Vec::new();
+        for &sample_offset in &self.composition_offsets {
+            match composition_offset.last_mut() {
+                Some(entry) if entry.sample_offset == sample_offset => entry.sample_count += 1,
+                _ => composition_offset.push(CompositionOffsetEntry {
+                    sample_count: 1,
+                    sample_offset,
+                }),
+            }
+        }
+        sample_table.composition_offset = self
+            .composition_offsets
+            .iter()
+            .any(|&sample_offset| sample_offset != 0)
+            .then_some(CompositionOffsetBox(composition_offset));
+
+        sample_table.sync_sample = self.syncs.contains(&false).then(|| {
+            SyncSampleBox(
+                (1..)
+                    .zip(&self.syncs)
+                    .filter(|(_, &sync)| sync)
+                    .map(|(sample, _)| sample)
+                    .collect(),
+            )
+        });
+
+        sample_table.sample_size = sample_size_box(&self.sizes);
+
+        let mut sample_to_chunk: Vec<SampleToChunkEntry> = Vec::new();
+        for (chunk, &(_, samples_per_chunk, sample_description_index)) in (1..).zip(&self.chunks) {
+            if sample_to_chunk.last().is_none_or(|entry| {
+                entry.samples_per_chunk != samples_per_chunk
+                    || entry.sample_description_index != sample_description_index
+            }) {
+                sample_to_chunk.push(SampleToChunkEntry {
+                    first_chunk: chunk,
+                    samples_per_chunk,
+                    sample_description_index,
+                });
+            }
+        }
+        sample_table.sample_to_chunk = SampleToChunkBox(sample_to_chunk);
+    }
+}
@@ -0,0 +1,387 @@
+use std::{
+    io::{ErrorKind, Read},
+    ops::Range,
+};
+
+use crate::{
+    defragment::decode_movie,
+    io::RandomAccess,
+    marshal::{Decode, Error, FourCC, MovieBox, Result},
+};
+
+#[derive(Debug)]
+pub struct ReadPlan(pub Vec<ChunkRead>);
+
+#[derive(Debug, Clone)]
+pub struct ChunkRead {
+    pub offset: u64,
+    pub length: u64,
+    pub track_id: u32,
+    pub samples: Range<u32>,
+    /// location of the data referenced by the dref if it isn't in the file, in
+    /// which case the offset is relative to it
+    pub external_location: Option<String>,
+}
+
+impl ChunkRead {
+    /// Reads the data from `input`, failing if it is stored elsewhere.
+    pub fn read(&self, input: &(impl RandomAccess + ?Sized)) -> Result<Vec<u8>> {
+        self.check_local()?;
+        input.read_range(self.offset..self.offset + self.length)
+    }
+
+    fn check_local(&self) -> Result<()> {
+        match &self.external_location {
+            Some(location) => Err(Error::ExternalDataReference {
+                track_id: self.track_id,
+                location: location.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn resolve(&self, resolver: &mut Option<Resolver>) -> Option<Result<Vec<u8>>> {
+        let location = self.external_location.as_deref()?;
+        let resolver = resolver.as_mut()?;
+        Some(resolver(location, self.offset..self.offset + self.length))
+    }
+}
+
+/// Reads the data of external data references, getting the location of the
+/// dref and the range in it, see [Reader::set_resolver].
+pub type Resolver = Box<dyn FnMut(&str, Range<u64>) -> Result<Vec<u8>> + Send>;
+
+impl ReadPlan {
+    pub fn new(movie: &MovieBox) -> Self {
+        let mut chunks = Vec::new();
+        for track in &movie.tracks {
+            let sample_table = &track.media.information.sample_table;
+            chunks.extend(sample_table.chunks().map(
+                |(offset, samples, sample_description_index)| {
+                    let length = samples
+                        .clone()
+                        .map(|sample| {
+                            sample_table.sample_size.sample_size(sample).unwrap_or(0) as u64
+                        })
+                        .sum();
+                    ChunkRead {
+                        offset,
+                        length,
+                        track_id: track.header.track_id,
+                        samples,
+                        external_location: track
+                            .external_location(sample_description_index)
+                            .map(ToOwned::to_owned),
+                    }
+                },
+            ));
+        }
+        // stable, so chunks of the same track keep their decode order
+        chunks.sort_by_key(|chunk| chunk.offset);
+        Self(chunks)
+    }
+}
+
+/// Reads the samples of a file in the order they are stored, with the moov
+/// before or after the mdat, as the input can be read at any offset.
+///
+/// Chunks stored outside of the file, as referenced by the dref, fail with
+/// [Error::ExternalDataReference] unless a resolver is set.
+pub struct Reader<R> {
+    input: R,
+    movie: MovieBox,
+    plan: ReadPlan,
+    next: usize,
+    resolver: Option<Resolver>,
+}
+
+impl<R: RandomAccess> Reader<R> {
+    pub fn new(input: R) -> Result<Self> {
+        let movie = decode_movie(&input)?;
+        let plan = ReadPlan::new(&movie);
+        Ok(Self {
+            input,
+            movie,
+            plan,
+            next: 0,
+            resolver: None,
+        })
+    }
+
+    pub fn movie(&self) -> &MovieBox {
+        &self.movie
+    }
+
+    /// Sets the function reading chunks stored outside of the file, which
+    /// gets the location of the data entry, like a relative URL, and the range
+    /// in it.
+    pub fn set_resolver(
+        &mut self,
+        resolver: impl FnMut(&str, Range<u64>) -> Result<Vec<u8>> + Send + 'static,
+    ) {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    /// Returns the next chunk with its data, or None after the last one.
+    pub fn next_chunk(&mut self) -> Option<Result<(ChunkRead, Vec<u8>)>> {
+        let chunk = self.plan.0.get(self.next)?.clone();
+        self.next += 1;
+        let data = match chunk.resolve(&mut self.resolver) {
+            Some(data) => data,
+            None => chunk.read(&self.input),
+        };
+        Some(data.map(|data| (chunk, data)))
+    }
+}
+
+/// Reads the samples of a file from a stream, like a pipe or a download, which
+/// can't be read at any offset.
+///
+/// Only the moov is kept in memory, all other boxes are skipped until it is
+/// found, so [new] fails if there is none. The samples can only be read if
+/// the moov comes first, otherwise [next_chunk] fails, as their data was
+/// already skipped.
+///
+/// Chunks stored outside of the file are handled like by [Reader].
+///
+/// [new]: StreamReader::new
+/// [next_chunk]: StreamReader::next_chunk
+pub struct StreamReader<R> {
+    input: R,
+    position: u64,
+    movie: MovieBox,
+    plan: ReadPlan,
+    next: usize,
+    resolver: Option<Resolver>,
+}
+
+impl<R: Read> StreamReader<R> {
+    pub fn new(mut input: R) -> Result<Self> {
+        let mut position = 0;
+        let movie = loop {
+            let mut header = [0; 4 + 4];
+            let mut length = 0;
+            while length < header.len() {
+                match input.read(&mut header[length..]) {
+                    Ok(0) => break,
+                    Ok(read) => length += read,
+                    Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            match length {
+                0 => {
+                    return Err(Error::InvalidBoxQuantity {
+                        r#type: "moov",
+                        quantity: 0,
+                        expected: 1,
+                    })
+                }
+                1..=7 => return Err(Error::Io(ErrorKind::UnexpectedEof.into())),
+                _ => {}
+            }
+            let size = u32::from_be_bytes(header[..4].try_into().unwrap());
+            let r#type = FourCC::from(<[u8; 4]>::try_from(&header[4..]).unwrap());
+            let (header_size, box_size) = match size {
+                // up to the end
+                0 => (4 + 4, None),
+                1 => {
+                    let mut large_size = [0; 8];
+                    input.read_exact(&mut large_size)?;
+                    (4 + 4 + 8, Some(u64::from_be_bytes(large_size)))
+                }
+                _ => (4 + 4, Some(size as u64)),
+            };
+            if box_size.is_some_and(|box_size| box_size < header_size) {
+                return Err(Error::InvalidBoxSize {
+                    r#type,
+                    size: box_size.unwrap(),
+                    remaining: 0,
+                });
+            }
+            position += header_size;
+            let data_size = box_size.map(|box_size| box_size - header_size);
+
+            let mut data = (&mut input).take(data_size.unwrap_or(u64::MAX));
+            if &r#type.to_bytes() == b"moov" {
+                let mut buffer = Vec::new();
+                data.read_to_end(&mut buffer)?;
+                if data_size.is_some_and(|data_size| buffer.len() as u64 != data_size) {
+                    return Err(Error::Io(ErrorKind::UnexpectedEof.into()));
+                }
+                position += buffer.len() as u64;
+                break MovieBox::decode(&mut buffer.as_slice())?;
+            }
+            let skipped = std::io::copy(&mut data, &mut std::io::sink())?;
+            if data_size.is_some_and(|data_size| skipped != data_size) {
+                return Err(Error::Io(ErrorKind::UnexpectedEof.into()));
+            }
+            position += skipped;
+        };
+        let plan = ReadPlan::new(&movie);
+        Ok(Self {
+            input,
+            position,
+            movie,
+            plan,
+            next: 0,
+            resolver: None,
+        })
+    }
+
+    pub fn movie(&self) -> &MovieBox {
+        &self.movie
+    }
+
+    /// See [Reader::set_resolver].
+    pub fn set_resolver(
+        &mut self,
+        resolver: impl FnMut(&str, Range<u64>) -> Result<Vec<u8>> + Send + 'static,
+    ) {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    /// Returns the next chunk with its data, or None after the last one.
+    pub fn next_chunk(&mut self) -> Option<Result<(ChunkRead, Vec<u8>)>> {
+        let chunk = self.plan.0.get(self.next)?.clone();
+        self.next += 1;
+        let data = match chunk.resolve(&mut self.resolver) {
+            Some(data) => data,
+            None => self.read_chunk(&chunk),
+        };
+        Some(data.map(|data| (chunk, data)))
+    }
+
+    fn read_chunk(&mut self, chunk: &ChunkRead) -> Result<Vec<u8>> {
+        chunk.check_local()?;
+        if chunk.offset < self.position {
+            return Err(Error::MediaDataBeforeMovie {
+                offset: chunk.offset,
+            });
+        }
+        let skip = chunk.offset - self.position;
+        if std::io::copy(&mut (&mut self.input).take(skip), &mut std::io::sink())? != skip {
+            return Err(Error::Io(ErrorKind::UnexpectedEof.into()));
+        }
+        let mut data = vec![
+            0;
+            usize::try_from(chunk.length)
+                .map_err(|_| Error::ValueOutOfRange { field: "length" })?
+        ];
+        self.input.read_exact(&mut data)?;
+        self.position = chunk.offset + chunk.length;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Reader, StreamReader};
+    use crate::{
+        append::AppendSession,
+        defragment::defragment,
+        marshal::{
+            timed_metadata::{URIBox, URIMetaSampleEntry},
+            Encode, Error, FileTypeBox, FourCC, MovieBox, MovieHeaderBox, Result, SampleEntry,
+            TrackBox,
+        },
+    };
+
+    const SAMPLES: [&[u8]; 3] = [b"first", b"second", b"third"];
+
+    /// Returns a file with the moov after the mdat, as written by
+    /// [AppendSession], and the same file with the moov first.
+    fn files() -> (Vec<u8>, Vec<u8>) {
+        let mut file = Cursor::new(Vec::new());
+        FileTypeBox {
+            major_brand: FourCC::from(*b"isom"),
+            minor_version: 0,
+            compatible_brands: vec![FourCC::from(*b"isom")],
+        }
+        .encode(&mut file)
+        .unwrap();
+        MovieBox {
+            header: MovieHeaderBox {
+                timescale: 1000,
+                next_track_id: 2,
+                ..Default::default()
+            },
+            tracks: vec![TrackBox::new_timed_metadata(
+                1,
+                100,
+                SampleEntry::URIMeta(URIMetaSampleEntry {
+                    data_reference_index: 1,
+                    uri: URIBox {
+                        the_uri: "urn:example".to_owned(),
+                    },
+                    init: None,
+                    bit_rate: None,
+                }),
+                None,
+            )],
+            extends: None,
+            user_data: None,
+            extra_boxes: Vec::new(),
+        }
+        .encode(&mut file)
+        .unwrap();
+
+        let mut session = AppendSession::open(file).unwrap();
+        for sample in SAMPLES {
+            session.append_sample(1, sample, 10, true).unwrap();
+        }
+        let movie_last = session.close().unwrap().0.into_inner();
+
+        let mut movie_first = Cursor::new(Vec::new());
+        defragment(&movie_last)
+            .unwrap()
+            .encode(&mut movie_first)
+            .unwrap();
+        (movie_last, movie_first.into_inner())
+    }
+
+    fn read_all(mut next_chunk: impl FnMut() -> Option<Result<Vec<u8>>>) -> Vec<u8> {
+        let mut data = Vec::new();
+        while let Some(chunk) = next_chunk() {
+            data.extend(chunk.unwrap());
+        }
+        data
+    }
+
+    #[test]
+    fn reader() {
+        let (movie_last, movie_first) = files();
+        for file in [movie_last, movie_first] {
+            let mut reader = Reader::new(file.as_slice()).unwrap();
+            assert_eq!(reader.movie().tracks.len(), 1);
+            let data = read_all(|| Some(reader.next_chunk()?.map(|(_, data)| data)));
+            assert_eq!(data, SAMPLES.concat());
+        }
+    }
+
+    #[test]
+    fn stream_reader() {
+        let (movie_last, movie_first) = files();
+
+        let mut reader = StreamReader::new(movie_first.as_slice()).unwrap();
+        let data = read_all(|| Some(reader.next_chunk()?.map(|(_, data)| data)));
+        assert_eq!(data, SAMPLES.concat());
+
+        // the moov is found, but the samples were skipped on the way
+        let mut reader = StreamReader::new(movie_last.as_slice()).unwrap();
+        assert_eq!(reader.movie().tracks.len(), 1);
+        assert!(matches!(
+            reader.next_chunk(),
+            Some(Err(Error::MediaDataBeforeMovie { .. }))
+        ));
+
+        // only the ftyp
+        let file_type_size = u32::from_be_bytes(movie_first[..4].try_into().unwrap()) as usize;
+        assert!(matches!(
+            StreamReader::new(&movie_first[..file_type_size]),
+            Err(Error::InvalidBoxQuantity { .. })
+        ));
+    }
+}
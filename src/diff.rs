@@ -0,0 +1,383 @@
+use std::{fmt::Debug, io::Cursor};
+
+use crate::{
+    edit::TypedBox,
+    marshal::{Boxes, Encode, File, FourCC, RawBox, Result, SampleSizeBox},
+    registry::{lookup, BoxInfo},
+};
+
+/// Difference between two files, at the path of the box, like
+/// `/moov/trak[1]/mdia/minf/stbl/stsz`, where the index is only given if
+/// there are multiple boxes of the type.
+#[derive(Debug)]
+pub enum Difference {
+    /// The box is only in the right file.
+    Added { path: String },
+    /// The box is only in the left file.
+    Removed { path: String },
+    /// A field of a box which is decoded in both files differs, like
+    /// `timescale`, or for boxes with a list of entries its length, like
+    /// `entries.len`, or the first entry which differs, like `entries[3]`.
+    Changed {
+        path: String,
+        field: String,
+        left: String,
+        right: String,
+    },
+    /// The box is in both files, but its data, which isn't decoded, differs,
+    /// starting at `offset` of the data after the box header.
+    ChangedData { path: String, offset: usize },
+}
+
+/// Compares two files box by box, and descends into the boxes known to have
+/// children, to find the innermost boxes which differ, and the fields of the
+/// ones which are decoded, see [TypedBox].
+///
+/// Fails if either file fails to encode, or its encoded boxes fail to parse.
+pub fn diff(left: &File, right: &File) -> Result<Vec<Difference>> {
+    let mut left_data = Cursor::new(Vec::new());
+    left.encode(&mut left_data)?;
+    let mut right_data = Cursor::new(Vec::new());
+    right.encode(&mut right_data)?;
+
+    let mut differences = Vec::new();
+    diff_children(
+        &mut differences,
+        "",
+        &parse_children(left_data.get_ref())?,
+        &parse_children(right_data.get_ref())?,
+    )?;
+    Ok(differences)
+}
+
+fn diff_children(
+    differences: &mut Vec<Difference>,
+    path: &str,
+    left: &[RawBox],
+    right: &[RawBox],
+) -> Result<()> {
+    let mut types = Vec::new();
+    for r#box in left.iter().chain(right) {
+        if !types.contains(&r#box.r#type) {
+            types.push(r#box.r#type);
+        }
+    }
+
+    for r#type in types {
+        let left: Vec<_> = left.iter().filter(|r#box| r#box.r#type == r#type).collect();
+        let right: Vec<_> = right
+            .iter()
+            .filter(|r#box| r#box.r#type == r#type)
+            .collect();
+        let count = left.len().max(right.len());
+        for index in 0..count {
+            let path = if count > 1 {
+                format!("{path}/{type:?}[{index}]")
+            } else {
+                format!("{path}/{type:?}")
+            };
+            match (left.get(index), right.get(index)) {
+                (Some(left), Some(right)) => diff_box(differences, path, left, right)?,
+                (Some(_), None) => differences.push(Difference::Removed { path }),
+                (None, _) => differences.push(Difference::Added { path }),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn diff_box(
+    differences: &mut Vec<Difference>,
+    path: String,
+    left: &RawBox,
+    right: &RawBox,
+) -> Result<()> {
+    if left.data == right.data {
+        return Ok(());
+    }
+
+    // kept as data if it can't be decoded, like in an EditableFile
+    if let (Some(Ok(left_value)), Some(Ok(right_value))) = (
+        TypedBox::decode(left.r#type, &left.data),
+        TypedBox::decode(right.r#type, &right.data),
+    ) {
+        let count = differences.len();
+        diff_fields(differences, &path, &left_value, &right_value);
+        // otherwise only reserved fields or the version differ
+        if differences.len() != count {
+            return Ok(());
+        }
+    }
+
+    if let Some(header_length) = children_offset(left.r#type) {
+        if left.data.len() >= header_length
+            && right.data.len() >= header_length
+            && left.data[..header_length] == right.data[..header_length]
+        {
+            return diff_children(
+                differences,
+                &path,
+                &parse_children(&left.data[header_length..])?,
+                &parse_children(&right.data[header_length..])?,
+            );
+        }
+    }
+
+    let offset = left
+        .data
+        .iter()
+        .zip(&right.data)
+        .position(|(left, right)| left != right)
+        .unwrap_or(left.data.len().min(right.data.len()));
+    differences.push(Difference::ChangedData { path, offset });
+    Ok(())
+}
+
+macro_rules! diff_fields {
+    ($differences:ident, $path:ident, $left:ident, $right:ident, $($field:ident),*) => {{
+        $(diff_field(
+            $differences,
+            $path,
+            stringify!($field).trim_start_matches("r#"),
+            &$left.$field,
+            &$right.$field,
+        );)*
+    }};
+}
+
+fn diff_fields(differences: &mut Vec<Difference>, path: &str, left: &TypedBox, right: &TypedBox) {
+    match (left, right) {
+        (TypedBox::FileType(left), TypedBox::FileType(right)) => {
+            diff_fields!(differences, path, left, right, major_brand, minor_version);
+            diff_entries(
+                differences,
+                path,
+                "compatible_brands",
+                &left.compatible_brands,
+                &right.compatible_brands,
+            );
+        }
+        (TypedBox::MovieHeader(left), TypedBox::MovieHeader(right)) => diff_fields!(
+            differences,
+            path,
+            left,
+            right,
+            creation_time,
+            modification_time,
+            timescale,
+            duration,
+            rate,
+            volume,
+            matrix,
+            preview_time,
+            preview_duration,
+            poster_time,
+            selection_time,
+            selection_duration,
+            current_time,
+            next_track_id
+        ),
+        (TypedBox::TrackHeader(left), TypedBox::TrackHeader(right)) => diff_fields!(
+            differences,
+            path,
+            left,
+            right,
+            enabled,
+            in_movie,
+            in_preview,
+            size_is_aspect_ratio,
+            creation_time,
+            modification_time,
+            track_id,
+            duration,
+            layer,
+            alternate_group,
+            volume,
+            matrix,
+            width,
+            height
+        ),
+        (TypedBox::EditList(left), TypedBox::EditList(right)) => {
+            diff_entries(differences, path, "entries", &left.0, &right.0)
+        }
+        (TypedBox::MediaHeader(left), TypedBox::MediaHeader(right)) => diff_fields!(
+            differences,
+            path,
+            left,
+            right,
+            creation_time,
+            modification_time,
+            timescale,
+            duration,
+            language
+        ),
+        (TypedBox::Handler(left), TypedBox::Handler(right)) => {
+            diff_fields!(differences, path, left, right, r#type, name)
+        }
+        (TypedBox::VideoMediaHeader(left), TypedBox::VideoMediaHeader(right)) => {
+            diff_fields!(differences, path, left, right, graphicsmode, opcolor)
+        }
+        (TypedBox::SoundMediaHeader(left), TypedBox::SoundMediaHeader(right)) => {
+            diff_fields!(differences, path, left, right, balance)
+        }
+        (TypedBox::TimeToSample(left), TypedBox::TimeToSample(right)) => {
+            diff_entries(differences, path, "entries", &left.0, &right.0)
+        }
+        (TypedBox::CompositionOffset(left), TypedBox::CompositionOffset(right)) => {
+            diff_entries(differences, path, "entries", &left.0, &right.0)
+        }
+        (TypedBox::SyncSample(left), TypedBox::SyncSample(right)) => {
+            diff_entries(differences, path, "sample_numbers", &left.0, &right.0)
+        }
+        (TypedBox::SampleSize(left), TypedBox::SampleSize(right)) => {
+            let sample_sizes = |sample_size: &SampleSizeBox| -> Vec<_> {
+                (0..sample_size.sample_count())
+                    .map(|sample| sample_size.sample_size(sample).unwrap_or(0))
+                    .collect()
+            };
+            diff_entries(
+                differences,
+                path,
+                "sample_sizes",
+                &sample_sizes(left),
+                &sample_sizes(right),
+            );
+        }
+        (TypedBox::SampleToChunk(left), TypedBox::SampleToChunk(right)) => {
+            diff_entries(differences, path, "entries", &left.0, &right.0)
+        }
+        (TypedBox::ChunkOffset(left), TypedBox::ChunkOffset(right)) => {
+            diff_entries(differences, path, "chunk_offsets", &left.0, &right.0)
+        }
+        (TypedBox::MovieExtendsHeader(left), TypedBox::MovieExtendsHeader(right)) => {
+            diff_fields!(differences, path, left, right, fragment_duration)
+        }
+        (TypedBox::TrackExtends(left), TypedBox::TrackExtends(right)) => diff_fields!(
+            differences,
+            path,
+            left,
+            right,
+            track_id,
+            default_sample_description_index,
+            default_sample_duration,
+            default_sample_size,
+            default_sample_flags
+        ),
+        (TypedBox::Kind(left), TypedBox::Kind(right)) => {
+            diff_fields!(differences, path, left, right, scheme_uri, value)
+        }
+        (TypedBox::Uuid(left), TypedBox::Uuid(right)) => {
+            diff_fields!(differences, path, left, right, extended_type);
+            diff_entries(differences, path, "data", &left.data, &right.data);
+        }
+        (TypedBox::ItemList(left), TypedBox::ItemList(right)) => {
+            diff_entries(differences, path, "items", &left.0, &right.0)
+        }
+        _ => {}
+    }
+}
+
+fn diff_field(
+    differences: &mut Vec<Difference>,
+    path: &str,
+    field: &str,
+    left: &impl Debug,
+    right: &impl Debug,
+) {
+    let (left, right) = (format!("{left:?}"), format!("{right:?}"));
+    if left != right {
+        differences.push(Difference::Changed {
+            path: path.to_owned(),
+            field: field.to_owned(),
+            left,
+            right,
+        });
+    }
+}
+
+/// Compares the length of two lists, and the first entry which differs.
+fn diff_entries<T: Debug>(
+    differences: &mut Vec<Difference>,
+    path: &str,
+    field: &str,
+    left: &[T],
+    right: &[T],
+) {
+    diff_field(
+        differences,
+        path,
+        &format!("{field}.len"),
+        &left.len(),
+        &right.len(),
+    );
+    if let Some((index, (left, right))) = left
+        .iter()
+        .zip(right)
+        .enumerate()
+        .find(|(_, (left, right))| format!("{left:?}") != format!("{right:?}"))
+    {
+        diff_field(differences, path, &format!("{field}[{index}]"), left, right);
+    }
+}
+
+fn parse_children(data: &[u8]) -> Result<Vec<RawBox>> {
+    Boxes::new(data).collect()
+}
+
+fn children_offset(r#type: FourCC) -> Option<usize> {
+    lookup(r#type).and_then(BoxInfo::children_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Difference};
+    use crate::marshal::{File, FileTypeBox, FourCC, MovieBox, MovieHeaderBox, RawBox};
+
+    fn file(timescale: u32, data: Vec<u8>) -> File {
+        File {
+            file_type: FileTypeBox {
+                major_brand: FourCC::from(*b"isom"),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"isom")],
+            },
+            movie: Some(MovieBox {
+                header: MovieHeaderBox {
+                    timescale,
+                    next_track_id: 1,
+                    ..Default::default()
+                },
+                tracks: Vec::new(),
+                extends: None,
+                user_data: None,
+                extra_boxes: Vec::new(),
+            }),
+            media_data: Vec::new(),
+            meta: None,
+            extra_boxes: vec![RawBox {
+                r#type: FourCC::from(*b"abcd"),
+                data,
+            }],
+        }
+    }
+
+    #[test]
+    fn fields() {
+        assert!(diff(&file(1000, vec![0; 4]), &file(1000, vec![0; 4]))
+            .unwrap()
+            .is_empty());
+
+        let differences =
+            diff(&file(1000, vec![0, 1, 2, 3]), &file(600, vec![0, 1, 0, 3])).unwrap();
+        assert!(matches!(
+            &differences[..],
+            [
+                Difference::Changed { path, field, left, right },
+                Difference::ChangedData { path: data_path, offset: 2 },
+            ] if path == "/moov/mvhd"
+                && field == "timescale"
+                && left == "1000"
+                && right == "600"
+                && data_path == "/abcd"
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::Cursor;
+
+use crate::{
+    marshal::{Encode, File, FourCC, Result},
+    visit::{visit_data, BoxVisitor, LeafBox},
+};
+
+#[derive(Debug)]
+pub struct BoxSummary {
+    pub path: String,
+    pub offset: u64,
+    pub size: u64,
+    /// CRC-32 of the whole box, including its header
+    pub crc32: Option<u32>,
+}
+
+/// Lists all boxes of a file, optionally with a hash of their content to spot
+/// which of them changed between two files.
+pub fn dump(file: &File, hash: bool) -> Result<Vec<BoxSummary>> {
+    let mut data = Cursor::new(Vec::new());
+    file.encode(&mut data)?;
+    let mut dumper = Dumper {
+        data: data.get_ref(),
+        hash,
+        path: Vec::new(),
+        summaries: Vec::new(),
+    };
+    visit_data(data.get_ref(), &mut dumper)?;
+    Ok(dumper.summaries)
+}
+
+struct Dumper<'a> {
+    data: &'a [u8],
+    hash: bool,
+    path: Vec<FourCC>,
+    summaries: Vec<BoxSummary>,
+}
+
+impl Dumper<'_> {
+    fn push(&mut self, r#type: FourCC, offset: u64) {
+        let path = self
+            .path
+            .iter()
+            .chain([&r#type])
+            .map(|r#type| format!("/{type:?}"))
+            .collect();
+        let data = &self.data[offset as usize..];
+        let size = match u32::from_be_bytes(data[0..4].try_into().unwrap()) {
+            0 => data.len() as u64,
+            1 => u64::from_be_bytes(data[8..16].try_into().unwrap()),
+            size => size as u64,
+        };
+        self.summaries.push(BoxSummary {
+            path,
+            offset,
+            size,
+            crc32: self.hash.then(|| crc32(&data[..size as usize])),
+        });
+    }
+}
+
+impl BoxVisitor for Dumper<'_> {
+    fn enter_box(&mut self, r#type: FourCC, offset: u64) -> Result<()> {
+        self.push(r#type, offset);
+        self.path.push(r#type);
+        Ok(())
+    }
+
+    fn leave_box(&mut self, _type: FourCC, _offset: u64) -> Result<()> {
+        self.path.pop();
+        Ok(())
+    }
+
+    fn leaf_box(&mut self, r#type: FourCC, offset: u64, _payload: LeafBox) -> Result<()> {
+        self.push(r#type, offset);
+        Ok(())
+    }
+}
+
+/// CRC-32 as used by zlib and PNG.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 {
+                crc >> 1 ^ 0xEDB88320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
@@ -0,0 +1,476 @@
+use std::io::{Cursor, Seek, Write};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use derivative::Derivative;
+
+use crate::{
+    marshal::{
+        checked_cast, ChunkLargeOffsetBox, ChunkOffsetBox, CompositionOffsetBox, Decode,
+        EditListBox, Encode, Error, FileTypeBox, FourCC, HandlerBox, ItemListBox, KindBox,
+        MediaHeaderBox, MovieExtendsHeaderBox, MovieHeaderBox, NullMediaHeaderBox, Result,
+        SampleSizeBox, SampleToChunkBox, SoundMediaHeaderBox, SyncSampleBox, TimeToSampleBox,
+        TrackExtendsBox, TrackHeaderBox, UuidBox, VideoMediaHeaderBox,
+    },
+    registry::{lookup, BoxInfo},
+};
+
+/// A file opened for editing, where the boxes of [TypedBox] are decoded, the
+/// containers of the [registry](crate::registry) are split into their
+/// children and everything else is kept as is.
+///
+/// Encoding it again without changes gives the same bytes, as typed boxes
+/// which are still equal to what was decoded are written as they were read.
+#[derive(Debug)]
+pub struct EditableFile {
+    pub boxes: Vec<EditNode>,
+}
+
+#[derive(Debug)]
+pub struct EditNode {
+    pub r#type: FourCC,
+    pub size: BoxSize,
+    pub content: EditContent,
+}
+
+/// How the size of a box is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxSize {
+    /// 32-bit, or 64-bit if it doesn't fit
+    Compact,
+    /// 64-bit
+    Large,
+    /// up to the end of the file, only for the last box
+    ToEnd,
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub enum EditContent {
+    Container {
+        /// in front of the children, like the version and flags of meta or the
+        /// fixed part of a sample entry
+        #[derivative(Debug = "ignore")]
+        fields: Vec<u8>,
+        children: Vec<EditNode>,
+    },
+    Typed {
+        value: TypedBox,
+        /// the whole box as it was read
+        #[derivative(Debug = "ignore")]
+        original: Vec<u8>,
+        /// the value encoded right after decoding, to tell if it was changed
+        #[derivative(Debug = "ignore")]
+        baseline: Vec<u8>,
+    },
+    Raw(#[derivative(Debug = "ignore")] Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum TypedBox {
+    FileType(FileTypeBox),
+    MovieHeader(MovieHeaderBox),
+    TrackHeader(TrackHeaderBox),
+    EditList(EditListBox),
+    MediaHeader(MediaHeaderBox),
+    Handler(HandlerBox),
+    VideoMediaHeader(VideoMediaHeaderBox),
+    SoundMediaHeader(SoundMediaHeaderBox),
+    NullMediaHeader(NullMediaHeaderBox),
+    TimeToSample(TimeToSampleBox),
+    CompositionOffset(CompositionOffsetBox),
+    SyncSample(SyncSampleBox),
+    SampleSize(SampleSizeBox),
+    SampleToChunk(SampleToChunkBox),
+    /// stco or co64
+    ChunkOffset(ChunkOffsetBox),
+    MovieExtendsHeader(MovieExtendsHeaderBox),
+    TrackExtends(TrackExtendsBox),
+    Kind(KindBox),
+    Uuid(UuidBox),
+    ItemList(ItemListBox),
+}
+
+impl TypedBox {
+    /// Decodes the payload of a box, None if the type is not one of these.
+    pub(crate) fn decode(r#type: FourCC, mut data: &[u8]) -> Option<Result<Self>> {
+        let data = &mut data;
+        Some(match &r#type.to_bytes() {
+            b"ftyp" => Decode::decode(data).map(Self::FileType),
+            b"mvhd" => Decode::decode(data).map(Self::MovieHeader),
+            b"tkhd" => Decode::decode(data).map(Self::TrackHeader),
+            b"elst" => Decode::decode(data).map(Self::EditList),
+            b"mdhd" => Decode::decode(data).map(Self::MediaHeader),
+            b"hdlr" => Decode::decode(data).map(Self::Handler),
+            b"vmhd" => Decode::decode(data).map(Self::VideoMediaHeader),
+            b"smhd" => Decode::decode(data).map(Self::SoundMediaHeader),
+            b"nmhd" => Decode::decode(data).map(Self::NullMediaHeader),
+            b"stts" => Decode::decode(data).map(Self::TimeToSample),
+            b"ctts" => Decode::decode(data).map(Self::CompositionOffset),
+            b"stss" => Decode::decode(data).map(Self::SyncSample),
+            b"stsz" => Decode::decode(data).map(Self::SampleSize),
+            b"stsc" => Decode::decode(data).map(Self::SampleToChunk),
+            b"stco" => Decode::decode(data).map(Self::ChunkOffset),
+            b"co64" => ChunkLargeOffsetBox::decode(data)
+                .map(|chunk_offset| Self::ChunkOffset(chunk_offset.0)),
+            b"mehd" => Decode::decode(data).map(Self::MovieExtendsHeader),
+            b"trex" => Decode::decode(data).map(Self::TrackExtends),
+            b"kind" => Decode::decode(data).map(Self::Kind),
+            b"uuid" => Decode::decode(data).map(Self::Uuid),
+            b"ilst" => Decode::decode(data).map(Self::ItemList),
+            _ => return None,
+        })
+    }
+}
+
+impl Encode for TypedBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        match self {
+            Self::FileType(value) => value.encode(output),
+            Self::MovieHeader(value) => value.encode(output),
+            Self::TrackHeader(value) => value.encode(output),
+            Self::EditList(value) => value.encode(output),
+            Self::MediaHeader(value) => value.encode(output),
+            Self::Handler(value) => value.encode(output),
+            Self::VideoMediaHeader(value) => value.encode(output),
+            Self::SoundMediaHeader(value) => value.encode(output),
+            Self::NullMediaHeader(value) => value.encode(output),
+            Self::TimeToSample(value) => value.encode(output),
+            Self::CompositionOffset(value) => value.encode(output),
+            Self::SyncSample(value) => value.encode(output),
+            Self::SampleSize(value) => value.encode(output),
+            Self::SampleToChunk(value) => value.encode(output),
+            Self::ChunkOffset(value) => value.encode(output),
+            Self::MovieExtendsHeader(value) => value.encode(output),
+            Self::TrackExtends(value) => value.encode(output),
+            Self::Kind(value) => value.encode(output),
+            Self::Uuid(value) => value.encode(output),
+            Self::ItemList(value) => value.encode(output),
+        }
+    }
+}
+
+impl EditableFile {
+    /// Returns the first box at the end of `path`, like `[moov, mvhd]`.
+    pub fn find(&self, path: &[FourCC]) -> Option<&EditNode> {
+        find(&self.boxes, path)
+    }
+
+    pub fn find_mut(&mut self, path: &[FourCC]) -> Option<&mut EditNode> {
+        find_mut(&mut self.boxes, path)
+    }
+}
+
+impl EditNode {
+    /// Creates a new box from a typed value.
+    pub fn typed(value: TypedBox) -> Result<Self> {
+        let mut baseline = Cursor::new(Vec::new());
+        value.encode(&mut baseline)?;
+        let baseline = baseline.into_inner();
+        Ok(Self {
+            r#type: FourCC::from(<[u8; 4]>::try_from(&baseline[4..8]).unwrap()),
+            size: BoxSize::Compact,
+            content: EditContent::Typed {
+                value,
+                original: baseline.clone(),
+                baseline,
+            },
+        })
+    }
+
+    /// Returns the children of a container, empty for other boxes.
+    pub fn children(&self) -> &[EditNode] {
+        match &self.content {
+            EditContent::Container { children, .. } => children,
+            _ => &[],
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<EditNode>> {
+        match &mut self.content {
+            EditContent::Container { children, .. } => Some(children),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self) -> Option<&TypedBox> {
+        match &self.content {
+            EditContent::Typed { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn value_mut(&mut self) -> Option<&mut TypedBox> {
+        match &mut self.content {
+            EditContent::Typed { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Decode for EditableFile {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let boxes = decode_nodes(input)?;
+        *input = &input[input.len()..];
+        Ok(Self { boxes })
+    }
+}
+
+impl Encode for EditableFile {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        for node in &self.boxes {
+            node.encode(output)?;
+        }
+        Ok(())
+    }
+}
+
+impl Encode for EditNode {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let payload = match &self.content {
+            EditContent::Container { fields, children } => {
+                let mut payload = Cursor::new(fields.clone());
+                payload.set_position(fields.len() as u64);
+                for child in children {
+                    child.encode(&mut payload)?;
+                }
+                payload.into_inner()
+            }
+            EditContent::Typed {
+                value,
+                original,
+                baseline,
+            } => {
+                let mut encoded = Cursor::new(Vec::new());
+                value.encode(&mut encoded)?;
+                let encoded = encoded.into_inner();
+                output.write_all(if &encoded == baseline {
+                    original
+                } else {
+                    &encoded
+                })?;
+                return Ok(());
+            }
+            EditContent::Raw(data) => data.clone(),
+        };
+
+        let compact_size = u32::try_from(4 + 4 + payload.len())
+            .ok()
+            .filter(|&size| size > 1);
+        match (self.size, compact_size) {
+            (BoxSize::ToEnd, _) => {
+                output.write_u32::<BigEndian>(0)?;
+                self.r#type.encode(output)?;
+            }
+            (BoxSize::Compact, Some(size)) => {
+                output.write_u32::<BigEndian>(size)?;
+                self.r#type.encode(output)?;
+            }
+            _ => {
+                output.write_u32::<BigEndian>(1)?;
+                self.r#type.encode(output)?;
+                checked_cast::<u64>(4 + 4 + 8 + payload.len(), "size")?.encode(output)?;
+            }
+        }
+        output.write_all(&payload)?;
+        Ok(())
+    }
+}
+
+fn decode_nodes(input: &[u8]) -> Result<Vec<EditNode>> {
+    let mut nodes = Vec::new();
+    let mut remaining = input;
+    while !remaining.is_empty() {
+        let begin = remaining;
+        let size = u32::decode(&mut remaining)?;
+        let r#type = FourCC::decode(&mut remaining)?;
+        let (size, length) = match size {
+            0 => (BoxSize::ToEnd, Some(remaining.len() as u64)),
+            1 => (
+                BoxSize::Large,
+                u64::decode(&mut remaining)?.checked_sub(4 + 4 + 8),
+            ),
+            _ => (BoxSize::Compact, (size as u64).checked_sub(4 + 4)),
+        };
+        let length = length
+            .filter(|&length| length <= remaining.len() as u64)
+            .ok_or(Error::InvalidBoxSize {
+                r#type,
+                size: size as u64,
+                remaining: remaining.len(),
+            })? as usize;
+        let (data, rest) = remaining.split_at(length);
+        let original = &begin[..begin.len() - rest.len()];
+        remaining = rest;
+
+        let content = match TypedBox::decode(r#type, data) {
+            Some(Ok(value)) => {
+                let mut baseline = Cursor::new(Vec::new());
+                value.encode(&mut baseline)?;
+                EditContent::Typed {
+                    value,
+                    original: original.to_owned(),
+                    baseline: baseline.into_inner(),
+                }
+            }
+            // kept as is if it can't be decoded
+            Some(Err(_)) => EditContent::Raw(data.to_owned()),
+            None => match lookup(r#type)
+                .and_then(BoxInfo::children_offset)
+                .filter(|&children_offset| children_offset <= data.len())
+                .and_then(|children_offset| {
+                    decode_nodes(&data[children_offset..]).ok().map(|children| {
+                        EditContent::Container {
+                            fields: data[..children_offset].to_owned(),
+                            children,
+                        }
+                    })
+                }) {
+                Some(container) => container,
+                None => EditContent::Raw(data.to_owned()),
+            },
+        };
+        nodes.push(EditNode {
+            r#type,
+            size,
+            content,
+        });
+    }
+    Ok(nodes)
+}
+
+fn find<'a>(nodes: &'a [EditNode], path: &[FourCC]) -> Option<&'a EditNode> {
+    let (r#type, path) = path.split_first()?;
+    let node = nodes.iter().find(|node| node.r#type == *r#type)?;
+    if path.is_empty() {
+        Some(node)
+    } else {
+        find(node.children(), path)
+    }
+}
+
+fn find_mut<'a>(nodes: &'a mut [EditNode], path: &[FourCC]) -> Option<&'a mut EditNode> {
+    let (r#type, path) = path.split_first()?;
+    let node = nodes.iter_mut().find(|node| node.r#type == *r#type)?;
+    if path.is_empty() {
+        Some(node)
+    } else {
+        find_mut(node.children_mut()?, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{BoxSize, EditContent, EditNode, EditableFile, TypedBox};
+    use crate::marshal::{Decode, Encode, FileTypeBox, FourCC, MovieHeaderBox, TimeToSampleEntry};
+
+    fn encode(value: &impl Encode) -> Vec<u8> {
+        let mut output = Cursor::new(Vec::new());
+        value.encode(&mut output).unwrap();
+        output.into_inner()
+    }
+
+    #[test]
+    fn unchanged() {
+        let mut input = encode(&FileTypeBox {
+            major_brand: FourCC::from(*b"isom"),
+            minor_version: 0,
+            compatible_brands: vec![FourCC::from(*b"isom")],
+        });
+        // vendor box, unknown to the registry
+        input.extend_from_slice(&12u32.to_be_bytes());
+        input.extend_from_slice(b"abcd");
+        input.extend_from_slice(&[1, 2, 3, 4]);
+        // moov with a 64-bit size, although it fits into 32 bits
+        let movie_header = encode(&MovieHeaderBox {
+            timescale: 1000,
+            next_track_id: 1,
+            ..Default::default()
+        });
+        input.extend_from_slice(&1u32.to_be_bytes());
+        input.extend_from_slice(b"moov");
+        input.extend_from_slice(&(16 + movie_header.len() as u64).to_be_bytes());
+        input.extend_from_slice(&movie_header);
+        // mdat up to the end of the file
+        input.extend_from_slice(&0u32.to_be_bytes());
+        input.extend_from_slice(b"mdat");
+        input.extend_from_slice(&[5; 16]);
+
+        let file = EditableFile::decode(&mut input.as_slice()).unwrap();
+        let sizes: Vec<_> = file.boxes.iter().map(|node| node.size).collect();
+        assert_eq!(
+            sizes,
+            [
+                BoxSize::Compact,
+                BoxSize::Compact,
+                BoxSize::Large,
+                BoxSize::ToEnd
+            ]
+        );
+        assert!(file
+            .find(&[FourCC::from(*b"moov"), FourCC::from(*b"mvhd")])
+            .is_some());
+        assert_eq!(encode(&file), input);
+    }
+
+    /// Asserts that the nodes encode the same, apart from the first one of
+    /// each type in `path`, of which only the last one has a different
+    /// content.
+    fn assert_changed(old: &[EditNode], new: &[EditNode], path: &[FourCC]) {
+        assert_eq!(old.len(), new.len());
+        let mut changed = path.first().copied();
+        for (old, new) in old.iter().zip(new) {
+            assert_eq!(old.r#type, new.r#type);
+            assert_eq!(old.size, new.size);
+            if changed != Some(old.r#type) {
+                assert_eq!(encode(old), encode(new));
+                continue;
+            }
+            changed = None;
+            match (&old.content, &new.content) {
+                (
+                    EditContent::Container {
+                        fields: old_fields,
+                        children: old_children,
+                    },
+                    EditContent::Container {
+                        fields: new_fields,
+                        children: new_children,
+                    },
+                ) => {
+                    assert_eq!(old_fields, new_fields);
+                    assert_changed(old_children, new_children, &path[1..]);
+                }
+                _ => {
+                    assert_eq!(path.len(), 1);
+                    assert_ne!(encode(old), encode(new));
+                }
+            }
+        }
+        assert!(changed.is_none());
+    }
+
+    #[test]
+    fn changed() {
+        let input = include_bytes!("../tests/fixtures/audio-video-progressive.mp4");
+        let path = [b"moov", b"trak", b"mdia", b"minf", b"stbl", b"stts"]
+            .map(|r#type| FourCC::from(*r#type));
+        let mut file = EditableFile::decode(&mut input.as_slice()).unwrap();
+        let Some(TypedBox::TimeToSample(time_to_sample)) =
+            file.find_mut(&path).and_then(EditNode::value_mut)
+        else {
+            panic!("no stts");
+        };
+        time_to_sample.0.push(TimeToSampleEntry {
+            sample_count: 0,
+            sample_delta: 1,
+        });
+        let output = encode(&file);
+        assert_eq!(output.len(), input.len() + 8);
+
+        let old = EditableFile::decode(&mut input.as_slice()).unwrap();
+        let new = EditableFile::decode(&mut output.as_slice()).unwrap();
+        assert_changed(&old.boxes, &new.boxes, &path);
+    }
+}
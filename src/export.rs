@@ -0,0 +1,62 @@
+use crate::{
+    defragment::{decode_movie, defragment_with, media_data_offset},
+    io::RandomAccess,
+    marshal::{File, Result, TrackBox},
+};
+
+/// Remuxes a file with only the tracks for which `predicate` returns true,
+/// leaving out the samples of the other tracks. Like [defragment], the result
+/// is progressive.
+///
+/// [defragment]: crate::defragment::defragment
+pub fn export_tracks(
+    input: &(impl RandomAccess + ?Sized),
+    mut predicate: impl FnMut(&TrackBox) -> bool,
+) -> Result<File> {
+    let track_ids = decode_movie(input)?
+        .tracks
+        .iter()
+        .filter(|track| predicate(track))
+        .map(|track| track.header.track_id)
+        .collect::<Vec<_>>();
+
+    let mut file = defragment_with(input, |track_id, sample| {
+        if !track_ids.contains(&track_id) {
+            sample.clear();
+        }
+        Ok(())
+    })?;
+
+    let mut offset = media_data_offset(&file)?;
+    let movie = file.movie.as_mut().unwrap();
+    movie.filter_tracks(|track| track_ids.contains(&track.header.track_id));
+    movie.recompute_durations();
+
+    // the moov shrank, which moves the mdat payload to the front
+    loop {
+        let new_offset = media_data_offset(&file)?;
+        if new_offset == offset {
+            break;
+        }
+        for track in &mut file.movie.as_mut().unwrap().tracks {
+            for chunk_offset in &mut track.media.information.sample_table.chunk_offset.0 {
+                *chunk_offset = *chunk_offset - offset + new_offset;
+            }
+        }
+        offset = new_offset;
+    }
+    Ok(file)
+}
+
+/// Remuxes a file with all non-audio tracks, but only the audio tracks in one
+/// of the given ISO 639-2/T languages, like "eng".
+pub fn export_languages(input: &(impl RandomAccess + ?Sized), languages: &[&str]) -> Result<File> {
+    export_tracks(input, |track| {
+        &track.media.handler.r#type.to_bytes() != b"soun"
+            || track
+                .media
+                .header
+                .language_code()
+                .is_some_and(|language| languages.contains(&language.as_str()))
+    })
+}
@@ -0,0 +1,65 @@
+use std::io::Cursor;
+
+use crate::{
+    demux::ReadPlan,
+    io::{read_box_data, read_box_headers, RandomAccess},
+    marshal::{Decode, Encode, File, MovieBox, Result},
+};
+
+#[derive(Debug)]
+pub struct FaststartReport {
+    pub movie_offset: Option<u64>,
+    pub media_data_offsets: Vec<u64>,
+    pub playback_offset: Option<u64>,
+}
+
+impl FaststartReport {
+    /// Only reads the box headers and the moov.
+    pub fn new(input: &(impl RandomAccess + ?Sized)) -> Result<Self> {
+        let mut movie_offset = None;
+        let mut media_data_offsets = Vec::new();
+        let mut playback_offset = None;
+
+        for header in read_box_headers(input)? {
+            let begin = header.offset;
+            let end = header.offset + header.size;
+            match &header.r#type.to_bytes() {
+                b"moov" => {
+                    let movie = MovieBox::decode(&mut read_box_data(input, &header)?.as_slice())?;
+                    let mut track_ids = Vec::new();
+                    let mut offset = end;
+                    for chunk in ReadPlan::new(&movie).0 {
+                        if !track_ids.contains(&chunk.track_id) {
+                            track_ids.push(chunk.track_id);
+                            offset = offset.max(chunk.offset.saturating_add(chunk.length));
+                        }
+                    }
+                    movie_offset = Some(begin);
+                    playback_offset = Some(offset);
+                }
+                b"mdat" => media_data_offsets.push(begin),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            movie_offset,
+            media_data_offsets,
+            playback_offset,
+        })
+    }
+
+    pub fn is_faststart(&self) -> bool {
+        self.movie_offset.is_some_and(|movie_offset| {
+            self.media_data_offsets
+                .iter()
+                .all(|&media_data_offset| media_data_offset > movie_offset)
+        })
+    }
+}
+
+pub fn is_faststart(file: &File) -> Result<bool> {
+    let mut data = Cursor::new(Vec::new());
+    file.encode(&mut data)?;
+    Ok(FaststartReport::new(data.get_ref())?.is_faststart())
+}
@@ -0,0 +1,194 @@
+use crate::marshal::{Error, Result};
+
+/// Yields the NAL units of a sample, each prefixed by its length in
+/// `length_size` bytes as in avcC and hvcC.
+pub fn nal_units(sample: &[u8], length_size: u8) -> impl Iterator<Item = Result<&[u8]>> {
+    let mut remaining = sample;
+    std::iter::from_fn(move || {
+        if remaining.is_empty() {
+            return None;
+        }
+        let length_size = length_size as usize;
+        let Some((length, data)) = remaining
+            .split_at_checked(length_size)
+            .filter(|_| (1..=4).contains(&length_size))
+        else {
+            remaining = &[];
+            return Some(Err(Error::ValueOutOfRange {
+                field: "length_size",
+            }));
+        };
+        let length = length
+            .iter()
+            .fold(0usize, |length, &byte| length << 8 | byte as usize);
+        let Some((nal_unit, data)) = data.split_at_checked(length) else {
+            remaining = &[];
+            return Some(Err(Error::ValueOutOfRange {
+                field: "nal_unit_length",
+            }));
+        };
+        remaining = data;
+        Some(Ok(nal_unit))
+    })
+}
+
+/// Removes the NAL units for which `predicate` returns true.
+pub fn strip_nal_units(
+    sample: &mut Vec<u8>,
+    length_size: u8,
+    mut predicate: impl FnMut(&[u8]) -> bool,
+) -> Result<()> {
+    let mut filtered = Vec::with_capacity(sample.len());
+    for nal_unit in nal_units(sample, length_size) {
+        let nal_unit = nal_unit?;
+        if !predicate(nal_unit) {
+            filtered.extend_from_slice(
+                &(nal_unit.len() as u32).to_be_bytes()[4 - length_size as usize..],
+            );
+            filtered.extend_from_slice(nal_unit);
+        }
+    }
+    *sample = filtered;
+    Ok(())
+}
+
+/// ISO/IEC 14496-10 7.4.1
+pub fn is_avc_sei(nal_unit: &[u8]) -> bool {
+    nal_unit.first().is_some_and(|&header| header & 0x1F == 6)
+}
+
+/// ISO/IEC 23008-2 7.4.2.2, prefix and suffix SEI
+pub fn is_hevc_sei(nal_unit: &[u8]) -> bool {
+    nal_unit
+        .first()
+        .is_some_and(|&header| matches!(header >> 1 & 0x3F, 39 | 40))
+}
+
+pub fn strip_avc_sei(sample: &mut Vec<u8>, length_size: u8) -> Result<()> {
+    strip_nal_units(sample, length_size, is_avc_sei)
+}
+
+pub fn strip_hevc_sei(sample: &mut Vec<u8>, length_size: u8) -> Result<()> {
+    strip_nal_units(sample, length_size, is_hevc_sei)
+}
+
+/// Returns the SEI NAL units of an H.264 sample, including their header.
+pub fn extract_avc_sei(sample: &[u8], length_size: u8) -> Result<Vec<&[u8]>> {
+    nal_units(sample, length_size)
+        .filter(|nal_unit| {
+            nal_unit
+                .as_ref()
+                .map_or(true, |nal_unit| is_avc_sei(nal_unit))
+        })
+        .collect()
+}
+
+/// Returns the SEI NAL units of an H.265 sample, including their header.
+pub fn extract_hevc_sei(sample: &[u8], length_size: u8) -> Result<Vec<&[u8]>> {
+    nal_units(sample, length_size)
+        .filter(|nal_unit| {
+            nal_unit
+                .as_ref()
+                .map_or(true, |nal_unit| is_hevc_sei(nal_unit))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Obu<'a> {
+    pub r#type: u8,
+    /// whole OBU, including its header
+    pub data: &'a [u8],
+    pub payload: &'a [u8],
+}
+
+impl Obu<'_> {
+    /// AV1 6.2.2
+    pub const METADATA: u8 = 5;
+
+    /// AV1 6.7.1, e.g. 4 for ITU-T T.35 as used by HDR10+
+    pub fn metadata_type(&self) -> Option<u64> {
+        if self.r#type != Self::METADATA {
+            return None;
+        }
+        read_leb128(&mut &*self.payload)
+    }
+}
+
+/// Yields the OBUs of an AV1 sample, of which only the last one may omit its
+/// size.
+pub fn obus(sample: &[u8]) -> impl Iterator<Item = Result<Obu<'_>>> {
+    let mut remaining = sample;
+    std::iter::from_fn(move || {
+        if remaining.is_empty() {
+            return None;
+        }
+        let obu = (|| {
+            let begin = remaining;
+            let mut input = remaining;
+            let (&header, data) = input.split_first()?;
+            input = data;
+            let r#type = header >> 3 & 0xF;
+            if header & 1 << 2 != 0 {
+                input = input.get(1..)?; // obu_extension_header
+            }
+            let size = if header & 1 << 1 != 0 {
+                usize::try_from(read_leb128(&mut input)?).ok()?
+            } else {
+                input.len()
+            };
+            let (payload, data) = input.split_at_checked(size)?;
+            remaining = data;
+            Some(Obu {
+                r#type,
+                data: &begin[..begin.len() - data.len()],
+                payload,
+            })
+        })();
+        if obu.is_none() {
+            remaining = &[];
+        }
+        Some(obu.ok_or(Error::ValueOutOfRange { field: "obu_size" }))
+    })
+}
+
+/// Removes the OBUs for which `predicate` returns true.
+pub fn strip_obus(sample: &mut Vec<u8>, mut predicate: impl FnMut(&Obu) -> bool) -> Result<()> {
+    let mut filtered = Vec::with_capacity(sample.len());
+    for obu in obus(sample) {
+        let obu = obu?;
+        if !predicate(&obu) {
+            filtered.extend_from_slice(obu.data);
+        }
+    }
+    *sample = filtered;
+    Ok(())
+}
+
+/// Removes metadata OBUs, or only those of the given metadata_type.
+pub fn strip_av1_metadata(sample: &mut Vec<u8>, metadata_type: Option<u64>) -> Result<()> {
+    strip_obus(sample, |obu| {
+        obu.r#type == Obu::METADATA
+            && metadata_type.is_none_or(|metadata_type| obu.metadata_type() == Some(metadata_type))
+    })
+}
+
+/// Returns the metadata OBUs of an AV1 sample.
+pub fn extract_av1_metadata(sample: &[u8]) -> Result<Vec<Obu<'_>>> {
+    obus(sample)
+        .filter(|obu| obu.as_ref().map_or(true, |obu| obu.r#type == Obu::METADATA))
+        .collect()
+}
+
+fn read_leb128(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for index in 0..8 {
+        let (&byte, remaining) = input.split_first()?;
+        *input = remaining;
+        value |= ((byte & 0x7F) as u64) << (index * 7);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
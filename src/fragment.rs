@@ -0,0 +1,540 @@
+use std::io::{Cursor, Seek, Write};
+
+use crate::{
+    io::{read_box_data, read_box_headers, RandomAccess},
+    marshal::{
+        checked_cast, ChunkOffsetBox, Decode, Encode, Error, FileTypeBox, MediaDataBox, MediaTime,
+        MetaBox, MovieBox, MovieExtendsBox, MovieExtendsHeaderBox, MovieFragmentBox,
+        MovieFragmentHeaderBox, MovieTime, Result, SampleDependency, SampleGroupDescriptionBox,
+        SampleSizeBox, SampleTableBox, SampleToChunkBox, SampleToGroupBox, SampleToGroupEntry,
+        TimeToSampleBox, TrackExtendsBox, TrackFragmentBaseMediaDecodeTimeBox, TrackFragmentBox,
+        TrackFragmentHeaderBox, TrackRunBox, TrackRunEntry,
+    },
+};
+
+/// Converts a progressive file into an init segment followed by movie
+/// fragments of about `fragment_duration`, without touching the samples.
+///
+/// Fragments start at sync samples of the first video or image sequence
+/// track, or the first track if there is none, and the other tracks are cut
+/// at the same times.
+pub fn fragment(
+    input: &(impl RandomAccess + ?Sized),
+    fragment_duration: MovieTime,
+    output: &mut (impl Write + Seek),
+) -> Result<()> {
+    fragment_with(input, fragment_duration, output, |_, _| Ok(()))
+}
+
+/// Like [fragment], but passes the data of each sample with its track id
+/// through `transform` before it is written, and takes over the new sizes in
+/// the track runs.
+pub fn fragment_with(
+    input: &(impl RandomAccess + ?Sized),
+    fragment_duration: MovieTime,
+    output: &mut (impl Write + Seek),
+    mut transform: impl FnMut(u32, &mut Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let mut file_type = None;
+    let mut movie = None;
+    let mut meta = None;
+    for header in read_box_headers(input)? {
+        let decode = || read_box_data(input, &header);
+        match &header.r#type.to_bytes() {
+            b"ftyp" => file_type = Some(FileTypeBox::decode(&mut decode()?.as_slice())?),
+            b"moov" => movie = Some(MovieBox::decode(&mut decode()?.as_slice())?),
+            b"meta" => meta = Some(MetaBox::decode(&mut decode()?.as_slice())?),
+            _ => {}
+        }
+    }
+    let file_type = file_type.ok_or(Error::InvalidBoxQuantity {
+        r#type: "ftyp",
+        quantity: 0,
+        expected: 1,
+    })?;
+    let mut movie = movie.ok_or(Error::InvalidBoxQuantity {
+        r#type: "moov",
+        quantity: 0,
+        expected: 1,
+    })?;
+
+    let movie_timescale = movie.header.timescale;
+    let tracks: Vec<_> = movie
+        .tracks
+        .iter()
+        .map(|track| {
+            let sample_table = &track.media.information.sample_table;
+            Track {
+                track_id: track.header.track_id,
+                timescale: track.media.header.timescale,
+                has_composition_offsets: sample_table.composition_offset.is_some(),
+                sample_group_description: sample_group_description(sample_table),
+                samples: samples_of(sample_table),
+            }
+        })
+        .collect();
+
+    // fragment boundaries in the movie timescale
+    let lead = movie
+        .tracks
+        .iter()
+        .position(|track| {
+            &track.media.handler.r#type.to_bytes() == b"vide" || track.is_image_sequence()
+        })
+        .unwrap_or(0);
+    let mut boundaries = Vec::new();
+    if let Some(track) = tracks.get(lead) {
+        let mut next = fragment_duration.0;
+        for sample in track.samples.iter().filter(|sample| sample.sync) {
+            let time = MediaTime(sample.time)
+                .to_movie(track.timescale, movie_timescale)
+                .0;
+            if fragment_duration.0 != 0 && time >= next {
+                boundaries.push(time);
+                while next <= time {
+                    next += fragment_duration.0;
+                }
+            }
+        }
+    }
+
+    // init segment, keeping the sample descriptions but none of the samples
+    for track in &mut movie.tracks {
+        let sample_table = &mut track.media.information.sample_table;
+        sample_table.time_to_sample = TimeToSampleBox(vec![]);
+        sample_table.composition_offset = None;
+        sample_table.sync_sample = None;
+        sample_table.sample_size = SampleSizeBox::PerSample(vec![]);
+        sample_table.sample_to_chunk = SampleToChunkBox(vec![]);
+        sample_table.chunk_offset = ChunkOffsetBox(vec![]);
+        sample_table.sample_dependency_type = None;
+        sample_table.padding_bits = None;
+        sample_table.sample_to_group = None;
+        sample_table.sample_group_description = None;
+    }
+    movie.extends = Some(MovieExtendsBox {
+        header: Some(MovieExtendsHeaderBox {
+            fragment_duration: movie.header.duration,
+        }),
+        track_extends: movie
+            .tracks
+            .iter()
+            .map(|track| TrackExtendsBox {
+                track_id: track.header.track_id,
+                default_sample_description_index: 1,
+                default_sample_duration: 0,
+                default_sample_size: 0,
+                default_sample_flags: 0,
+            })
+            .collect(),
+        extra_boxes: Vec::new(),
+    });
+    file_type.encode(output)?;
+    movie.encode(output)?;
+    meta.encode(output)?;
+
+    let mut begins = vec![0; tracks.len()];
+    let mut sequence_number = 0;
+    for fragment_index in 0..=boundaries.len() {
+        let mut fragment = MovieFragmentBox {
+            header: MovieFragmentHeaderBox {
+                sequence_number: sequence_number + 1,
+            },
+            track_fragments: Vec::new(),
+            protection_system_headers: Vec::new(),
+            extra_boxes: Vec::new(),
+        };
+        let mut data = Vec::new();
+        let mut data_offsets = Vec::new();
+        for (track, begin) in tracks.iter().zip(&mut begins) {
+            let end = boundaries
+                .get(fragment_index)
+                .map_or(track.samples.len(), |&boundary| {
+                    *begin
+                        + track.samples[*begin..].partition_point(|sample| {
+                            MediaTime(sample.time)
+                                .to_movie(track.timescale, movie_timescale)
+                                .0
+                                < boundary
+                        })
+                });
+            let samples = &track.samples[*begin..end];
+            *begin = end;
+
+            // a track fragment only references a single sample description
+            for samples in
+                samples.chunk_by(|a, b| a.sample_description_index == b.sample_description_index)
+            {
+                let first_sample = &samples[0];
+                data_offsets.push(data.len());
+                let mut entries = Vec::with_capacity(samples.len());
+                for sample in samples {
+                    let mut sample_data = vec![0; sample.size as usize];
+                    input
+                        .read_at(sample.offset, &mut sample_data)
+                        .map_err(|_| Error::ValueOutOfRange {
+                            field: "chunk_offset",
+                        })?;
+                    transform(track.track_id, &mut sample_data)?;
+                    entries.push(TrackRunEntry {
+                        sample_duration: Some(sample.duration),
+                        sample_size: Some(checked_cast(sample_data.len(), "sample_size")?),
+                        sample_flags: Some(
+                            // is_leading up to sample_has_redundancy, in the
+                            // same order as in the sdtp
+                            (sample.dependency.0 as u32) << 20
+                                | if sample.sync {
+                                    0
+                                } else {
+                                    TrackRunEntry::NON_SYNC_SAMPLE
+                                },
+                        ),
+                        sample_composition_time_offset: track
+                            .has_composition_offsets
+                            .then_some(sample.composition_offset),
+                    });
+                    data.extend_from_slice(&sample_data);
+                }
+                let (sample_to_groups, sample_group_descriptions) = track
+                    .sample_group_description
+                    .as_ref()
+                    .and_then(|description| sample_groups(description, samples))
+                    .into_iter()
+                    .unzip();
+                fragment.track_fragments.push(TrackFragmentBox {
+                    header: TrackFragmentHeaderBox {
+                        track_id: track.track_id,
+                        sample_description_index: (first_sample.sample_description_index != 1)
+                            .then_some(first_sample.sample_description_index),
+                        default_base_is_moof: true,
+                        ..Default::default()
+                    },
+                    decode_time: Some(TrackFragmentBaseMediaDecodeTimeBox {
+                        base_media_decode_time: MediaTime(first_sample.time),
+                    }),
+                    runs: vec![TrackRunBox {
+                        data_offset: Some(0),
+                        first_sample_flags: None,
+                        entries,
+                    }],
+                    sample_to_groups,
+                    sample_group_descriptions,
+                    auxiliary_information_sizes: Vec::new(),
+                    auxiliary_information_offsets: Vec::new(),
+                    sample_encryption: None,
+                    extra_boxes: Vec::new(),
+                });
+            }
+        }
+        if fragment.track_fragments.is_empty() {
+            continue;
+        }
+        sequence_number += 1;
+        encode_fragment(output, fragment, data, &data_offsets)?;
+    }
+    Ok(())
+}
+
+/// Encodes the moof followed by the mdat with `data`, in which the samples of
+/// the single run of each track fragment start at `data_offsets`.
+pub(crate) fn encode_fragment(
+    output: &mut (impl Write + Seek),
+    mut fragment: MovieFragmentBox,
+    data: Vec<u8>,
+    data_offsets: &[usize],
+) -> Result<()> {
+    // the data offsets are relative to the moof, whose size doesn't depend on
+    // their values
+    let mut moof = Cursor::new(Vec::new());
+    fragment.encode(&mut moof)?;
+    let media_data_header_size = if u32::try_from(4 + 4 + data.len()).is_ok() {
+        4 + 4
+    } else {
+        4 + 4 + 8
+    };
+    let base = moof.get_ref().len() + media_data_header_size;
+    for (track_fragment, data_offset) in fragment.track_fragments.iter_mut().zip(data_offsets) {
+        track_fragment.runs[0].data_offset = Some(checked_cast(base + data_offset, "data_offset")?);
+    }
+    fragment.encode(output)?;
+    MediaDataBox(data).encode(output)
+}
+
+struct Track {
+    track_id: u32,
+    timescale: u32,
+    has_composition_offsets: bool,
+    /// sgpd of the stbl, if its sbgp refers to it
+    sample_group_description: Option<SampleGroupDescriptionBox>,
+    samples: Vec<Sample>,
+}
+
+struct Sample {
+    time: u64,
+    duration: u32,
+    offset: u64,
+    size: u32,
+    sync: bool,
+    composition_offset: i32,
+    sample_description_index: u32,
+    /// of the sbgp in the stbl, 0 if in no group
+    group_description_index: u32,
+    dependency: SampleDependency,
+}
+
+fn sample_group_description(sample_table: &SampleTableBox) -> Option<SampleGroupDescriptionBox> {
+    let sample_to_group = sample_table.sample_to_group.as_ref()?;
+    sample_table
+        .sample_group_description
+        .as_ref()
+        .filter(|description| description.grouping_type == sample_to_group.0)
+        .cloned()
+}
+
+/// Returns the sbgp of the samples of a track fragment with a sgpd of only
+/// the entries of `description` they refer to, or None if they are in no
+/// group.
+fn sample_groups(
+    description: &SampleGroupDescriptionBox,
+    samples: &[Sample],
+) -> Option<(SampleToGroupBox, SampleGroupDescriptionBox)> {
+    // group description indices of the stbl in the order of the sgpd of the
+    // track fragment
+    let mut group_description_indices = Vec::new();
+    let mut sample_to_group: Vec<SampleToGroupEntry> = Vec::new();
+    for sample in samples {
+        let group_description_index = if description.get(sample.group_description_index).is_some() {
+            let position = match group_description_indices
+                .iter()
+                .position(|&index| index == sample.group_description_index)
+            {
+                Some(position) => position,
+                None => {
+                    group_description_indices.push(sample.group_description_index);
+                    group_description_indices.len() - 1
+                }
+            };
+            TrackFragmentBox::FRAGMENT_LOCAL_GROUP_DESCRIPTION_INDEX + 1 + position as u32
+        } else {
+            0
+        };
+        match sample_to_group.last_mut() {
+            Some(entry) if entry.group_description_index == group_description_index => {
+                entry.sample_count += 1
+            }
+            _ => sample_to_group.push(SampleToGroupEntry {
+                sample_count: 1,
+                group_description_index,
+            }),
+        }
+    }
+    if group_description_indices.is_empty() {
+        return None;
+    }
+    Some((
+        SampleToGroupBox(description.grouping_type, sample_to_group),
+        SampleGroupDescriptionBox {
+            grouping_type: description.grouping_type,
+            entries: group_description_indices
+                .into_iter()
+                .filter_map(|index| description.get(index).cloned())
+                .collect(),
+        },
+    ))
+}
+
+fn samples_of(sample_table: &SampleTableBox) -> Vec<Sample> {
+    let durations = sample_table
+        .time_to_sample
+        .0
+        .iter()
+        .flat_map(|entry| std::iter::repeat_n(entry.sample_delta, entry.sample_count as usize));
+    let mut composition_offsets = sample_table
+        .composition_offset
+        .iter()
+        .flat_map(|composition_offset| &composition_offset.0)
+        .flat_map(|entry| std::iter::repeat_n(entry.sample_offset, entry.sample_count as usize));
+    let mut group_description_indices = sample_table
+        .sample_to_group
+        .iter()
+        .flat_map(|sample_to_group| &sample_to_group.1)
+        .flat_map(|entry| {
+            std::iter::repeat_n(entry.group_description_index, entry.sample_count as usize)
+        });
+    let mut dependencies = sample_table
+        .sample_dependency_type
+        .iter()
+        .flat_map(|sample_dependency_type| &sample_dependency_type.0);
+    // stss is sorted, so it is walked along with the samples
+    let mut sync_samples = sample_table
+        .sync_sample
+        .as_ref()
+        .map(|sync_sample| sync_sample.0.iter().copied().peekable());
+
+    let mut samples = Vec::new();
+    for ((((sample, time), duration), offset), sample_description_index) in (0..)
+        .zip(sample_table.sample_times())
+        .zip(durations)
+        .zip(sample_table.sample_offsets())
+        .zip(sample_table.sample_description_indices())
+    {
+        let sync = match &mut sync_samples {
+            Some(sync_samples) => {
+                while sync_samples
+                    .next_if(|&sync_sample| sync_sample <= sample)
+                    .is_some()
+                {}
+                sync_samples.next_if_eq(&(sample + 1)).is_some()
+            }
+            None => true,
+        };
+        samples.push(Sample {
+            time: time.0,
+            duration,
+            offset,
+            size: sample_table.sample_size.sample_size(sample).unwrap_or(0),
+            sync,
+            composition_offset: composition_offsets.next().unwrap_or_default(),
+            sample_description_index,
+            group_description_index: group_description_indices.next().unwrap_or_default(),
+            dependency: dependencies.next().copied().unwrap_or_default(),
+        });
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{fragment, fragment_with};
+    use crate::{
+        defragment::defragment,
+        marshal::{
+            Decode, Encode, File, MovieFragmentBox, MovieTime, SampleDependency,
+            SampleDependencyTypeBox, SampleGroupEntry, SyncSampleBox, TrackFragmentBox,
+            TrackRunEntry,
+        },
+    };
+
+    /// Data of the samples of each track.
+    fn samples(file: &[u8]) -> Vec<Vec<Vec<u8>>> {
+        let movie = File::decode(&mut &file[..]).unwrap().movie.unwrap();
+        movie
+            .tracks
+            .iter()
+            .map(|track| {
+                track
+                    .samples()
+                    .zip(track.sample_offsets())
+                    .map(|(sample, offset)| {
+                        file[offset as usize..][..sample.size as usize].to_vec()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transform() {
+        let input = include_bytes!("../tests/fixtures/audio-video-progressive.mp4").as_slice();
+        let mut output = Cursor::new(Vec::new());
+        // appends the track id to each sample of the first track
+        fragment_with(input, MovieTime(500), &mut output, |track_id, sample| {
+            if track_id == 1 {
+                sample.push(track_id as u8);
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        let mut defragmented = Cursor::new(Vec::new());
+        defragment(output.get_ref())
+            .unwrap()
+            .encode(&mut defragmented)
+            .unwrap();
+        let mut expected = samples(input);
+        for sample in &mut expected[0] {
+            sample.push(1);
+        }
+        assert_eq!(samples(defragmented.get_ref()), expected);
+    }
+
+    #[test]
+    fn sample_groups_and_dependencies() {
+        let input = include_bytes!("../tests/fixtures/audio-video-progressive.mp4");
+        let mut file = File::decode(&mut input.as_slice()).unwrap();
+        let media_data_offset = file.media_data_offsets().unwrap()[0];
+        let movie = file.movie.as_mut().unwrap();
+        for track in &mut movie.tracks {
+            let sample_table = &mut track.media.information.sample_table;
+            if &track.media.handler.r#type.to_bytes() == b"soun" {
+                sample_table.set_roll_distance(-1);
+            } else {
+                // fragments start at the sync samples, with a duplicate
+                // entry as written by some muxers
+                sample_table.sync_sample = Some(SyncSampleBox(vec![1, 1, 3]));
+                // sample_depends_on of 1 for all but the sync samples
+                let sample_count = sample_table.sample_size.sample_count();
+                sample_table.sample_dependency_type = Some(SampleDependencyTypeBox(
+                    (1..=sample_count)
+                        .map(|sample| {
+                            let sync = matches!(sample, 1 | 3);
+                            SampleDependency(if sync { 2 << 4 } else { 1 << 4 })
+                        })
+                        .collect(),
+                ));
+            }
+        }
+        // the new boxes move the mdat
+        let shift = file.media_data_offsets().unwrap()[0] - media_data_offset;
+        for track in &mut file.movie.as_mut().unwrap().tracks {
+            for offset in &mut track.media.information.sample_table.chunk_offset.0 {
+                *offset += shift;
+            }
+        }
+        let mut input = Cursor::new(Vec::new());
+        file.encode(&mut input).unwrap();
+
+        let mut output = Cursor::new(Vec::new());
+        fragment(input.get_ref(), MovieTime(40), &mut output).unwrap();
+        let file = File::decode(&mut output.get_ref().as_slice()).unwrap();
+        let movie = file.movie.unwrap();
+        let fragments: Vec<_> = file
+            .extra_boxes
+            .iter()
+            .filter(|raw_box| &raw_box.r#type.to_bytes() == b"moof")
+            .map(|raw_box| MovieFragmentBox::decode(&mut raw_box.data.as_slice()).unwrap())
+            .collect();
+        assert_eq!(fragments.len(), 2);
+        for track_fragment in fragments
+            .iter()
+            .flat_map(|fragment| &fragment.track_fragments)
+        {
+            let track = movie.track(track_fragment.header.track_id).unwrap();
+            if &track.media.handler.r#type.to_bytes() == b"soun" {
+                let [sample_to_group] = track_fragment.sample_to_groups.as_slice() else {
+                    panic!("no sbgp");
+                };
+                assert_eq!(&sample_to_group.0.to_bytes(), b"roll");
+                assert!(sample_to_group.1.iter().all(|entry| {
+                    entry.group_description_index
+                        == TrackFragmentBox::FRAGMENT_LOCAL_GROUP_DESCRIPTION_INDEX + 1
+                }));
+                assert!(matches!(
+                    track_fragment.sample_group_descriptions[0]
+                        .entries
+                        .as_slice(),
+                    [SampleGroupEntry::Roll(-1)]
+                ));
+            } else {
+                for entry in track_fragment.runs.iter().flat_map(|run| &run.entries) {
+                    let flags = entry.sample_flags.unwrap();
+                    let depends_on = flags >> 24 & 0x3;
+                    let sync = flags & TrackRunEntry::NON_SYNC_SAMPLE == 0;
+                    assert_eq!(depends_on, if sync { 2 } else { 1 });
+                }
+            }
+        }
+    }
+}
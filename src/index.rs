@@ -0,0 +1,258 @@
+use std::io::{Seek, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::marshal::{checked_cast, Decode, Encode, Error, MediaTime, MovieBox, Result};
+
+const MAGIC: [u8; 4] = *b"ISIX";
+const VERSION: u8 = 0;
+
+/// The resolved samples of all tracks, to be stored next to a file and loaded
+/// instead of decoding its moov again, which can take a while for recordings
+/// of several hours.
+///
+/// The encoding starts with a magic and version, followed by the size of the
+/// file it was built from and per track the samples as variable-length
+/// integers relative to the previous sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleIndex {
+    /// size of the indexed file, to tell if the index is stale
+    pub file_size: u64,
+    pub tracks: Vec<TrackIndex>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackIndex {
+    pub track_id: u32,
+    pub timescale: u32,
+    pub samples: Vec<IndexedSample>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexedSample {
+    pub offset: u64,
+    pub size: u32,
+    pub decode_time: MediaTime,
+    pub composition_offset: i32,
+    pub is_sync: bool,
+}
+
+impl SampleIndex {
+    pub fn new(movie: &MovieBox, file_size: u64) -> Self {
+        Self {
+            file_size,
+            tracks: movie
+                .tracks
+                .iter()
+                .map(|track| TrackIndex {
+                    track_id: track.header.track_id,
+                    timescale: track.media.header.timescale,
+                    samples: track
+                        .samples()
+                        .zip(track.sample_offsets())
+                        .map(|(sample, offset)| IndexedSample {
+                            offset,
+                            size: sample.size,
+                            decode_time: sample.decode_time,
+                            composition_offset: sample.composition_offset,
+                            is_sync: sample.is_sync,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns whether the index was built from a file of this size.
+    pub fn matches(&self, file_size: u64) -> bool {
+        self.file_size == file_size
+    }
+
+    pub fn track(&self, track_id: u32) -> Option<&TrackIndex> {
+        self.tracks.iter().find(|track| track.track_id == track_id)
+    }
+}
+
+impl TrackIndex {
+    /// Returns the index of the last sync sample decoded at or before `time`.
+    pub fn sync_sample_before(&self, time: MediaTime) -> Option<usize> {
+        let end = self
+            .samples
+            .partition_point(|sample| sample.decode_time <= time);
+        self.samples[..end]
+            .iter()
+            .rposition(|sample| sample.is_sync)
+    }
+}
+
+impl Encode for SampleIndex {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        output.write_all(&MAGIC)?;
+        output.write_u8(VERSION)?;
+        output.write_u64::<BigEndian>(self.file_size)?;
+        encode_varint(output, self.tracks.len() as u64)?;
+        for track in &self.tracks {
+            encode_varint(output, track.track_id as u64)?;
+            encode_varint(output, track.timescale as u64)?;
+            encode_varint(output, track.samples.len() as u64)?;
+            let mut end = 0;
+            let mut decode_time = 0;
+            for sample in &track.samples {
+                // mostly the sample directly follows the previous one, the sync
+                // flag is stored in the lowest bit of the size
+                encode_varint(output, zigzag(sample.offset.wrapping_sub(end) as i64))?;
+                encode_varint(output, (sample.size as u64) << 1 | sample.is_sync as u64)?;
+                encode_varint(output, sample.decode_time.0.wrapping_sub(decode_time))?;
+                encode_varint(output, zigzag(sample.composition_offset as i64))?;
+                end = sample.offset + sample.size as u64;
+                decode_time = sample.decode_time.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Decode for SampleIndex {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut magic = [0; 4];
+        std::io::Read::read_exact(input, &mut magic)?;
+        if magic != MAGIC {
+            return Err(Error::ValueOutOfRange { field: "magic" });
+        }
+        if input.read_u8()? != VERSION {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        let file_size = input.read_u64::<BigEndian>()?;
+        let track_count = decode_varint(input)?;
+        let mut tracks = Vec::new();
+        for _ in 0..track_count {
+            let track_id = checked_cast(decode_varint(input)?, "track_id")?;
+            let timescale = checked_cast(decode_varint(input)?, "timescale")?;
+            let sample_count = decode_varint(input)?;
+            // every sample takes at least four bytes
+            let mut samples = Vec::with_capacity((sample_count as usize).min(input.len() / 4));
+            let mut end = 0u64;
+            let mut decode_time = 0u64;
+            for _ in 0..sample_count {
+                let offset = end.wrapping_add(unzigzag(decode_varint(input)?) as u64);
+                let size_and_sync = decode_varint(input)?;
+                let size: u32 = checked_cast(size_and_sync >> 1, "size")?;
+                decode_time = decode_time.wrapping_add(decode_varint(input)?);
+                let composition_offset =
+                    checked_cast(unzigzag(decode_varint(input)?), "composition_offset")?;
+                samples.push(IndexedSample {
+                    offset,
+                    size,
+                    decode_time: MediaTime(decode_time),
+                    composition_offset,
+                    is_sync: size_and_sync & 1 != 0,
+                });
+                end = offset.wrapping_add(size as u64);
+            }
+            tracks.push(TrackIndex {
+                track_id,
+                timescale,
+                samples,
+            });
+        }
+        Ok(Self { file_size, tracks })
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn encode_varint(output: &mut impl Write, mut value: u64) -> Result<()> {
+    while value >= 0x80 {
+        output.write_u8(value as u8 | 0x80)?;
+        value >>= 7;
+    }
+    output.write_u8(value as u8)?;
+    Ok(())
+}
+
+fn decode_varint(input: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = input.read_u8()?;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::ValueOutOfRange { field: "varint" })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{IndexedSample, SampleIndex, TrackIndex};
+    use crate::marshal::{Decode, Encode, Error, MediaTime};
+
+    fn index() -> SampleIndex {
+        let sample = |offset, size, decode_time, composition_offset, is_sync| IndexedSample {
+            offset,
+            size,
+            decode_time: MediaTime(decode_time),
+            composition_offset,
+            is_sync,
+        };
+        SampleIndex {
+            file_size: 1 << 33,
+            tracks: vec![
+                TrackIndex {
+                    track_id: 1,
+                    timescale: 90000,
+                    samples: vec![
+                        sample(48, 1000, 0, 3000, true),
+                        // B-frame, presented before the previous sample
+                        sample(1048, 500, 3000, -3000, false),
+                        // chunks of other tracks are stored in between
+                        sample(1 << 32, u32::MAX - 1, 6000, 0, true),
+                        // non-monotonic chunk, before the previous sample
+                        sample(2048, 10, 9000, 0, false),
+                    ],
+                },
+                TrackIndex {
+                    track_id: 2,
+                    timescale: 48000,
+                    samples: vec![sample(1548, 500, 0, 0, true)],
+                },
+            ],
+        }
+    }
+
+    fn encode(index: &SampleIndex) -> Vec<u8> {
+        let mut output = Cursor::new(Vec::new());
+        index.encode(&mut output).unwrap();
+        output.into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let index = index();
+        let data = encode(&index);
+        assert_eq!(SampleIndex::decode(&mut data.as_slice()).unwrap(), index);
+    }
+
+    #[test]
+    fn invalid() {
+        let data = encode(&index());
+        for length in 0..data.len() {
+            assert!(SampleIndex::decode(&mut &data[..length]).is_err());
+        }
+
+        let mut data = data;
+        data[0] = b'X';
+        assert!(matches!(
+            SampleIndex::decode(&mut data.as_slice()),
+            Err(Error::ValueOutOfRange { field: "magic" })
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+use std::ops::Range;
+
+use crate::{
+    defragment::{media_data_offset, sample_size_box},
+    io::{read_box_data, read_box_headers, RandomAccess},
+    marshal::{
+        checked_cast, ChunkOffsetBox, Decode, Error, File, FileTypeBox, MediaDataBox, MetaBox,
+        MovieBox, MovieTime, Result, SampleToChunkBox, SampleToChunkEntry,
+    },
+};
+
+/// How far apart the samples of different tracks are stored, which is how much
+/// a progressive player has to read ahead to play all tracks in sync.
+#[derive(Debug, Clone)]
+pub struct InterleaveReport {
+    /// largest distance in the file between samples of different tracks which
+    /// are next to each other in decode time
+    pub max_distance: u64,
+    /// largest difference in decode time between samples of different tracks
+    /// which are next to each other in the file
+    pub max_time_distance: MovieTime,
+}
+
+impl InterleaveReport {
+    pub fn new(movie: &MovieBox) -> Self {
+        let mut samples = Vec::new();
+        for (index, track) in movie.tracks.iter().enumerate() {
+            let timescale = track.media.header.timescale;
+            samples.extend(
+                track
+                    .samples()
+                    .zip(track.sample_offsets())
+                    .map(|(sample, offset)| {
+                        (
+                            offset,
+                            sample
+                                .decode_time
+                                .to_movie(timescale, movie.header.timescale),
+                            index,
+                        )
+                    }),
+            );
+        }
+
+        let mut max_distance = 0;
+        samples.sort_by_key(|&(_, time, _)| time);
+        for pair in samples.windows(2) {
+            let [(offset, _, track), (next_offset, _, next_track)] = pair else {
+                unreachable!()
+            };
+            if track != next_track {
+                max_distance = max_distance.max(offset.abs_diff(*next_offset));
+            }
+        }
+
+        let mut max_time_distance = 0;
+        samples.sort_by_key(|&(offset, _, _)| offset);
+        for pair in samples.windows(2) {
+            let [(_, time, track), (_, next_time, next_track)] = pair else {
+                unreachable!()
+            };
+            if track != next_track {
+                max_time_distance = max_time_distance.max(time.0.abs_diff(next_time.0));
+            }
+        }
+
+        Self {
+            max_distance,
+            max_time_distance: MovieTime(max_time_distance),
+        }
+    }
+}
+
+/// Rewrites a progressive file into a single mdat, with one chunk per track
+/// for each `target_window` of decode time, so that samples which are played
+/// together are stored close to each other.
+pub fn reinterleave(
+    input: &(impl RandomAccess + ?Sized),
+    target_window: MovieTime,
+) -> Result<File> {
+    reinterleave_with(input, target_window, |_, _| Ok(()))
+}
+
+/// Like [reinterleave], but passes the data of each sample with its track id
+/// through `transform` before it is written, and takes over the new sizes.
+pub fn reinterleave_with(
+    input: &(impl RandomAccess + ?Sized),
+    target_window: MovieTime,
+    mut transform: impl FnMut(u32, &mut Vec<u8>) -> Result<()>,
+) -> Result<File> {
+    if target_window.0 == 0 {
+        return Err(Error::ValueOutOfRange {
+            field: "target_window",
+        });
+    }
+
+    let mut file_type = None;
+    let mut movie = None;
+    let mut meta = None;
+    for header in read_box_headers(input)? {
+        match &header.r#type.to_bytes() {
+            b"ftyp" => {
+                file_type = Some(FileTypeBox::decode(
+                    &mut read_box_data(input, &header)?.as_slice(),
+                )?)
+            }
+            b"moov" => {
+                movie = Some(MovieBox::decode(
+                    &mut read_box_data(input, &header)?.as_slice(),
+                )?)
+            }
+            b"meta" => {
+                meta = Some(MetaBox::decode(
+                    &mut read_box_data(input, &header)?.as_slice(),
+                )?)
+            }
+            _ => {}
+        }
+    }
+    let file_type = file_type.ok_or(Error::InvalidBoxQuantity {
+        r#type: "ftyp",
+        quantity: 0,
+        expected: 1,
+    })?;
+    let mut movie = movie.ok_or(Error::InvalidBoxQuantity {
+        r#type: "moov",
+        quantity: 0,
+        expected: 1,
+    })?;
+
+    // consecutive samples of a track in the same window and with the same
+    // sample description, sorted by window and track
+    let mut runs = Vec::new();
+    for (index, track) in movie.tracks.iter().enumerate() {
+        let timescale = track.media.header.timescale;
+        let mut samples = track.samples().zip(track.sample_offsets()).peekable();
+        while let Some((sample, offset)) = samples.next() {
+            let window = sample
+                .decode_time
+                .to_movie(timescale, movie.header.timescale)
+                .0
+                / target_window.0;
+            let mut run = Run {
+                window,
+                track: index,
+                sample_description_index: sample.sample_description_index,
+                samples: Vec::new(),
+            };
+            run.samples.push(offset..offset + sample.size as u64);
+            while let Some((sample, offset)) = samples.next_if(|(sample, _)| {
+                sample.sample_description_index == run.sample_description_index
+                    && sample
+                        .decode_time
+                        .to_movie(timescale, movie.header.timescale)
+                        .0
+                        / target_window.0
+                        == window
+            }) {
+                run.samples.push(offset..offset + sample.size as u64);
+            }
+            runs.push(run);
+        }
+    }
+    runs.sort_by_key(|run| (run.window, run.track));
+
+    // offset into the new mdat payload, sample count and sample description
+    // index of each chunk of each track, and the sizes of its samples
+    let mut chunks = vec![Vec::new(); movie.tracks.len()];
+    let mut sizes = vec![Vec::new(); movie.tracks.len()];
+    let mut data = Vec::new();
+    for run in &runs {
+        let track_id = movie.tracks[run.track].header.track_id;
+        chunks[run.track].push((
+            data.len() as u64,
+            run.samples.len() as u32,
+            run.sample_description_index,
+        ));
+        // read samples which are also next to each other in the input at once
+        let mut samples = run.samples.as_slice();
+        while let [first, ..] = samples {
+            let count = 1 + samples
+                .windows(2)
+                .take_while(|pair| pair[0].end == pair[1].start)
+                .count();
+            let (adjacent, rest) = samples.split_at(count);
+            samples = rest;
+            let range = first.start..adjacent[count - 1].end;
+            if range.end > input.len()? {
+                return Err(Error::ValueOutOfRange {
+                    field: "data_offset",
+                });
+            }
+            let adjacent_data = input.read_range(range)?;
+            for sample in adjacent {
+                let begin = (sample.start - first.start) as usize;
+                let mut sample_data =
+                    adjacent_data[begin..begin + (sample.end - sample.start) as usize].to_vec();
+                transform(track_id, &mut sample_data)?;
+                sizes[run.track].push(checked_cast(sample_data.len(), "sample_size")?);
+                data.extend_from_slice(&sample_data);
+            }
+        }
+    }
+
+    for ((track, chunks), sizes) in movie.tracks.iter_mut().zip(&chunks).zip(&sizes) {
+        track.media.information.sample_table.sample_size = sample_size_box(sizes);
+        let mut sample_to_chunk: Vec<SampleToChunkEntry> = Vec::new();
+        for (chunk, &(_, samples_per_chunk, sample_description_index)) in (1..).zip(chunks) {
+            if sample_to_chunk.last().is_none_or(|entry| {
+                entry.samples_per_chunk != samples_per_chunk
+                    || entry.sample_description_index != sample_description_index
+            }) {
+                sample_to_chunk.push(SampleToChunkEntry {
+                    first_chunk: chunk,
+                    samples_per_chunk,
+                    sample_description_index,
+                });
+            }
+        }
+        track.media.information.sample_table.sample_to_chunk = SampleToChunkBox(sample_to_chunk);
+    }
+
+    let mut file = File {
+        file_type,
+        movie: Some(movie),
+        media_data: vec![MediaDataBox(data)],
+        meta,
+        extra_boxes: Vec::new(),
+    };
+
+    // same as for defragment, the chunk offsets depend on the size of the moov
+    let mut header_size = 0;
+    loop {
+        let movie = file.movie.as_mut().unwrap();
+        for (track, chunks) in movie.tracks.iter_mut().zip(&chunks) {
+            track.media.information.sample_table.chunk_offset = ChunkOffsetBox(
+                chunks
+                    .iter()
+                    .map(|&(offset, _, _)| offset + header_size)
+                    .collect(),
+            );
+        }
+
+        let size = media_data_offset(&file)?;
+        if size == header_size {
+            break;
+        }
+        header_size = size;
+    }
+    Ok(file)
+}
+
+struct Run {
+    window: u64,
+    track: usize,
+    sample_description_index: u32,
+    samples: Vec<Range<u64>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::reinterleave_with;
+    use crate::marshal::{Decode, Encode, File, MovieTime};
+
+    /// Data of the samples of each track.
+    fn samples(file: &[u8]) -> Vec<Vec<Vec<u8>>> {
+        let movie = File::decode(&mut &file[..]).unwrap().movie.unwrap();
+        movie
+            .tracks
+            .iter()
+            .map(|track| {
+                track
+                    .samples()
+                    .zip(track.sample_offsets())
+                    .map(|(sample, offset)| {
+                        file[offset as usize..][..sample.size as usize].to_vec()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transform() {
+        let input = include_bytes!("../tests/fixtures/audio-video-progressive.mp4").as_slice();
+        // drops the last byte of each sample of the second track
+        let file = reinterleave_with(input, MovieTime(500), |track_id, sample| {
+            if track_id == 2 {
+                sample.pop();
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        let mut output = Cursor::new(Vec::new());
+        file.encode(&mut output).unwrap();
+        let mut expected = samples(input);
+        for sample in &mut expected[1] {
+            sample.pop();
+        }
+        assert_eq!(samples(output.get_ref()), expected);
+    }
+}
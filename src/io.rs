@@ -0,0 +1,578 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    sync::mpsc::{self, Receiver, Sender, SyncSender},
+    thread::JoinHandle,
+};
+
+use crate::marshal::{Error, FourCC, Result};
+
+/// Data that is read at arbitrary offsets, like memory, a file or an object in
+/// remote storage, so that only the required parts have to be fetched.
+pub trait RandomAccess {
+    fn len(&self) -> Result<u64>;
+
+    /// Fills `buffer` with the data at `offset`, failing if not all of it is
+    /// available.
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()>;
+
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn read_range(&self, range: Range<u64>) -> Result<Vec<u8>> {
+        let mut buffer = vec![
+            0;
+            usize::try_from(range.end.saturating_sub(range.start))
+                .map_err(|_| Error::ValueOutOfRange { field: "range" })?
+        ];
+        self.read_at(range.start, &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl RandomAccess for [u8] {
+    fn len(&self) -> Result<u64> {
+        Ok(<[u8]>::len(self) as u64)
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        let data = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.get(offset..offset.checked_add(buffer.len())?))
+            .ok_or(Error::Io(ErrorKind::UnexpectedEof.into()))?;
+        buffer.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+impl RandomAccess for Vec<u8> {
+    fn len(&self) -> Result<u64> {
+        RandomAccess::len(self.as_slice())
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        self.as_slice().read_at(offset, buffer)
+    }
+}
+
+impl<T: RandomAccess + ?Sized> RandomAccess for &T {
+    fn len(&self) -> Result<u64> {
+        (**self).len()
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        (**self).read_at(offset, buffer)
+    }
+}
+
+impl RandomAccess for std::fs::File {
+    fn len(&self) -> Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    #[cfg(unix)]
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        use std::os::unix::fs::FileExt;
+
+        Ok(self.read_exact_at(buffer, offset)?)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, mut offset: u64, mut buffer: &mut [u8]) -> Result<()> {
+        use std::os::windows::fs::FileExt;
+
+        while !buffer.is_empty() {
+            match self.seek_read(buffer, offset)? {
+                0 => return Err(Error::Io(ErrorKind::UnexpectedEof.into())),
+                length => {
+                    buffer = &mut buffer[length..];
+                    offset += length as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads through a function that fetches byte ranges, like HTTP range requests
+/// or object storage, which is only called for the parts that are needed.
+///
+/// Small reads, like box headers, are served from blocks of `block_size` that
+/// are kept once fetched, up to [RangeReader::DEFAULT_BLOCK_CAPACITY] of them,
+/// dropping the oldest first, larger reads, like the moov or samples, are
+/// fetched as is.
+pub struct RangeReader<F> {
+    len: u64,
+    block_size: u64,
+    fetch: F,
+    blocks: RefCell<HashMap<u64, Vec<u8>>>,
+    /// blocks in the order they were fetched
+    block_order: RefCell<VecDeque<u64>>,
+    block_capacity: usize,
+}
+
+impl<F> RangeReader<F> {
+    pub const DEFAULT_BLOCK_CAPACITY: usize = 1024;
+
+    /// Sets the number of blocks which are kept, at least 2, as a read spans
+    /// up to 2 blocks.
+    pub fn set_block_capacity(&mut self, capacity: usize) {
+        self.block_capacity = capacity.max(2);
+    }
+}
+
+impl<F: Fn(Range<u64>) -> Result<Vec<u8>>> RangeReader<F> {
+    /// Creates a reader for data of `len` bytes, like the Content-Length of a
+    /// HEAD request.
+    pub fn new(len: u64, block_size: u64, fetch: F) -> Self {
+        Self {
+            len,
+            block_size: block_size.max(1),
+            fetch,
+            blocks: Default::default(),
+            block_order: Default::default(),
+            block_capacity: Self::DEFAULT_BLOCK_CAPACITY,
+        }
+    }
+
+    fn fetch(&self, range: Range<u64>) -> Result<Vec<u8>> {
+        let length = range.end - range.start;
+        let data = (self.fetch)(range)?;
+        if data.len() as u64 != length {
+            return Err(Error::Io(ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(data)
+    }
+}
+
+impl<F: Fn(Range<u64>) -> Result<Vec<u8>>> RandomAccess for RangeReader<F> {
+    fn len(&self) -> Result<u64> {
+        Ok(self.len)
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        let end = offset
+            .checked_add(buffer.len() as u64)
+            .filter(|&end| end <= self.len)
+            .ok_or(Error::Io(ErrorKind::UnexpectedEof.into()))?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        if buffer.len() as u64 > self.block_size {
+            buffer.copy_from_slice(&self.fetch(offset..end)?);
+            return Ok(());
+        }
+
+        let first_block = offset / self.block_size;
+        let last_block = (end - 1) / self.block_size;
+        let mut blocks = self.blocks.borrow_mut();
+        let mut block_order = self.block_order.borrow_mut();
+        // missing blocks are fetched with a single request
+        if let Some(missing_block) =
+            (first_block..=last_block).find(|block| !blocks.contains_key(block))
+        {
+            let data = self.fetch(
+                missing_block * self.block_size..((last_block + 1) * self.block_size).min(self.len),
+            )?;
+            for (block, data) in (missing_block..).zip(data.chunks(self.block_size as usize)) {
+                blocks.entry(block).or_insert_with(|| {
+                    block_order.push_back(block);
+                    data.to_owned()
+                });
+            }
+        }
+        let mut position = offset;
+        for block in first_block..=last_block {
+            let data = &blocks[&block];
+            let begin = (position - block * self.block_size) as usize;
+            let length = data
+                .len()
+                .saturating_sub(begin)
+                .min((end - position) as usize);
+            let written = (position - offset) as usize;
+            buffer[written..written + length].copy_from_slice(&data[begin..begin + length]);
+            position += length as u64;
+        }
+
+        // only evicted after the copy, as the blocks of this read might be the
+        // oldest
+        while block_order.len() > self.block_capacity {
+            if let Some(block) = block_order.pop_front() {
+                blocks.remove(&block);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fetches a byte range for a [RangeReader], like the one of HTTP range
+/// requests.
+pub type Fetch = Box<dyn Fn(Range<u64>) -> Result<Vec<u8>> + Send>;
+
+/// Creates a [RangeReader] for a URL, which is fetched with HTTP range
+/// requests, after a HEAD request for its length.
+#[cfg(feature = "http")]
+pub fn http_range_reader(
+    url: impl reqwest::IntoUrl,
+    block_size: u64,
+) -> Result<RangeReader<Fetch>> {
+    let http_error = |error: reqwest::Error| Error::Io(std::io::Error::other(error));
+
+    let client = reqwest::blocking::Client::new();
+    let url = url.into_url().map_err(http_error)?;
+    let len = client
+        .head(url.clone())
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(http_error)?
+        .content_length()
+        .ok_or(Error::Io(ErrorKind::Unsupported.into()))?;
+    let fetch: Fetch = Box::new(move |range: Range<u64>| {
+        let response = client
+            .get(url.clone())
+            .header(
+                reqwest::header::RANGE,
+                format!("bytes={}-{}", range.start, range.end - 1),
+            )
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(http_error)?;
+        // a server without range support would send all of it
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            return Err(Error::Io(ErrorKind::Unsupported.into()));
+        }
+        Ok(response.bytes().map_err(http_error)?.to_vec())
+    });
+    Ok(RangeReader::new(len, block_size, fetch))
+}
+
+/// Writes to a file or stream on a background thread, so that the thread
+/// encoding the samples, and whatever feeds it, like the packets of an
+/// encoder, doesn't stall on a slow disk or network.
+///
+/// Writes are collected into blocks of `block_size`, of which up to
+/// `capacity` are queued, only when the queue is full writing blocks, which
+/// gives back-pressure instead of an unbounded buffer. Seeking is free, reads
+/// wait for the queued blocks, and errors of the background thread are
+/// returned by the next call.
+pub struct BackgroundWriter<W> {
+    sender: Option<SyncSender<BackgroundOperation>>,
+    thread: Option<JoinHandle<std::io::Result<W>>>,
+    block_size: usize,
+    block: Vec<u8>,
+    block_position: u64,
+    position: u64,
+    len: u64,
+}
+
+enum BackgroundOperation {
+    Write(u64, Vec<u8>),
+    Read(u64, usize, Sender<std::io::Result<Vec<u8>>>),
+    Flush(Sender<std::io::Result<()>>),
+}
+
+impl<W: Read + Write + Seek + Send + 'static> BackgroundWriter<W> {
+    pub fn new(mut inner: W, block_size: usize, capacity: usize) -> std::io::Result<Self> {
+        let position = inner.stream_position()?;
+        let len = inner.seek(SeekFrom::End(0))?;
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let thread = std::thread::spawn(move || {
+            for operation in receiver {
+                match operation {
+                    BackgroundOperation::Write(position, data) => {
+                        inner.seek(SeekFrom::Start(position))?;
+                        inner.write_all(&data)?;
+                    }
+                    BackgroundOperation::Read(position, length, reply) => {
+                        let mut data = vec![0; length];
+                        let result = inner
+                            .seek(SeekFrom::Start(position))
+                            .and_then(|_| inner.read(&mut data))
+                            .map(|length| {
+                                data.truncate(length);
+                                data
+                            });
+                        let _ = reply.send(result);
+                    }
+                    BackgroundOperation::Flush(reply) => {
+                        let _ = reply.send(inner.flush());
+                    }
+                }
+            }
+            inner.flush()?;
+            Ok(inner)
+        });
+        Ok(Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            block_size: block_size.max(1),
+            block: Vec::new(),
+            block_position: position,
+            position,
+            len,
+        })
+    }
+
+    /// Waits for all queued writes, returning the file or stream.
+    pub fn into_inner(mut self) -> std::io::Result<W> {
+        self.write_block()?;
+        self.sender = None;
+        self.join()
+    }
+
+    fn send(&mut self, operation: BackgroundOperation) -> std::io::Result<()> {
+        let sender = self.sender.as_ref().ok_or(ErrorKind::BrokenPipe)?;
+        if sender.send(operation).is_err() {
+            // the thread only stops on errors
+            self.sender = None;
+            return Err(self.join().err().unwrap_or(ErrorKind::BrokenPipe.into()));
+        }
+        Ok(())
+    }
+
+    fn join(&mut self) -> std::io::Result<W> {
+        self.thread
+            .take()
+            .ok_or(ErrorKind::BrokenPipe)?
+            .join()
+            .map_err(|_| std::io::Error::other("background writer panicked"))?
+    }
+
+    fn write_block(&mut self) -> std::io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let block = std::mem::replace(&mut self.block, Vec::with_capacity(self.block_size));
+        self.send(BackgroundOperation::Write(self.block_position, block))
+    }
+
+    fn reply<T>(&mut self, receiver: Receiver<std::io::Result<T>>) -> std::io::Result<T> {
+        match receiver.recv() {
+            Ok(result) => result,
+            Err(_) => {
+                self.sender = None;
+                Err(self.join().err().unwrap_or(ErrorKind::BrokenPipe.into()))
+            }
+        }
+    }
+}
+
+impl<W: Read + Write + Seek + Send + 'static> Write for BackgroundWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        if self.position != self.block_position + self.block.len() as u64
+            || self.block.len() >= self.block_size
+        {
+            self.write_block()?;
+        }
+        if self.block.is_empty() {
+            self.block_position = self.position;
+        }
+        self.block.extend_from_slice(buffer);
+        self.position += buffer.len() as u64;
+        self.len = self.len.max(self.position);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_block()?;
+        let (sender, receiver) = mpsc::channel();
+        self.send(BackgroundOperation::Flush(sender))?;
+        self.reply(receiver)
+    }
+}
+
+impl<W: Read + Write + Seek + Send + 'static> Read for BackgroundWriter<W> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.write_block()?;
+        let (sender, receiver) = mpsc::channel();
+        self.send(BackgroundOperation::Read(
+            self.position,
+            buffer.len(),
+            sender,
+        ))?;
+        let data = self.reply(receiver)?;
+        buffer[..data.len()].copy_from_slice(&data);
+        self.position += data.len() as u64;
+        Ok(data.len())
+    }
+}
+
+impl<W> Seek for BackgroundWriter<W> {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or(ErrorKind::InvalidInput)?;
+        Ok(self.position)
+    }
+}
+
+impl<W> Drop for BackgroundWriter<W> {
+    fn drop(&mut self) {
+        // like BufWriter, errors can't be reported here, use into_inner instead
+        if !self.block.is_empty() {
+            if let Some(sender) = &self.sender {
+                let _ = sender.send(BackgroundOperation::Write(
+                    self.block_position,
+                    std::mem::take(&mut self.block),
+                ));
+            }
+        }
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Position of a box, without its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoxHeader {
+    pub r#type: FourCC,
+    pub offset: u64,
+    pub header_size: u64,
+    /// including the header
+    pub size: u64,
+}
+
+impl BoxHeader {
+    pub fn data_range(&self) -> Range<u64> {
+        self.offset + self.header_size..self.offset + self.size
+    }
+}
+
+/// Reads the headers of the top-level boxes, skipping their data.
+pub fn read_box_headers(input: &(impl RandomAccess + ?Sized)) -> Result<Vec<BoxHeader>> {
+    let length = input.len()?;
+    let mut headers = Vec::new();
+    let mut offset = 0;
+    while offset < length {
+        let mut header = [0; 4 + 4];
+        input.read_at(offset, &mut header)?;
+        let size = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let r#type = FourCC::from(<[u8; 4]>::try_from(&header[4..]).unwrap());
+        let (header_size, box_size) = match size {
+            0 => (4 + 4, length - offset),
+            1 => {
+                let mut large_size = [0; 8];
+                input.read_at(offset + 4 + 4, &mut large_size)?;
+                (4 + 4 + 8, u64::from_be_bytes(large_size))
+            }
+            _ => (4 + 4, size as u64),
+        };
+        if box_size < header_size || box_size > length - offset {
+            return Err(Error::InvalidBoxSize {
+                r#type,
+                size: box_size,
+                remaining: (length - offset) as usize,
+            });
+        }
+        headers.push(BoxHeader {
+            r#type,
+            offset,
+            header_size,
+            size: box_size,
+        });
+        offset += box_size;
+    }
+    Ok(headers)
+}
+
+/// Reads the data of a box, without its header.
+pub fn read_box_data(input: &(impl RandomAccess + ?Sized), header: &BoxHeader) -> Result<Vec<u8>> {
+    input.read_range(header.data_range())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+    };
+
+    use super::{BackgroundWriter, RandomAccess, RangeReader};
+
+    #[test]
+    fn range_reader() {
+        let data: Vec<u8> = (0..=255).collect();
+        let fetches = Cell::new(0);
+        let mut reader = RangeReader::new(data.len() as u64, 16, |range| {
+            fetches.set(fetches.get() + 1);
+            Ok(data[range.start as usize..range.end as usize].to_vec())
+        });
+        reader.set_block_capacity(2);
+
+        reader.read_at(256, &mut []).unwrap();
+        reader.read_at(0, &mut []).unwrap();
+        assert_eq!(fetches.get(), 0);
+
+        // spanning two blocks
+        assert_eq!(reader.read_range(14..18).unwrap(), data[14..18]);
+        assert_eq!(reader.read_range(16..20).unwrap(), data[16..20]);
+        assert_eq!(fetches.get(), 1);
+
+        // the oldest block is dropped
+        assert_eq!(reader.read_range(32..36).unwrap(), data[32..36]);
+        assert_eq!(reader.read_range(0..4).unwrap(), data[0..4]);
+        assert_eq!(fetches.get(), 3);
+        assert_eq!(reader.blocks.borrow().len(), 2);
+    }
+
+    #[test]
+    fn background_writer() {
+        let mut writer = BackgroundWriter::new(Cursor::new(Vec::new()), 4, 2).unwrap();
+        // a header which is patched once the size of the data is known
+        writer.write_all(&[0; 8]).unwrap();
+        let data: Vec<u8> = (0..100).collect();
+        writer.write_all(&data).unwrap();
+        writer.seek(SeekFrom::Start(0)).unwrap();
+        writer.write_all(&108u64.to_be_bytes()).unwrap();
+        writer.seek(SeekFrom::End(0)).unwrap();
+
+        let mut expected = 108u64.to_be_bytes().to_vec();
+        expected.extend_from_slice(&data);
+        // reads see the blocks which are still queued
+        let mut read = vec![0; expected.len()];
+        writer.seek(SeekFrom::Start(0)).unwrap();
+        writer.read_exact(&mut read).unwrap();
+        assert_eq!(read, expected);
+        assert_eq!(writer.into_inner().unwrap().into_inner(), expected);
+    }
+
+    #[test]
+    fn background_writer_error() {
+        struct Failing;
+
+        impl Read for Failing {
+            fn read(&mut self, _buffer: &mut [u8]) -> std::io::Result<usize> {
+                Ok(0)
+            }
+        }
+
+        impl Write for Failing {
+            fn write(&mut self, _buffer: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Seek for Failing {
+            fn seek(&mut self, _position: SeekFrom) -> std::io::Result<u64> {
+                Ok(0)
+            }
+        }
+
+        let mut writer = BackgroundWriter::new(Failing, 4, 2).unwrap();
+        writer.write_all(&[0; 16]).unwrap();
+        let error = writer.flush().unwrap_err();
+        assert_eq!(error.to_string(), "disk full");
+    }
+}
@@ -1 +1,35 @@
+//! Reading and writing of ISO base media files, like MP4, MOV and HEIF.
+//!
+//! Malformed or hostile input never causes a panic while decoding, only an
+//! [Error](marshal::Error), and the crate contains no unsafe code.
+
+#![forbid(unsafe_code)]
+
+pub mod append;
+pub mod defragment;
+pub mod demux;
+pub mod diff;
+pub mod dump;
+pub mod edit;
+pub mod export;
+pub mod faststart;
+pub mod filter;
+pub mod fragment;
+pub mod index;
+pub mod interleave;
+pub mod io;
+pub mod m4a;
 pub mod marshal;
+pub mod metadata;
+pub mod mux;
+pub mod parameter_sets;
+pub mod registry;
+pub mod rollover;
+pub mod scrub;
+#[cfg(feature = "symphonia")]
+pub mod symphonia;
+pub mod sync;
+#[cfg(feature = "testgen")]
+pub mod testgen;
+pub mod trim;
+pub mod visit;
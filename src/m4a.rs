@@ -0,0 +1,228 @@
+use std::io::{Read, Seek, Write};
+
+use crate::{
+    append::AppendSession,
+    marshal::{
+        aac::AACSampleEntry, checked_cast, ChunkOffsetBox, Encode, Error, FileTypeBox, FourCC,
+        HandlerBox, MediaBox, MediaHeaderBox, MediaInformationBox, MediaInformationHeader,
+        MediaTime, MovieBox, MovieHeaderBox, Result, SampleDescriptionBox, SampleEntry,
+        SampleSizeBox, SampleTableBox, SampleToChunkBox, SoundMediaHeaderBox, TimeToSampleBox,
+        TrackBox, TrackHeaderBox,
+    },
+    mux::{MuxSummary, WriterConfig},
+};
+
+/// Writes audio-only files like iTunes does for .m4a, with a single sound
+/// track and the iTunes metadata in the udta of the movie.
+///
+/// All samples are sync samples, so no stss is written, and for AAC the roll
+/// distance is signaled for seeking, and the priming of the common encoders is
+/// hidden behind an edit list, see [Self::set_priming].
+pub struct M4aWriter<F: Read + Write + Seek> {
+    session: AppendSession<F>,
+}
+
+impl<F: Read + Write + Seek> M4aWriter<F> {
+    pub const TRACK_ID: u32 = 1;
+
+    /// Starts a file with a track of `entry`, which is AAC, MP3 or PCM, using
+    /// its sample rate as timescale.
+    pub fn new(file: F, entry: SampleEntry) -> Result<Self> {
+        Self::with_config(file, entry, Default::default())
+    }
+
+    /// Like [Self::new], with the brands, handler name and encoder tag of
+    /// `config`.
+    pub fn with_config(mut file: F, entry: SampleEntry, config: WriterConfig) -> Result<Self> {
+        let (base, roll_distance, priming) = match &entry {
+            SampleEntry::AAC(entry) => (
+                &entry.base,
+                Some(AACSampleEntry::ROLL_DISTANCE),
+                Some(MediaTime(AACSampleEntry::PRIMING)),
+            ),
+            SampleEntry::MP3(entry) => (&entry.base, None, None),
+            SampleEntry::PCM(entry) => (&entry.base, None, None),
+            _ => {
+                return Err(Error::ValueOutOfRange {
+                    field: "sample_entry",
+                })
+            }
+        };
+        let timescale = checked_cast(base.sample_rate() as u64, "samplerate")?;
+        if timescale == 0 {
+            return Err(Error::ValueOutOfRange {
+                field: "samplerate",
+            });
+        }
+
+        let mut media_header = MediaHeaderBox {
+            timescale,
+            ..Default::default()
+        };
+        media_header.set_language_code("und")?;
+
+        let mut file_type = FileTypeBox {
+            major_brand: FourCC::from(*b"M4A "),
+            minor_version: 0,
+            compatible_brands: vec![
+                FourCC::from(*b"M4A "),
+                FourCC::from(*b"mp42"),
+                FourCC::from(*b"isom"),
+            ],
+        };
+        let mut movie = MovieBox {
+            header: MovieHeaderBox {
+                timescale,
+                next_track_id: Self::TRACK_ID + 1,
+                ..Default::default()
+            },
+            tracks: vec![TrackBox {
+                header: TrackHeaderBox {
+                    track_id: Self::TRACK_ID,
+                    ..Default::default()
+                },
+                media: MediaBox {
+                    header: media_header,
+                    handler: HandlerBox {
+                        r#type: FourCC::from(*b"soun"),
+                        name: String::new(),
+                    },
+                    information: MediaInformationBox {
+                        header: MediaInformationHeader::Sound(SoundMediaHeaderBox {
+                            balance: Default::default(),
+                        }),
+                        data_information: Default::default(),
+                        sample_table: SampleTableBox {
+                            description: SampleDescriptionBox(vec![entry]),
+                            time_to_sample: TimeToSampleBox(vec![]),
+                            composition_offset: None,
+                            sync_sample: None,
+                            sample_size: SampleSizeBox::Value {
+                                sample_size: 0,
+                                sample_count: 0,
+                            },
+                            sample_to_chunk: SampleToChunkBox(vec![]),
+                            chunk_offset: ChunkOffsetBox(vec![]),
+                            sample_dependency_type: None,
+                            padding_bits: None,
+                            sample_to_group: None,
+                            sample_group_description: None,
+                            extra_boxes: Vec::new(),
+                        },
+                        extra_boxes: Vec::new(),
+                    },
+                    extra_boxes: Vec::new(),
+                },
+                edit: None,
+                user_data: None,
+                extra_boxes: Vec::new(),
+            }],
+            extends: None,
+            user_data: None,
+            extra_boxes: Vec::new(),
+        };
+        config.apply(&mut file_type, &mut movie);
+        file_type.encode(&mut file)?;
+        movie.encode(&mut file)?;
+
+        let mut session = AppendSession::open(file)?;
+        if let Some(roll_distance) = roll_distance {
+            session.set_roll_distance(Self::TRACK_ID, roll_distance)?;
+        }
+        session.set_priming(Self::TRACK_ID, priming)?;
+        Ok(Self { session })
+    }
+
+    /// Sets the encoder delay in samples, which is hidden behind an edit list
+    /// on close, or None to write no edit list.
+    pub fn set_priming(&mut self, priming: Option<MediaTime>) -> Result<()> {
+        self.session.set_priming(Self::TRACK_ID, priming)
+    }
+
+    /// Sets an iTunes text tag, see [AppendSession::set_metadata_text].
+    pub fn set_text(&mut self, key: FourCC, value: &str) {
+        self.session.set_metadata_text(key, value);
+    }
+
+    /// See [AppendSession::set_cover_art].
+    pub fn set_cover_art(&mut self, image: Vec<u8>) -> Result<()> {
+        self.session.set_cover_art(image)
+    }
+
+    /// Appends an encoded frame, with its duration in samples.
+    pub fn append_sample(&mut self, data: &[u8], duration: u32) -> Result<()> {
+        self.session
+            .append_sample(Self::TRACK_ID, data, duration, true)
+    }
+
+    pub fn close(self) -> Result<(F, MuxSummary)> {
+        self.session.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::M4aWriter;
+    use crate::{
+        marshal::{
+            aac::{AACSampleEntry, AudioSpecificConfig},
+            Decode, File, FileTypeBox, FourCC, MetadataItem, MovieTime, SampleEntry,
+        },
+        mux::WriterConfig,
+    };
+
+    #[test]
+    fn priming_edit_list() {
+        let entry = AACSampleEntry::new(AudioSpecificConfig::AAC_LC, 48000, 2).unwrap();
+        let mut writer = M4aWriter::new(Cursor::new(Vec::new()), SampleEntry::AAC(entry)).unwrap();
+        for _ in 0..10 {
+            writer.append_sample(&[0; 8], 1024).unwrap();
+        }
+        let file = writer.close().unwrap().0.into_inner();
+
+        let movie = File::decode(&mut file.as_slice()).unwrap().movie.unwrap();
+        let track = &movie.tracks[0];
+        let edit_list = &track.edit.as_ref().unwrap().edit_list.as_ref().unwrap().0;
+        assert_eq!(edit_list.len(), 1);
+        assert_eq!(edit_list[0].media_time, AACSampleEntry::PRIMING as i64);
+        assert_eq!(edit_list[0].segment_duration, MovieTime(10 * 1024 - 2112));
+        assert_eq!(movie.header.duration, MovieTime(10 * 1024 - 2112));
+    }
+
+    #[test]
+    fn writer_config() {
+        let entry = AACSampleEntry::new(AudioSpecificConfig::AAC_LC, 48000, 2).unwrap();
+        let writer = M4aWriter::new(Cursor::new(Vec::new()), SampleEntry::AAC(entry)).unwrap();
+        let file = writer.close().unwrap().0.into_inner();
+        let file = File::decode(&mut file.as_slice()).unwrap();
+        assert_eq!(file.file_type.major_brand, FourCC::from(*b"M4A "));
+        let mut movie = file.movie.unwrap();
+        assert_eq!(movie.tracks[0].media.handler.name, "SoundHandler");
+        assert!(movie.item_list_mut().0.is_empty());
+
+        let entry = AACSampleEntry::new(AudioSpecificConfig::AAC_LC, 48000, 2).unwrap();
+        let config = WriterConfig {
+            file_type: Some(FileTypeBox {
+                major_brand: FourCC::from(*b"mp42"),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"mp42"), FourCC::from(*b"isom")],
+            }),
+            handler_names: vec![(FourCC::from(*b"soun"), "Core Media Audio".to_owned())],
+            encoder: Some("Lavf60.16.100".to_owned()),
+            ..Default::default()
+        };
+        let writer =
+            M4aWriter::with_config(Cursor::new(Vec::new()), SampleEntry::AAC(entry), config)
+                .unwrap();
+        let file = writer.close().unwrap().0.into_inner();
+        let file = File::decode(&mut file.as_slice()).unwrap();
+        assert_eq!(file.file_type.major_brand, FourCC::from(*b"mp42"));
+        let mut movie = file.movie.unwrap();
+        assert_eq!(movie.tracks[0].media.handler.name, "Core Media Audio");
+        let item_list = movie.item_list_mut();
+        assert_eq!(item_list.0.len(), 1);
+        assert_eq!(item_list.0[0].key, MetadataItem::ENCODER);
+    }
+}
@@ -1,21 +1,55 @@
 use std::{
     fmt::{Debug, Formatter},
-    io::{Read, Seek, SeekFrom, Write},
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    num::NonZero,
+    ops::Range,
     str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use bstringify::bstringify;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use derivative::Derivative;
-use fixed::types::{U16F16, U2F30, U8F8};
+// re-exported, so that the fixed types of the public fields don't depend on
+// the version of fixed used by the application
+pub use fixed::types::{U16F16, U2F30, U8F8};
 use fixed_macro::types::{U16F16, U2F30, U8F8};
 use thiserror::Error;
 
-use crate::marshal::{aac::AACSampleEntry, av1::AV1SampleEntry, avc::AVCSampleEntry};
+use crate::{
+    marshal::{
+        aac::AACSampleEntry,
+        av1::AV1SampleEntry,
+        avc::AVCSampleEntry,
+        bits::{BitReader, BitWriter},
+        cenc::{
+            CencSampleEncryptionInformation, ProtectionSystemSpecificHeaderBox, SampleEncryptionBox,
+        },
+        heif::{CodingConstraintsBox, ExifData, ItemPropertiesBox},
+        hevc::HEVCSampleEntry,
+        intermediate::IntermediateVideoSampleEntry,
+        loudness::LoudnessBox,
+        mp3::MP3SampleEntry,
+        pcm::{LPCMSampleEntry, PCMFormat, PCMSampleEntry},
+        timed_metadata::{TextMetaDataSampleEntry, URIMetaSampleEntry, XMLMetaDataSampleEntry},
+        uncv::{UncompressedVideoSampleEntry, V210SampleEntry},
+    },
+    registry::VISUAL_SAMPLE_ENTRY_BOXES,
+};
 
 pub mod aac;
 pub mod av1;
 pub mod avc;
+pub mod bits;
+pub mod cenc;
+pub mod esds;
+pub mod heif;
+pub mod hevc;
+pub mod intermediate;
+pub mod loudness;
+pub mod mp3;
+pub mod pcm;
+pub mod timed_metadata;
+pub mod uncv;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -28,6 +62,96 @@ pub enum Error {
         quantity: usize,
         expected: usize,
     },
+
+    #[error("Invalid {r#type:?} box size: {size}, remaining: {remaining}")]
+    InvalidBoxSize {
+        r#type: FourCC,
+        size: u64,
+        remaining: usize,
+    },
+
+    #[error("Invalid descriptor {tag} quantity: {quantity}, expected: {expected}")]
+    InvalidDescriptorQuantity {
+        tag: u8,
+        quantity: usize,
+        expected: usize,
+    },
+
+    #[error("Invalid descriptor {tag} size: {size}, remaining: {remaining}")]
+    InvalidDescriptorSize {
+        tag: u8,
+        size: usize,
+        remaining: usize,
+    },
+
+    #[error("Value out of range: {field}")]
+    ValueOutOfRange { field: &'static str },
+
+    #[error("Invalid track id: {track_id}")]
+    InvalidTrackId { track_id: u32 },
+
+    #[error("Duplicate track id: {track_id}")]
+    DuplicateTrackId { track_id: u32 },
+
+    #[error("Invalid next track id: {next_track_id}, expected greater than: {track_id}")]
+    InvalidNextTrackId { next_track_id: u32, track_id: u32 },
+
+    #[error("Invalid string")]
+    InvalidString,
+
+    #[error("{profile:?} profile violation: {rule}")]
+    ProfileViolation {
+        profile: Profile,
+        rule: &'static str,
+    },
+
+    #[error(
+        "Invalid track {track_id} dimensions: {width}x{height}, expected: {expected_width}x{expected_height}"
+    )]
+    InvalidTrackDimensions {
+        track_id: u32,
+        width: U16F16,
+        height: U16F16,
+        expected_width: u16,
+        expected_height: u16,
+    },
+
+    #[error("Sample data at {offset} was already passed, the moov box comes after it")]
+    MediaDataBeforeMovie { offset: u64 },
+
+    #[error("Invalid {r#type:?} box in {parent:?}")]
+    InvalidBoxParent { r#type: FourCC, parent: FourCC },
+
+    #[error("Invalid track {track_id} matrix, it doesn't map to a rectangle of the movie")]
+    InvalidTrackMatrix { track_id: u32 },
+
+    #[error("Tracks {track_id} and {other_track_id} overlap in the same layer {layer}")]
+    OverlappingTrackLayer {
+        track_id: u32,
+        other_track_id: u32,
+        layer: i16,
+    },
+
+    #[error("Samples of track {track_id} are stored outside of the file at {location:?}")]
+    ExternalDataReference { track_id: u32, location: String },
+
+    #[error(
+        "Tracks {track_id} and {other_track_id} of alternate group {alternate_group} have \
+         different media types: {handler:?}, {other_handler:?}"
+    )]
+    MixedAlternateGroup {
+        alternate_group: u16,
+        track_id: u32,
+        handler: FourCC,
+        other_track_id: u32,
+        other_handler: FourCC,
+    },
+
+    #[error("Alternate group {alternate_group} has {enabled} enabled tracks, expected: 1")]
+    InvalidEnabledTrackCount {
+        alternate_group: u16,
+        enabled: usize,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -118,6 +242,27 @@ impl Decode for u64 {
     }
 }
 
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        for value in self {
+            value.encode(output)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut values = Vec::with_capacity(N);
+        for _ in 0..N {
+            values.push(T::decode(input)?);
+        }
+        values
+            .try_into()
+            .map_err(|_| Error::ValueOutOfRange { field: "array" })
+    }
+}
+
 impl<T: Encode> Encode for Option<T> {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
         if let Some(value) = self {
@@ -137,18 +282,56 @@ impl Encode for String {
 
 impl Decode for String {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        let length = input.iter().position(|&c| c == 0).unwrap();
-        let (data, remaining_data) = input.split_at(length);
+        // strings at the end of a box are not always terminated, in which case
+        // the rest of the box is used
+        let (data, remaining_data) = match input.iter().position(|&c| c == 0) {
+            Some(length) => (&input[..length], &input[length + 1..]),
+            None if cfg!(feature = "strict") => return Err(Error::InvalidString),
+            None => (*input, &input[input.len()..]),
+        };
         *input = remaining_data;
-        Ok(String::from_utf8(data.to_owned()).unwrap())
+        if cfg!(feature = "strict") {
+            String::from_utf8(data.to_owned()).map_err(|_| Error::InvalidString)
+        } else {
+            Ok(String::from_utf8_lossy(data).into_owned())
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FourCC(pub(crate) u32);
+
+impl FourCC {
+    pub fn to_bytes(self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+}
+
+impl Encode for FourCC {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        self.0.encode(output)
+    }
+}
+
+impl Decode for FourCC {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Self(Decode::decode(input)?))
     }
 }
 
-pub struct FourCC(u32);
+impl From<[u8; 4]> for FourCC {
+    fn from(value: [u8; 4]) -> Self {
+        Self(u32::from_be_bytes(value))
+    }
+}
 
 impl Debug for FourCC {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str(std::str::from_utf8(&self.0.to_be_bytes()).unwrap())
+        // like \xA9nam of iTunes metadata, which isn't valid UTF-8
+        for byte in self.0.to_be_bytes() {
+            write!(f, "{}", byte.escape_ascii())?;
+        }
+        Ok(())
     }
 }
 
@@ -156,7 +339,259 @@ impl FromStr for FourCC {
     type Err = ();
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        Ok(Self(u32::from_be_bytes(s.as_bytes().try_into().unwrap())))
+        Ok(Self(u32::from_be_bytes(
+            s.as_bytes().try_into().map_err(|_| ())?,
+        )))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational<T> {
+    pub numerator: T,
+    pub denominator: T,
+}
+
+pub type Rational32 = Rational<u32>;
+pub type Rational64 = Rational<u64>;
+
+macro_rules! impl_rational {
+    ($type:ty) => {
+        impl Rational<$type> {
+            pub fn new(numerator: $type, denominator: $type) -> Self {
+                let mut a = numerator;
+                let mut b = denominator;
+                while b != 0 {
+                    (a, b) = (b, a % b);
+                }
+                let divisor = a.max(1);
+                Self {
+                    numerator: numerator / divisor,
+                    denominator: denominator / divisor,
+                }
+            }
+
+            pub fn to_f64(self) -> f64 {
+                self.numerator as f64 / self.denominator as f64
+            }
+        }
+    };
+}
+
+impl_rational!(u32);
+impl_rational!(u64);
+
+impl From<Rational32> for Rational64 {
+    fn from(value: Rational32) -> Self {
+        Self {
+            numerator: value.numerator as u64,
+            denominator: value.denominator as u64,
+        }
+    }
+}
+
+impl From<U8F8> for Rational32 {
+    fn from(value: U8F8) -> Self {
+        Self::new(value.to_bits() as u32, 1 << U8F8::FRAC_NBITS)
+    }
+}
+
+impl From<U16F16> for Rational32 {
+    fn from(value: U16F16) -> Self {
+        Self::new(value.to_bits(), 1 << U16F16::FRAC_NBITS)
+    }
+}
+
+impl From<Rational32> for U8F8 {
+    fn from(value: Rational32) -> Self {
+        Self::from_bits(fixed_bits(value, Self::FRAC_NBITS).min(u16::MAX as u64) as u16)
+    }
+}
+
+impl From<Rational32> for U16F16 {
+    fn from(value: Rational32) -> Self {
+        Self::from_bits(fixed_bits(value, Self::FRAC_NBITS).min(u32::MAX as u64) as u32)
+    }
+}
+
+fn fixed_bits(value: Rational32, frac_nbits: u32) -> u64 {
+    if value.denominator == 0 {
+        return u64::MAX;
+    }
+    let numerator = (value.numerator as u64) << frac_nbits;
+    let denominator = value.denominator as u64;
+    (numerator + denominator / 2) / denominator
+}
+
+/// Time in the timescale of the movie header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MovieTime(pub u64);
+
+/// Time in the timescale of a track's media header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaTime(pub u64);
+
+impl MovieTime {
+    pub fn to_media(self, movie_timescale: u32, media_timescale: u32) -> MediaTime {
+        if movie_timescale == 0 {
+            return MediaTime(0);
+        }
+        MediaTime(rescale(self.0, movie_timescale, media_timescale))
+    }
+}
+
+impl MediaTime {
+    pub fn to_movie(self, media_timescale: u32, movie_timescale: u32) -> MovieTime {
+        if media_timescale == 0 {
+            return MovieTime(0);
+        }
+        MovieTime(rescale(self.0, media_timescale, movie_timescale))
+    }
+}
+
+/// Returns the current time in seconds since midnight, Jan. 1, 1904, in UTC,
+/// as used for creation and modification times.
+pub fn current_time() -> u64 {
+    // 66 years, of which 17 are leap years
+    const OFFSET: u64 = (66 * 365 + 17) * 24 * 60 * 60;
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+        + OFFSET
+}
+
+impl Decode for MovieTime {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Self(Decode::decode(input)?))
+    }
+}
+
+impl Decode for MediaTime {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Self(Decode::decode(input)?))
+    }
+}
+
+/// Preferred playback rate of a movie, 1.0 is normal speed and higher values
+/// fast-forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PlaybackRate(pub U16F16);
+
+impl PlaybackRate {
+    pub const NORMAL: Self = Self(U16F16!(1));
+
+    /// Clamps to what can be stored, from 0.0 to just below 65536.0, NaN is
+    /// normal speed.
+    pub fn from_f32(rate: f32) -> Self {
+        if rate.is_nan() {
+            return Self::NORMAL;
+        }
+        Self(U16F16::saturating_from_num(rate))
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0.to_num()
+    }
+}
+
+impl Default for PlaybackRate {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+impl Encode for PlaybackRate {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        self.0.encode(output)
+    }
+}
+
+impl Decode for PlaybackRate {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Self(Decode::decode(input)?))
+    }
+}
+
+/// Audio volume of a movie or track, 1.0 is full and 0.0 is muted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Volume(pub U8F8);
+
+impl Volume {
+    pub const FULL: Self = Self(U8F8!(1));
+    pub const MUTED: Self = Self(U8F8!(0));
+
+    /// Clamps to what can be stored, from 0.0 to just below 256.0, NaN is
+    /// muted.
+    pub fn from_f32(volume: f32) -> Self {
+        if volume.is_nan() {
+            return Self::MUTED;
+        }
+        Self(U8F8::saturating_from_num(volume))
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0.to_num()
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl Encode for Volume {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        self.0.encode(output)
+    }
+}
+
+impl Decode for Volume {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Self(Decode::decode(input)?))
+    }
+}
+
+#[derive(Default)]
+pub struct Compressorname([u8; 32]);
+
+impl Compressorname {
+    pub fn as_str(&self) -> &str {
+        let length = (self.0[0] as usize).min(31);
+        std::str::from_utf8(&self.0[1..1 + length]).unwrap_or_default()
+    }
+}
+
+impl Debug for Compressorname {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl From<&str> for Compressorname {
+    fn from(value: &str) -> Self {
+        let mut length = value.len().min(31);
+        while !value.is_char_boundary(length) {
+            length -= 1;
+        }
+        let mut data = [0u8; 32];
+        data[0] = length as u8;
+        data[1..1 + length].copy_from_slice(&value.as_bytes()[..length]);
+        Self(data)
+    }
+}
+
+impl Encode for Compressorname {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        output.write_all(&self.0)?;
+        Ok(())
+    }
+}
+
+impl Decode for Compressorname {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut data = [0u8; 32];
+        input.read_exact(&mut data)?;
+        Ok(Self(data))
     }
 }
 
@@ -187,6 +622,81 @@ impl Matrix {
             w: U2F30!(1),
         }
     }
+
+    /// Creates a matrix from the values in the order a, b, u, c, d, v, x, y,
+    /// w, see [Matrix::to_f64].
+    pub fn from_f64(values: [f64; 9]) -> Result<Self> {
+        let [a, b, u, c, d, v, x, y, w] = values;
+        Ok(Self {
+            a: U16F16::from_bits(signed_fixed_bits::<i32>(a, 16, "matrix")? as u32),
+            b: U16F16::from_bits(signed_fixed_bits::<i32>(b, 16, "matrix")? as u32),
+            u: U2F30::from_bits(signed_fixed_bits::<i32>(u, 30, "matrix")? as u32),
+            c: U16F16::from_bits(signed_fixed_bits::<i32>(c, 16, "matrix")? as u32),
+            d: U16F16::from_bits(signed_fixed_bits::<i32>(d, 16, "matrix")? as u32),
+            v: U2F30::from_bits(signed_fixed_bits::<i32>(v, 30, "matrix")? as u32),
+            x: U16F16::from_bits(signed_fixed_bits::<i32>(x, 16, "matrix")? as u32),
+            y: U16F16::from_bits(signed_fixed_bits::<i32>(y, 16, "matrix")? as u32),
+            w: U2F30::from_bits(signed_fixed_bits::<i32>(w, 30, "matrix")? as u32),
+        })
+    }
+
+    /// Returns the values in the order a, b, u, c, d, v, x, y, w. Unlike the
+    /// types of the fields they are signed, e.g. a and d are -1.0 for a
+    /// rotation by 180°.
+    pub fn to_f64(&self) -> [f64; 9] {
+        let signed = |bits: u32, frac_nbits: u32| bits as i32 as f64 / (1u64 << frac_nbits) as f64;
+        [
+            signed(self.a.to_bits(), 16),
+            signed(self.b.to_bits(), 16),
+            signed(self.u.to_bits(), 30),
+            signed(self.c.to_bits(), 16),
+            signed(self.d.to_bits(), 16),
+            signed(self.v.to_bits(), 30),
+            signed(self.x.to_bits(), 16),
+            signed(self.y.to_bits(), 16),
+            signed(self.w.to_bits(), 30),
+        ]
+    }
+
+    /// Maps a point of the track to the movie, or None if it is at infinity.
+    pub fn transform(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let [a, b, u, c, d, v, tx, ty, w] = self.to_f64();
+        let z = u * x + v * y + w;
+        (z != 0.0).then(|| ((a * x + c * y + tx) / z, (b * x + d * y + ty) / z))
+    }
+}
+
+/// Rectangle in the movie, see [TrackHeaderBox::placement].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Placement {
+    fn overlaps(&self, other: &Self) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+}
+
+/// Converts to the bits of a signed fixed-point number, like the values of the
+/// matrix, which are stored in unsigned types.
+fn signed_fixed_bits<T: TryFrom<i64>>(
+    value: f64,
+    frac_nbits: u32,
+    field: &'static str,
+) -> Result<T> {
+    let bits = (value * (1u64 << frac_nbits) as f64).round();
+    // also false for NaN
+    if !(bits >= i64::MIN as f64 && bits < i64::MAX as f64) {
+        return Err(Error::ValueOutOfRange { field });
+    }
+    checked_cast(bits as i64, field)
 }
 
 impl Encode for Matrix {
@@ -223,66 +733,224 @@ impl Decode for Matrix {
 // ISO/IEC 14496-12:2008
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
+fn rescale(value: u64, timescale: u32, new_timescale: u32) -> u64 {
+    let timescale = timescale as u128;
+    ((value as u128 * new_timescale as u128 + timescale / 2) / timescale) as u64
+}
+
+fn rescale_signed(value: i64, timescale: u32, new_timescale: u32) -> i64 {
+    let timescale = timescale as i128;
+    (value as i128 * new_timescale as i128 + timescale / 2).div_euclid(timescale) as i64
+}
+
+pub(crate) fn checked_cast<T>(value: impl TryInto<T>, field: &'static str) -> Result<T> {
+    value
+        .try_into()
+        .map_err(|_| Error::ValueOutOfRange { field })
+}
+
 pub(crate) fn encode_box_header(output: &mut (impl Write + Seek), r#type: [u8; 4]) -> Result<u64> {
     let begin = output.stream_position()?;
+    #[cfg(feature = "tracing")]
+    tracing::trace!(r#type = ?FourCC(u32::from_be_bytes(r#type)), offset = begin, "encode");
     0u32.encode(output)?; // size
     output.write_all(&r#type)?;
     Ok(begin)
 }
 
 pub(crate) fn update_box_header(output: &mut (impl Write + Seek), begin: u64) -> Result<()> {
-    let end = output.stream_position()?;
-    let size = end - begin;
-    output.seek(SeekFrom::Start(begin))?;
-    (size as u32).encode(output)?;
-    output.seek(SeekFrom::Start(end))?;
-    Ok(())
+    let size = output.stream_position()? - begin;
+    #[cfg(feature = "tracing")]
+    tracing::trace!(offset = begin, size, "encoded");
+    Patch::u32_at(begin).apply(output, checked_cast::<u32>(size, "size")? as u64)
+}
+
+/// Value at a position in the output, which is only known once the following
+/// data is written, like the size of a box.
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct Patch {
+    position: u64,
+    large: bool,
+}
+
+impl Patch {
+    pub fn u32_at(position: u64) -> Self {
+        Self {
+            position,
+            large: false,
+        }
+    }
+
+    pub fn u64_at(position: u64) -> Self {
+        Self {
+            position,
+            large: true,
+        }
+    }
+
+    /// Writes a placeholder at the current position.
+    pub fn encode(output: &mut (impl Write + Seek), large: bool) -> Result<Self> {
+        let position = output.stream_position()?;
+        if large {
+            0u64.encode(output)?;
+        } else {
+            0u32.encode(output)?;
+        }
+        Ok(Self { position, large })
+    }
+
+    /// Writes the value, and returns to the current position.
+    pub fn apply(self, output: &mut (impl Write + Seek), value: u64) -> Result<()> {
+        let end = output.stream_position()?;
+        output.seek(SeekFrom::Start(self.position))?;
+        if self.large {
+            value.encode(output)?;
+        } else {
+            checked_cast::<u32>(value, "patch")?.encode(output)?;
+        }
+        output.seek(SeekFrom::Start(end))?;
+        Ok(())
+    }
+}
+
+/// Placeholder of an offset which is only known once the layout is final,
+/// patched like the size of a box by update_box_header.
+#[derive(Debug)]
+#[must_use]
+pub struct DeferredOffset {
+    patch: Patch,
+    base: u64,
+}
+
+impl DeferredOffset {
+    /// Writes the placeholder, for an offset relative to `base`.
+    pub(crate) fn encode(output: &mut (impl Write + Seek), large: bool, base: u64) -> Result<Self> {
+        Ok(Self {
+            patch: Patch::encode(output, large)?,
+            base,
+        })
+    }
+
+    /// Patches the placeholder to point to `target`, the absolute position in
+    /// the output.
+    pub fn update(self, output: &mut (impl Write + Seek), target: u64) -> Result<()> {
+        let offset = self.resolve(target)?;
+        self.patch.apply(output, offset)
+    }
+
+    fn resolve(&self, target: u64) -> Result<u64> {
+        let offset = target
+            .checked_sub(self.base)
+            .ok_or(Error::ValueOutOfRange { field: "offset" })?;
+        if !self.patch.large {
+            checked_cast::<u32>(offset, "offset")?;
+        }
+        Ok(offset)
+    }
+}
+
+pub(crate) fn decode_box_header<'a>(input: &mut &'a [u8]) -> Result<([u8; 4], &'a [u8])> {
+    let size = u32::decode(input)?;
+    let r#type: [u8; 4] = u32::decode(input)?.to_be_bytes();
+    let length = match size {
+        0 => Some(input.len() as u64),
+        1 => u64::decode(input)?.checked_sub(4 + 4 + 8),
+        _ => (size as u64).checked_sub(4 + 4),
+    };
+    let length = length
+        .filter(|&length| length <= input.len() as u64)
+        .ok_or(Error::InvalidBoxSize {
+            r#type: FourCC(u32::from_be_bytes(r#type)),
+            size: size as u64,
+            remaining: input.len(),
+        })?;
+
+    let (data, remaining_data) = input.split_at(length as usize);
+    *input = remaining_data;
+    Ok((r#type, data))
 }
 
 macro_rules! decode_boxes {(
     $input:ident,
+    unknown $unknown:ident,
     $($quantifier:ident $type:ident $name:ident),* $(,)?
 ) => (
-     while !$input.is_empty() {
-        let size = u32::decode($input)?;
-        let r#type: [u8; 4] = u32::decode($input)?.to_be_bytes();
+    let mut boxes = Vec::new();
+    while !$input.is_empty() {
+        boxes.push($crate::marshal::decode_box_header($input)?);
+    }
 
-        let (mut data, remaining_data) = $input.split_at((size - 4 - 4) as usize);
-        match &r#type {
-            $(bstringify!($type) => decode_box!(data $quantifier $type $name),)*
-            _ => {}
+    $(for (r#type, data) in &boxes {
+        if r#type == ::bstringify::bstringify!($type) {
+            let mut data = *data;
+            $crate::marshal::decode_box!(data $quantifier $type $name);
         }
-        *$input = remaining_data;
+    })*
+
+    for (r#type, data) in &boxes {
+        if $(r#type != ::bstringify::bstringify!($type))&&* {
+            $unknown.push($crate::marshal::RawBox {
+                r#type: $crate::marshal::FourCC::from(*r#type),
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    $($crate::marshal::unwrap_box!($quantifier $type $name);)*
+);
+(
+    $input:ident,
+    $($quantifier:ident $type:ident $name:ident),* $(,)?
+) => (
+    let mut boxes = Vec::new();
+    while !$input.is_empty() {
+        boxes.push($crate::marshal::decode_box_header($input)?);
     }
 
-    $(unwrap_box!($quantifier $type $name);)*
+    $(for (r#type, data) in &boxes {
+        if r#type == ::bstringify::bstringify!($type) {
+            let mut data = *data;
+            $crate::marshal::decode_box!(data $quantifier $type $name);
+        }
+    })*
+
+    $($crate::marshal::unwrap_box!($quantifier $type $name);)*
 )}
 
 macro_rules! decode_box {
-    ($input:ident optional $type:ident $name:ident) => {{
+    ($input:ident $quantifier:ident $type:ident $name:ident) => {{
+        #[cfg(feature = "tracing")]
+        let _span =
+            ::tracing::debug_span!("decode", r#type = stringify!($type), size = $input.len())
+                .entered();
+        $crate::marshal::decode_box!(@ $input $quantifier $type $name);
+    }};
+
+    (@ $input:ident optional $type:ident $name:ident) => {{
         if $name.is_some() {
-            return Err(Error::InvalidBoxQuantity {
+            return Err($crate::marshal::Error::InvalidBoxQuantity {
                 r#type: stringify!($type),
                 quantity: 2,
                 expected: 1,
             });
         }
-        $name = Some(Decode::decode(&mut $input)?);
+        $name = Some($crate::marshal::Decode::decode(&mut $input)?);
     }};
 
-    ($input:ident required $type:ident $name:ident) => {{
+    (@ $input:ident required $type:ident $name:ident) => {{
         if $name.is_some() {
-            return Err(Error::InvalidBoxQuantity {
+            return Err($crate::marshal::Error::InvalidBoxQuantity {
                 r#type: stringify!($type),
                 quantity: 2,
                 expected: 1,
             });
         }
-        $name = Some(Decode::decode(&mut $input)?);
+        $name = Some($crate::marshal::Decode::decode(&mut $input)?);
     }};
 
-    ($input:ident multiple $type:ident $name:ident) => {
-        $name.push(Decode::decode(&mut $input)?)
+    (@ $input:ident multiple $type:ident $name:ident) => {
+        $name.push($crate::marshal::Decode::decode(&mut $input)?)
     };
 }
 
@@ -290,7 +958,7 @@ macro_rules! unwrap_box {
     (optional $type:ident $name:ident) => {};
 
     (required $type:ident $name:ident) => {
-        let $name = $name.ok_or(Error::InvalidBoxQuantity {
+        let $name = $name.ok_or($crate::marshal::Error::InvalidBoxQuantity {
             r#type: stringify!($type),
             quantity: 0,
             expected: 1,
@@ -300,14 +968,276 @@ macro_rules! unwrap_box {
     (multiple $type:ident $name:ident) => {};
 }
 
-#[derive(Debug)]
-pub struct File {
-    pub file_type: FileTypeBox,
-    pub movie: Option<MovieBox>,
-    pub media_data: Vec<MediaDataBox>,
-    pub meta: Option<MetaBox>,
-}
-
+pub(crate) use decode_box;
+pub(crate) use decode_boxes;
+pub(crate) use unwrap_box;
+
+/// Declares a box which is only a sequence of fields, and implements Encode
+/// and Decode for it.
+///
+/// The kind is either `box` or `full_box`, the latter with an optional
+/// `flags` value. Each field ends with a comma and is one of:
+/// - `name: Type`
+/// - `versioned name: Type`, 32 bits in version 0 and 64 bits in version 1,
+///   which is only used if one of them doesn't fit into 32 bits
+/// - `reserved Type = value`, which is skipped when decoding
+macro_rules! define_box {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident: $kind:ident $type:literal $(, flags $flags:literal)? {
+            $($fields:tt)*
+        }
+    ) => (
+        $crate::marshal::define_box!(@munch
+            [$(#[$meta])* $vis struct $name: $kind $type, [$($flags)?]]
+            [] [] [] [];
+            $($fields)*
+        );
+    );
+
+    (@munch $header:tt [$($struct:tt)*] [$($entries:tt)*] [$($names:ident)*] [$($versioned:ident)*];
+        reserved $type:ty = $value:expr, $($rest:tt)*
+    ) => (
+        $crate::marshal::define_box!(@munch
+            $header
+            [$($struct)*]
+            [$($entries)* (reserved $type, $value)]
+            [$($names)*]
+            [$($versioned)*];
+            $($rest)*
+        );
+    );
+
+    (@munch $header:tt [$($struct:tt)*] [$($entries:tt)*] [$($names:ident)*] [$($versioned:ident)*];
+        versioned $(#[$field_meta:meta])* $field_vis:vis $field:ident: $type:ty, $($rest:tt)*
+    ) => (
+        $crate::marshal::define_box!(@munch
+            $header
+            [$($struct)* $(#[$field_meta])* $field_vis $field: $type,]
+            [$($entries)* (versioned $field)]
+            [$($names)* $field]
+            [$($versioned)* $field];
+            $($rest)*
+        );
+    );
+
+    (@munch $header:tt [$($struct:tt)*] [$($entries:tt)*] [$($names:ident)*] [$($versioned:ident)*];
+        $(#[$field_meta:meta])* $field_vis:vis $field:ident: $type:ty, $($rest:tt)*
+    ) => (
+        $crate::marshal::define_box!(@munch
+            $header
+            [$($struct)* $(#[$field_meta])* $field_vis $field: $type,]
+            [$($entries)* (plain $field)]
+            [$($names)* $field]
+            [$($versioned)*];
+            $($rest)*
+        );
+    );
+
+    (@munch
+        [$(#[$meta:meta])* $vis:vis struct $name:ident: $kind:ident $type:literal, [$($flags:literal)?]]
+        [$($struct:tt)*] [$($entries:tt)*] [$($names:ident)*] [$($versioned:ident)*];
+    ) => (
+        $(#[$meta])*
+        $vis struct $name {
+            $($struct)*
+        }
+
+        impl $crate::marshal::Encode for $name {
+            fn encode(
+                &self,
+                output: &mut (impl ::std::io::Write + ::std::io::Seek),
+            ) -> $crate::marshal::Result<()> {
+                let begin = $crate::marshal::encode_box_header(output, *$type)?;
+                #[allow(unused_variables)]
+                let version = $crate::marshal::define_box!(
+                    @encode_header $kind self output [$($flags)?] [$($versioned)*]
+                );
+
+                $($crate::marshal::define_box!(@encode self output version $entries);)*
+
+                $crate::marshal::update_box_header(output, begin)
+            }
+        }
+
+        impl $crate::marshal::Decode for $name {
+            fn decode(input: &mut &[u8]) -> $crate::marshal::Result<Self> {
+                #[allow(unused_variables)]
+                let version = $crate::marshal::define_box!(@decode_header $kind input [$($versioned)*]);
+
+                $($crate::marshal::define_box!(@decode input version $entries);)*
+                Ok(Self { $($names),* })
+            }
+        }
+    );
+
+    (@encode_header box $self:ident $output:ident [] []) => (0);
+
+    (@encode_header full_box $self:ident $output:ident [$($flags:literal)?] [$($versioned:ident)*]) => {{
+        let version: u8 = if false $(|| $crate::marshal::VersionedField::is_large(&$self.$versioned))* {
+            1
+        } else {
+            0
+        };
+        ::byteorder::WriteBytesExt::write_u8($output, version)?;
+        ::byteorder::WriteBytesExt::write_u24::<::byteorder::BigEndian>($output, 0 $(| $flags)?)?; // flags
+        version
+    }};
+
+    (@decode_header box $input:ident []) => (0);
+
+    (@decode_header full_box $input:ident [$($versioned:ident)*]) => {{
+        let version = ::byteorder::ReadBytesExt::read_u8($input)?;
+        ::byteorder::ReadBytesExt::read_u24::<::byteorder::BigEndian>($input)?; // flags
+        if version > if <[&str]>::is_empty(&[$(stringify!($versioned)),*]) { 0 } else { 1 } {
+            return Err($crate::marshal::Error::ValueOutOfRange { field: "version" });
+        }
+        version
+    }};
+
+    (@encode $self:ident $output:ident $version:ident (plain $field:ident)) => (
+        $crate::marshal::Encode::encode(&$self.$field, $output)?
+    );
+
+    (@encode $self:ident $output:ident $version:ident (versioned $field:ident)) => (
+        $crate::marshal::VersionedField::encode_versioned(&$self.$field, $output, $version)?
+    );
+
+    (@encode $self:ident $output:ident $version:ident (reserved $type:ty, $value:expr)) => (
+        $crate::marshal::Encode::encode(&{ let value: $type = $value; value }, $output)?
+    );
+
+    (@decode $input:ident $version:ident (plain $field:ident)) => (
+        let $field = $crate::marshal::Decode::decode($input)?;
+    );
+
+    (@decode $input:ident $version:ident (versioned $field:ident)) => (
+        let $field = $crate::marshal::VersionedField::decode_versioned($input, $version)?;
+    );
+
+    (@decode $input:ident $version:ident (reserved $type:ty, $value:expr)) => (
+        <$type as $crate::marshal::Decode>::decode($input)?;
+    );
+}
+
+pub(crate) use define_box;
+
+/// Field which is 32 bits in version 0 and 64 bits in version 1 of a box.
+pub(crate) trait VersionedField: Sized {
+    fn is_large(&self) -> bool;
+
+    fn encode_versioned(&self, output: &mut (impl Write + Seek), version: u8) -> Result<()>;
+
+    fn decode_versioned(input: &mut &[u8], version: u8) -> Result<Self>;
+}
+
+macro_rules! impl_versioned_field {
+    ($type:ty, $value:ident => $bits:expr, $bits_ident:ident => $construct:expr) => {
+        impl VersionedField for $type {
+            fn is_large(&self) -> bool {
+                let $value = self;
+                $bits > u32::MAX as u64
+            }
+
+            fn encode_versioned(
+                &self,
+                output: &mut (impl Write + Seek),
+                version: u8,
+            ) -> Result<()> {
+                let $value = self;
+                match version {
+                    0 => checked_cast::<u32>($bits, "version")?.encode(output),
+                    _ => $bits.encode(output),
+                }
+            }
+
+            fn decode_versioned(input: &mut &[u8], version: u8) -> Result<Self> {
+                let $bits_ident = match version {
+                    0 => u32::decode(input)? as u64,
+                    _ => u64::decode(input)?,
+                };
+                Ok($construct)
+            }
+        }
+    };
+}
+
+impl_versioned_field!(u64, value => *value, bits => bits);
+impl_versioned_field!(MovieTime, value => value.0, bits => MovieTime(bits));
+impl_versioned_field!(MediaTime, value => value.0, bits => MediaTime(bits));
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct RawBox {
+    pub r#type: FourCC,
+    #[derivative(Debug = "ignore")]
+    pub data: Vec<u8>,
+}
+
+impl Encode for RawBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, self.r#type.0.to_be_bytes())?;
+
+        output.write_all(&self.data)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+pub struct Boxes<'a> {
+    input: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> Boxes<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            failed: false,
+        }
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        self.input
+    }
+}
+
+impl Iterator for Boxes<'_> {
+    type Item = Result<RawBox>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.input.is_empty() {
+            return None;
+        }
+
+        let mut input = self.input;
+        match decode_box_header(&mut input) {
+            Ok((r#type, data)) => {
+                self.input = input;
+                Some(Ok(RawBox {
+                    r#type: FourCC(u32::from_be_bytes(r#type)),
+                    data: data.to_owned(),
+                }))
+            }
+            Err(error) => {
+                self.failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct File {
+    pub file_type: FileTypeBox,
+    pub movie: Option<MovieBox>,
+    pub media_data: Vec<MediaDataBox>,
+    pub meta: Option<MetaBox>,
+    /// top-level boxes of other types, like uuid boxes of camera telemetry,
+    /// which are encoded after the known ones, without free and skip
+    pub extra_boxes: Vec<RawBox>,
+}
+
 impl Encode for File {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
         self.file_type.encode(output)?;
@@ -315,7 +1245,234 @@ impl Encode for File {
         for media_data in &self.media_data {
             media_data.encode(output)?;
         }
-        self.meta.encode(output)
+        self.meta.encode(output)?;
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Brand-specific constraints on top of the base format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// CMAF track file, ISO/IEC 23000-19 7.3, the header followed by the
+    /// fragments of a single track
+    Cmaf,
+    /// CMAF header alone, without fragments, as delivered in the
+    /// initialization segment of a stream
+    CmafHeader,
+    /// AVIF baseline profile
+    Avif,
+    /// Progressive MP4 as played by AVFoundation
+    IosProgressive,
+}
+
+impl File {
+    /// Validates the movie, and the rules of the given profile.
+    pub fn validate_with(&self, profile: Profile) -> Result<()> {
+        let check = |valid: bool, rule| {
+            if valid {
+                Ok(())
+            } else {
+                Err(Error::ProfileViolation { profile, rule })
+            }
+        };
+        let brand = |brand: &[u8; 4]| self.file_type.is_compatible(FourCC::from(*brand));
+
+        if let Some(movie) = &self.movie {
+            movie.validate()?;
+        }
+        match profile {
+            Profile::Cmaf | Profile::CmafHeader => {
+                check(brand(b"cmfc"), "cmfc brand")?;
+                let movie = self.movie.as_ref();
+                check(
+                    movie.is_some_and(|movie| movie.tracks.len() == 1),
+                    "single track",
+                )?;
+                let movie = movie.unwrap();
+                check(movie.extends.is_some(), "mvex")?;
+                let track = &movie.tracks[0];
+                check(
+                    matches!(
+                        &track.media.handler.r#type.to_bytes(),
+                        b"vide" | b"soun" | b"text" | b"subt"
+                    ),
+                    "handler type",
+                )?;
+                check(
+                    track
+                        .media
+                        .information
+                        .sample_table
+                        .sample_size
+                        .sample_count()
+                        == 0,
+                    "no samples in moov",
+                )?;
+
+                let fragments = self
+                    .extra_boxes
+                    .iter()
+                    .filter(|raw_box| &raw_box.r#type.to_bytes() == b"moof")
+                    .map(|raw_box| MovieFragmentBox::decode(&mut raw_box.data.as_slice()))
+                    .collect::<Result<Vec<_>>>()?;
+                if profile == Profile::CmafHeader {
+                    check(
+                        fragments.is_empty() && self.media_data.is_empty(),
+                        "no fragments in header",
+                    )?;
+                    return Ok(());
+                }
+
+                // every moof is followed by its mdat, as both are kept in file
+                // order they pair up by index
+                check(
+                    !fragments.is_empty() && fragments.len() == self.media_data.len(),
+                    "moof and mdat pairs",
+                )?;
+                for fragment in &fragments {
+                    check(fragment.track_fragments.len() == 1, "single traf")?;
+                    let track_fragment = &fragment.track_fragments[0];
+                    check(
+                        track_fragment.header.track_id == track.header.track_id,
+                        "traf of the track",
+                    )?;
+                    check(track_fragment.decode_time.is_some(), "tfdt")?;
+                }
+            }
+            Profile::Avif => {
+                check(
+                    brand(b"avif") && brand(b"mif1") && brand(b"miaf"),
+                    "avif, mif1 and miaf brands",
+                )?;
+                let meta = self.meta.as_ref();
+                check(
+                    meta.is_some_and(|meta| &meta.handler.r#type.to_bytes() == b"pict"),
+                    "pict handler",
+                )?;
+                let meta = meta.unwrap();
+                check(
+                    meta.item_info.as_ref().is_some_and(|item_info| {
+                        item_info
+                            .0
+                            .iter()
+                            .any(|item| &item.item_type.to_bytes() == b"av01")
+                    }),
+                    "av01 item",
+                )?;
+                check(meta.item_location.is_some(), "iloc")?;
+                check(meta.item_properties.is_some(), "iprp")?;
+            }
+            Profile::IosProgressive => {
+                check(
+                    [b"isom", b"mp41", b"mp42", b"M4V ", b"M4A ", b"qt  "]
+                        .into_iter()
+                        .any(brand),
+                    "isom, mp41, mp42, M4V, M4A or qt brand",
+                )?;
+                let movie = self.movie.as_ref();
+                check(movie.is_some(), "moov")?;
+                let movie = movie.unwrap();
+                check(movie.extends.is_none(), "not fragmented")?;
+                for track in &movie.tracks {
+                    // avc3 and hev1 are not played, only avc1 and hvc1
+                    check(
+                        track
+                            .media
+                            .information
+                            .sample_table
+                            .description
+                            .0
+                            .iter()
+                            .all(|entry| match entry {
+                                SampleEntry::AVC(entry) => !entry.has_in_band_parameter_sets(),
+                                SampleEntry::HEVC(entry) => !entry.has_in_band_parameter_sets(),
+                                SampleEntry::AAC(_)
+                                | SampleEntry::MP3(_)
+                                | SampleEntry::PCM(_)
+                                | SampleEntry::LPCM(_) => true,
+                                _ => false,
+                            }),
+                        "avc1, hvc1, mp4a, .mp3 or PCM sample entries",
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the MIME type with the codecs parameter of RFC 6381, like
+    /// `video/mp4; codecs="avc1.64001F, mp4a.40.2"`, as needed for DASH and
+    /// HLS manifests.
+    pub fn mime_type(&self) -> Result<String> {
+        // AVIF registers image/avif for both images and sequences, the latter
+        // with the avis brand
+        let avif = self.file_type.is_compatible(FourCC::from(*b"avif"))
+            || self.file_type.is_compatible(FourCC::from(*b"avis"));
+        let Some(movie) = &self.movie else {
+            return Ok(match (&self.meta, avif) {
+                (Some(_), true) => "image/avif",
+                (Some(_), false) => "image/heif",
+                (None, _) => "application/mp4",
+            }
+            .to_owned());
+        };
+
+        let kinds: Vec<_> = movie.tracks.iter().map(TrackBox::kind).collect();
+        let mime_type = if kinds.contains(&TrackKind::Video) {
+            "video/mp4"
+        } else if kinds.contains(&TrackKind::Audio) {
+            "audio/mp4"
+        } else if kinds.contains(&TrackKind::Image) {
+            if avif {
+                "image/avif"
+            } else {
+                "image/heif-sequence"
+            }
+        } else {
+            "application/mp4"
+        };
+
+        let mut codecs = Vec::new();
+        for track in &movie.tracks {
+            for entry in &track.media.information.sample_table.description.0 {
+                let codec = entry.codec_string()?;
+                if !codecs.contains(&codec) {
+                    codecs.push(codec);
+                }
+            }
+        }
+        Ok(if codecs.is_empty() {
+            mime_type.to_owned()
+        } else {
+            format!("{mime_type}; codecs=\"{}\"", codecs.join(", "))
+        })
+    }
+
+    /// Returns where the payload of each mdat starts once the file is encoded,
+    /// which chunk offsets and item locations are relative to.
+    pub fn media_data_offsets(&self) -> Result<Vec<u64>> {
+        let mut output = Cursor::new(Vec::new());
+        self.file_type.encode(&mut output)?;
+        self.movie.encode(&mut output)?;
+        let mut offset = output.position();
+        Ok(self
+            .media_data
+            .iter()
+            .map(|media_data| {
+                let header_size = if u32::try_from(4 + 4 + media_data.0.len()).is_ok() {
+                    4 + 4
+                } else {
+                    4 + 4 + 8
+                };
+                let data_offset = offset + header_size;
+                offset = data_offset + media_data.0.len() as u64;
+                data_offset
+            })
+            .collect())
     }
 }
 
@@ -326,34 +1483,145 @@ impl Decode for File {
         let mut media_data = Vec::new();
         let mut meta = None;
 
+        let mut extra_boxes = Vec::new();
+
         decode_boxes! {
             input,
+            unknown extra_boxes,
             required ftyp file_type,
             optional moov movie,
             multiple mdat media_data,
             optional meta meta,
         }
+        retain_content(&mut extra_boxes);
+
+        Ok(Self {
+            file_type,
+            media_data,
+            movie,
+            meta,
+            extra_boxes,
+        })
+    }
+}
+
+impl File {
+    /// Decodes a file of any dialect, like a QuickTime movie without ftyp,
+    /// and undoes the differences of it, see [MovieBox::normalize].
+    pub fn decode_with(input: &mut &[u8], options: &DecodeOptions) -> Result<Self> {
+        let mut file_type = None;
+        let mut movie: Option<MovieBox> = None;
+        let mut media_data = Vec::new();
+        let mut meta = None;
+
+        let mut extra_boxes = Vec::new();
 
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            optional ftyp file_type,
+            optional moov movie,
+            multiple mdat media_data,
+            optional meta meta,
+        }
+        retain_content(&mut extra_boxes);
+
+        let dialect = options.dialect(file_type.as_ref());
+        let file_type = match file_type {
+            Some(file_type) => file_type,
+            None if dialect == Dialect::QuickTime => FileTypeBox {
+                major_brand: FourCC::from(*b"qt  "),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"qt  ")],
+            },
+            None => {
+                return Err(Error::InvalidBoxQuantity {
+                    r#type: "ftyp",
+                    quantity: 0,
+                    expected: 1,
+                })
+            }
+        };
+        if let Some(movie) = &mut movie {
+            movie.normalize(dialect);
+        }
         Ok(Self {
             file_type,
             media_data,
             movie,
             meta,
+            extra_boxes,
         })
     }
 }
 
+/// Drops free and skip boxes, which are only padding, like the space of a moov
+/// that was moved.
+fn retain_content(boxes: &mut Vec<RawBox>) {
+    boxes.retain(|raw_box| !matches!(&raw_box.r#type.to_bytes(), b"free" | b"skip"));
+}
+
+/// Variant of the format, which differs in details that can't be told apart
+/// from the boxes alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Iso,
+    /// QuickTime movies, with counted instead of terminated strings, and
+    /// without ftyp if written before it existed
+    QuickTime,
+    /// 3GPP and 3GPP2, with the metadata in asset boxes of the udta
+    ThirdGeneration,
+}
+
+impl Dialect {
+    /// Detects the dialect from the major brand, as 3GPP files are often
+    /// also compatible to isom, or a QuickTime movie if there is no ftyp.
+    pub fn detect(file_type: Option<&FileTypeBox>) -> Self {
+        let Some(file_type) = file_type else {
+            return Self::QuickTime;
+        };
+        match &file_type.major_brand.to_bytes() {
+            b"qt  " => Self::QuickTime,
+            [b'3', b'g', ..] => Self::ThirdGeneration,
+            _ => Self::Iso,
+        }
+    }
+}
+
+/// Options of [File::decode_with].
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// detected from the ftyp if not set
+    pub dialect: Option<Dialect>,
+}
+
+impl DecodeOptions {
+    pub fn dialect(&self, file_type: Option<&FileTypeBox>) -> Dialect {
+        self.dialect.unwrap_or_else(|| Dialect::detect(file_type))
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // ISO/IEC 14496-12:2008 4.3
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileTypeBox {
     pub major_brand: FourCC,
     pub minor_version: u32,
     pub compatible_brands: Vec<FourCC>,
 }
 
+impl FileTypeBox {
+    /// HEIF image sequences.
+    pub const IMAGE_SEQUENCE: FourCC = FourCC(u32::from_be_bytes(*b"msf1"));
+
+    pub fn is_compatible(&self, brand: FourCC) -> bool {
+        self.major_brand == brand || self.compatible_brands.contains(&brand)
+    }
+}
+
 impl Encode for FileTypeBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
         let begin = encode_box_header(output, *b"ftyp")?;
@@ -372,9 +1640,10 @@ impl Decode for FileTypeBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
         let major_brand = FourCC(Decode::decode(input)?);
         let minor_version = Decode::decode(input)?;
+        // a trailing partial brand is ignored
         let compatible_brands = input
-            .chunks(4)
-            .map(|chunk| FourCC(u32::from_be_bytes(chunk.try_into().unwrap())))
+            .chunks_exact(4)
+            .map(|chunk| FourCC(u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])))
             .collect();
         *input = &input[input.len()..];
         Ok(Self {
@@ -395,11 +1664,18 @@ pub struct MediaDataBox(#[derivative(Debug = "ignore")] pub Vec<u8>);
 
 impl Encode for MediaDataBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"mdat")?;
+        let size = 4 + 4 + self.0.len() as u64;
+        if let Ok(size) = u32::try_from(size) {
+            size.encode(output)?;
+            output.write_all(b"mdat")?;
+        } else {
+            1u32.encode(output)?; // size
+            output.write_all(b"mdat")?;
+            (size + 8).encode(output)?; // largesize
+        }
 
         output.write_all(&self.0)?;
-
-        update_box_header(output, begin)
+        Ok(())
     }
 }
 
@@ -419,18 +1695,21 @@ impl Decode for MediaDataBox {
 pub struct MovieBox {
     pub header: MovieHeaderBox,
     pub tracks: Vec<TrackBox>,
+    pub extends: Option<MovieExtendsBox>,
+    pub user_data: Option<UserDataBox>,
+    /// boxes of other types, like proprietary ones, which are encoded after
+    /// the known ones
+    pub extra_boxes: Vec<RawBox>,
 }
 
 impl Encode for MovieBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"moov")?;
-
-        self.header.encode(output)?;
-        for track in &self.tracks {
-            track.encode(output)?;
-        }
-
-        update_box_header(output, begin)
+        self.encode_with(output, |output| {
+            for track in &self.tracks {
+                track.encode(output)?;
+            }
+            Ok(())
+        })
     }
 }
 
@@ -438,135 +1717,663 @@ impl Decode for MovieBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
         let mut header = None;
         let mut tracks = Vec::new();
+        let mut extends = None;
+        let mut user_data = None;
+
+        let mut extra_boxes = Vec::new();
 
         decode_boxes! {
             input,
+            unknown extra_boxes,
             required mvhd header,
             multiple trak tracks,
+            optional mvex extends,
+            optional udta user_data,
         }
 
-        Ok(Self { header, tracks })
+        Ok(Self {
+            header,
+            tracks,
+            extends,
+            user_data,
+            extra_boxes,
+        })
     }
 }
 
-////////////////////////////////////////////////////////////////////////////////////////////////////
-// ISO/IEC 14496-12:2008 8.2.2
-////////////////////////////////////////////////////////////////////////////////////////////////////
-
-#[derive(Debug)]
-pub struct MovieHeaderBox {
-    pub creation_time: u64,
-    pub modification_time: u64,
-    pub timescale: u32,
-    pub duration: u64,
-    pub rate: U16F16,
-    pub volume: U8F8,
-    pub matrix: Matrix,
-    pub next_track_id: u32,
-}
-
-impl Default for MovieHeaderBox {
-    fn default() -> Self {
-        Self {
-            creation_time: 0,
-            modification_time: 0,
-            timescale: 0,
-            duration: 0,
-            rate: U16F16!(1),
-            volume: U8F8!(1),
-            matrix: Matrix::identity(),
-            next_track_id: 0,
+impl MovieBox {
+    /// Undoes the differences of a dialect, so that the movie can be handled
+    /// like any other, and written as an ISO file.
+    ///
+    /// The counted handler names of QuickTime are converted into strings, and
+    /// the title, performer, album, description, genre and copyright of 3GPP
+    /// are added to the iTunes metadata, unless already present.
+    pub fn normalize(&mut self, dialect: Dialect) {
+        match dialect {
+            Dialect::Iso => {}
+            Dialect::QuickTime => {
+                let user_datas = self
+                    .tracks
+                    .iter_mut()
+                    .map(|track| {
+                        uncount_string(&mut track.media.handler.name);
+                        &mut track.user_data
+                    })
+                    .chain([&mut self.user_data]);
+                for user_data in user_datas.flatten() {
+                    if let Some(meta) = &mut user_data.meta {
+                        uncount_string(&mut meta.handler.name);
+                    }
+                }
+            }
+            Dialect::ThirdGeneration => {
+                const ASSETS: [(&[u8; 4], FourCC); 6] = [
+                    (b"titl", MetadataItem::TITLE),
+                    (b"perf", MetadataItem::ARTIST),
+                    (b"albm", MetadataItem::ALBUM),
+                    (b"dscp", MetadataItem::DESCRIPTION),
+                    (b"gnre", MetadataItem::GENRE),
+                    (b"cprt", MetadataItem::COPYRIGHT),
+                ];
+                let Some(user_data) = &self.user_data else {
+                    return;
+                };
+                let texts: Vec<_> = ASSETS
+                    .iter()
+                    .filter_map(|(asset, key)| {
+                        let text = user_data
+                            .extra_boxes
+                            .iter()
+                            .find(|extra_box| &extra_box.r#type.to_bytes() == *asset)
+                            .and_then(|extra_box| decode_asset_string(&extra_box.data))?;
+                        Some((*key, text))
+                    })
+                    .collect();
+                if texts.is_empty() {
+                    return;
+                }
+                let item_list = self.item_list_mut();
+                for (key, text) in texts {
+                    if item_list.get(key).is_none() {
+                        item_list.set_text(key, &text);
+                    }
+                }
+            }
         }
     }
-}
-
-impl Encode for MovieHeaderBox {
-    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"mvhd")?;
-        output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(0)?; // flags
 
-        (self.creation_time as u32).encode(output)?;
-        (self.modification_time as u32).encode(output)?;
-        self.timescale.encode(output)?;
-        (self.duration as u32).encode(output)?;
-        self.rate.encode(output)?;
-        self.volume.encode(output)?;
-        0u16.encode(output)?; // reserved
-        0u32.encode(output)?; // reserved
-        0u32.encode(output)?; // reserved
-        self.matrix.encode(output)?;
-        0u32.encode(output)?; // pre_defined
-        0u32.encode(output)?; // pre_defined
-        0u32.encode(output)?; // pre_defined
-        0u32.encode(output)?; // pre_defined
-        0u32.encode(output)?; // pre_defined
-        0u32.encode(output)?; // pre_defined
-        self.next_track_id.encode(output)?;
+    /// Returns the time of the frame shown as thumbnail of the movie, which
+    /// QuickTime stores in the mvhd and is 0 for files of other writers.
+    pub fn poster_time(&self) -> MovieTime {
+        MovieTime(self.header.poster_time as u64)
+    }
 
-        update_box_header(output, begin)
+    /// Adds the track with the next free id, and returns the id, or an error
+    /// if the ids are exhausted.
+    pub fn add_track(&mut self, mut track: TrackBox) -> Result<u32> {
+        let track_id = self
+            .tracks
+            .iter()
+            .map(|track| track.header.track_id.checked_add(1))
+            .try_fold(self.header.next_track_id.max(1), |track_id, next| {
+                next.map(|next| track_id.max(next))
+            })
+            .ok_or(Error::ValueOutOfRange { field: "track_id" })?;
+        let next_track_id = track_id
+            .checked_add(1)
+            .ok_or(Error::ValueOutOfRange { field: "track_id" })?;
+        track.header.track_id = track_id;
+        self.tracks.push(track);
+        self.header.next_track_id = next_track_id;
+        Ok(track_id)
     }
-}
 
-impl Decode for MovieHeaderBox {
-    fn decode(input: &mut &[u8]) -> Result<Self> {
-        let version = input.read_u8()?;
-        input.read_u24::<BigEndian>()?; // flags
+    /// Like [Encode::encode], but encodes the tracks into separate buffers on
+    /// worker threads, which is faster for long recordings whose sample tables
+    /// have millions of entries.
+    pub fn encode_parallel(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let threads = std::thread::available_parallelism().map_or(1, NonZero::get);
+        if threads == 1 || self.tracks.len() < 2 {
+            return self.encode(output);
+        }
+        let tracks_per_thread = self.tracks.len().div_ceil(threads);
+        let tracks = std::thread::scope(|scope| {
+            let workers: Vec<_> = self
+                .tracks
+                .chunks(tracks_per_thread)
+                .map(|tracks| {
+                    scope.spawn(move || {
+                        let mut output = Cursor::new(Vec::new());
+                        for track in tracks {
+                            track.encode(&mut output)?;
+                        }
+                        Ok(output.into_inner())
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Result<Vec<Vec<u8>>>>()
+        })?;
 
-        let creation_time;
-        let modification_time;
-        let timescale;
-        let duration;
-        match version {
-            0 => {
-                creation_time = u32::decode(input)? as u64;
-                modification_time = u32::decode(input)? as u64;
-                timescale = Decode::decode(input)?;
-                duration = u32::decode(input)? as u64;
+        self.encode_with(output, |output| {
+            for tracks in &tracks {
+                output.write_all(tracks)?;
             }
-            1 => {
-                creation_time = Decode::decode(input)?;
-                modification_time = Decode::decode(input)?;
-                timescale = Decode::decode(input)?;
-                duration = Decode::decode(input)?;
-            }
-            _ => panic!(),
-        }
-        let rate = Decode::decode(input)?;
-        let volume = Decode::decode(input)?;
-        assert_eq!(u16::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        let matrix = Decode::decode(input)?;
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        let next_track_id = Decode::decode(input)?;
-        Ok(Self {
-            creation_time,
-            modification_time,
-            timescale,
-            duration,
-            rate,
-            volume,
-            matrix,
-            next_track_id,
+            Ok(())
         })
     }
-}
 
-////////////////////////////////////////////////////////////////////////////////////////////////////
-// ISO/IEC 14496-12:2008 8.3.1
-////////////////////////////////////////////////////////////////////////////////////////////////////
+    /// Encodes the moov, with the tracks written by `encode_tracks`.
+    fn encode_with<W: Write + Seek>(
+        &self,
+        output: &mut W,
+        encode_tracks: impl FnOnce(&mut W) -> Result<()>,
+    ) -> Result<()> {
+        let begin = encode_box_header(output, *b"moov")?;
+
+        self.header.encode(output)?;
+        encode_tracks(output)?;
+        self.extends.encode(output)?;
+        self.user_data.encode(output)?;
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+
+    /// Derives the durations of all tracks from their samples and edit lists,
+    /// and the movie duration from the longest track.
+    pub fn recompute_durations(&mut self) {
+        self.header.duration = MovieTime(0);
+        for track in &mut self.tracks {
+            track.recompute_duration(self.header.timescale);
+            self.header.duration = self.header.duration.max(track.header.duration);
+        }
+    }
+
+    /// Returns the iTunes metadata, adding the udta, meta and ilst if missing.
+    pub fn item_list_mut(&mut self) -> &mut ItemListBox {
+        let meta = self
+            .user_data
+            .get_or_insert_with(Default::default)
+            .meta
+            .get_or_insert_with(|| MetaBox {
+                handler: HandlerBox {
+                    r#type: FourCC(u32::from_be_bytes(*b"mdir")),
+                    name: String::new(),
+                },
+                item_location: None,
+                item_info: None,
+                item_reference: None,
+                item_properties: None,
+                item_list: None,
+                extra_boxes: Vec::new(),
+            });
+        meta.item_list.get_or_insert_with(Default::default)
+    }
+
+    /// Embeds a JPEG or PNG image as iTunes cover art, replacing any previous
+    /// one, so that galleries can show it without decoding the video.
+    pub fn set_cover_art(&mut self, image: Vec<u8>) -> Result<()> {
+        let type_indicator = if image.starts_with(&[0xFF, 0xD8]) {
+            DataBox::JPEG
+        } else if image.starts_with(b"\x89PNG") {
+            DataBox::PNG
+        } else {
+            return Err(Error::ValueOutOfRange { field: "covr" });
+        };
+
+        let item_list = self.item_list_mut();
+        item_list
+            .0
+            .retain(|item| item.key != MetadataItem::COVER_ART);
+        item_list.0.push(MetadataItem {
+            key: MetadataItem::COVER_ART,
+            values: vec![DataBox {
+                type_indicator,
+                locale: 0,
+                data: image,
+            }],
+            children: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Sets the modification time of the movie and of all tracks, like after
+    /// editing them, e.g. to [current_time]. The creation times are kept.
+    pub fn set_modification_time(&mut self, modification_time: u64) {
+        self.header.modification_time = modification_time;
+        for track in &mut self.tracks {
+            track.header.modification_time = modification_time;
+            track.media.header.modification_time = modification_time;
+        }
+    }
+
+    /// Keeps only the tracks for which `predicate` returns true. Their samples
+    /// stay in the mdat, use [export_tracks] to also drop those.
+    ///
+    /// [export_tracks]: crate::export::export_tracks
+    pub fn filter_tracks(&mut self, predicate: impl FnMut(&TrackBox) -> bool) {
+        self.tracks.retain(predicate);
+    }
+
+    pub fn track(&self, track_id: u32) -> Option<&TrackBox> {
+        self.tracks
+            .iter()
+            .find(|track| track.header.track_id == track_id)
+    }
+
+    pub fn remove_track(&mut self, track_id: u32) -> Option<TrackBox> {
+        let index = self
+            .tracks
+            .iter()
+            .position(|track| track.header.track_id == track_id)?;
+        Some(self.tracks.remove(index))
+    }
+
+    /// Returns the placements of the enabled video tracks relative to the
+    /// bounding rectangle of all of them, so that (0, 0) is the top left and
+    /// (1, 1) the bottom right corner of the composition, ordered back to
+    /// front.
+    pub fn normalized_placements(&self) -> Vec<(u32, Placement)> {
+        let mut tracks: Vec<_> = self
+            .tracks
+            .iter()
+            .filter(|track| track.header.enabled)
+            .filter_map(|track| {
+                Some((
+                    track.header.layer,
+                    track.header.track_id,
+                    track.header.placement()?,
+                ))
+            })
+            .collect();
+        let Some(bounds) = tracks
+            .iter()
+            .map(|(_, _, placement)| *placement)
+            .reduce(|a, b| {
+                let x = a.x.min(b.x);
+                let y = a.y.min(b.y);
+                Placement {
+                    x,
+                    y,
+                    width: (a.x + a.width).max(b.x + b.width) - x,
+                    height: (a.y + a.height).max(b.y + b.height) - y,
+                }
+            })
+        else {
+            return Vec::new();
+        };
+        // stable, so tracks in the same layer keep their order
+        tracks.sort_by_key(|(layer, _, _)| std::cmp::Reverse(*layer));
+        tracks
+            .into_iter()
+            .map(|(_, track_id, placement)| {
+                (
+                    track_id,
+                    Placement {
+                        x: (placement.x - bounds.x) / bounds.width,
+                        y: (placement.y - bounds.y) / bounds.height,
+                        width: placement.width / bounds.width,
+                        height: placement.height / bounds.height,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Validates the composition of overlapping video tracks: the matrices
+    /// have to map the tracks to a rectangle of the movie, and tracks shown at
+    /// the same time can't overlap in the same layer, as their order is
+    /// undefined then.
+    ///
+    /// Tracks of the same alternate group are never shown at the same time.
+    pub fn validate_composition(&self) -> Result<()> {
+        let mut tracks: Vec<(&TrackHeaderBox, Placement)> = Vec::new();
+        for track in &self.tracks {
+            let header = &track.header;
+            let (width, height) = header.dimensions();
+            if !header.enabled || width == 0.0 || height == 0.0 {
+                continue;
+            }
+            let [a, b, u, c, d, v, _, _, w] = header.matrix.to_f64();
+            // a projection that flips the sign of z somewhere on the track
+            // doesn't give a rectangle either
+            let in_front = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)]
+                .iter()
+                .all(|(x, y)| u * x + v * y + w > 0.0);
+            let placement = header
+                .placement()
+                .filter(|_| in_front && a * d - b * c != 0.0)
+                .ok_or(Error::InvalidTrackMatrix {
+                    track_id: header.track_id,
+                })?;
+            for (other, other_placement) in &tracks {
+                if other.layer == header.layer
+                    && (header.alternate_group == 0
+                        || other.alternate_group != header.alternate_group)
+                    && placement.overlaps(other_placement)
+                {
+                    return Err(Error::OverlappingTrackLayer {
+                        track_id: header.track_id,
+                        other_track_id: other.track_id,
+                        layer: header.layer,
+                    });
+                }
+            }
+            tracks.push((header, placement));
+        }
+        Ok(())
+    }
+
+    /// Checks that the tracks of each alternate group have the same media
+    /// type, like all audio, and that exactly one of them is enabled, which
+    /// is the one played by default.
+    pub fn validate_alternate_groups(&self) -> Result<()> {
+        for (alternate_group, tracks) in self.alternate_groups() {
+            let first = tracks[0];
+            if let Some(other) = tracks
+                .iter()
+                .find(|track| track.media.handler.r#type != first.media.handler.r#type)
+            {
+                return Err(Error::MixedAlternateGroup {
+                    alternate_group,
+                    track_id: first.header.track_id,
+                    handler: first.media.handler.r#type,
+                    other_track_id: other.header.track_id,
+                    other_handler: other.media.handler.r#type,
+                });
+            }
+            let enabled = tracks.iter().filter(|track| track.header.enabled).count();
+            if enabled != 1 {
+                return Err(Error::InvalidEnabledTrackCount {
+                    alternate_group,
+                    enabled,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Enables the track, and disables the other tracks of its alternate
+    /// group, e.g. to switch the default audio language.
+    pub fn enable_alternate(&mut self, track_id: u32) -> Result<()> {
+        let alternate_group = self
+            .tracks
+            .iter()
+            .find(|track| track.header.track_id == track_id)
+            .ok_or(Error::InvalidTrackId { track_id })?
+            .header
+            .alternate_group;
+        for track in &mut self.tracks {
+            if track.header.track_id == track_id {
+                track.header.enabled = true;
+            } else if alternate_group != 0 && track.header.alternate_group == alternate_group {
+                track.header.enabled = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fixes the alternate groups, so that they pass
+    /// [Self::validate_alternate_groups], and returns what was fixed.
+    ///
+    /// Tracks with another media type than the first track of their group
+    /// are moved into a new group per media type. In groups without an
+    /// enabled track the first one is enabled, and in groups with more only
+    /// the first enabled one is kept.
+    pub fn repair_alternate_groups(&mut self) -> Vec<AlternateGroupRepair> {
+        let mut repairs = Vec::new();
+
+        let mut last_alternate_group = self
+            .tracks
+            .iter()
+            .map(|track| track.header.alternate_group)
+            .max()
+            .unwrap_or(0);
+        let mut handlers: Vec<(u16, FourCC)> = Vec::new();
+        // new group of each group and media type
+        let mut new_alternate_groups: Vec<((u16, FourCC), u16)> = Vec::new();
+        for track in &mut self.tracks {
+            let alternate_group = track.header.alternate_group;
+            let handler = track.media.handler.r#type;
+            if alternate_group == 0 {
+                continue;
+            }
+            match handlers
+                .iter()
+                .find(|(other_alternate_group, _)| *other_alternate_group == alternate_group)
+            {
+                None => handlers.push((alternate_group, handler)),
+                Some(&(_, first_handler)) if first_handler == handler => {}
+                Some(_) => {
+                    let key = (alternate_group, handler);
+                    let new_alternate_group = match new_alternate_groups
+                        .iter()
+                        .find(|(other_key, _)| *other_key == key)
+                    {
+                        Some(&(_, new_alternate_group)) => new_alternate_group,
+                        None => {
+                            let Some(new_alternate_group) = last_alternate_group.checked_add(1)
+                            else {
+                                continue;
+                            };
+                            last_alternate_group = new_alternate_group;
+                            new_alternate_groups.push((key, new_alternate_group));
+                            new_alternate_group
+                        }
+                    };
+                    track.header.alternate_group = new_alternate_group;
+                    repairs.push(AlternateGroupRepair::Regrouped {
+                        track_id: track.header.track_id,
+                        alternate_group: new_alternate_group,
+                    });
+                }
+            }
+        }
+
+        let alternate_groups: Vec<_> = self
+            .alternate_groups()
+            .into_iter()
+            .map(|(alternate_group, _)| alternate_group)
+            .collect();
+        for alternate_group in alternate_groups {
+            let mut headers: Vec<_> = self
+                .tracks
+                .iter_mut()
+                .filter(|track| track.header.alternate_group == alternate_group)
+                .map(|track| &mut track.header)
+                .collect();
+            match headers.iter().position(|header| header.enabled) {
+                None => {
+                    headers[0].enabled = true;
+                    repairs.push(AlternateGroupRepair::Enabled {
+                        track_id: headers[0].track_id,
+                    });
+                }
+                Some(first_enabled) => {
+                    for header in &mut headers[first_enabled + 1..] {
+                        if header.enabled {
+                            header.enabled = false;
+                            repairs.push(AlternateGroupRepair::Disabled {
+                                track_id: header.track_id,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        repairs
+    }
+
+    /// Tracks of each alternate group other than 0, in order of their first
+    /// track.
+    fn alternate_groups(&self) -> Vec<(u16, Vec<&TrackBox>)> {
+        let mut alternate_groups: Vec<(u16, Vec<&TrackBox>)> = Vec::new();
+        for track in &self.tracks {
+            let alternate_group = track.header.alternate_group;
+            if alternate_group == 0 {
+                continue;
+            }
+            match alternate_groups
+                .iter_mut()
+                .find(|(other_alternate_group, _)| *other_alternate_group == alternate_group)
+            {
+                Some((_, tracks)) => tracks.push(track),
+                None => alternate_groups.push((alternate_group, vec![track])),
+            }
+        }
+        alternate_groups
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (index, track) in self.tracks.iter().enumerate() {
+            let track_id = track.header.track_id;
+            if track_id == 0 {
+                return Err(Error::InvalidTrackId { track_id });
+            }
+            if self.tracks[..index]
+                .iter()
+                .any(|track| track.header.track_id == track_id)
+            {
+                return Err(Error::DuplicateTrackId { track_id });
+            }
+            if track_id >= self.header.next_track_id {
+                return Err(Error::InvalidNextTrackId {
+                    next_track_id: self.header.next_track_id,
+                    track_id,
+                });
+            }
+
+            // players differ in whether they scale to the tkhd or the sample
+            // entry dimensions, therefore they have to match, after applying
+            // the clean aperture and pixel aspect ratio, give or take a pixel
+            let header = &track.header;
+            let entries: Vec<_> = track
+                .media
+                .information
+                .sample_table
+                .description
+                .0
+                .iter()
+                .filter_map(SampleEntry::visual)
+                .collect();
+            if let Some(entry) = entries.first() {
+                let (width, height) = header.dimensions();
+                if !header.size_is_aspect_ratio
+                    && !entries.iter().any(|entry| {
+                        let (expected_width, expected_height) = entry.display_dimensions();
+                        (width - expected_width).abs() <= 1.0
+                            && (height - expected_height).abs() <= 1.0
+                    })
+                {
+                    let (expected_width, expected_height) = entry.display_dimensions();
+                    return Err(Error::InvalidTrackDimensions {
+                        track_id,
+                        width: header.width,
+                        height: header.height,
+                        expected_width: expected_width.round() as u16,
+                        expected_height: expected_height.round() as u16,
+                    });
+                }
+            }
+        }
+        self.validate_alternate_groups()
+    }
+}
+
+/// Fix applied by [MovieBox::repair_alternate_groups].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlternateGroupRepair {
+    /// moved out of a group of another media type
+    Regrouped { track_id: u32, alternate_group: u16 },
+    /// no track of the group was enabled
+    Enabled { track_id: u32 },
+    /// another track of the group was already enabled
+    Disabled { track_id: u32 },
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.2.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+define_box! {
+    #[derive(Debug)]
+    pub struct MovieHeaderBox: full_box b"mvhd" {
+        versioned pub creation_time: u64,
+        versioned pub modification_time: u64,
+        pub timescale: u32,
+        versioned pub duration: MovieTime,
+        pub rate: PlaybackRate,
+        pub volume: Volume,
+        reserved u16 = 0,
+        reserved [u32; 2] = [0; 2],
+        pub matrix: Matrix,
+        // pre_defined in ISO/IEC 14496-12, but used by QuickTime for the
+        // following times in the movie timescale
+        pub preview_time: u32,
+        pub preview_duration: u32,
+        pub poster_time: u32,
+        pub selection_time: u32,
+        pub selection_duration: u32,
+        pub current_time: u32,
+        pub next_track_id: u32,
+    }
+}
+
+impl Default for MovieHeaderBox {
+    fn default() -> Self {
+        Self {
+            creation_time: 0,
+            modification_time: 0,
+            timescale: 0,
+            duration: MovieTime(0),
+            rate: PlaybackRate::NORMAL,
+            volume: Volume::FULL,
+            matrix: Matrix::identity(),
+            preview_time: 0,
+            preview_duration: 0,
+            poster_time: 0,
+            selection_time: 0,
+            selection_duration: 0,
+            current_time: 0,
+            next_track_id: 0,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.3.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackKind {
+    Video,
+    Audio,
+    /// HEIF image sequence
+    Image,
+    /// subtitles and captions
+    Text,
+    /// timed metadata
+    Metadata,
+    Other,
+}
 
 #[derive(Debug)]
 pub struct TrackBox {
     pub header: TrackHeaderBox,
     pub media: MediaBox,
     pub edit: Option<EditBox>,
+    pub user_data: Option<UserDataBox>,
+    pub extra_boxes: Vec<RawBox>,
 }
 
 impl Encode for TrackBox {
@@ -576,6 +2383,11 @@ impl Encode for TrackBox {
         self.header.encode(output)?;
         self.media.encode(output)?;
         self.edit.encode(output)?;
+        self.user_data.encode(output)?;
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
 
         update_box_header(output, begin)
     }
@@ -586,22 +2398,414 @@ impl Decode for TrackBox {
         let mut header = None;
         let mut edit = None;
         let mut media = None;
+        let mut user_data = None;
+
+        let mut extra_boxes = Vec::new();
 
         decode_boxes! {
             input,
+            unknown extra_boxes,
             required tkhd header,
             required mdia media,
             optional edts edit,
+            optional udta user_data,
         }
 
         Ok(Self {
             header,
             edit,
             media,
+            user_data,
+            extra_boxes,
         })
     }
 }
 
+impl TrackBox {
+    /// Creates a timed metadata track without samples, like for telemetry
+    /// recorded along a video, which `described_track_id` refers to with a
+    /// cdsc track reference.
+    ///
+    /// Its samples are added like for any other track, e.g. with
+    /// [AppendSession](crate::append::AppendSession), and read back with their
+    /// decode time by [TrackBox::samples].
+    pub fn new_timed_metadata(
+        track_id: u32,
+        timescale: u32,
+        entry: SampleEntry,
+        described_track_id: Option<u32>,
+    ) -> Self {
+        let mut extra_boxes = Vec::new();
+        if let Some(described_track_id) = described_track_id {
+            // TrackReferenceBox with a single TrackReferenceTypeBox
+            let mut data = Vec::new();
+            data.extend_from_slice(&(4u32 + 4 + 4).to_be_bytes());
+            data.extend_from_slice(b"cdsc");
+            data.extend_from_slice(&described_track_id.to_be_bytes());
+            extra_boxes.push(RawBox {
+                r#type: FourCC::from(*b"tref"),
+                data,
+            });
+        }
+
+        Self {
+            header: TrackHeaderBox {
+                track_id,
+                volume: Volume::MUTED,
+                ..Default::default()
+            },
+            media: MediaBox {
+                header: MediaHeaderBox {
+                    timescale,
+                    ..Default::default()
+                },
+                handler: HandlerBox {
+                    r#type: FourCC::from(*b"meta"),
+                    name: String::new(),
+                },
+                information: MediaInformationBox {
+                    header: MediaInformationHeader::Null(Default::default()),
+                    data_information: Default::default(),
+                    sample_table: SampleTableBox {
+                        description: SampleDescriptionBox(vec![entry]),
+                        time_to_sample: TimeToSampleBox(vec![]),
+                        composition_offset: None,
+                        sync_sample: None,
+                        sample_size: SampleSizeBox::PerSample(vec![]),
+                        sample_to_chunk: SampleToChunkBox(vec![]),
+                        chunk_offset: ChunkOffsetBox(vec![]),
+                        sample_dependency_type: None,
+                        padding_bits: None,
+                        sample_to_group: None,
+                        sample_group_description: None,
+                        extra_boxes: Vec::new(),
+                    },
+                    extra_boxes: Vec::new(),
+                },
+                extra_boxes: Vec::new(),
+            },
+            edit: None,
+            user_data: None,
+            extra_boxes,
+        }
+    }
+
+    pub fn frame_rate(&self) -> Option<Rational32> {
+        let sample_delta = match self
+            .media
+            .information
+            .sample_table
+            .time_to_sample
+            .0
+            .as_slice()
+        {
+            [entry]
+            | [entry, TimeToSampleEntry {
+                sample_count: 1, ..
+            }] => entry.sample_delta,
+            _ => return None,
+        };
+        if sample_delta == 0 {
+            return None;
+        }
+        Some(Rational32::new(self.media.header.timescale, sample_delta))
+    }
+
+    pub fn sample_times(&self) -> impl Iterator<Item = MediaTime> + '_ {
+        self.media.information.sample_table.sample_times()
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = SampleMetadata> + '_ {
+        self.media.information.sample_table.samples()
+    }
+
+    /// Returns when the first sample is presented on the movie timeline, after
+    /// applying composition offsets and the edit list.
+    pub fn first_presentation_time(&self, movie_timescale: u32) -> MovieTime {
+        let sample_table = &self.media.information.sample_table;
+        let composition_offsets = sample_table
+            .composition_offset
+            .iter()
+            .flat_map(|composition_offset| &composition_offset.0)
+            .flat_map(|entry| std::iter::repeat_n(entry.sample_offset, entry.sample_count as usize))
+            .chain(std::iter::repeat(0));
+        let first_composition_time = sample_table
+            .sample_times()
+            .zip(composition_offsets)
+            .map(|(time, composition_offset)| time.0 as i64 + composition_offset as i64)
+            .min()
+            .unwrap_or_default();
+
+        // empty edits delay the track, the first other one selects where the
+        // media starts
+        let mut delay = MovieTime(0);
+        let mut media_time = 0;
+        for entry in self
+            .edit
+            .iter()
+            .flat_map(|edit| &edit.edit_list)
+            .flat_map(|edit_list| &edit_list.0)
+        {
+            if entry.media_time == -1 {
+                delay.0 += entry.segment_duration.0;
+            } else {
+                media_time = entry.media_time;
+                break;
+            }
+        }
+        let skipped = MediaTime(first_composition_time.saturating_sub(media_time).max(0) as u64)
+            .to_movie(self.media.header.timescale, movie_timescale);
+        MovieTime(delay.0 + skipped.0)
+    }
+
+    pub fn rendition(&self) -> Result<Option<RenditionInfo>> {
+        match &self.user_data {
+            Some(user_data) => user_data.rendition(),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_rendition(&mut self, rendition: &RenditionInfo) -> Result<()> {
+        self.user_data
+            .get_or_insert_with(Default::default)
+            .set_rendition(rendition)
+    }
+
+    /// Returns whether the track is a HEIF image sequence, like a burst or the
+    /// motion of a live photo.
+    pub fn is_image_sequence(&self) -> bool {
+        &self.media.handler.r#type.to_bytes() == b"pict"
+    }
+
+    /// Infers what the track contains from the handler type, or from the
+    /// sample entries if the handler type is unknown.
+    pub fn kind(&self) -> TrackKind {
+        match &self.media.handler.r#type.to_bytes() {
+            b"vide" => TrackKind::Video,
+            b"soun" => TrackKind::Audio,
+            b"pict" => TrackKind::Image,
+            b"text" | b"subt" | b"sbtl" => TrackKind::Text,
+            b"meta" => TrackKind::Metadata,
+            _ => match self.media.information.sample_table.description.0.first() {
+                Some(entry) if entry.visual().is_some() => TrackKind::Video,
+                Some(
+                    SampleEntry::AAC(_)
+                    | SampleEntry::MP3(_)
+                    | SampleEntry::PCM(_)
+                    | SampleEntry::LPCM(_),
+                ) => TrackKind::Audio,
+                Some(
+                    SampleEntry::XMLMetaData(_)
+                    | SampleEntry::TextMetaData(_)
+                    | SampleEntry::URIMeta(_),
+                ) => TrackKind::Metadata,
+                _ => TrackKind::Other,
+            },
+        }
+    }
+
+    /// Returns the location of the samples described by the sample entry at
+    /// `sample_description_index`, or None if they are in this file.
+    ///
+    /// Entries referencing no or an invalid data entry are assumed to be in
+    /// this file, like some writers do by using 0.
+    pub fn external_location(&self, sample_description_index: u32) -> Option<&str> {
+        let data_reference_index = self
+            .media
+            .information
+            .sample_table
+            .description
+            .0
+            .get(sample_description_index.checked_sub(1)? as usize)?
+            .data_reference_index()?;
+        self.media
+            .information
+            .data_information
+            .reference
+            .0
+            .get(data_reference_index.checked_sub(1)? as usize)?
+            .external_location()
+    }
+
+    /// Returns the codecs parameter of RFC 6381 for the first sample entry,
+    /// see [SampleEntry::codec_string].
+    pub fn codec_string(&self) -> Result<Option<String>> {
+        self.media
+            .information
+            .sample_table
+            .description
+            .0
+            .first()
+            .map(SampleEntry::codec_string)
+            .transpose()
+    }
+
+    pub fn sample_offsets(&self) -> impl Iterator<Item = u64> + '_ {
+        self.media.information.sample_table.sample_offsets()
+    }
+
+    /// Hides the first `priming` media time units, like the encoder delay of
+    /// AAC, behind an edit list and sets the track duration to the remaining
+    /// presentation.
+    ///
+    /// The edit covers the samples so far, and is extended to the samples
+    /// added later by [Self::recompute_duration].
+    pub fn set_priming(&mut self, priming: MediaTime, movie_timescale: u32) {
+        self.media.header.duration = self.sample_duration();
+        let segment_duration = MediaTime(self.media.header.duration.0.saturating_sub(priming.0))
+            .to_movie(self.media.header.timescale, movie_timescale);
+        self.edit = Some(EditBox {
+            edit_list: Some(EditListBox(vec![EditListEntry {
+                segment_duration,
+                media_time: priming.0 as i64,
+                media_rate: U16F16!(1),
+            }])),
+            extra_boxes: Vec::new(),
+        });
+        self.header.duration = segment_duration;
+    }
+
+    /// Sum of the sample durations.
+    fn sample_duration(&self) -> MediaTime {
+        MediaTime(
+            self.media
+                .information
+                .sample_table
+                .time_to_sample
+                .0
+                .iter()
+                .map(|entry| entry.sample_count as u64 * entry.sample_delta as u64)
+                .sum(),
+        )
+    }
+
+    /// Sets the media duration to the sum of the sample durations, and the track
+    /// duration to the sum of the edits, or else the media duration.
+    ///
+    /// If the last edit played the media up to its previous end, it is extended
+    /// to the new end, so that samples added since are presented too.
+    /// Shorter edits, like trimming the padding of audio, are kept.
+    pub fn recompute_duration(&mut self, movie_timescale: u32) {
+        let previous_duration = self.media.header.duration;
+        self.media.header.duration = self.sample_duration();
+        let media_timescale = self.media.header.timescale;
+        let media_end = |media_time: i64, duration: MediaTime| {
+            MediaTime(duration.0.saturating_sub(media_time as u64))
+                .to_movie(media_timescale, movie_timescale)
+        };
+        self.header.duration = match self.edit.as_mut().and_then(|edit| edit.edit_list.as_mut()) {
+            Some(edit_list) if !edit_list.0.is_empty() => {
+                if let Some(entry) = edit_list.0.last_mut() {
+                    // -1 denotes an empty edit
+                    if entry.media_time >= 0
+                        && entry.media_rate == U16F16!(1)
+                        && entry.segment_duration.0.saturating_add(1)
+                            >= media_end(entry.media_time, previous_duration).0
+                    {
+                        entry.segment_duration =
+                            media_end(entry.media_time, self.media.header.duration)
+                                .max(entry.segment_duration);
+                    }
+                }
+                MovieTime(
+                    edit_list
+                        .0
+                        .iter()
+                        .map(|entry| entry.segment_duration.0)
+                        .sum(),
+                )
+            }
+            _ => self
+                .media
+                .header
+                .duration
+                .to_movie(media_timescale, movie_timescale),
+        };
+    }
+
+    /// Converts the media timescale, and rescales the sample durations,
+    /// composition offsets, media duration and edit list media times with it.
+    pub fn convert_timescale(&mut self, timescale: u32) -> Result<()> {
+        let previous_timescale = self.media.header.timescale;
+        if previous_timescale == 0 || previous_timescale == timescale {
+            self.media.header.timescale = timescale;
+            return Ok(());
+        }
+
+        // rescale the absolute sample times instead of every delta on its own, so that
+        // rounding errors don't accumulate over the course of the track, and the
+        // composition times, so that presentation order is kept
+        let sample_table = &mut self.media.information.sample_table;
+        let mut composition_offsets = sample_table
+            .composition_offset
+            .iter()
+            .flat_map(|composition_offset| &composition_offset.0)
+            .flat_map(|entry| {
+                std::iter::repeat_n(entry.sample_offset, entry.sample_count as usize)
+            });
+        let mut time_to_sample: Vec<TimeToSampleEntry> = Vec::new();
+        let mut composition_offset: Vec<CompositionOffsetEntry> = Vec::new();
+        let mut time = 0;
+        let mut rescaled_time = 0;
+        for entry in &sample_table.time_to_sample.0 {
+            for _ in 0..entry.sample_count {
+                if let Some(sample_offset) = composition_offsets.next() {
+                    let composition_time = time as i64 + sample_offset as i64;
+                    let sample_offset = checked_cast::<i32>(
+                        rescale_signed(composition_time, previous_timescale, timescale)
+                            - rescaled_time as i64,
+                        "sample_offset",
+                    )?;
+                    match composition_offset.last_mut() {
+                        Some(entry) if entry.sample_offset == sample_offset => {
+                            entry.sample_count += 1
+                        }
+                        _ => composition_offset.push(CompositionOffsetEntry {
+                            sample_count: 1,
+                            sample_offset,
+                        }),
+                    }
+                }
+
+                time += entry.sample_delta as u64;
+                let next_rescaled_time = rescale(time, previous_timescale, timescale);
+                let sample_delta =
+                    checked_cast::<u32>(next_rescaled_time - rescaled_time, "sample_delta")?;
+                rescaled_time = next_rescaled_time;
+                match time_to_sample.last_mut() {
+                    Some(entry) if entry.sample_delta == sample_delta => entry.sample_count += 1,
+                    _ => time_to_sample.push(TimeToSampleEntry {
+                        sample_count: 1,
+                        sample_delta,
+                    }),
+                }
+            }
+        }
+        // nothing is changed if any of the samples can't be converted
+        sample_table.time_to_sample.0 = time_to_sample;
+        if let Some(previous_composition_offset) = &mut sample_table.composition_offset {
+            previous_composition_offset.0 = composition_offset;
+        }
+        self.media.header.timescale = timescale;
+        self.media.header.duration = MediaTime(rescale(
+            self.media.header.duration.0,
+            previous_timescale,
+            timescale,
+        ));
+
+        if let Some(edit_list) = self.edit.as_mut().and_then(|edit| edit.edit_list.as_mut()) {
+            for entry in &mut edit_list.0 {
+                // -1 denotes an empty edit
+                if entry.media_time >= 0 {
+                    entry.media_time =
+                        rescale(entry.media_time as u64, previous_timescale, timescale) as i64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // ISO/IEC 14496-12:2008 8.3.2
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -611,31 +2815,107 @@ pub struct TrackHeaderBox {
     pub enabled: bool,
     pub in_movie: bool,
     pub in_preview: bool,
+    /// width and height only give the aspect ratio, ISO/IEC 14496-12:2015
+    pub size_is_aspect_ratio: bool,
     pub creation_time: u64,
     pub modification_time: u64,
     pub track_id: u32,
-    pub duration: u64,
-    pub layer: u16,
+    pub duration: MovieTime,
+    /// front to back order of overlapping video tracks, lower is in front
+    pub layer: i16,
     pub alternate_group: u16,
-    pub volume: U8F8,
+    /// muted for tracks other than audio
+    pub volume: Volume,
     pub matrix: Matrix,
     pub width: U16F16,
     pub height: U16F16,
 }
 
+impl TrackHeaderBox {
+    /// Returns width and height rounded down to whole pixels.
+    pub fn pixel_dimensions(&self) -> (u32, u32) {
+        (self.width.to_num(), self.height.to_num())
+    }
+
+    pub fn set_pixel_dimensions(&mut self, width: u32, height: u32) -> Result<()> {
+        self.width =
+            U16F16::checked_from_num(width).ok_or(Error::ValueOutOfRange { field: "width" })?;
+        self.height =
+            U16F16::checked_from_num(height).ok_or(Error::ValueOutOfRange { field: "height" })?;
+        Ok(())
+    }
+
+    /// Returns width and height including their fraction.
+    pub fn dimensions(&self) -> (f64, f64) {
+        (self.width.to_num(), self.height.to_num())
+    }
+
+    pub fn set_dimensions(&mut self, width: f64, height: f64) -> Result<()> {
+        self.width =
+            U16F16::checked_from_num(width).ok_or(Error::ValueOutOfRange { field: "width" })?;
+        self.height =
+            U16F16::checked_from_num(height).ok_or(Error::ValueOutOfRange { field: "height" })?;
+        Ok(())
+    }
+
+    /// Returns the bounding rectangle of the track in the movie, or None if
+    /// the track has no dimensions or the matrix maps a corner to infinity.
+    pub fn placement(&self) -> Option<Placement> {
+        let (width, height) = self.dimensions();
+        if width == 0.0 || height == 0.0 {
+            return None;
+        }
+        let corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)]
+            .map(|(x, y)| self.matrix.transform(x, y));
+        let mut corners = corners.into_iter().collect::<Option<Vec<_>>>()?.into_iter();
+        let (x, y) = corners.next()?;
+        let (min, max) = corners.fold(((x, y), (x, y)), |(min, max), (x, y)| {
+            ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+        });
+        Some(Placement {
+            x: min.0,
+            y: min.1,
+            width: max.0 - min.0,
+            height: max.1 - min.1,
+        })
+    }
+
+    /// Sets the matrix to scale and move the track to `placement`, like for
+    /// picture-in-picture, keeping the dimensions of the track.
+    pub fn set_placement(&mut self, placement: Placement) -> Result<()> {
+        let (width, height) = self.dimensions();
+        if width == 0.0 || height == 0.0 {
+            return Err(Error::ValueOutOfRange { field: "width" });
+        }
+        self.matrix = Matrix::from_f64([
+            placement.width / width,
+            0.0,
+            0.0,
+            0.0,
+            placement.height / height,
+            0.0,
+            placement.x,
+            placement.y,
+            1.0,
+        ])?;
+        Ok(())
+    }
+}
+
 impl Default for TrackHeaderBox {
     fn default() -> Self {
         Self {
             enabled: true,
             in_movie: true,
             in_preview: true,
+            size_is_aspect_ratio: false,
             creation_time: 0,
             modification_time: 0,
             track_id: 1,
-            duration: 0,
+            duration: MovieTime(0),
             layer: 0,
             alternate_group: 0,
-            volume: U8F8!(1),
+            volume: Volume::FULL,
             matrix: Matrix::identity(),
             width: U16F16!(0),
             height: U16F16!(0),
@@ -645,22 +2925,32 @@ impl Default for TrackHeaderBox {
 
 impl Encode for TrackHeaderBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let version = if self.creation_time.is_large()
+            || self.modification_time.is_large()
+            || self.duration.is_large()
+        {
+            1
+        } else {
+            0
+        };
+
         let begin = encode_box_header(output, *b"tkhd")?;
-        output.write_u8(0)?; // version
+        output.write_u8(version)?; // version
         output.write_u24::<BigEndian>(
             if self.enabled { 1 << 0 } else { 0 }
                 | if self.in_movie { 1 << 1 } else { 0 }
-                | if self.in_preview { 1 << 2 } else { 0 },
+                | if self.in_preview { 1 << 2 } else { 0 }
+                | if self.size_is_aspect_ratio { 1 << 3 } else { 0 },
         )?;
 
-        (self.creation_time as u32).encode(output)?;
-        (self.modification_time as u32).encode(output)?;
+        self.creation_time.encode_versioned(output, version)?;
+        self.modification_time.encode_versioned(output, version)?;
         self.track_id.encode(output)?;
         0u32.encode(output)?; // reserved
-        (self.duration as u32).encode(output)?;
+        self.duration.encode_versioned(output, version)?;
         0u32.encode(output)?; // reserved
         0u32.encode(output)?; // reserved
-        self.layer.encode(output)?;
+        output.write_i16::<BigEndian>(self.layer)?;
         self.alternate_group.encode(output)?;
         self.volume.encode(output)?;
         0u16.encode(output)?; // reserved
@@ -676,34 +2966,21 @@ impl Decode for TrackHeaderBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
         let version = input.read_u8()?;
         let flags = input.read_u24::<BigEndian>()?;
-
-        let creation_time;
-        let modification_time;
-        let track_id;
-        let duration;
-        match version {
-            0 => {
-                creation_time = u32::decode(input)? as u64;
-                modification_time = u32::decode(input)? as u64;
-                track_id = Decode::decode(input)?;
-                assert_eq!(u32::decode(input)?, 0); // reserved
-                duration = u32::decode(input)? as u64;
-            }
-            1 => {
-                creation_time = Decode::decode(input)?;
-                modification_time = Decode::decode(input)?;
-                track_id = Decode::decode(input)?;
-                assert_eq!(u32::decode(input)?, 0); // reserved
-                duration = Decode::decode(input)?;
-            }
-            _ => panic!(),
+        if version > 1 {
+            return Err(Error::ValueOutOfRange { field: "version" });
         }
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        let layer = Decode::decode(input)?;
+
+        let creation_time = VersionedField::decode_versioned(input, version)?;
+        let modification_time = VersionedField::decode_versioned(input, version)?;
+        let track_id = Decode::decode(input)?;
+        u32::decode(input)?; // reserved
+        let duration = VersionedField::decode_versioned(input, version)?;
+        u32::decode(input)?; // reserved
+        u32::decode(input)?; // reserved
+        let layer = input.read_i16::<BigEndian>()?;
         let alternate_group = Decode::decode(input)?;
         let volume = Decode::decode(input)?;
-        assert_eq!(u16::decode(input)?, 0); // reserved
+        u16::decode(input)?; // reserved
         let matrix = Decode::decode(input)?;
         let width = Decode::decode(input)?;
         let height = Decode::decode(input)?;
@@ -711,6 +2988,7 @@ impl Decode for TrackHeaderBox {
             enabled: flags & 1 << 0 != 0,
             in_movie: flags & 1 << 1 != 0,
             in_preview: flags & 1 << 2 != 0,
+            size_is_aspect_ratio: flags & 1 << 3 != 0,
             creation_time,
             modification_time,
             track_id,
@@ -734,6 +3012,7 @@ pub struct MediaBox {
     pub header: MediaHeaderBox,
     pub handler: HandlerBox,
     pub information: MediaInformationBox,
+    pub extra_boxes: Vec<RawBox>,
 }
 
 impl Encode for MediaBox {
@@ -744,6 +3023,10 @@ impl Encode for MediaBox {
         self.handler.encode(output)?;
         self.information.encode(output)?;
 
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
         update_box_header(output, begin)
     }
 }
@@ -754,8 +3037,11 @@ impl Decode for MediaBox {
         let mut handler = None;
         let mut information = None;
 
+        let mut extra_boxes = Vec::new();
+
         decode_boxes! {
             input,
+            unknown extra_boxes,
             required mdhd header,
             required hdlr handler,
             required minf information,
@@ -765,6 +3051,7 @@ impl Decode for MediaBox {
             header,
             handler,
             information,
+            extra_boxes,
         })
     }
 }
@@ -773,65 +3060,36 @@ impl Decode for MediaBox {
 // ISO/IEC 14496-12:2008 8.4.2
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug, Default)]
-pub struct MediaHeaderBox {
-    pub creation_time: u64,
-    pub modification_time: u64,
-    pub timescale: u32,
-    pub duration: u64,
-    pub language: u16,
-}
-
-impl Encode for MediaHeaderBox {
-    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"mdhd")?;
-        output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(0)?; // flags
-
-        (self.creation_time as u32).encode(output)?;
-        (self.modification_time as u32).encode(output)?;
-        self.timescale.encode(output)?;
-        (self.duration as u32).encode(output)?;
-        self.language.encode(output)?;
-        0u16.encode(output)?; // pre_defined
-
-        update_box_header(output, begin)
+define_box! {
+    #[derive(Debug, Default)]
+    pub struct MediaHeaderBox: full_box b"mdhd" {
+        versioned pub creation_time: u64,
+        versioned pub modification_time: u64,
+        pub timescale: u32,
+        versioned pub duration: MediaTime,
+        pub language: u16,
+        reserved u16 = 0, // pre_defined
     }
 }
 
-impl Decode for MediaHeaderBox {
-    fn decode(input: &mut &[u8]) -> Result<Self> {
-        let version = input.read_u8()?;
-        input.read_u24::<BigEndian>()?; // flags
+impl MediaHeaderBox {
+    /// Returns the ISO 639-2/T code, like "eng", unless it's not set.
+    pub fn language_code(&self) -> Option<String> {
+        (0..3)
+            .rev()
+            .map(|index| (self.language >> (index * 5) & 0x1F) as u8)
+            .map(|value| (1..=26).contains(&value).then_some((0x60 + value) as char))
+            .collect()
+    }
 
-        let creation_time;
-        let modification_time;
-        let timescale;
-        let duration;
-        match version {
-            0 => {
-                creation_time = u32::decode(input)? as u64;
-                modification_time = u32::decode(input)? as u64;
-                timescale = Decode::decode(input)?;
-                duration = u32::decode(input)? as u64;
-            }
-            1 => {
-                creation_time = Decode::decode(input)?;
-                modification_time = Decode::decode(input)?;
-                timescale = Decode::decode(input)?;
-                duration = Decode::decode(input)?;
-            }
-            _ => panic!(),
+    pub fn set_language_code(&mut self, code: &str) -> Result<()> {
+        if code.len() != 3 || !code.bytes().all(|byte| byte.is_ascii_lowercase()) {
+            return Err(Error::ValueOutOfRange { field: "language" });
         }
-        let language = Decode::decode(input)?;
-        assert_eq!(u16::decode(input)?, 0); // pre_defined
-        Ok(Self {
-            creation_time,
-            modification_time,
-            timescale,
-            duration,
-            language,
-        })
+        self.language = code
+            .bytes()
+            .fold(0, |language, byte| language << 5 | (byte - 0x60) as u16);
+        Ok(())
     }
 }
 
@@ -839,42 +3097,16 @@ impl Decode for MediaHeaderBox {
 // ISO/IEC 14496-12:2008 8.4.3
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
-pub struct HandlerBox {
-    pub r#type: FourCC,
-    pub name: String,
-}
-
-impl Encode for HandlerBox {
-    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"hdlr")?;
-        output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(0)?; // flags
-
-        0u32.encode(output)?; // pre_defined
-        self.r#type.0.encode(output)?;
-        0u32.encode(output)?; // reserved
-        0u32.encode(output)?; // reserved
-        0u32.encode(output)?; // reserved
-        self.name.encode(output)?;
-
-        update_box_header(output, begin)
-    }
-}
-
-impl Decode for HandlerBox {
-    fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
-
-        assert_eq!(input.read_u32::<BigEndian>()?, 0); // pre_defined
-        let r#type = FourCC(input.read_u32::<BigEndian>()?);
-        assert_eq!(input.read_u32::<BigEndian>()?, 0); // reserved
-        assert_eq!(input.read_u32::<BigEndian>()?, 0); // reserved
-        assert_eq!(input.read_u32::<BigEndian>()?, 0); // reserved
-        let name = Decode::decode(input)?;
-        Ok(Self { r#type, name })
-    }
+define_box! {
+    #[derive(Debug)]
+    pub struct HandlerBox: full_box b"hdlr" {
+        reserved u32 = 0, // pre_defined
+        pub r#type: FourCC,
+        // reserved, but the manufacturer in QuickTime, e.g. "appl" for iTunes
+        // metadata
+        reserved [u32; 3] = [0; 3],
+        pub name: String,
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -886,6 +3118,7 @@ pub struct MediaInformationBox {
     pub header: MediaInformationHeader,
     pub data_information: DataInformationBox,
     pub sample_table: SampleTableBox,
+    pub extra_boxes: Vec<RawBox>,
 }
 
 impl Encode for MediaInformationBox {
@@ -895,10 +3128,15 @@ impl Encode for MediaInformationBox {
         match &self.header {
             MediaInformationHeader::Video(header) => header.encode(output),
             MediaInformationHeader::Sound(header) => header.encode(output),
+            MediaInformationHeader::Null(header) => header.encode(output),
         }?;
         self.data_information.encode(output)?;
         self.sample_table.encode(output)?;
 
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
         update_box_header(output, begin)
     }
 }
@@ -907,13 +3145,18 @@ impl Decode for MediaInformationBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
         let mut video_header = None;
         let mut sound_header = None;
+        let mut null_header = None;
         let mut data_information = None;
         let mut sample_table = None;
 
+        let mut extra_boxes = Vec::new();
+
         decode_boxes! {
             input,
+            unknown extra_boxes,
             optional vmhd video_header,
             optional smhd sound_header,
+            optional nmhd null_header,
             required dinf data_information,
             required stbl sample_table,
         }
@@ -923,11 +3166,18 @@ impl Decode for MediaInformationBox {
                 MediaInformationHeader::Video(video_header)
             } else if let Some(sound_header) = sound_header {
                 MediaInformationHeader::Sound(sound_header)
+            } else if let Some(null_header) = null_header {
+                MediaInformationHeader::Null(null_header)
             } else {
-                todo!()
+                return Err(Error::InvalidBoxQuantity {
+                    r#type: "vmhd, smhd or nmhd",
+                    quantity: 0,
+                    expected: 1,
+                });
             },
             data_information,
             sample_table,
+            extra_boxes,
         })
     }
 }
@@ -940,48 +3190,19 @@ impl Decode for MediaInformationBox {
 pub enum MediaInformationHeader {
     Video(VideoMediaHeaderBox),
     Sound(SoundMediaHeaderBox),
+    /// for timed metadata and other tracks without a specific header
+    Null(NullMediaHeaderBox),
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // ISO/IEC 14496-12:2008 8.4.5.2
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug, Default)]
-pub struct VideoMediaHeaderBox {
-    pub graphicsmode: u16,
-    pub opcolor: [u16; 3],
-}
-
-impl Encode for VideoMediaHeaderBox {
-    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"vmhd")?;
-        output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(1)?; // flags
-
-        self.graphicsmode.encode(output)?;
-        for value in self.opcolor {
-            value.encode(output)?;
-        }
-
-        update_box_header(output, begin)
-    }
-}
-
-impl Decode for VideoMediaHeaderBox {
-    fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
-
-        let graphicsmode = Decode::decode(input)?;
-        let opcolor = [
-            Decode::decode(input)?,
-            Decode::decode(input)?,
-            Decode::decode(input)?,
-        ];
-        Ok(Self {
-            graphicsmode,
-            opcolor,
-        })
+define_box! {
+    #[derive(Debug, Default)]
+    pub struct VideoMediaHeaderBox: full_box b"vmhd", flags 1 {
+        pub graphicsmode: u16,
+        pub opcolor: [u16; 3],
     }
 }
 
@@ -989,33 +3210,35 @@ impl Decode for VideoMediaHeaderBox {
 // ISO/IEC 14496-12:2008 8.4.5.3
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
-pub struct SoundMediaHeaderBox {
-    pub balance: U8F8,
+define_box! {
+    #[derive(Debug)]
+    pub struct SoundMediaHeaderBox: full_box b"smhd" {
+        pub balance: U8F8,
+        reserved u16 = 0,
+    }
 }
 
-impl Encode for SoundMediaHeaderBox {
-    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"smhd")?;
-        output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(0)?; // flags
-
-        self.balance.encode(output)?;
-        0u16.encode(output)?; // reserved
+impl SoundMediaHeaderBox {
+    /// Returns the stereo position of a mono track, which unlike the type of
+    /// the field is signed, from -1.0 for left over 0.0 for the center to 1.0
+    /// for right.
+    pub fn pan(&self) -> f64 {
+        self.balance.to_bits() as i16 as f64 / 256.0
+    }
 
-        update_box_header(output, begin)
+    pub fn set_pan(&mut self, pan: f64) -> Result<()> {
+        self.balance = U8F8::from_bits(signed_fixed_bits::<i16>(pan, 8, "balance")? as u16);
+        Ok(())
     }
 }
 
-impl Decode for SoundMediaHeaderBox {
-    fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.4.5.5
+////////////////////////////////////////////////////////////////////////////////////////////////////
 
-        let balance = U8F8::from_bits(input.read_u16::<BigEndian>()?);
-        assert_eq!(input.read_u16::<BigEndian>()?, 0); // reserved
-        Ok(Self { balance })
-    }
+define_box! {
+    #[derive(Debug, Default)]
+    pub struct NullMediaHeaderBox: full_box b"nmhd" {}
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -1026,11 +3249,35 @@ impl Decode for SoundMediaHeaderBox {
 pub struct SampleTableBox {
     pub description: SampleDescriptionBox,
     pub time_to_sample: TimeToSampleBox,
+    pub composition_offset: Option<CompositionOffsetBox>,
     pub sync_sample: Option<SyncSampleBox>,
     pub sample_size: SampleSizeBox,
     pub sample_to_chunk: SampleToChunkBox,
     pub chunk_offset: ChunkOffsetBox,
+    pub sample_dependency_type: Option<SampleDependencyTypeBox>,
+    pub padding_bits: Option<PaddingBitsBox>,
     pub sample_to_group: Option<SampleToGroupBox>,
+    pub sample_group_description: Option<SampleGroupDescriptionBox>,
+    pub extra_boxes: Vec<RawBox>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SampleMetadata {
+    pub decode_time: MediaTime,
+    pub composition_offset: i32,
+    pub size: u32,
+    pub sample_description_index: u32,
+    pub is_sync: bool,
+    /// None if unknown
+    pub is_leading: Option<bool>,
+    pub depends_on_others: Option<bool>,
+    pub is_depended_on: Option<bool>,
+    /// number of samples to decode before (negative) or after this one for
+    /// the output to be correct
+    pub roll_distance: Option<i16>,
+    /// number of bits at the end which are not part of the sample, like for
+    /// audio which doesn't end on a byte boundary
+    pub padding_bits: u8,
 }
 
 impl Encode for SampleTableBox {
@@ -1039,11 +3286,19 @@ impl Encode for SampleTableBox {
 
         self.description.encode(output)?;
         self.time_to_sample.encode(output)?;
+        self.composition_offset.encode(output)?;
         self.sync_sample.encode(output)?;
         self.sample_size.encode(output)?;
         self.sample_to_chunk.encode(output)?;
         self.chunk_offset.encode(output)?;
+        self.sample_dependency_type.encode(output)?;
+        self.padding_bits.encode(output)?;
         self.sample_to_group.encode(output)?;
+        self.sample_group_description.encode(output)?;
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
 
         update_box_header(output, begin)
     }
@@ -1053,33 +3308,392 @@ impl Decode for SampleTableBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
         let mut description = None;
         let mut time_to_sample = None;
+        let mut composition_offset = None;
         let mut sync_sample = None;
         let mut sample_size = None;
         let mut sample_to_chunk = None;
         let mut chunk_offset = None;
+        let mut chunk_large_offset = None;
+        let mut sample_dependency_type = None;
+        let mut padding_bits = None;
         let mut sample_to_group = None;
+        let mut sample_group_description = None;
+
+        let mut extra_boxes = Vec::new();
 
         decode_boxes! {
             input,
+            unknown extra_boxes,
             required stsd description,
             required stts time_to_sample,
+            optional ctts composition_offset,
             optional stss sync_sample,
             required stsz sample_size,
             required stsc sample_to_chunk,
-            required stco chunk_offset,
+            optional stco chunk_offset,
+            optional co64 chunk_large_offset,
+            optional sdtp sample_dependency_type,
+            optional padb padding_bits,
             optional sbgp sample_to_group,
+            optional sgpd sample_group_description,
         }
 
+        let chunk_offset = match (chunk_offset, chunk_large_offset) {
+            (Some(chunk_offset), None) => chunk_offset,
+            (None, Some(ChunkLargeOffsetBox(chunk_offset))) => chunk_offset,
+            (chunk_offset, chunk_large_offset) => {
+                return Err(Error::InvalidBoxQuantity {
+                    r#type: "stco",
+                    quantity: chunk_offset.is_some() as usize
+                        + chunk_large_offset.is_some() as usize,
+                    expected: 1,
+                })
+            }
+        };
+
         Ok(Self {
             description,
             time_to_sample,
+            composition_offset,
             sync_sample,
             sample_size,
             sample_to_chunk,
             chunk_offset,
+            sample_dependency_type,
+            padding_bits,
             sample_to_group,
+            sample_group_description,
+            extra_boxes,
+        })
+    }
+}
+
+impl SampleTableBox {
+    /// Decode time of each sample, expanded from the time-to-sample runs
+    /// on the fly.
+    pub fn sample_times(&self) -> impl Iterator<Item = MediaTime> + '_ {
+        self.time_to_sample
+            .0
+            .iter()
+            .flat_map(|entry| std::iter::repeat_n(entry.sample_delta, entry.sample_count as usize))
+            .scan(0u64, |time, sample_delta| {
+                let sample_time = *time;
+                *time += sample_delta as u64;
+                Some(MediaTime(sample_time))
+            })
+    }
+
+    /// Yields the timing, size, dependencies and padding of each sample,
+    /// combined from stts, ctts, stsz, stsc, stss, sdtp, padb and the roll
+    /// sample group.
+    pub fn samples(&self) -> impl Iterator<Item = SampleMetadata> + '_ {
+        let composition_offsets = self
+            .composition_offset
+            .iter()
+            .flat_map(|composition_offset| &composition_offset.0)
+            .flat_map(|entry| std::iter::repeat_n(entry.sample_offset, entry.sample_count as usize))
+            .chain(std::iter::repeat(0));
+        // only the roll sample groups are of interest
+        let roll_distances = self
+            .sample_to_group
+            .iter()
+            .filter(|sample_to_group| {
+                matches!(&sample_to_group.0.to_bytes(), b"roll" | b"prol")
+                    && self
+                        .sample_group_description
+                        .as_ref()
+                        .is_some_and(|description| description.grouping_type == sample_to_group.0)
+            })
+            .flat_map(|sample_to_group| &sample_to_group.1)
+            .flat_map(|entry| {
+                let roll_distance = self
+                    .sample_group_description
+                    .as_ref()
+                    .and_then(|description| description.get(entry.group_description_index))
+                    .and_then(|entry| match entry {
+                        SampleGroupEntry::Roll(roll_distance) => Some(*roll_distance),
+                        _ => None,
+                    });
+                std::iter::repeat_n(roll_distance, entry.sample_count as usize)
+            })
+            .chain(std::iter::repeat(None));
+        (0..)
+            .zip(self.sample_times())
+            .zip(composition_offsets)
+            .zip(self.sample_description_indices())
+            .zip(roll_distances)
+            .map(
+                move |(
+                    (((sample, decode_time), composition_offset), sample_description_index),
+                    roll_distance,
+                )| {
+                    let dependency = self
+                        .sample_dependency_type
+                        .as_ref()
+                        .and_then(|sample_dependency_type| {
+                            sample_dependency_type.0.get(sample as usize)
+                        })
+                        .copied()
+                        .unwrap_or_default();
+                    SampleMetadata {
+                        decode_time,
+                        composition_offset,
+                        size: self.sample_size.sample_size(sample).unwrap_or(0),
+                        sample_description_index,
+                        is_sync: self
+                            .sync_sample
+                            .as_ref()
+                            .is_none_or(|sync_sample| sync_sample.0.contains(&(sample + 1))),
+                        is_leading: dependency.is_leading(),
+                        depends_on_others: dependency.depends_on_others(),
+                        is_depended_on: dependency.is_depended_on(),
+                        roll_distance,
+                        padding_bits: self
+                            .padding_bits
+                            .as_ref()
+                            .and_then(|padding_bits| padding_bits.0.get(sample as usize))
+                            .copied()
+                            .unwrap_or(0),
+                    }
+                },
+            )
+    }
+
+    /// File offset of each sample, expanded from the sample-to-chunk runs
+    /// on the fly.
+    pub fn sample_offsets(&self) -> impl Iterator<Item = u64> + '_ {
+        self.chunks().flat_map(move |(offset, samples, _)| {
+            samples.scan(offset, move |offset, sample| {
+                let sample_offset = *offset;
+                *offset += self.sample_size.sample_size(sample).unwrap_or(0) as u64;
+                Some(sample_offset)
+            })
         })
     }
+
+    /// Yields the data of each sample, frame by frame.
+    pub fn sample_data<'a>(&'a self, input: &'a [u8]) -> impl Iterator<Item = Result<&'a [u8]>> {
+        (0..)
+            .zip(self.sample_offsets())
+            .map(move |(sample, offset)| {
+                let size = self.sample_size.sample_size(sample).unwrap_or(0);
+                usize::try_from(offset)
+                    .ok()
+                    .and_then(|offset| input.get(offset..offset + size as usize))
+                    .ok_or(Error::ValueOutOfRange {
+                        field: "chunk_offset",
+                    })
+            })
+    }
+
+    /// Yields the sample_description_index of each sample.
+    pub fn sample_description_indices(&self) -> impl Iterator<Item = u32> + '_ {
+        self.chunks()
+            .flat_map(|(_, samples, sample_description_index)| {
+                samples.map(move |_| sample_description_index)
+            })
+    }
+
+    /// Signals for all samples, including the ones added later by
+    /// [AppendSession](crate::append::AppendSession), how many samples have to
+    /// be decoded before one for its output to be correct, like
+    /// [AACSampleEntry::ROLL_DISTANCE](aac::AACSampleEntry::ROLL_DISTANCE), so
+    /// that players decode from there when seeking.
+    pub fn set_roll_distance(&mut self, roll_distance: i16) {
+        let grouping_type = FourCC::from(*b"roll");
+        self.sample_group_description = Some(SampleGroupDescriptionBox {
+            grouping_type,
+            entries: vec![SampleGroupEntry::Roll(roll_distance)],
+        });
+        let sample_count = self.sample_size.sample_count();
+        self.sample_to_group = Some(SampleToGroupBox(
+            grouping_type,
+            if sample_count == 0 {
+                vec![]
+            } else {
+                vec![SampleToGroupEntry {
+                    sample_count,
+                    group_description_index: 1,
+                }]
+            },
+        ));
+    }
+
+    /// Like [SampleTableBox::set_roll_distance], for codecs whose pre-roll is
+    /// given as a duration, like the 80 ms of Opus, with samples of
+    /// `sample_duration`.
+    pub fn set_pre_roll(&mut self, pre_roll: MediaTime, sample_duration: u32) -> Result<()> {
+        if sample_duration == 0 {
+            return Err(Error::ValueOutOfRange {
+                field: "sample_duration",
+            });
+        }
+        let samples: i16 =
+            checked_cast(pre_roll.0.div_ceil(sample_duration as u64), "roll_distance")?;
+        self.set_roll_distance(-samples);
+        Ok(())
+    }
+
+    /// Fixes the inconsistencies commonly left by broken writers and cut
+    /// transfers, where `data_len` is the size of the file the chunk offsets
+    /// point into, and returns what was fixed.
+    ///
+    /// Chunks that don't fit into the data are dropped with all following
+    /// chunks, the samples are cut to the ones that have a size, a duration and
+    /// a chunk, and sync samples that don't exist anymore are dropped.
+    pub fn repair(&mut self, data_len: u64) -> Vec<SampleTableRepair> {
+        let mut repairs = Vec::new();
+
+        let chunk_count = self.chunk_offset.0.len();
+        let complete_chunk_count = self
+            .chunks()
+            .position(|(offset, samples, _)| {
+                let length: u64 = samples
+                    .map(|sample| self.sample_size.sample_size(sample).unwrap_or(0) as u64)
+                    .sum();
+                offset.checked_add(length).is_none_or(|end| end > data_len)
+            })
+            .unwrap_or(chunk_count);
+
+        let sample_size_count = self.sample_size.sample_count();
+        let time_to_sample_count = self
+            .time_to_sample
+            .0
+            .iter()
+            .map(|entry| entry.sample_count as u64)
+            .sum::<u64>();
+        let chunked_sample_count = self
+            .chunks()
+            .take(complete_chunk_count)
+            .last()
+            .map_or(0, |(_, samples, _)| samples.end);
+        let sample_count = (sample_size_count as u64)
+            .min(time_to_sample_count)
+            .min(chunked_sample_count as u64) as u32;
+        if sample_count as u64 != time_to_sample_count || sample_count != sample_size_count {
+            repairs.push(SampleTableRepair::TruncatedSamples {
+                sample_size_count,
+                time_to_sample_count,
+                sample_count,
+            });
+        }
+
+        // also drop the chunks that are left without samples
+        let new_chunk_count = self
+            .chunks()
+            .take(complete_chunk_count)
+            .take_while(|(_, samples, _)| samples.start < sample_count)
+            .count();
+        if new_chunk_count != chunk_count {
+            repairs.push(SampleTableRepair::DroppedChunks {
+                chunk_count: chunk_count as u32,
+                new_chunk_count: new_chunk_count as u32,
+            });
+            self.chunk_offset.0.truncate(new_chunk_count);
+            self.sample_to_chunk
+                .0
+                .retain(|entry| entry.first_chunk <= new_chunk_count as u32);
+        }
+
+        match &mut self.sample_size {
+            SampleSizeBox::Value {
+                sample_count: value,
+                ..
+            } => *value = sample_count,
+            SampleSizeBox::PerSample(sizes) => sizes.truncate(sample_count as usize),
+        }
+        truncate_runs(&mut self.time_to_sample.0, sample_count, |entry| {
+            &mut entry.sample_count
+        });
+        if let Some(composition_offset) = &mut self.composition_offset {
+            truncate_runs(&mut composition_offset.0, sample_count, |entry| {
+                &mut entry.sample_count
+            });
+        }
+        if let Some(sample_to_group) = &mut self.sample_to_group {
+            truncate_runs(&mut sample_to_group.1, sample_count, |entry| {
+                &mut entry.sample_count
+            });
+        }
+        if let Some(sample_dependency_type) = &mut self.sample_dependency_type {
+            sample_dependency_type.0.truncate(sample_count as usize);
+        }
+        if let Some(padding_bits) = &mut self.padding_bits {
+            padding_bits.0.truncate(sample_count as usize);
+        }
+
+        if let Some(sync_sample) = &mut self.sync_sample {
+            let sync_sample_count = sync_sample.0.len();
+            sync_sample
+                .0
+                .retain(|&sample| (1..=sample_count).contains(&sample));
+            if sync_sample.0.len() != sync_sample_count {
+                repairs.push(SampleTableRepair::DroppedSyncSamples {
+                    count: (sync_sample_count - sync_sample.0.len()) as u32,
+                });
+            }
+        }
+
+        repairs
+    }
+
+    /// Yields the offset, samples and sample_description_index of each chunk.
+    pub(crate) fn chunks(&self) -> impl Iterator<Item = (u64, Range<u32>, u32)> + '_ {
+        let sample_count = self.sample_size.sample_count();
+        let entries = &self.sample_to_chunk.0;
+        let mut entry_index = 0;
+        let mut sample = 0u32;
+        self.chunk_offset
+            .0
+            .iter()
+            .enumerate()
+            .map_while(move |(chunk_index, &offset)| {
+                let chunk = chunk_index as u32 + 1;
+                while entries
+                    .get(entry_index + 1)
+                    .is_some_and(|entry| entry.first_chunk <= chunk)
+                {
+                    entry_index += 1;
+                }
+                let entry = entries.get(entry_index)?;
+                let samples = sample
+                    ..sample
+                        .saturating_add(entry.samples_per_chunk)
+                        .min(sample_count);
+                sample = samples.end;
+                Some((offset, samples, entry.sample_description_index))
+            })
+    }
+}
+
+/// Fix applied by [SampleTableBox::repair].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleTableRepair {
+    /// stsz, stts and stsc disagreed on the number of samples
+    TruncatedSamples {
+        sample_size_count: u32,
+        time_to_sample_count: u64,
+        sample_count: u32,
+    },
+    /// chunks past the end of the data, or without samples
+    DroppedChunks {
+        chunk_count: u32,
+        new_chunk_count: u32,
+    },
+    /// stss referenced samples that don't exist
+    DroppedSyncSamples { count: u32 },
+}
+
+/// Shortens run-length coded entries, like those of stts, to cover at most
+/// `sample_count` samples.
+fn truncate_runs<T>(entries: &mut Vec<T>, sample_count: u32, run: impl Fn(&mut T) -> &mut u32) {
+    let mut remaining = sample_count;
+    entries.retain_mut(|entry| {
+        let count = run(entry);
+        *count = (*count).min(remaining);
+        remaining -= *count;
+        *count != 0
+    });
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -1087,10 +3701,39 @@ impl Decode for SampleTableBox {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Debug)]
-pub enum SampleDescriptionBox {
+pub struct SampleDescriptionBox(pub Vec<SampleEntry>);
+
+impl SampleDescriptionBox {
+    /// Returns the entry referenced by a 1-based sample_description_index.
+    pub fn get(&self, sample_description_index: u32) -> Option<&SampleEntry> {
+        self.0
+            .get((sample_description_index as usize).checked_sub(1)?)
+    }
+
+    /// Adds an entry and returns its sample_description_index.
+    pub fn push(&mut self, entry: SampleEntry) -> u32 {
+        self.0.push(entry);
+        self.0.len() as u32
+    }
+}
+
+#[derive(Debug)]
+pub enum SampleEntry {
     AV1(AV1SampleEntry),
     AVC(AVCSampleEntry),
+    HEVC(HEVCSampleEntry),
+    Uncompressed(UncompressedVideoSampleEntry),
+    V210(V210SampleEntry),
+    Intermediate(IntermediateVideoSampleEntry),
     AAC(AACSampleEntry),
+    MP3(MP3SampleEntry),
+    PCM(PCMSampleEntry),
+    LPCM(LPCMSampleEntry),
+    XMLMetaData(XMLMetaDataSampleEntry),
+    TextMetaData(TextMetaDataSampleEntry),
+    URIMeta(URIMetaSampleEntry),
+    /// entries of other codecs, kept as they are
+    Unknown(RawBox),
 }
 
 #[derive(Debug)]
@@ -1101,8 +3744,75 @@ pub struct VisualSampleEntry {
     pub horizresolution: U16F16,
     pub vertresolution: U16F16,
     pub frame_count: u16,
-    pub compressorname: [u8; 32],
+    pub compressorname: Compressorname,
     pub depth: u16,
+    pub colour: Option<ColourInformationBox>,
+    pub field: Option<FieldHandlingBox>,
+    pub gamma: Option<GammaLevelBox>,
+    pub coding_constraints: Option<CodingConstraintsBox>,
+    pub bit_rate: Option<BitRateBox>,
+    /// boxes of other types which the entry of the codec doesn't decode
+    /// either, like pasp and clap, and which are encoded after the known ones
+    pub extra_boxes: Vec<RawBox>,
+}
+
+impl VisualSampleEntry {
+    /// Takes the boxes which the entry of the codec didn't decode, except for
+    /// those already decoded by this one.
+    pub(crate) fn set_extra_boxes(&mut self, extra_boxes: Vec<RawBox>) {
+        self.extra_boxes = extra_boxes
+            .into_iter()
+            .filter(|extra_box| !VISUAL_SAMPLE_ENTRY_BOXES.contains(&extra_box.r#type))
+            .collect();
+    }
+
+    /// Returns the horizontal and vertical resolution in pixels per inch.
+    pub fn resolution(&self) -> (f64, f64) {
+        (self.horizresolution.to_num(), self.vertresolution.to_num())
+    }
+
+    /// Returns the size the frames are displayed with, which is the clean
+    /// aperture of the clap if present, stretched by the pixel aspect ratio of
+    /// the pasp.
+    pub fn display_dimensions(&self) -> (f64, f64) {
+        let extra_box = |r#type: &[u8; 4]| {
+            self.extra_boxes
+                .iter()
+                .find(|extra_box| &extra_box.r#type.to_bytes() == r#type)
+                .map(|extra_box| extra_box.data.as_slice())
+        };
+        // numerator and denominator of each value, unless one is 0
+        let fractions = |mut data: &[u8], count: usize| -> Option<Vec<f64>> {
+            (0..count)
+                .map(|_| {
+                    let numerator = u32::decode(&mut data).ok()?;
+                    let denominator = u32::decode(&mut data).ok()?;
+                    (numerator != 0 && denominator != 0)
+                        .then(|| numerator as f64 / denominator as f64)
+                })
+                .collect()
+        };
+
+        let (mut width, height) = match extra_box(b"clap").and_then(|data| fractions(data, 2)) {
+            Some(clean_aperture) => (clean_aperture[0], clean_aperture[1]),
+            None => (self.width as f64, self.height as f64),
+        };
+        if let Some(pixel_aspect_ratio) = extra_box(b"pasp").and_then(|data| fractions(data, 1)) {
+            width *= pixel_aspect_ratio[0];
+        }
+        (width, height)
+    }
+
+    pub fn set_resolution(&mut self, horizontal: f64, vertical: f64) -> Result<()> {
+        self.horizresolution =
+            U16F16::checked_from_num(horizontal).ok_or(Error::ValueOutOfRange {
+                field: "horizresolution",
+            })?;
+        self.vertresolution = U16F16::checked_from_num(vertical).ok_or(Error::ValueOutOfRange {
+            field: "vertresolution",
+        })?;
+        Ok(())
+    }
 }
 
 impl Encode for VisualSampleEntry {
@@ -1126,37 +3836,67 @@ impl Encode for VisualSampleEntry {
         self.vertresolution.encode(output)?;
         0u32.encode(output)?;
         self.frame_count.encode(output)?;
-        output.write_all(&self.compressorname)?;
+        self.compressorname.encode(output)?;
         self.depth.encode(output)?;
-        u16::MAX.encode(output) // pre_defined
+        u16::MAX.encode(output)?; // pre_defined
+
+        self.colour.encode(output)?;
+        self.field.encode(output)?;
+        self.gamma.encode(output)?;
+        self.coding_constraints.encode(output)?;
+        self.bit_rate.encode(output)?;
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+        Ok(())
     }
 }
 
 impl Decode for VisualSampleEntry {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
+        input.read_exact(&mut [0; 6])?; // reserved
         let data_reference_index = Decode::decode(input)?;
 
-        assert_eq!(u16::decode(input)?, 0); // pre_defined
-        assert_eq!(u16::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // pre_defined
-        assert_eq!(u32::decode(input)?, 0); // pre_defined
-        assert_eq!(u32::decode(input)?, 0); // pre_defined
+        // pre_defined and reserved in ISO/IEC 14496-12, but version,
+        // revision_level, vendor, temporal_quality and spatial_quality in
+        // QuickTime
+        u16::decode(input)?; // pre_defined
+        u16::decode(input)?; // reserved
+        u32::decode(input)?; // pre_defined
+        u32::decode(input)?; // pre_defined
+        u32::decode(input)?; // pre_defined
         let width = Decode::decode(input)?;
         let height = Decode::decode(input)?;
         let horizresolution = Decode::decode(input)?;
         let vertresolution = Decode::decode(input)?;
-        assert_eq!(u32::decode(input)?, 0); // reserved
+        u32::decode(input)?; // reserved
         let frame_count = Decode::decode(input)?;
-        let mut compressorname = [0u8; 32];
-        input.read_exact(&mut compressorname)?;
+        let compressorname = Decode::decode(input)?;
         let depth = Decode::decode(input)?;
-        assert_eq!(u16::decode(input)?, u16::MAX); // pre_defined
+        u16::decode(input)?; // pre_defined
+
+        // the boxes common to all visual sample entries are picked out
+        // without consuming them, the remaining ones are up to the entry
+        let mut colour = None;
+        let mut field = None;
+        let mut gamma = None;
+        let mut coding_constraints = None;
+        let mut bit_rate = None;
+        let mut children = *input;
+        while let Ok((r#type, mut data)) = decode_box_header(&mut children) {
+            match &r#type {
+                b"colr" if colour.is_none() => colour = Some(Decode::decode(&mut data)?),
+                b"fiel" if field.is_none() => field = Some(Decode::decode(&mut data)?),
+                b"gama" if gamma.is_none() => gamma = Some(Decode::decode(&mut data)?),
+                b"ccst" if coding_constraints.is_none() => {
+                    coding_constraints = Some(Decode::decode(&mut data)?)
+                }
+                b"btrt" if bit_rate.is_none() => bit_rate = Some(Decode::decode(&mut data)?),
+                _ => {}
+            }
+        }
+
         Ok(Self {
             data_reference_index,
             width,
@@ -1166,6 +3906,12 @@ impl Decode for VisualSampleEntry {
             frame_count,
             compressorname,
             depth,
+            colour,
+            field,
+            gamma,
+            coding_constraints,
+            bit_rate,
+            extra_boxes: Vec::new(),
         })
     }
 }
@@ -1176,6 +3922,81 @@ pub struct AudioSampleEntry {
     pub channelcount: u16,
     pub samplesize: u16,
     pub samplerate: U16F16,
+    pub version: SoundDescriptionVersion,
+    pub bit_rate: Option<BitRateBox>,
+    /// boxes of other types which the entry of the codec doesn't decode
+    /// either, like chnl, and which are encoded after the known ones
+    pub extra_boxes: Vec<RawBox>,
+}
+
+/// Extension of the QuickTime sound sample description, selected by the
+/// version in place of the first reserved field.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum SoundDescriptionVersion {
+    #[default]
+    V0,
+    V1 {
+        compression_id: i16,
+        samples_per_packet: u32,
+        bytes_per_packet: u32,
+        bytes_per_frame: u32,
+        bytes_per_sample: u32,
+    },
+    /// Supersedes channelcount, samplesize and samplerate, which only hold the
+    /// nearest values on decode and are ignored on encode.
+    V2 {
+        sample_rate: f64,
+        channel_count: u32,
+        bits_per_channel: u32,
+        format_specific_flags: u32,
+        bytes_per_packet: u32,
+        frames_per_packet: u32,
+    },
+}
+
+impl AudioSampleEntry {
+    /// Like [VisualSampleEntry::set_extra_boxes].
+    pub(crate) fn set_extra_boxes(&mut self, extra_boxes: Vec<RawBox>) {
+        self.extra_boxes = extra_boxes
+            .into_iter()
+            .filter(|extra_box| &extra_box.r#type.to_bytes() != b"btrt")
+            .collect();
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        match self.version {
+            SoundDescriptionVersion::V2 { sample_rate, .. } => sample_rate,
+            _ => self.samplerate.to_num(),
+        }
+    }
+
+    /// Sets the sample rate, which only with version 2 of the QuickTime sound
+    /// sample description can be 65536 Hz or more.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) -> Result<()> {
+        match &mut self.version {
+            SoundDescriptionVersion::V2 {
+                sample_rate: version_sample_rate,
+                ..
+            } if sample_rate.is_finite() && sample_rate >= 0.0 => {
+                *version_sample_rate = sample_rate;
+                self.samplerate = U16F16::saturating_from_num(sample_rate);
+            }
+            _ => {
+                self.samplerate =
+                    U16F16::checked_from_num(sample_rate).ok_or(Error::ValueOutOfRange {
+                        field: "samplerate",
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn channel_count(&self) -> u32 {
+        match self.version {
+            SoundDescriptionVersion::V2 { channel_count, .. } => channel_count,
+            _ => self.channelcount as u32,
+        }
+    }
 }
 
 impl Encode for AudioSampleEntry {
@@ -1188,38 +4009,143 @@ impl Encode for AudioSampleEntry {
         output.write_u8(0)?; // reserved
         self.data_reference_index.encode(output)?;
 
-        0u32.encode(output)?; // reserved
-        0u32.encode(output)?; // reserved
-        self.channelcount.encode(output)?;
-        self.samplesize.encode(output)?;
-        0u16.encode(output)?; // pre_defined
-        0u16.encode(output)?; // reserved
-        self.samplerate.encode(output)
+        match &self.version {
+            SoundDescriptionVersion::V0 => {
+                0u32.encode(output)?; // reserved
+                0u32.encode(output)?; // reserved
+                self.channelcount.encode(output)?;
+                self.samplesize.encode(output)?;
+                0u16.encode(output)?; // pre_defined
+                0u16.encode(output)?; // reserved
+                self.samplerate.encode(output)
+            }
+            SoundDescriptionVersion::V1 {
+                compression_id,
+                samples_per_packet,
+                bytes_per_packet,
+                bytes_per_frame,
+                bytes_per_sample,
+            } => {
+                1u16.encode(output)?; // version
+                0u16.encode(output)?; // revision_level
+                0u32.encode(output)?; // vendor
+                self.channelcount.encode(output)?;
+                self.samplesize.encode(output)?;
+                output.write_i16::<BigEndian>(*compression_id)?;
+                0u16.encode(output)?; // packet_size
+                self.samplerate.encode(output)?;
+
+                samples_per_packet.encode(output)?;
+                bytes_per_packet.encode(output)?;
+                bytes_per_frame.encode(output)?;
+                bytes_per_sample.encode(output)
+            }
+            SoundDescriptionVersion::V2 {
+                sample_rate,
+                channel_count,
+                bits_per_channel,
+                format_specific_flags,
+                bytes_per_packet,
+                frames_per_packet,
+            } => {
+                2u16.encode(output)?; // version
+                0u16.encode(output)?; // revision_level
+                0u32.encode(output)?; // vendor
+                3u16.encode(output)?; // always3
+                16u16.encode(output)?; // always16
+                output.write_i16::<BigEndian>(-2)?; // always_minus2
+                0u16.encode(output)?; // always0
+                0x10000u32.encode(output)?; // always65536
+
+                72u32.encode(output)?; // size_of_struct_only
+                output.write_f64::<BigEndian>(*sample_rate)?;
+                channel_count.encode(output)?;
+                0x7F000000u32.encode(output)?; // always7F000000
+                bits_per_channel.encode(output)?;
+                format_specific_flags.encode(output)?;
+                bytes_per_packet.encode(output)?;
+                frames_per_packet.encode(output)
+            }
+        }?;
+
+        self.bit_rate.encode(output)?;
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+        Ok(())
     }
 }
 
 impl Decode for AudioSampleEntry {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
-        assert_eq!(input.read_u8()?, 0); // reserved
+        input.read_exact(&mut [0; 6])?; // reserved
         let data_reference_index = Decode::decode(input)?;
 
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        assert_eq!(u32::decode(input)?, 0); // reserved
-        let channelcount = Decode::decode(input)?;
-        let samplesize = Decode::decode(input)?;
-        assert_eq!(u16::decode(input)?, 0); // pre_defined
-        assert_eq!(u16::decode(input)?, 0); // reserved
-        let samplerate = Decode::decode(input)?;
+        // reserved in ISO/IEC 14496-12, but version, revision_level and vendor
+        // in QuickTime
+        let version = u16::decode(input)?;
+        u16::decode(input)?; // revision_level
+        u32::decode(input)?; // vendor
+        let mut channelcount = Decode::decode(input)?;
+        let mut samplesize = Decode::decode(input)?;
+        let compression_id = input.read_i16::<BigEndian>()?;
+        u16::decode(input)?; // packet_size
+        let mut samplerate = Decode::decode(input)?;
+
+        let version = match version {
+            1 => SoundDescriptionVersion::V1 {
+                compression_id,
+                samples_per_packet: Decode::decode(input)?,
+                bytes_per_packet: Decode::decode(input)?,
+                bytes_per_frame: Decode::decode(input)?,
+                bytes_per_sample: Decode::decode(input)?,
+            },
+            2 => {
+                let size_of_struct_only = u32::decode(input)?;
+                let sample_rate = input.read_f64::<BigEndian>()?;
+                let channel_count = u32::decode(input)?;
+                u32::decode(input)?; // always7F000000
+                let bits_per_channel = u32::decode(input)?;
+                let format_specific_flags = Decode::decode(input)?;
+                let bytes_per_packet = Decode::decode(input)?;
+                let frames_per_packet = Decode::decode(input)?;
+                // skip any fields of later revisions
+                let extra = (size_of_struct_only as usize).saturating_sub(72);
+                *input = input.get(extra..).unwrap_or_default();
+
+                channelcount = channel_count.try_into().unwrap_or(u16::MAX);
+                samplesize = bits_per_channel.try_into().unwrap_or(u16::MAX);
+                samplerate = U16F16::saturating_from_num(sample_rate);
+                SoundDescriptionVersion::V2 {
+                    sample_rate,
+                    channel_count,
+                    bits_per_channel,
+                    format_specific_flags,
+                    bytes_per_packet,
+                    frames_per_packet,
+                }
+            }
+            _ => SoundDescriptionVersion::V0,
+        };
+
+        // like for visual sample entries, without consuming it
+        let mut bit_rate = None;
+        let mut children = *input;
+        while let Ok((r#type, mut data)) = decode_box_header(&mut children) {
+            if &r#type == b"btrt" && bit_rate.is_none() {
+                bit_rate = Some(Decode::decode(&mut data)?);
+            }
+        }
+
         Ok(Self {
             data_reference_index,
             channelcount,
             samplesize,
             samplerate,
+            version,
+            bit_rate,
+            extra_boxes: Vec::new(),
         })
     }
 }
@@ -1230,88 +4156,500 @@ impl Encode for SampleDescriptionBox {
         output.write_u8(0)?; // version
         output.write_u24::<BigEndian>(0)?; // flags
 
-        1u32.encode(output)?; // entry_count
+        checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
+        for entry in &self.0 {
+            entry.encode(output)?;
+        }
 
         update_box_header(output, begin)
     }
 }
 
-impl Decode for SampleDescriptionBox {
-    fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
-
-        let mut entry = None;
-
-        assert_eq!(u32::decode(input)?, 1); // entry_count
-        let size = u32::decode(input)?;
-        let r#type: [u8; 4] = u32::decode(input)?.to_be_bytes();
+impl SampleEntry {
+    /// Returns the index of the data entry in the dref, or None for unknown
+    /// entries too short to have one.
+    pub fn data_reference_index(&self) -> Option<u16> {
+        Some(match self {
+            Self::AV1(entry) => entry.base.data_reference_index,
+            Self::AVC(entry) => entry.base.data_reference_index,
+            Self::HEVC(entry) => entry.base.data_reference_index,
+            Self::Uncompressed(entry) => entry.base.data_reference_index,
+            Self::V210(entry) => entry.base.data_reference_index,
+            Self::Intermediate(entry) => entry.base.data_reference_index,
+            Self::AAC(entry) => entry.base.data_reference_index,
+            Self::MP3(entry) => entry.base.data_reference_index,
+            Self::PCM(entry) => entry.base.data_reference_index,
+            Self::LPCM(entry) => entry.data_reference_index,
+            Self::XMLMetaData(entry) => entry.data_reference_index,
+            Self::TextMetaData(entry) => entry.data_reference_index,
+            Self::URIMeta(entry) => entry.data_reference_index,
+            // all sample entries start with 6 reserved bytes and the index
+            Self::Unknown(entry) => u16::from_be_bytes(entry.data.get(6..8)?.try_into().ok()?),
+        })
+    }
 
-        let (mut data, remaining_data) = input.split_at((size - 4 - 4) as usize);
-        match &r#type {
-            b"av01" => entry = Some(SampleDescriptionBox::AV1(Decode::decode(&mut data)?)),
-            b"avc1" => entry = Some(SampleDescriptionBox::AVC(Decode::decode(&mut data)?)),
-            b"mp4a" => entry = Some(SampleDescriptionBox::AAC(Decode::decode(&mut data)?)),
-            _ => {}
+    pub fn visual(&self) -> Option<&VisualSampleEntry> {
+        match self {
+            Self::AV1(entry) => Some(&entry.base),
+            Self::AVC(entry) => Some(&entry.base),
+            Self::HEVC(entry) => Some(&entry.base),
+            Self::Uncompressed(entry) => Some(&entry.base),
+            Self::V210(entry) => Some(&entry.base),
+            Self::Intermediate(entry) => Some(&entry.base),
+            _ => None,
         }
-        *input = remaining_data;
-
-        Ok(entry.unwrap())
     }
-}
-
-////////////////////////////////////////////////////////////////////////////////////////////////////
-// ISO/IEC 14496-12:2008 8.6.1.2
-////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
-pub struct TimeToSampleBox(pub Vec<TimeToSampleEntry>);
+    pub fn visual_mut(&mut self) -> Option<&mut VisualSampleEntry> {
+        match self {
+            Self::AV1(entry) => Some(&mut entry.base),
+            Self::AVC(entry) => Some(&mut entry.base),
+            Self::HEVC(entry) => Some(&mut entry.base),
+            Self::Uncompressed(entry) => Some(&mut entry.base),
+            Self::V210(entry) => Some(&mut entry.base),
+            Self::Intermediate(entry) => Some(&mut entry.base),
+            _ => None,
+        }
+    }
 
-#[derive(Debug)]
-pub struct TimeToSampleEntry {
-    pub sample_count: u32,
-    pub sample_delta: u32,
+    /// Returns the codecs parameter of RFC 6381, from the configuration
+    /// record if the codec has one, otherwise the sample entry type.
+    pub fn codec_string(&self) -> Result<String> {
+        Ok(match self {
+            Self::AV1(entry) => entry.codec_string(),
+            Self::AVC(entry) => entry.codec_string(),
+            Self::HEVC(entry) => entry.codec_string(),
+            Self::Uncompressed(_) => "uncv".to_owned(),
+            Self::V210(_) => "v210".to_owned(),
+            Self::Intermediate(entry) => format!("{:?}", entry.codec),
+            Self::AAC(entry) => entry.codec_string()?,
+            Self::MP3(_) => "mp3".to_owned(),
+            Self::PCM(entry) => match entry.format {
+                PCMFormat::Integer => "ipcm",
+                PCMFormat::FloatingPoint => "fpcm",
+            }
+            .to_owned(),
+            Self::LPCM(_) => "lpcm".to_owned(),
+            Self::XMLMetaData(_) => "metx".to_owned(),
+            Self::TextMetaData(_) => "mett".to_owned(),
+            Self::URIMeta(_) => "urim".to_owned(),
+            Self::Unknown(entry) => format!("{:?}", entry.r#type),
+        })
+    }
 }
 
-impl Encode for TimeToSampleBox {
+impl Encode for SampleEntry {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"stts")?;
-        output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(0)?; // flags
-
-        (self.0.len() as u32).encode(output)?;
-        for entry in &self.0 {
-            entry.sample_count.encode(output)?;
-            entry.sample_delta.encode(output)?;
+        match self {
+            SampleEntry::AV1(entry) => entry.encode(output),
+            SampleEntry::AVC(entry) => entry.encode(output),
+            SampleEntry::HEVC(entry) => entry.encode(output),
+            SampleEntry::Uncompressed(entry) => entry.encode(output),
+            SampleEntry::V210(entry) => entry.encode(output),
+            SampleEntry::Intermediate(entry) => entry.encode(output),
+            SampleEntry::AAC(entry) => entry.encode(output),
+            SampleEntry::MP3(entry) => entry.encode(output),
+            SampleEntry::PCM(entry) => entry.encode(output),
+            SampleEntry::LPCM(entry) => entry.encode(output),
+            SampleEntry::XMLMetaData(entry) => entry.encode(output),
+            SampleEntry::TextMetaData(entry) => entry.encode(output),
+            SampleEntry::URIMeta(entry) => entry.encode(output),
+            SampleEntry::Unknown(entry) => entry.encode(output),
         }
-
-        update_box_header(output, begin)
     }
 }
 
-impl Decode for TimeToSampleBox {
+impl Decode for SampleDescriptionBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
         input.read_u24::<BigEndian>()?; // flags
 
         let entry_count = u32::decode(input)?;
-        let mut entries = Vec::default();
+        let mut entries = Vec::new();
         for _ in 0..entry_count {
-            let sample_count = Decode::decode(input)?;
-            let sample_delta = Decode::decode(input)?;
-            entries.push(TimeToSampleEntry {
-                sample_count,
-                sample_delta,
-            });
+            entries.push(SampleEntry::decode(input)?);
         }
+
         Ok(Self(entries))
     }
 }
 
-////////////////////////////////////////////////////////////////////////////////////////////////////
-// ISO/IEC 14496-12:2008 8.6.2
-////////////////////////////////////////////////////////////////////////////////////////////////////
-
+impl Decode for SampleEntry {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let (r#type, mut data) = decode_box_header(input)?;
+        Ok(match &r#type {
+            b"av01" => SampleEntry::AV1(Decode::decode(&mut data)?),
+            b"avc1" | b"avc2" | b"avc3" | b"avc4" => {
+                SampleEntry::AVC(AVCSampleEntry::decode(&mut data, r#type.into())?)
+            }
+            b"hvc1" | b"hev1" => {
+                SampleEntry::HEVC(HEVCSampleEntry::decode(&mut data, r#type.into())?)
+            }
+            b"uncv" => SampleEntry::Uncompressed(Decode::decode(&mut data)?),
+            b"v210" => SampleEntry::V210(Decode::decode(&mut data)?),
+            b"apco" | b"apcs" | b"apcn" | b"apch" | b"ap4h" | b"ap4x" | b"AVdn" | b"AVdh" => {
+                SampleEntry::Intermediate(IntermediateVideoSampleEntry::decode(
+                    &mut data,
+                    r#type.into(),
+                )?)
+            }
+            b"mp4a" => {
+                let AACSampleEntry { base, es } = Decode::decode(&mut data)?;
+                match es.0.decoder_config.object_type_indication {
+                    // ISO/IEC 13818-3, ISO/IEC 11172-3
+                    0x69 | 0x6B => SampleEntry::MP3(MP3SampleEntry {
+                        codec: MP3SampleEntry::MP4A,
+                        base,
+                        es: Some(es),
+                    }),
+                    _ => SampleEntry::AAC(AACSampleEntry { base, es }),
+                }
+            }
+            b".mp3" => SampleEntry::MP3(MP3SampleEntry::decode(&mut data, r#type.into())?),
+            b"ipcm" => SampleEntry::PCM(PCMSampleEntry::decode(&mut data, PCMFormat::Integer)?),
+            b"fpcm" => {
+                SampleEntry::PCM(PCMSampleEntry::decode(&mut data, PCMFormat::FloatingPoint)?)
+            }
+            b"lpcm" => SampleEntry::LPCM(Decode::decode(&mut data)?),
+            b"metx" => SampleEntry::XMLMetaData(Decode::decode(&mut data)?),
+            b"mett" => SampleEntry::TextMetaData(Decode::decode(&mut data)?),
+            b"urim" => SampleEntry::URIMeta(Decode::decode(&mut data)?),
+            _ => SampleEntry::Unknown(RawBox {
+                r#type: r#type.into(),
+                data: data.to_vec(),
+            }),
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.5.2.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+define_box! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct BitRateBox: box b"btrt" {
+        pub buffer_size_db: u32,
+        pub max_bitrate: u32,
+        pub avg_bitrate: u32,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2015 12.1.5
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub enum ColourInformationBox {
+    Nclx {
+        colour_primaries: u16,
+        transfer_characteristics: u16,
+        matrix_coefficients: u16,
+        full_range: bool,
+    },
+    /// QuickTime variant of nclx, without the full range flag.
+    Nclc {
+        colour_primaries: u16,
+        transfer_characteristics: u16,
+        matrix_coefficients: u16,
+    },
+    Icc {
+        restricted: bool,
+        #[derivative(Debug = "ignore")]
+        profile: Vec<u8>,
+    },
+    Unknown {
+        colour_type: FourCC,
+        #[derivative(Debug = "ignore")]
+        data: Vec<u8>,
+    },
+}
+
+impl Encode for ColourInformationBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"colr")?;
+
+        match self {
+            ColourInformationBox::Nclx {
+                colour_primaries,
+                transfer_characteristics,
+                matrix_coefficients,
+                full_range,
+            } => {
+                output.write_all(b"nclx")?;
+                colour_primaries.encode(output)?;
+                transfer_characteristics.encode(output)?;
+                matrix_coefficients.encode(output)?;
+                output.write_u8(if *full_range { 1 << 7 } else { 0 })?;
+            }
+            ColourInformationBox::Nclc {
+                colour_primaries,
+                transfer_characteristics,
+                matrix_coefficients,
+            } => {
+                output.write_all(b"nclc")?;
+                colour_primaries.encode(output)?;
+                transfer_characteristics.encode(output)?;
+                matrix_coefficients.encode(output)?;
+            }
+            ColourInformationBox::Icc {
+                restricted,
+                profile,
+            } => {
+                output.write_all(if *restricted { b"rICC" } else { b"prof" })?;
+                output.write_all(profile)?;
+            }
+            ColourInformationBox::Unknown { colour_type, data } => {
+                colour_type.0.encode(output)?;
+                output.write_all(data)?;
+            }
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ColourInformationBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let colour_type = u32::decode(input)?;
+        let value = match &colour_type.to_be_bytes() {
+            b"nclx" => ColourInformationBox::Nclx {
+                colour_primaries: Decode::decode(input)?,
+                transfer_characteristics: Decode::decode(input)?,
+                matrix_coefficients: Decode::decode(input)?,
+                full_range: input.read_u8()? & 1 << 7 != 0,
+            },
+            b"nclc" => ColourInformationBox::Nclc {
+                colour_primaries: Decode::decode(input)?,
+                transfer_characteristics: Decode::decode(input)?,
+                matrix_coefficients: Decode::decode(input)?,
+            },
+            b"rICC" | b"prof" => ColourInformationBox::Icc {
+                restricted: &colour_type.to_be_bytes() == b"rICC",
+                profile: input.to_owned(),
+            },
+            _ => ColourInformationBox::Unknown {
+                colour_type: FourCC(colour_type),
+                data: input.to_owned(),
+            },
+        };
+        *input = &input[input.len()..];
+        Ok(value)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// QuickTime File Format, Field Handling Atom
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldHandlingBox {
+    pub field_count: u8,
+    pub field_ordering: u8,
+}
+
+impl FieldHandlingBox {
+    pub fn is_interlaced(&self) -> bool {
+        self.field_count == 2
+    }
+
+    /// Returns whether the top field is first in time, if interlaced.
+    pub fn top_field_first(&self) -> Option<bool> {
+        match (self.field_count, self.field_ordering) {
+            (2, 1 | 9) => Some(true),
+            (2, 6 | 14) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl Encode for FieldHandlingBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"fiel")?;
+
+        output.write_u8(self.field_count)?;
+        output.write_u8(self.field_ordering)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for FieldHandlingBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            field_count: input.read_u8()?,
+            field_ordering: input.read_u8()?,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// QuickTime File Format, Gamma Level Atom
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GammaLevelBox {
+    pub gamma: U16F16,
+}
+
+impl GammaLevelBox {
+    pub fn from_f64(gamma: f64) -> Result<Self> {
+        Ok(Self {
+            gamma: U16F16::checked_from_num(gamma)
+                .ok_or(Error::ValueOutOfRange { field: "gamma" })?,
+        })
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.gamma.to_num()
+    }
+}
+
+impl Encode for GammaLevelBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"gama")?;
+
+        self.gamma.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for GammaLevelBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            gamma: Decode::decode(input)?,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.6.1.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct TimeToSampleBox(pub Vec<TimeToSampleEntry>);
+
+#[derive(Debug)]
+pub struct TimeToSampleEntry {
+    pub sample_count: u32,
+    pub sample_delta: u32,
+}
+
+impl TimeToSampleBox {
+    pub fn constant(sample_count: u32, sample_delta: u32) -> Self {
+        Self(vec![TimeToSampleEntry {
+            sample_count,
+            sample_delta,
+        }])
+    }
+
+    /// Like [Decode::decode], but into `entries`, which is cleared first, so
+    /// that its allocation can be reused when parsing many files.
+    pub fn decode_into(input: &mut &[u8], entries: &mut Vec<TimeToSampleEntry>) -> Result<()> {
+        entries.clear();
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let entry_count = u32::decode(input)?;
+        entries.reserve((entry_count as usize).min(input.len() / 8));
+        for _ in 0..entry_count {
+            let sample_count = Decode::decode(input)?;
+            let sample_delta = Decode::decode(input)?;
+            entries.push(TimeToSampleEntry {
+                sample_count,
+                sample_delta,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Encode for TimeToSampleBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"stts")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
+        for entry in &self.0 {
+            entry.sample_count.encode(output)?;
+            entry.sample_delta.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for TimeToSampleBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut entries = Vec::default();
+        Self::decode_into(input, &mut entries)?;
+        Ok(Self(entries))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.6.1.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct CompositionOffsetBox(pub Vec<CompositionOffsetEntry>);
+
+#[derive(Debug)]
+pub struct CompositionOffsetEntry {
+    pub sample_count: u32,
+    pub sample_offset: i32,
+}
+
+impl Encode for CompositionOffsetBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"ctts")?;
+        output.write_u8(if self.0.iter().any(|entry| entry.sample_offset < 0) {
+            1
+        } else {
+            0
+        })?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
+        for entry in &self.0 {
+            entry.sample_count.encode(output)?;
+            output.write_i32::<BigEndian>(entry.sample_offset)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for CompositionOffsetBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_u8()?; // version
+        input.read_u24::<BigEndian>()?; // flags
+
+        let entry_count = u32::decode(input)?;
+        let mut entries = Vec::new();
+        for _ in 0..entry_count {
+            let sample_count = Decode::decode(input)?;
+            let sample_offset = input.read_i32::<BigEndian>()?;
+            entries.push(CompositionOffsetEntry {
+                sample_count,
+                sample_offset,
+            });
+        }
+        Ok(Self(entries))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.6.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct SyncSampleBox(#[derivative(Debug = "ignore")] pub Vec<u32>);
@@ -1322,7 +4660,7 @@ impl Encode for SyncSampleBox {
         output.write_u8(0)?; // version
         output.write_u24::<BigEndian>(0)?; // flags
 
-        (self.0.len() as u32).encode(output)?;
+        checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
         for entry in &self.0 {
             entry.encode(output)?;
         }
@@ -1333,7 +4671,9 @@ impl Encode for SyncSampleBox {
 
 impl Decode for SyncSampleBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
         input.read_u24::<BigEndian>()?; // flags
 
         let entry_count = u32::decode(input)?;
@@ -1346,6 +4686,75 @@ impl Decode for SyncSampleBox {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.6.4
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct SampleDependencyTypeBox(#[derivative(Debug = "ignore")] pub Vec<SampleDependency>);
+
+/// is_leading, sample_depends_on, sample_is_depended_on and
+/// sample_has_redundancy, each 0 if unknown, 1 for yes and 2 for no.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SampleDependency(pub u8);
+
+impl SampleDependency {
+    fn value(self, shift: u8) -> Option<bool> {
+        match self.0 >> shift & 0x3 {
+            0 => None,
+            2 => Some(false),
+            // 3 is only defined for is_leading, a leading sample without
+            // dependency on the previous sync sample
+            _ => Some(true),
+        }
+    }
+
+    pub fn is_leading(self) -> Option<bool> {
+        self.value(6)
+    }
+
+    pub fn depends_on_others(self) -> Option<bool> {
+        self.value(4)
+    }
+
+    pub fn is_depended_on(self) -> Option<bool> {
+        self.value(2)
+    }
+
+    pub fn has_redundancy(self) -> Option<bool> {
+        self.value(0)
+    }
+}
+
+impl Encode for SampleDependencyTypeBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"sdtp")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        for entry in &self.0 {
+            output.write_u8(entry.0)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for SampleDependencyTypeBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        // the sample count is only known from stsz
+        let entries = input.iter().map(|&entry| SampleDependency(entry)).collect();
+        *input = &input[input.len()..];
+        Ok(Self(entries))
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // ISO/IEC 14496-12:2008 8.6.5
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -1353,6 +4762,7 @@ impl Decode for SyncSampleBox {
 #[derive(Debug)]
 pub struct EditBox {
     pub edit_list: Option<EditListBox>,
+    pub extra_boxes: Vec<RawBox>,
 }
 
 impl Encode for EditBox {
@@ -1361,6 +4771,10 @@ impl Encode for EditBox {
 
         self.edit_list.encode(output)?;
 
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
         update_box_header(output, begin)
     }
 }
@@ -1369,12 +4783,18 @@ impl Decode for EditBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
         let mut edit_list = None;
 
+        let mut extra_boxes = Vec::new();
+
         decode_boxes! {
             input,
+            unknown extra_boxes,
             optional elst edit_list,
         }
 
-        Ok(Self { edit_list })
+        Ok(Self {
+            edit_list,
+            extra_boxes,
+        })
     }
 }
 
@@ -1387,21 +4807,34 @@ pub struct EditListBox(pub Vec<EditListEntry>);
 
 #[derive(Debug)]
 pub struct EditListEntry {
-    pub segment_duration: u64,
-    pub media_time: u64,
+    pub segment_duration: MovieTime,
+    pub media_time: i64,
     pub media_rate: U16F16,
 }
 
+impl EditListEntry {
+    /// Returns the media rate, which unlike the type of the field is signed.
+    pub fn rate(&self) -> f64 {
+        self.media_rate.to_bits() as i32 as f64 / 65536.0
+    }
+
+    pub fn set_rate(&mut self, rate: f64) -> Result<()> {
+        self.media_rate =
+            U16F16::from_bits(signed_fixed_bits::<i32>(rate, 16, "media_rate")? as u32);
+        Ok(())
+    }
+}
+
 impl Encode for EditListBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
         let begin = encode_box_header(output, *b"elst")?;
         output.write_u8(0)?; // version
         output.write_u24::<BigEndian>(0)?; // flags
 
-        (self.0.len() as u32).encode(output)?;
+        checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
         for entry in &self.0 {
-            (entry.segment_duration as u32).encode(output)?;
-            (entry.media_time as u32).encode(output)?;
+            checked_cast::<u32>(entry.segment_duration.0, "segment_duration")?.encode(output)?;
+            output.write_i32::<BigEndian>(checked_cast(entry.media_time, "media_time")?)?;
             entry.media_rate.encode(output)?;
         }
 
@@ -1421,14 +4854,14 @@ impl Decode for EditListBox {
             let media_time;
             match version {
                 0 => {
-                    segment_duration = u32::decode(input)? as u64;
-                    media_time = u32::decode(input)? as u64;
+                    segment_duration = MovieTime(u32::decode(input)? as u64);
+                    media_time = input.read_i32::<BigEndian>()? as i64;
                 }
                 1 => {
                     segment_duration = Decode::decode(input)?;
-                    media_time = Decode::decode(input)?;
+                    media_time = input.read_i64::<BigEndian>()?;
                 }
-                _ => panic!(),
+                _ => return Err(Error::ValueOutOfRange { field: "version" }),
             }
             let media_rate = Decode::decode(input)?;
             entries.push(EditListEntry {
@@ -1448,12 +4881,14 @@ impl Decode for EditListBox {
 #[derive(Debug)]
 pub struct DataInformationBox {
     pub reference: DataReferenceBox,
+    pub extra_boxes: Vec<RawBox>,
 }
 
 impl Default for DataInformationBox {
     fn default() -> Self {
         Self {
             reference: DataReferenceBox(vec![DataEntry::Url(DataEntryUrlBox { location: None })]),
+            extra_boxes: Vec::new(),
         }
     }
 }
@@ -1464,6 +4899,10 @@ impl Encode for DataInformationBox {
 
         self.reference.encode(output)?;
 
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
         update_box_header(output, begin)
     }
 }
@@ -1472,12 +4911,18 @@ impl Decode for DataInformationBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
         let mut reference = None;
 
+        let mut extra_boxes = Vec::new();
+
         decode_boxes! {
             input,
+            unknown extra_boxes,
             required dref reference,
         }
 
-        Ok(Self { reference })
+        Ok(Self {
+            reference,
+            extra_boxes,
+        })
     }
 }
 
@@ -1500,6 +4945,18 @@ pub enum DataEntry {
     Urn(DataEntryUrnBox),
 }
 
+impl DataEntry {
+    /// Returns the location of the data, or None if it is in this file, as
+    /// signaled by the self-contained flag.
+    pub fn external_location(&self) -> Option<&str> {
+        match self {
+            Self::Url(entry) => entry.location.as_deref(),
+            Self::Urn(entry) if entry.location.is_empty() => Some(&entry.name),
+            Self::Urn(entry) => Some(&entry.location),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct DataEntryUrlBox {
     pub location: Option<String>,
@@ -1519,7 +4976,9 @@ impl Encode for DataEntryUrlBox {
 
 impl Decode for DataEntryUrlBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
         let flags = input.read_u24::<BigEndian>()?; // flags
 
         let location = if flags & 1 << 0 == 0 {
@@ -1552,7 +5011,9 @@ impl Encode for DataEntryUrnBox {
 
 impl Decode for DataEntryUrnBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
         input.read_u24::<BigEndian>()?; // flags
 
         let name = Decode::decode(input)?;
@@ -1567,7 +5028,7 @@ impl Encode for DataReferenceBox {
         output.write_u8(0)?; // version
         output.write_u24::<BigEndian>(0)?; // flags
 
-        (self.0.len() as u32).encode(output)?;
+        checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
         for entry in &self.0 {
             match entry {
                 DataEntry::Url(entry) => entry.encode(output),
@@ -1581,16 +5042,15 @@ impl Encode for DataReferenceBox {
 
 impl Decode for DataReferenceBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
         input.read_u24::<BigEndian>()?; // flags
 
         let entry_count = u32::decode(input)?;
         let mut entries = Vec::default();
         for _ in 0..entry_count {
-            let size = u32::decode(input)?;
-            let r#type: [u8; 4] = u32::decode(input)?.to_be_bytes();
-
-            let (mut data, remaining_data) = input.split_at((size - 4 - 4) as usize);
+            let (r#type, mut data) = decode_box_header(input)?;
             match &r#type {
                 b"url " => {
                     entries.push(DataEntry::Url(Decode::decode(&mut data)?));
@@ -1600,7 +5060,6 @@ impl Decode for DataReferenceBox {
                 }
                 _ => {}
             }
-            *input = remaining_data;
         }
         Ok(Self(entries))
     }
@@ -1617,6 +5076,50 @@ pub enum SampleSizeBox {
     PerSample(#[derivative(Debug = "ignore")] Vec<u32>),
 }
 
+impl SampleSizeBox {
+    pub fn sample_count(&self) -> u32 {
+        match self {
+            SampleSizeBox::Value { sample_count, .. } => *sample_count,
+            SampleSizeBox::PerSample(samples) => samples.len() as u32,
+        }
+    }
+
+    pub fn sample_size(&self, index: u32) -> Option<u32> {
+        match self {
+            SampleSizeBox::Value {
+                sample_size,
+                sample_count,
+            } => (index < *sample_count).then_some(*sample_size),
+            SampleSizeBox::PerSample(samples) => samples.get(index as usize).copied(),
+        }
+    }
+
+    /// Like [Decode::decode], but into `sizes`, which is cleared first, so
+    /// that its allocation can be reused when parsing many files.
+    ///
+    /// Returns the sample count, and the size of all samples if they have the
+    /// same, in which case `sizes` stays empty.
+    pub fn decode_into(input: &mut &[u8], sizes: &mut Vec<u32>) -> Result<(u32, Option<u32>)> {
+        sizes.clear();
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let sample_size = Decode::decode(input)?;
+        let sample_count = Decode::decode(input)?;
+        if sample_size != 0 {
+            return Ok((sample_count, Some(sample_size)));
+        }
+        sizes.reserve((sample_count as usize).min(input.len() / 4));
+        for _ in 0..sample_count {
+            let entry_size = Decode::decode(input)?;
+            sizes.push(entry_size);
+        }
+        Ok((sample_count, None))
+    }
+}
+
 impl Encode for SampleSizeBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
         let begin = encode_box_header(output, *b"stsz")?;
@@ -1633,7 +5136,7 @@ impl Encode for SampleSizeBox {
             }
             SampleSizeBox::PerSample(samples) => {
                 0u32.encode(output)?; // sample_size
-                (samples.len() as u32).encode(output)?;
+                checked_cast::<u32>(samples.len(), "sample_count")?.encode(output)?;
                 for sample in samples {
                     sample.encode(output)?;
                 }
@@ -1646,23 +5149,14 @@ impl Encode for SampleSizeBox {
 
 impl Decode for SampleSizeBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
-
-        let sample_size = Decode::decode(input)?;
-        let sample_count = Decode::decode(input)?;
-        if sample_size != 0 {
-            return Ok(SampleSizeBox::Value {
+        let mut samples = Vec::default();
+        Ok(match Self::decode_into(input, &mut samples)? {
+            (sample_count, Some(sample_size)) => SampleSizeBox::Value {
                 sample_size,
                 sample_count,
-            });
-        }
-        let mut samples = Vec::default();
-        for _ in 0..sample_count {
-            let entry_size = Decode::decode(input)?;
-            samples.push(entry_size);
-        }
-        Ok(SampleSizeBox::PerSample(samples))
+            },
+            (_, None) => SampleSizeBox::PerSample(samples),
+        })
     }
 }
 
@@ -1681,13 +5175,44 @@ pub struct SampleToChunkEntry {
     pub sample_description_index: u32,
 }
 
+impl SampleToChunkBox {
+    /// Returns the 0-based chunk containing a 0-based sample, and the index of
+    /// the sample within that chunk.
+    pub fn chunk_for_sample(&self, sample_index: u32, chunk_count: u32) -> Option<(u32, u32)> {
+        // first sample of each entry, the last entry runs up to chunk_count
+        let mut first_samples = Vec::with_capacity(self.0.len());
+        let mut first_sample = 0u64;
+        for (index, entry) in self.0.iter().enumerate() {
+            first_samples.push(first_sample);
+            let next_first_chunk = self
+                .0
+                .get(index + 1)
+                .map_or(chunk_count.saturating_add(1), |entry| entry.first_chunk);
+            let chunks = next_first_chunk.saturating_sub(entry.first_chunk) as u64;
+            first_sample += chunks * entry.samples_per_chunk as u64;
+        }
+        if sample_index as u64 >= first_sample {
+            return None;
+        }
+
+        let index =
+            first_samples.partition_point(|&first_sample| first_sample <= sample_index as u64) - 1;
+        let entry = &self.0[index];
+        let offset = sample_index as u64 - first_samples[index];
+        let chunk =
+            (entry.first_chunk as u64).saturating_sub(1) + offset / entry.samples_per_chunk as u64;
+        let index_within_chunk = offset % entry.samples_per_chunk as u64;
+        Some((chunk as u32, index_within_chunk as u32))
+    }
+}
+
 impl Encode for SampleToChunkBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
         let begin = encode_box_header(output, *b"stsc")?;
         output.write_u8(0)?; // version
         output.write_u24::<BigEndian>(0)?; // flags
 
-        (self.0.len() as u32).encode(output)?;
+        checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
         for entry in &self.0 {
             entry.first_chunk.encode(output)?;
             entry.samples_per_chunk.encode(output)?;
@@ -1700,7 +5225,9 @@ impl Encode for SampleToChunkBox {
 
 impl Decode for SampleToChunkBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
         input.read_u24::<BigEndian>()?; // flags
 
         let entry_count = u32::decode(input)?;
@@ -1725,17 +5252,48 @@ impl Decode for SampleToChunkBox {
 
 #[derive(Derivative)]
 #[derivative(Debug)]
-pub struct ChunkOffsetBox(#[derivative(Debug = "ignore")] pub Vec<u32>);
+pub struct ChunkOffsetBox(#[derivative(Debug = "ignore")] pub Vec<u64>);
+
+impl ChunkOffsetBox {
+    /// Like [Decode::decode], but into `offsets`, which is cleared first, so
+    /// that its allocation can be reused when parsing many files. `large` is
+    /// set for co64 and not for stco.
+    pub fn decode_into(input: &mut &[u8], large: bool, offsets: &mut Vec<u64>) -> Result<()> {
+        offsets.clear();
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let entry_count = u32::decode(input)?;
+        let entry_size = if large { 8 } else { 4 };
+        offsets.reserve((entry_count as usize).min(input.len() / entry_size));
+        for _ in 0..entry_count {
+            let chunk_offset = if large {
+                Decode::decode(input)?
+            } else {
+                u32::decode(input)? as u64
+            };
+            offsets.push(chunk_offset);
+        }
+        Ok(())
+    }
+}
 
 impl Encode for ChunkOffsetBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"stco")?;
+        let large = self.0.iter().any(|&entry| entry > u32::MAX as u64);
+        let begin = encode_box_header(output, if large { *b"co64" } else { *b"stco" })?;
         output.write_u8(0)?; // version
         output.write_u24::<BigEndian>(0)?; // flags
 
-        (self.0.len() as u32).encode(output)?;
-        for entry in &self.0 {
-            entry.encode(output)?;
+        checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
+        for &entry in &self.0 {
+            if large {
+                entry.encode(output)?;
+            } else {
+                (entry as u32).encode(output)?;
+            }
         }
 
         update_box_header(output, begin)
@@ -1744,189 +5302,2737 @@ impl Encode for ChunkOffsetBox {
 
 impl Decode for ChunkOffsetBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
-
-        let entry_count = u32::decode(input)?;
         let mut entries = Vec::default();
-        for _ in 0..entry_count {
-            let chunk_offset = Decode::decode(input)?;
-            entries.push(chunk_offset);
-        }
+        Self::decode_into(input, false, &mut entries)?;
         Ok(Self(entries))
     }
 }
 
+pub(crate) struct ChunkLargeOffsetBox(pub(crate) ChunkOffsetBox);
+
+impl Decode for ChunkLargeOffsetBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut entries = Vec::default();
+        ChunkOffsetBox::decode_into(input, true, &mut entries)?;
+        Ok(Self(ChunkOffsetBox(entries)))
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
-// ISO/IEC 14496-12:2008 8.9.2
+// ISO/IEC 14496-12:2008 8.7.6
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
-pub struct SampleToGroupBox(pub FourCC, pub Vec<SampleToGroupEntry>);
+/// Number of bits at the end of each sample which are not part of it, from 0
+/// to 7.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct PaddingBitsBox(#[derivative(Debug = "ignore")] pub Vec<u8>);
 
-#[derive(Debug)]
-pub struct SampleToGroupEntry {
+impl Encode for PaddingBitsBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"padb")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        checked_cast::<u32>(self.0.len(), "padding_bits")?.encode(output)?;
+        for pads in self.0.chunks(2) {
+            if pads.iter().any(|&pad| pad > 0x7) {
+                return Err(Error::ValueOutOfRange {
+                    field: "padding_bits",
+                });
+            }
+            // reserved bits are 0
+            output.write_u8(pads[0] << 4 | pads.get(1).copied().unwrap_or(0))?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for PaddingBitsBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_u8()?; // version
+        input.read_u24::<BigEndian>()?; // flags
+
+        let sample_count = u32::decode(input)? as usize;
+        if sample_count.div_ceil(2) > input.len() {
+            return Err(Error::ValueOutOfRange {
+                field: "sample_count",
+            });
+        }
+        let mut pads = Vec::with_capacity(sample_count);
+        for _ in 0..sample_count.div_ceil(2) {
+            let value = input.read_u8()?;
+            pads.push(value >> 4 & 0x7);
+            pads.push(value & 0x7);
+        }
+        pads.truncate(sample_count);
+        Ok(Self(pads))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.7.8
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Sizes of the auxiliary information of each sample, like the IVs and
+/// subsamples of CENC.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct SampleAuxiliaryInformationSizesBox {
+    /// aux_info_type and aux_info_type_parameter, if not implied by the
+    /// sample entry
+    pub aux_info_type: Option<(FourCC, u32)>,
+    /// 0 if the sizes differ
+    pub default_sample_info_size: u8,
     pub sample_count: u32,
-    pub group_description_index: u32,
+    /// only if the default_sample_info_size is 0
+    #[derivative(Debug = "ignore")]
+    pub sample_info_sizes: Vec<u8>,
 }
 
-impl Encode for SampleToGroupBox {
+impl SampleAuxiliaryInformationSizesBox {
+    /// Uses a default size if all sizes are the same.
+    pub fn new(aux_info_type: Option<(FourCC, u32)>, sizes: Vec<u8>) -> Result<Self> {
+        let sample_count = checked_cast(sizes.len(), "sample_count")?;
+        Ok(match sizes.first() {
+            Some(&size) if size != 0 && sizes.iter().all(|&other_size| other_size == size) => {
+                Self {
+                    aux_info_type,
+                    default_sample_info_size: size,
+                    sample_count,
+                    sample_info_sizes: Vec::new(),
+                }
+            }
+            _ => Self {
+                aux_info_type,
+                default_sample_info_size: 0,
+                sample_count,
+                sample_info_sizes: sizes,
+            },
+        })
+    }
+}
+
+impl Encode for SampleAuxiliaryInformationSizesBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"sbgp")?;
+        let begin = encode_box_header(output, *b"saiz")?;
         output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(0)?; // flags
+        output.write_u24::<BigEndian>(self.aux_info_type.is_some() as u32)?; // flags
 
-        self.0 .0.encode(output)?;
-        (self.1.len() as u32).encode(output)?;
-        for entry in &self.1 {
-            entry.sample_count.encode(output)?;
-            entry.group_description_index.encode(output)?;
+        if let Some((aux_info_type, aux_info_type_parameter)) = self.aux_info_type {
+            aux_info_type.0.encode(output)?;
+            aux_info_type_parameter.encode(output)?;
+        }
+        output.write_u8(self.default_sample_info_size)?;
+        self.sample_count.encode(output)?;
+        if self.default_sample_info_size == 0 {
+            if self.sample_info_sizes.len() != self.sample_count as usize {
+                return Err(Error::ValueOutOfRange {
+                    field: "sample_count",
+                });
+            }
+            output.write_all(&self.sample_info_sizes)?;
         }
 
         update_box_header(output, begin)
     }
 }
 
-impl Decode for SampleToGroupBox {
+impl Decode for SampleAuxiliaryInformationSizesBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
+        input.read_u8()?; // version
+        let flags = input.read_u24::<BigEndian>()?;
 
-        let grouping_type = FourCC(Decode::decode(input)?);
+        let aux_info_type = if flags & 1 != 0 {
+            Some((FourCC(Decode::decode(input)?), Decode::decode(input)?))
+        } else {
+            None
+        };
+        let default_sample_info_size = input.read_u8()?;
+        let sample_count = u32::decode(input)?;
+        let mut sample_info_sizes = Vec::new();
+        if default_sample_info_size == 0 {
+            if sample_count as u64 > input.len() as u64 {
+                return Err(Error::ValueOutOfRange {
+                    field: "sample_count",
+                });
+            }
+            sample_info_sizes = vec![0; sample_count as usize];
+            input.read_exact(&mut sample_info_sizes)?;
+        }
+        Ok(Self {
+            aux_info_type,
+            default_sample_info_size,
+            sample_count,
+            sample_info_sizes,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.7.9
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Where the auxiliary information of the samples is, one offset per chunk,
+/// or a single one for all samples of a track fragment.
+#[derive(Debug)]
+pub struct SampleAuxiliaryInformationOffsetsBox {
+    /// aux_info_type and aux_info_type_parameter, if not implied by the
+    /// sample entry
+    pub aux_info_type: Option<(FourCC, u32)>,
+    /// absolute, or in a traf relative to the base data offset, like the
+    /// data_offset of the trun
+    pub offsets: Vec<u64>,
+}
+
+impl SampleAuxiliaryInformationOffsetsBox {
+    /// Encodes the saio with a placeholder for a single offset, relative to
+    /// `base`.
+    fn encode_deferred(
+        &self,
+        output: &mut (impl Write + Seek),
+        base: u64,
+    ) -> Result<DeferredOffset> {
+        let begin = self.encode_header(output, 0)?;
+        1u32.encode(output)?; // entry_count
+        let offset = DeferredOffset::encode(output, false, base)?;
+        update_box_header(output, begin)?;
+        Ok(offset)
+    }
+
+    fn encode_header(&self, output: &mut (impl Write + Seek), version: u8) -> Result<u64> {
+        let begin = encode_box_header(output, *b"saio")?;
+        output.write_u8(version)?; // version
+        output.write_u24::<BigEndian>(self.aux_info_type.is_some() as u32)?; // flags
+
+        if let Some((aux_info_type, aux_info_type_parameter)) = self.aux_info_type {
+            aux_info_type.0.encode(output)?;
+            aux_info_type_parameter.encode(output)?;
+        }
+        Ok(begin)
+    }
+}
+
+impl Encode for SampleAuxiliaryInformationOffsetsBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let version = self.offsets.iter().any(|&offset| offset > u32::MAX as u64) as u8;
+        let begin = self.encode_header(output, version)?;
+
+        checked_cast::<u32>(self.offsets.len(), "entry_count")?.encode(output)?;
+        for &offset in &self.offsets {
+            if version == 0 {
+                (offset as u32).encode(output)?;
+            } else {
+                offset.encode(output)?;
+            }
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for SampleAuxiliaryInformationOffsetsBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        let flags = input.read_u24::<BigEndian>()?;
+
+        let aux_info_type = if flags & 1 != 0 {
+            Some((FourCC(Decode::decode(input)?), Decode::decode(input)?))
+        } else {
+            None
+        };
         let entry_count = u32::decode(input)?;
-        let mut entries = Vec::new();
+        let offset_size = if version == 0 { 4 } else { 8 };
+        if entry_count as u64 * offset_size > input.len() as u64 {
+            return Err(Error::ValueOutOfRange {
+                field: "entry_count",
+            });
+        }
+        let mut offsets = Vec::with_capacity(entry_count as usize);
         for _ in 0..entry_count {
-            let sample_count = Decode::decode(input)?;
-            let group_description_index = Decode::decode(input)?;
-            entries.push(SampleToGroupEntry {
-                sample_count,
-                group_description_index,
+            offsets.push(if version == 0 {
+                u32::decode(input)? as u64
+            } else {
+                u64::decode(input)?
             });
         }
-        Ok(Self(grouping_type, entries))
+        Ok(Self {
+            aux_info_type,
+            offsets,
+        })
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
-// ISO/IEC 14496-12:2008 8.11.1
+// ISO/IEC 14496-12:2012 8.8.1
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
-pub struct MetaBox {
-    pub handler: HandlerBox,
-    pub item_location: Option<ItemLocationBox>,
+#[derive(Debug, Default)]
+pub struct MovieExtendsBox {
+    pub header: Option<MovieExtendsHeaderBox>,
+    pub track_extends: Vec<TrackExtendsBox>,
+    pub extra_boxes: Vec<RawBox>,
 }
 
-impl Encode for MetaBox {
+impl MovieExtendsBox {
+    pub fn get(&self, track_id: u32) -> Option<&TrackExtendsBox> {
+        self.track_extends
+            .iter()
+            .find(|track_extends| track_extends.track_id == track_id)
+    }
+}
+
+impl Encode for MovieExtendsBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"meta")?;
-        output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(0)?; // flags
+        let begin = encode_box_header(output, *b"mvex")?;
 
-        self.handler.encode(output)?;
-        self.item_location.encode(output)?;
+        self.header.encode(output)?;
+        for track_extends in &self.track_extends {
+            track_extends.encode(output)?;
+        }
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
 
         update_box_header(output, begin)
     }
 }
 
-impl Decode for MetaBox {
+impl Decode for MovieExtendsBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
+        let mut header = None;
+        let mut track_extends = Vec::new();
 
-        let mut handler = None;
-        let mut item_location = None;
+        let mut extra_boxes = Vec::new();
 
         decode_boxes! {
             input,
-            required hdlr handler,
-            optional iloc item_location,
+            unknown extra_boxes,
+            optional mehd header,
+            multiple trex track_extends,
         }
 
         Ok(Self {
-            handler,
-            item_location,
+            header,
+            track_extends,
+            extra_boxes,
         })
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
-// ISO/IEC 14496-12:2008 8.11.3
+// ISO/IEC 14496-12:2012 8.8.2
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug)]
-pub struct ItemLocationBox(Vec<ItemLocationEntry>);
+define_box! {
+    #[derive(Debug)]
+    pub struct MovieExtendsHeaderBox: full_box b"mehd" {
+        versioned pub fragment_duration: MovieTime,
+    }
+}
 
-#[derive(Debug)]
-pub struct ItemLocationEntry {
-    pub item_id: u16,
-    pub data_reference_index: u16,
-    pub base_offset: u64,
-    pub extents: Vec<ItemLocationEntryExtent>,
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+define_box! {
+    #[derive(Debug)]
+    pub struct TrackExtendsBox: full_box b"trex" {
+        pub track_id: u32,
+        pub default_sample_description_index: u32,
+        pub default_sample_duration: u32,
+        pub default_sample_size: u32,
+        pub default_sample_flags: u32,
+    }
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.4
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
 #[derive(Debug)]
-pub struct ItemLocationEntryExtent {
-    pub extent_offset: u64,
-    pub extent_length: u64,
+pub struct MovieFragmentBox {
+    pub header: MovieFragmentHeaderBox,
+    pub track_fragments: Vec<TrackFragmentBox>,
+    /// pssh of the keys introduced by this fragment, when keys are rotated
+    pub protection_system_headers: Vec<ProtectionSystemSpecificHeaderBox>,
+    pub extra_boxes: Vec<RawBox>,
 }
 
-impl Encode for ItemLocationBox {
+impl Encode for MovieFragmentBox {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"iloc")?;
-        output.write_u8(0)?; // version
-        output.write_u24::<BigEndian>(0)?; // flags
+        let begin = encode_box_header(output, *b"moof")?;
+
+        self.header.encode(output)?;
+        for protection_system_header in &self.protection_system_headers {
+            protection_system_header.encode(output)?;
+        }
+        for track_fragment in &self.track_fragments {
+            track_fragment.encode_in(output, Some(begin))?;
+        }
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
 
         update_box_header(output, begin)
     }
 }
 
-impl Decode for ItemLocationBox {
+impl Decode for MovieFragmentBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        assert_eq!(input.read_u8()?, 0); // version
-        input.read_u24::<BigEndian>()?; // flags
+        let mut header = None;
+        let mut track_fragments = Vec::new();
+        let mut protection_system_headers = Vec::new();
 
-        let offset_and_length_size = input.read_u8()?;
-        let base_offset_size = input.read_u8()?;
-        let item_count = u16::decode(input)?;
-        let mut items = Vec::new();
-        for _ in 0..item_count {
-            let item_id = Decode::decode(input)?;
-            let data_reference_index = Decode::decode(input)?;
-            let base_offset = match base_offset_size & 0xF {
-                0 => 0,
-                4 => input.read_u32::<BigEndian>()? as u64,
-                8 => input.read_u64::<BigEndian>()?,
-                _ => todo!(),
-            };
-            let extent_count = u16::decode(input)?;
-            let mut extents = Vec::new();
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            required mfhd header,
+            multiple traf track_fragments,
+            multiple pssh protection_system_headers,
+        }
+
+        Ok(Self {
+            header,
+            track_fragments,
+            protection_system_headers,
+            extra_boxes,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.5
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+define_box! {
+    #[derive(Debug)]
+    pub struct MovieFragmentHeaderBox: full_box b"mfhd" {
+        pub sequence_number: u32,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.6
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct TrackFragmentBox {
+    pub header: TrackFragmentHeaderBox,
+    pub decode_time: Option<TrackFragmentBaseMediaDecodeTimeBox>,
+    pub runs: Vec<TrackRunBox>,
+    /// one per grouping type
+    pub sample_to_groups: Vec<SampleToGroupBox>,
+    pub sample_group_descriptions: Vec<SampleGroupDescriptionBox>,
+    /// one per aux_info_type
+    pub auxiliary_information_sizes: Vec<SampleAuxiliaryInformationSizesBox>,
+    /// one per aux_info_type, the one of the senc is patched to point to it
+    /// when encoded in a moof
+    pub auxiliary_information_offsets: Vec<SampleAuxiliaryInformationOffsetsBox>,
+    pub sample_encryption: Option<SampleEncryptionBox>,
+    pub extra_boxes: Vec<RawBox>,
+}
+
+impl TrackFragmentBox {
+    /// Group description indices above this refer to the entries of the
+    /// sgpd in the traf, instead of the one in the stbl.
+    pub const FRAGMENT_LOCAL_GROUP_DESCRIPTION_INDEX: u32 = 0x10000;
+
+    /// Encrypts all samples of the track fragment with the key of `entry`,
+    /// instead of the default of the tenc, by mapping them to a seig entry of
+    /// the track fragment, as used for key rotation.
+    ///
+    /// Call after adding the runs, the pssh of the key goes into the moof.
+    pub fn set_sample_encryption(&mut self, entry: CencSampleEncryptionInformation) -> Result<()> {
+        let grouping_type = FourCC::from(*b"seig");
+        self.sample_to_groups
+            .retain(|sample_to_group| sample_to_group.0 != grouping_type);
+        self.sample_group_descriptions
+            .retain(|description| description.grouping_type != grouping_type);
+
+        let sample_count: usize = self.runs.iter().map(|run| run.entries.len()).sum();
+        self.sample_group_descriptions
+            .push(SampleGroupDescriptionBox {
+                grouping_type,
+                entries: vec![SampleGroupEntry::CencSampleEncryptionInformation(entry)],
+            });
+        self.sample_to_groups.push(SampleToGroupBox(
+            grouping_type,
+            vec![SampleToGroupEntry {
+                sample_count: checked_cast(sample_count, "sample_count")?,
+                group_description_index: Self::FRAGMENT_LOCAL_GROUP_DESCRIPTION_INDEX + 1,
+            }],
+        ));
+        Ok(())
+    }
+}
+
+impl TrackFragmentBox {
+    /// Encodes the traf, with the saio of the senc relative to the base data
+    /// offset, which is the beginning of the moof unless explicit.
+    fn encode_in(&self, output: &mut (impl Write + Seek), moof_begin: Option<u64>) -> Result<()> {
+        let begin = encode_box_header(output, *b"traf")?;
+
+        self.header.encode(output)?;
+        self.decode_time.encode(output)?;
+        for run in &self.runs {
+            run.encode(output)?;
+        }
+        for sample_to_group in &self.sample_to_groups {
+            sample_to_group.encode(output)?;
+        }
+        for sample_group_description in &self.sample_group_descriptions {
+            sample_group_description.encode(output)?;
+        }
+        for auxiliary_information_sizes in &self.auxiliary_information_sizes {
+            auxiliary_information_sizes.encode(output)?;
+        }
+        let base = self.header.base_data_offset.or(moof_begin);
+        let mut sample_encryption_offset = None;
+        for auxiliary_information_offsets in &self.auxiliary_information_offsets {
+            match base {
+                Some(base)
+                    if self.sample_encryption.is_some()
+                        && sample_encryption_offset.is_none()
+                        && auxiliary_information_offsets.aux_info_type.is_none_or(
+                            |(aux_info_type, _)| {
+                                [b"cenc", b"cens", b"cbc1", b"cbcs"]
+                                    .contains(&&aux_info_type.to_bytes())
+                            },
+                        ) =>
+                {
+                    sample_encryption_offset =
+                        Some(auxiliary_information_offsets.encode_deferred(output, base)?);
+                }
+                _ => auxiliary_information_offsets.encode(output)?,
+            }
+        }
+        if let Some(sample_encryption) = &self.sample_encryption {
+            let sample_encryption_begin = output.stream_position()?;
+            sample_encryption.encode(output)?;
+            if let Some(sample_encryption_offset) = sample_encryption_offset {
+                sample_encryption_offset.update(
+                    output,
+                    sample_encryption_begin + SampleEncryptionBox::DATA_OFFSET,
+                )?;
+            }
+        }
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Encode for TrackFragmentBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        self.encode_in(output, None)
+    }
+}
+
+impl Decode for TrackFragmentBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut header = None;
+        let mut decode_time = None;
+        let mut runs = Vec::new();
+        let mut sample_to_groups = Vec::new();
+        let mut sample_group_descriptions = Vec::new();
+        let mut auxiliary_information_sizes = Vec::new();
+        let mut auxiliary_information_offsets = Vec::new();
+        let mut sample_encryption = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            required tfhd header,
+            optional tfdt decode_time,
+            multiple trun runs,
+            multiple sbgp sample_to_groups,
+            multiple sgpd sample_group_descriptions,
+            multiple saiz auxiliary_information_sizes,
+            multiple saio auxiliary_information_offsets,
+            optional senc sample_encryption,
+        }
+
+        Ok(Self {
+            header,
+            decode_time,
+            runs,
+            sample_to_groups,
+            sample_group_descriptions,
+            auxiliary_information_sizes,
+            auxiliary_information_offsets,
+            sample_encryption,
+            extra_boxes,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.7
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+pub struct TrackFragmentHeaderBox {
+    pub track_id: u32,
+    pub base_data_offset: Option<u64>,
+    pub sample_description_index: Option<u32>,
+    pub default_sample_duration: Option<u32>,
+    pub default_sample_size: Option<u32>,
+    pub default_sample_flags: Option<u32>,
+    pub duration_is_empty: bool,
+    pub default_base_is_moof: bool,
+}
+
+impl Encode for TrackFragmentHeaderBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"tfhd")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(
+            self.base_data_offset.is_some() as u32
+                | (self.sample_description_index.is_some() as u32) << 1
+                | (self.default_sample_duration.is_some() as u32) << 3
+                | (self.default_sample_size.is_some() as u32) << 4
+                | (self.default_sample_flags.is_some() as u32) << 5
+                | (self.duration_is_empty as u32) << 16
+                | (self.default_base_is_moof as u32) << 17,
+        )?;
+
+        self.track_id.encode(output)?;
+        self.base_data_offset.encode(output)?;
+        self.sample_description_index.encode(output)?;
+        self.default_sample_duration.encode(output)?;
+        self.default_sample_size.encode(output)?;
+        self.default_sample_flags.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for TrackFragmentHeaderBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_u8()?; // version
+        let flags = input.read_u24::<BigEndian>()?;
+
+        let track_id = Decode::decode(input)?;
+        let base_data_offset = (flags & 1 << 0 != 0)
+            .then(|| u64::decode(input))
+            .transpose()?;
+        let sample_description_index = (flags & 1 << 1 != 0)
+            .then(|| u32::decode(input))
+            .transpose()?;
+        let default_sample_duration = (flags & 1 << 3 != 0)
+            .then(|| u32::decode(input))
+            .transpose()?;
+        let default_sample_size = (flags & 1 << 4 != 0)
+            .then(|| u32::decode(input))
+            .transpose()?;
+        let default_sample_flags = (flags & 1 << 5 != 0)
+            .then(|| u32::decode(input))
+            .transpose()?;
+        Ok(Self {
+            track_id,
+            base_data_offset,
+            sample_description_index,
+            default_sample_duration,
+            default_sample_size,
+            default_sample_flags,
+            duration_is_empty: flags & 1 << 16 != 0,
+            default_base_is_moof: flags & 1 << 17 != 0,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.8
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct TrackRunBox {
+    pub data_offset: Option<i32>,
+    pub first_sample_flags: Option<u32>,
+    #[derivative(Debug = "ignore")]
+    pub entries: Vec<TrackRunEntry>,
+}
+
+/// Fields which are [None] for the first entry have to be [None] for all
+/// entries, and fall back to the track fragment or track defaults.
+#[derive(Debug, Clone, Default)]
+pub struct TrackRunEntry {
+    pub sample_duration: Option<u32>,
+    pub sample_size: Option<u32>,
+    pub sample_flags: Option<u32>,
+    pub sample_composition_time_offset: Option<i32>,
+}
+
+impl TrackRunEntry {
+    /// sample_is_non_sync_sample of the sample flags
+    pub const NON_SYNC_SAMPLE: u32 = 1 << 16;
+}
+
+impl Encode for TrackRunBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let first_entry = self.entries.first().cloned().unwrap_or_default();
+        let version = if self.entries.iter().any(|entry| {
+            entry
+                .sample_composition_time_offset
+                .is_some_and(|value| value < 0)
+        }) {
+            1
+        } else {
+            0
+        };
+
+        let begin = encode_box_header(output, *b"trun")?;
+        output.write_u8(version)?; // version
+        output.write_u24::<BigEndian>(
+            self.data_offset.is_some() as u32
+                | (self.first_sample_flags.is_some() as u32) << 2
+                | (first_entry.sample_duration.is_some() as u32) << 8
+                | (first_entry.sample_size.is_some() as u32) << 9
+                | (first_entry.sample_flags.is_some() as u32) << 10
+                | (first_entry.sample_composition_time_offset.is_some() as u32) << 11,
+        )?;
+
+        checked_cast::<u32>(self.entries.len(), "sample_count")?.encode(output)?;
+        if let Some(data_offset) = self.data_offset {
+            output.write_i32::<BigEndian>(data_offset)?;
+        }
+        self.first_sample_flags.encode(output)?;
+        for entry in &self.entries {
+            if first_entry.sample_duration.is_some() {
+                entry.sample_duration.unwrap_or_default().encode(output)?;
+            }
+            if first_entry.sample_size.is_some() {
+                entry.sample_size.unwrap_or_default().encode(output)?;
+            }
+            if first_entry.sample_flags.is_some() {
+                entry.sample_flags.unwrap_or_default().encode(output)?;
+            }
+            if first_entry.sample_composition_time_offset.is_some() {
+                output.write_i32::<BigEndian>(
+                    entry.sample_composition_time_offset.unwrap_or_default(),
+                )?;
+            }
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for TrackRunBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_u8()?; // version
+        let flags = input.read_u24::<BigEndian>()?;
+
+        let sample_count = u32::decode(input)?;
+        let data_offset = (flags & 1 << 0 != 0)
+            .then(|| input.read_i32::<BigEndian>())
+            .transpose()?;
+        let first_sample_flags = (flags & 1 << 2 != 0)
+            .then(|| u32::decode(input))
+            .transpose()?;
+        // without any fields per sample a few bytes could claim billions of
+        // samples, more than any fragment has
+        let entry_size = (flags >> 8 & 0xF).count_ones() as u64 * 4;
+        if sample_count as u64 * entry_size > input.len() as u64
+            || entry_size == 0 && sample_count > 1 << 20
+        {
+            return Err(Error::ValueOutOfRange {
+                field: "sample_count",
+            });
+        }
+        let mut entries = Vec::with_capacity(sample_count as usize);
+        for _ in 0..sample_count {
+            let sample_duration = (flags & 1 << 8 != 0)
+                .then(|| u32::decode(input))
+                .transpose()?;
+            let sample_size = (flags & 1 << 9 != 0)
+                .then(|| u32::decode(input))
+                .transpose()?;
+            let sample_flags = (flags & 1 << 10 != 0)
+                .then(|| u32::decode(input))
+                .transpose()?;
+            // version 0 offsets are unsigned, but nobody writes offsets above i32::MAX
+            let sample_composition_time_offset = (flags & 1 << 11 != 0)
+                .then(|| input.read_i32::<BigEndian>())
+                .transpose()?;
+            entries.push(TrackRunEntry {
+                sample_duration,
+                sample_size,
+                sample_flags,
+                sample_composition_time_offset,
+            });
+        }
+        Ok(Self {
+            data_offset,
+            first_sample_flags,
+            entries,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.12
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+define_box! {
+    #[derive(Debug)]
+    pub struct TrackFragmentBaseMediaDecodeTimeBox: full_box b"tfdt" {
+        versioned pub base_media_decode_time: MediaTime,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.9
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Index of the sync samples of the movie fragments, at the end of the file,
+/// with the mfro which gives its size for finding it from the end.
+#[derive(Debug)]
+pub struct MovieFragmentRandomAccessBox(pub Vec<TrackFragmentRandomAccessBox>);
+
+impl Encode for MovieFragmentRandomAccessBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"mfra")?;
+
+        for track_fragment_random_access in &self.0 {
+            track_fragment_random_access.encode(output)?;
+        }
+
+        // MovieFragmentRandomAccessOffsetBox, which is the last box
+        let offset_begin = encode_box_header(output, *b"mfro")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+        let size = DeferredOffset::encode(output, false, begin)?;
+        update_box_header(output, offset_begin)?;
+        let end = output.stream_position()?;
+        size.update(output, end)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for MovieFragmentRandomAccessBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut track_fragment_random_access = Vec::new();
+
+        decode_boxes! {
+            input,
+            multiple tfra track_fragment_random_access,
+        }
+
+        Ok(Self(track_fragment_random_access))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.8.10
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct TrackFragmentRandomAccessBox {
+    pub track_id: u32,
+    #[derivative(Debug = "ignore")]
+    pub entries: Vec<TrackFragmentRandomAccessEntry>,
+}
+
+/// Sync sample at `time`, with the 1-based numbers of the traf in the moof at
+/// `moof_offset`, of the trun in the traf, and of the sample in the trun.
+#[derive(Debug, Clone)]
+pub struct TrackFragmentRandomAccessEntry {
+    pub time: MediaTime,
+    pub moof_offset: u64,
+    pub traf_number: u32,
+    pub trun_number: u32,
+    pub sample_number: u32,
+}
+
+impl Encode for TrackFragmentRandomAccessBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let version = if self
+            .entries
+            .iter()
+            .any(|entry| entry.time.0 > u32::MAX as u64 || entry.moof_offset > u32::MAX as u64)
+        {
+            1
+        } else {
+            0
+        };
+        // smallest number of bytes, from 1 to 4, for all entries
+        let length_size = |number: fn(&TrackFragmentRandomAccessEntry) -> u32| {
+            let max = self.entries.iter().map(number).max().unwrap_or_default();
+            (4 - max.leading_zeros() as usize / 8).max(1)
+        };
+        let traf_number_size = length_size(|entry| entry.traf_number);
+        let trun_number_size = length_size(|entry| entry.trun_number);
+        let sample_number_size = length_size(|entry| entry.sample_number);
+
+        let begin = encode_box_header(output, *b"tfra")?;
+        output.write_u8(version)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        self.track_id.encode(output)?;
+        ((traf_number_size as u32 - 1) << 4
+            | (trun_number_size as u32 - 1) << 2
+            | (sample_number_size as u32 - 1))
+            .encode(output)?;
+        checked_cast::<u32>(self.entries.len(), "number_of_entry")?.encode(output)?;
+        for entry in &self.entries {
+            if version == 0 {
+                (entry.time.0 as u32).encode(output)?;
+                (entry.moof_offset as u32).encode(output)?;
+            } else {
+                entry.time.0.encode(output)?;
+                entry.moof_offset.encode(output)?;
+            }
+            output.write_uint::<BigEndian>(entry.traf_number as u64, traf_number_size)?;
+            output.write_uint::<BigEndian>(entry.trun_number as u64, trun_number_size)?;
+            output.write_uint::<BigEndian>(entry.sample_number as u64, sample_number_size)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for TrackFragmentRandomAccessBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        input.read_u24::<BigEndian>()?; // flags
+        if version > 1 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+
+        let track_id = Decode::decode(input)?;
+        let length_sizes = u32::decode(input)?;
+        let traf_number_size = (length_sizes >> 4 & 0x3) as usize + 1;
+        let trun_number_size = (length_sizes >> 2 & 0x3) as usize + 1;
+        let sample_number_size = (length_sizes & 0x3) as usize + 1;
+        let number_of_entry = u32::decode(input)?;
+        let entry_size = if version == 0 { 4 + 4 } else { 8 + 8 }
+            + traf_number_size
+            + trun_number_size
+            + sample_number_size;
+        if number_of_entry as u64 * entry_size as u64 > input.len() as u64 {
+            return Err(Error::ValueOutOfRange {
+                field: "number_of_entry",
+            });
+        }
+        let mut entries = Vec::with_capacity(number_of_entry as usize);
+        for _ in 0..number_of_entry {
+            let (time, moof_offset) = if version == 0 {
+                (u32::decode(input)? as u64, u32::decode(input)? as u64)
+            } else {
+                (Decode::decode(input)?, Decode::decode(input)?)
+            };
+            entries.push(TrackFragmentRandomAccessEntry {
+                time: MediaTime(time),
+                moof_offset,
+                traf_number: input.read_uint::<BigEndian>(traf_number_size)? as u32,
+                trun_number: input.read_uint::<BigEndian>(trun_number_size)? as u32,
+                sample_number: input.read_uint::<BigEndian>(sample_number_size)? as u32,
+            });
+        }
+        Ok(Self { track_id, entries })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.9.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct SampleToGroupBox(pub FourCC, pub Vec<SampleToGroupEntry>);
+
+#[derive(Debug)]
+pub struct SampleToGroupEntry {
+    pub sample_count: u32,
+    pub group_description_index: u32,
+}
+
+impl Encode for SampleToGroupBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"sbgp")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        self.0 .0.encode(output)?;
+        checked_cast::<u32>(self.1.len(), "entry_count")?.encode(output)?;
+        for entry in &self.1 {
+            entry.sample_count.encode(output)?;
+            entry.group_description_index.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for SampleToGroupBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let grouping_type = FourCC(Decode::decode(input)?);
+        let entry_count = u32::decode(input)?;
+        let mut entries = Vec::new();
+        for _ in 0..entry_count {
+            let sample_count = Decode::decode(input)?;
+            let group_description_index = Decode::decode(input)?;
+            entries.push(SampleToGroupEntry {
+                sample_count,
+                group_description_index,
+            });
+        }
+        Ok(Self(grouping_type, entries))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.9.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+pub struct SampleGroupDescriptionBox {
+    pub grouping_type: FourCC,
+    pub entries: Vec<SampleGroupEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SampleGroupEntry {
+    /// roll or prol, ISO/IEC 14496-12:2008 10.1
+    Roll(i16),
+    /// seig, ISO/IEC 23001-7:2016 6
+    CencSampleEncryptionInformation(CencSampleEncryptionInformation),
+    Unknown(Vec<u8>),
+}
+
+impl SampleGroupDescriptionBox {
+    /// Returns the entry referenced by a 1-based group_description_index.
+    pub fn get(&self, group_description_index: u32) -> Option<&SampleGroupEntry> {
+        self.entries
+            .get((group_description_index as usize).checked_sub(1)?)
+    }
+}
+
+impl Encode for SampleGroupDescriptionBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"sgpd")?;
+        output.write_u8(1)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| {
+                Ok(match entry {
+                    SampleGroupEntry::Roll(roll_distance) => roll_distance.to_be_bytes().to_vec(),
+                    SampleGroupEntry::CencSampleEncryptionInformation(entry) => entry.to_bytes()?,
+                    SampleGroupEntry::Unknown(data) => data.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let default_length = match entries.as_slice() {
+            [entry, others @ ..] if others.iter().all(|other| other.len() == entry.len()) => {
+                entry.len()
+            }
+            _ => 0,
+        };
+
+        self.grouping_type.0.encode(output)?;
+        checked_cast::<u32>(default_length, "default_length")?.encode(output)?;
+        checked_cast::<u32>(entries.len(), "entry_count")?.encode(output)?;
+        for entry in &entries {
+            if default_length == 0 {
+                checked_cast::<u32>(entry.len(), "description_length")?.encode(output)?;
+            }
+            output.write_all(entry)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for SampleGroupDescriptionBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        input.read_u24::<BigEndian>()?; // flags
+
+        let grouping_type = FourCC(Decode::decode(input)?);
+        let roll = matches!(&grouping_type.to_bytes(), b"roll" | b"prol");
+        let seig = &grouping_type.to_bytes() == b"seig";
+        let default_length = if version == 1 { u32::decode(input)? } else { 0 };
+        if version >= 2 {
+            u32::decode(input)?; // default_sample_description_index
+        }
+        let entry_count = u32::decode(input)?;
+        let mut entries = Vec::new();
+        for _ in 0..entry_count {
+            let length = match (version, default_length) {
+                (1, 0) => u32::decode(input)? as usize,
+                (1, default_length) => default_length as usize,
+                // without a length, only known entries can be parsed
+                _ if roll => 2,
+                _ => break,
+            };
+            let Some((data, remaining_data)) = input.split_at_checked(length) else {
+                return Err(Error::InvalidBoxSize {
+                    r#type: grouping_type,
+                    size: length as u64,
+                    remaining: input.len(),
+                });
+            };
+            *input = remaining_data;
+            entries.push(match data {
+                &[a, b] if roll => SampleGroupEntry::Roll(i16::from_be_bytes([a, b])),
+                mut data if seig => {
+                    SampleGroupEntry::CencSampleEncryptionInformation(Decode::decode(&mut data)?)
+                }
+                data => SampleGroupEntry::Unknown(data.to_owned()),
+            });
+        }
+        Ok(Self {
+            grouping_type,
+            entries,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.10.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+pub struct UserDataBox {
+    pub kinds: Vec<KindBox>,
+    pub meta: Option<MetaBox>,
+    /// only of audio tracks
+    pub loudness: Option<LoudnessBox>,
+    pub extensions: Vec<UuidBox>,
+    pub extra_boxes: Vec<RawBox>,
+}
+
+impl UserDataBox {
+    pub fn rendition(&self) -> Result<Option<RenditionInfo>> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.extended_type == RenditionInfo::EXTENDED_TYPE)
+            .map(|extension| RenditionInfo::decode(&mut extension.data.as_slice()))
+            .transpose()
+    }
+
+    pub fn set_rendition(&mut self, rendition: &RenditionInfo) -> Result<()> {
+        let mut data = std::io::Cursor::new(Vec::new());
+        rendition.encode(&mut data)?;
+        self.extensions
+            .retain(|extension| extension.extended_type != RenditionInfo::EXTENDED_TYPE);
+        self.extensions.push(UuidBox {
+            extended_type: RenditionInfo::EXTENDED_TYPE,
+            data: data.into_inner(),
+        });
+        Ok(())
+    }
+}
+
+impl Encode for UserDataBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"udta")?;
+
+        for kind in &self.kinds {
+            kind.encode(output)?;
+        }
+        self.meta.encode(output)?;
+        self.loudness.encode(output)?;
+        for extension in &self.extensions {
+            extension.encode(output)?;
+        }
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for UserDataBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut kinds = Vec::new();
+        let mut meta = None;
+        let mut loudness = None;
+        let mut extensions = Vec::new();
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            multiple kind kinds,
+            optional meta meta,
+            optional ludt loudness,
+            multiple uuid extensions,
+        }
+
+        Ok(Self {
+            kinds,
+            meta,
+            loudness,
+            extensions,
+            extra_boxes,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 4.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct UuidBox {
+    pub extended_type: [u8; 16],
+    #[derivative(Debug = "ignore")]
+    pub data: Vec<u8>,
+}
+
+impl Encode for UuidBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"uuid")?;
+
+        output.write_all(&self.extended_type)?;
+        output.write_all(&self.data)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for UuidBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut extended_type = [0; 16];
+        input.read_exact(&mut extended_type)?;
+        let data = input.to_owned();
+        *input = &input[input.len()..];
+        Ok(Self {
+            extended_type,
+            data,
+        })
+    }
+}
+
+/// Rendition of an adaptive bitrate ladder a track was encoded for, carried
+/// in a uuid box of the user data.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenditionInfo {
+    pub name: String,
+    pub target_bitrate: u32,
+    pub max_bitrate: u32,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl RenditionInfo {
+    pub const EXTENDED_TYPE: [u8; 16] = [
+        0x6B, 0x2E, 0x1D, 0x95, 0x3C, 0x5A, 0x4F, 0x1B, 0x9E, 0x47, 0xA3, 0x0C, 0xD8, 0x61, 0x52,
+        0xF4,
+    ];
+}
+
+impl Encode for RenditionInfo {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        self.target_bitrate.encode(output)?;
+        self.max_bitrate.encode(output)?;
+        self.width.encode(output)?;
+        self.height.encode(output)?;
+        self.name.encode(output)
+    }
+}
+
+impl Decode for RenditionInfo {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        Ok(Self {
+            target_bitrate: Decode::decode(input)?,
+            max_bitrate: Decode::decode(input)?,
+            width: Decode::decode(input)?,
+            height: Decode::decode(input)?,
+            name: Decode::decode(input)?,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.10.4
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+define_box! {
+    #[derive(Debug)]
+    pub struct KindBox: full_box b"kind" {
+        pub scheme_uri: String,
+        pub value: String,
+    }
+}
+
+impl KindBox {
+    pub const DASH_ROLE_SCHEME_URI: &'static str = "urn:mpeg:dash:role:2011";
+
+    pub fn dash_role(&self) -> Option<DashRole> {
+        if self.scheme_uri != Self::DASH_ROLE_SCHEME_URI {
+            return None;
+        }
+        self.value.parse().ok()
+    }
+}
+
+impl From<DashRole> for KindBox {
+    fn from(value: DashRole) -> Self {
+        Self {
+            scheme_uri: Self::DASH_ROLE_SCHEME_URI.to_owned(),
+            value: value.as_str().to_owned(),
+        }
+    }
+}
+
+// ISO/IEC 23009-1:2022 5.8.5.5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DashRole {
+    Caption,
+    Subtitle,
+    Main,
+    Alternate,
+    Supplementary,
+    Commentary,
+    Dub,
+    Description,
+    Sign,
+    Metadata,
+    EnhancedAudioIntelligibility,
+    Emergency,
+    ForcedSubtitle,
+    Easyreader,
+    Karaoke,
+}
+
+impl DashRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DashRole::Caption => "caption",
+            DashRole::Subtitle => "subtitle",
+            DashRole::Main => "main",
+            DashRole::Alternate => "alternate",
+            DashRole::Supplementary => "supplementary",
+            DashRole::Commentary => "commentary",
+            DashRole::Dub => "dub",
+            DashRole::Description => "description",
+            DashRole::Sign => "sign",
+            DashRole::Metadata => "metadata",
+            DashRole::EnhancedAudioIntelligibility => "enhanced-audio-intelligibility",
+            DashRole::Emergency => "emergency",
+            DashRole::ForcedSubtitle => "forced-subtitle",
+            DashRole::Easyreader => "easyreader",
+            DashRole::Karaoke => "karaoke",
+        }
+    }
+}
+
+impl FromStr for DashRole {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "caption" => DashRole::Caption,
+            "subtitle" => DashRole::Subtitle,
+            "main" => DashRole::Main,
+            "alternate" => DashRole::Alternate,
+            "supplementary" => DashRole::Supplementary,
+            "commentary" => DashRole::Commentary,
+            "dub" => DashRole::Dub,
+            "description" => DashRole::Description,
+            "sign" => DashRole::Sign,
+            "metadata" => DashRole::Metadata,
+            "enhanced-audio-intelligibility" => DashRole::EnhancedAudioIntelligibility,
+            "emergency" => DashRole::Emergency,
+            "forced-subtitle" => DashRole::ForcedSubtitle,
+            "easyreader" => DashRole::Easyreader,
+            "karaoke" => DashRole::Karaoke,
+            _ => return Err(()),
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.11.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct MetaBox {
+    pub handler: HandlerBox,
+    pub item_location: Option<ItemLocationBox>,
+    pub item_info: Option<ItemInfoBox>,
+    pub item_reference: Option<ItemReferenceBox>,
+    pub item_properties: Option<ItemPropertiesBox>,
+    pub item_list: Option<ItemListBox>,
+    pub extra_boxes: Vec<RawBox>,
+}
+
+impl MetaBox {
+    /// Reads the first Exif item, `input` being the whole file the item
+    /// locations are relative to.
+    pub fn exif(&self, input: &[u8]) -> Result<Option<ExifData>> {
+        let Some(item) = self.item_info.as_ref().and_then(|item_info| {
+            item_info
+                .0
+                .iter()
+                .find(|item| item.item_type == ItemInfoEntry::EXIF)
+        }) else {
+            return Ok(None);
+        };
+        let Some(data) = self
+            .item_location
+            .as_ref()
+            .and_then(|item_location| item_location.get(item.item_id))
+            .and_then(|item_location| item_location.read(input))
+        else {
+            return Ok(None);
+        };
+        ExifData::decode(&mut data.as_slice()).map(Some)
+    }
+}
+
+impl Encode for MetaBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"meta")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        self.handler.encode(output)?;
+        self.item_location.encode(output)?;
+        self.item_info.encode(output)?;
+        self.item_reference.encode(output)?;
+        self.item_properties.encode(output)?;
+        self.item_list.encode(output)?;
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for MetaBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let mut handler = None;
+        let mut item_location = None;
+        let mut item_info = None;
+        let mut item_reference = None;
+        let mut item_properties = None;
+        let mut item_list = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            required hdlr handler,
+            optional iloc item_location,
+            optional iinf item_info,
+            optional iref item_reference,
+            optional iprp item_properties,
+            optional ilst item_list,
+        }
+
+        Ok(Self {
+            handler,
+            item_location,
+            item_info,
+            item_reference,
+            item_properties,
+            item_list,
+            extra_boxes,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2008 8.11.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+pub struct ItemLocationBox(Vec<ItemLocationEntry>);
+
+#[derive(Debug)]
+pub struct ItemLocationEntry {
+    pub item_id: u32,
+    pub construction_method: u8,
+    pub data_reference_index: u16,
+    pub base_offset: u64,
+    pub extents: Vec<ItemLocationEntryExtent>,
+}
+
+#[derive(Debug)]
+pub struct ItemLocationEntryExtent {
+    pub extent_offset: u64,
+    pub extent_length: u64,
+}
+
+impl ItemLocationBox {
+    pub fn get(&self, item_id: u32) -> Option<&ItemLocationEntry> {
+        self.0.iter().find(|item| item.item_id == item_id)
+    }
+
+    pub fn push(&mut self, item: ItemLocationEntry) {
+        self.0.push(item);
+    }
+
+    pub fn remove(&mut self, item_id: u32) -> Option<ItemLocationEntry> {
+        let index = self.0.iter().position(|item| item.item_id == item_id)?;
+        Some(self.0.remove(index))
+    }
+}
+
+impl ItemLocationEntry {
+    /// Concatenates the extents of an item stored in the file itself,
+    /// returns [None] if the item is stored elsewhere or out of bounds.
+    pub fn read(&self, input: &[u8]) -> Option<Vec<u8>> {
+        if self.construction_method != 0 || self.data_reference_index != 0 {
+            return None;
+        }
+
+        let mut data = Vec::new();
+        for extent in &self.extents {
+            let begin =
+                usize::try_from(self.base_offset.checked_add(extent.extent_offset)?).ok()?;
+            // a length of 0 denotes the entire remaining file
+            let end = if extent.extent_length == 0 {
+                input.len()
+            } else {
+                usize::try_from((begin as u64).checked_add(extent.extent_length)?).ok()?
+            };
+            data.extend_from_slice(input.get(begin..end)?);
+        }
+        Some(data)
+    }
+}
+
+impl Encode for ItemLocationBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let field_size = |large: bool| if large { 8 } else { 4 };
+        let offset_size = field_size(self.0.iter().any(|item| {
+            item.extents
+                .iter()
+                .any(|extent| extent.extent_offset > u32::MAX as u64)
+        }));
+        let length_size = field_size(self.0.iter().any(|item| {
+            item.extents
+                .iter()
+                .any(|extent| extent.extent_length > u32::MAX as u64)
+        }));
+        let base_offset_size =
+            field_size(self.0.iter().any(|item| item.base_offset > u32::MAX as u64));
+        let version = if self.0.iter().any(|item| item.item_id > u16::MAX as u32) {
+            2
+        } else if self.0.iter().any(|item| item.construction_method != 0) {
+            1
+        } else {
+            0
+        };
+
+        let begin = encode_box_header(output, *b"iloc")?;
+        output.write_u8(version)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        output.write_u8(offset_size << 4 | length_size)?;
+        output.write_u8(base_offset_size << 4)?;
+        if version < 2 {
+            checked_cast::<u16>(self.0.len(), "item_count")?.encode(output)?;
+        } else {
+            checked_cast::<u32>(self.0.len(), "item_count")?.encode(output)?;
+        }
+        let encode_sized = |output: &mut _, size, value: u64| -> Result<()> {
+            match size {
+                4 => (value as u32).encode(output),
+                _ => value.encode(output),
+            }
+        };
+        for item in &self.0 {
+            if version < 2 {
+                (item.item_id as u16).encode(output)?;
+            } else {
+                item.item_id.encode(output)?;
+            }
+            if version > 0 {
+                (item.construction_method as u16 & 0xF).encode(output)?;
+            }
+            item.data_reference_index.encode(output)?;
+            encode_sized(output, base_offset_size, item.base_offset)?;
+            checked_cast::<u16>(item.extents.len(), "extent_count")?.encode(output)?;
+            for extent in &item.extents {
+                encode_sized(output, offset_size, extent.extent_offset)?;
+                encode_sized(output, length_size, extent.extent_length)?;
+            }
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ItemLocationBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        input.read_u24::<BigEndian>()?; // flags
+        if version > 2 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+
+        let offset_and_length_size = input.read_u8()?;
+        let base_offset_and_index_size = input.read_u8()?;
+        let offset_size = offset_and_length_size >> 4;
+        let length_size = offset_and_length_size & 0xF;
+        let base_offset_size = base_offset_and_index_size >> 4;
+        let index_size = if version > 0 {
+            base_offset_and_index_size & 0xF
+        } else {
+            0
+        };
+        let decode_sized = |input: &mut &[u8], size| -> Result<u64> {
+            Ok(match size {
+                0 => 0,
+                4 => input.read_u32::<BigEndian>()? as u64,
+                8 => input.read_u64::<BigEndian>()?,
+                _ => return Err(Error::ValueOutOfRange { field: "size" }),
+            })
+        };
+        let item_count = if version < 2 {
+            u16::decode(input)? as u32
+        } else {
+            u32::decode(input)?
+        };
+        let mut items = Vec::new();
+        for _ in 0..item_count {
+            let item_id = if version < 2 {
+                u16::decode(input)? as u32
+            } else {
+                u32::decode(input)?
+            };
+            let construction_method = if version > 0 {
+                (u16::decode(input)? & 0xF) as u8
+            } else {
+                0
+            };
+            let data_reference_index = Decode::decode(input)?;
+            let base_offset = decode_sized(input, base_offset_size)?;
+            let extent_count = u16::decode(input)?;
+            // extents without any fields would take no space at all
+            let extent_size = (index_size + offset_size + length_size) as u64;
+            if extent_count as u64 * extent_size > input.len() as u64
+                || extent_size == 0 && extent_count > 1
+            {
+                return Err(Error::ValueOutOfRange {
+                    field: "extent_count",
+                });
+            }
+            let mut extents = Vec::new();
             for _ in 0..extent_count {
-                let extent_offset = match offset_and_length_size & 0xF {
-                    0 => 0,
-                    4 => input.read_u32::<BigEndian>()? as u64,
-                    8 => input.read_u64::<BigEndian>()?,
-                    _ => todo!(),
-                };
-                let extent_length = match offset_and_length_size >> 4 & 0xF {
-                    0 => 0,
-                    4 => input.read_u32::<BigEndian>()? as u64,
-                    8 => input.read_u64::<BigEndian>()?,
-                    _ => todo!(),
-                };
+                decode_sized(input, index_size)?; // extent_index
+                let extent_offset = decode_sized(input, offset_size)?;
+                let extent_length = decode_sized(input, length_size)?;
                 extents.push(ItemLocationEntryExtent {
                     extent_offset,
                     extent_length,
                 });
             }
-            items.push(ItemLocationEntry {
-                item_id,
-                data_reference_index,
-                base_offset,
-                extents,
+            items.push(ItemLocationEntry {
+                item_id,
+                construction_method,
+                data_reference_index,
+                base_offset,
+                extents,
+            })
+        }
+        Ok(Self(items))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.11.6
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+pub struct ItemInfoBox(pub Vec<ItemInfoEntry>);
+
+#[derive(Debug)]
+pub struct ItemInfoEntry {
+    pub item_id: u32,
+    pub hidden: bool,
+    pub item_protection_index: u16,
+    pub item_type: FourCC,
+    pub item_name: String,
+    pub content_type: String,
+    pub content_encoding: Option<String>,
+    pub item_uri_type: String,
+}
+
+impl ItemInfoEntry {
+    pub const MIME: FourCC = FourCC(u32::from_be_bytes(*b"mime"));
+    pub const URI: FourCC = FourCC(u32::from_be_bytes(*b"uri "));
+    pub const EXIF: FourCC = FourCC(u32::from_be_bytes(*b"Exif"));
+
+    pub fn new(item_id: u32, item_type: FourCC) -> Self {
+        Self {
+            item_id,
+            hidden: false,
+            item_protection_index: 0,
+            item_type,
+            item_name: String::new(),
+            content_type: String::new(),
+            content_encoding: None,
+            item_uri_type: String::new(),
+        }
+    }
+}
+
+impl ItemInfoBox {
+    pub fn get(&self, item_id: u32) -> Option<&ItemInfoEntry> {
+        self.0.iter().find(|item| item.item_id == item_id)
+    }
+}
+
+impl Encode for ItemInfoBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let large = self.0.len() > u16::MAX as usize;
+
+        let begin = encode_box_header(output, *b"iinf")?;
+        output.write_u8(if large { 1 } else { 0 })?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        if large {
+            checked_cast::<u32>(self.0.len(), "entry_count")?.encode(output)?;
+        } else {
+            (self.0.len() as u16).encode(output)?;
+        }
+        for entry in &self.0 {
+            entry.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ItemInfoBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        input.read_u24::<BigEndian>()?; // flags
+
+        let entry_count = if version == 0 {
+            u16::decode(input)? as u32
+        } else {
+            u32::decode(input)?
+        };
+        let mut entries = Vec::new();
+        for _ in 0..entry_count {
+            let (r#type, mut data) = decode_box_header(input)?;
+            if &r#type == b"infe" {
+                entries.push(Decode::decode(&mut data)?);
+            }
+        }
+        Ok(Self(entries))
+    }
+}
+
+impl Encode for ItemInfoEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let large = self.item_id > u16::MAX as u32;
+
+        let begin = encode_box_header(output, *b"infe")?;
+        output.write_u8(if large { 3 } else { 2 })?; // version
+        output.write_u24::<BigEndian>(if self.hidden { 1 << 0 } else { 0 })?; // flags
+
+        if large {
+            self.item_id.encode(output)?;
+        } else {
+            (self.item_id as u16).encode(output)?;
+        }
+        self.item_protection_index.encode(output)?;
+        self.item_type.0.encode(output)?;
+        self.item_name.encode(output)?;
+        if self.item_type == Self::MIME {
+            self.content_type.encode(output)?;
+            self.content_encoding.encode(output)?;
+        } else if self.item_type == Self::URI {
+            self.item_uri_type.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ItemInfoEntry {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        let flags = input.read_u24::<BigEndian>()?;
+
+        let item_id = match version {
+            2 => u16::decode(input)? as u32,
+            3 => u32::decode(input)?,
+            _ => return Err(Error::ValueOutOfRange { field: "version" }),
+        };
+        let mut entry = Self::new(item_id, FourCC(0));
+        entry.hidden = flags & 1 << 0 != 0;
+        entry.item_protection_index = Decode::decode(input)?;
+        entry.item_type = FourCC(Decode::decode(input)?);
+        entry.item_name = Decode::decode(input)?;
+        if entry.item_type == Self::MIME {
+            entry.content_type = Decode::decode(input)?;
+            if !input.is_empty() {
+                entry.content_encoding = Some(Decode::decode(input)?);
+            }
+        } else if entry.item_type == Self::URI {
+            entry.item_uri_type = Decode::decode(input)?;
+        }
+        Ok(entry)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.11.12
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+pub struct ItemReferenceBox(pub Vec<SingleItemTypeReference>);
+
+#[derive(Debug, Clone)]
+pub struct SingleItemTypeReference {
+    pub r#type: FourCC,
+    pub from_item_id: u32,
+    pub to_item_ids: Vec<u32>,
+}
+
+impl SingleItemTypeReference {
+    pub const DERIVED_IMAGE: FourCC = FourCC(u32::from_be_bytes(*b"dimg"));
+    pub const THUMBNAIL: FourCC = FourCC(u32::from_be_bytes(*b"thmb"));
+    pub const AUXILIARY: FourCC = FourCC(u32::from_be_bytes(*b"auxl"));
+    pub const CONTENT_DESCRIBES: FourCC = FourCC(u32::from_be_bytes(*b"cdsc"));
+}
+
+impl ItemReferenceBox {
+    pub fn references(&self, r#type: FourCC, from_item_id: u32) -> &[u32] {
+        self.0
+            .iter()
+            .find(|reference| reference.r#type == r#type && reference.from_item_id == from_item_id)
+            .map_or(&[], |reference| &reference.to_item_ids)
+    }
+
+    pub fn derived_images(&self, item_id: u32) -> &[u32] {
+        self.references(SingleItemTypeReference::DERIVED_IMAGE, item_id)
+    }
+
+    pub fn thumbnails(&self, item_id: u32) -> impl Iterator<Item = u32> + '_ {
+        self.0
+            .iter()
+            .filter(move |reference| {
+                reference.r#type == SingleItemTypeReference::THUMBNAIL
+                    && reference.to_item_ids.contains(&item_id)
+            })
+            .map(|reference| reference.from_item_id)
+    }
+}
+
+impl Encode for ItemReferenceBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let large = self.0.iter().any(|reference| {
+            reference.from_item_id > u16::MAX as u32
+                || reference
+                    .to_item_ids
+                    .iter()
+                    .any(|&to_item_id| to_item_id > u16::MAX as u32)
+        });
+
+        let begin = encode_box_header(output, *b"iref")?;
+        output.write_u8(if large { 1 } else { 0 })?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        for reference in &self.0 {
+            let begin = encode_box_header(output, reference.r#type.to_bytes())?;
+            if large {
+                reference.from_item_id.encode(output)?;
+            } else {
+                (reference.from_item_id as u16).encode(output)?;
+            }
+            checked_cast::<u16>(reference.to_item_ids.len(), "reference_count")?.encode(output)?;
+            for &to_item_id in &reference.to_item_ids {
+                if large {
+                    to_item_id.encode(output)?;
+                } else {
+                    (to_item_id as u16).encode(output)?;
+                }
+            }
+            update_box_header(output, begin)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ItemReferenceBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        input.read_u24::<BigEndian>()?; // flags
+
+        let decode_item_id = |input: &mut &[u8]| -> Result<u32> {
+            Ok(match version {
+                0 => u16::decode(input)? as u32,
+                1 => u32::decode(input)?,
+                _ => return Err(Error::ValueOutOfRange { field: "version" }),
             })
+        };
+        let mut references = Vec::new();
+        while !input.is_empty() {
+            let (r#type, mut data) = decode_box_header(input)?;
+            let from_item_id = decode_item_id(&mut data)?;
+            let reference_count = u16::decode(&mut data)?;
+            let mut to_item_ids = Vec::new();
+            for _ in 0..reference_count {
+                to_item_ids.push(decode_item_id(&mut data)?);
+            }
+            references.push(SingleItemTypeReference {
+                r#type: FourCC(u32::from_be_bytes(r#type)),
+                from_item_id,
+                to_item_ids,
+            });
+        }
+        Ok(Self(references))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2012 8.16.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct SegmentIndexBox {
+    pub reference_id: u32,
+    pub timescale: u32,
+    pub earliest_presentation_time: u64,
+    pub first_offset: u64,
+    pub references: Vec<SegmentIndexReference>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentIndexReference {
+    pub reference_type: SegmentIndexReferenceType,
+    pub referenced_size: u32,
+    pub subsegment_duration: u32,
+    pub starts_with_sap: bool,
+    pub sap_type: u8,
+    pub sap_delta_time: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentIndexReferenceType {
+    Media,
+    Index,
+}
+
+impl SegmentIndexBox {
+    /// Builds a two-level index over the given media subsegments, grouping
+    /// `references_per_index` of them under each child index.
+    ///
+    /// Returns the top-level index, followed by the child indexes. The
+    /// top-level index has to be written directly before the first child
+    /// index, and each child index directly before the subsegments it
+    /// references.
+    pub fn hierarchy(
+        reference_id: u32,
+        timescale: u32,
+        earliest_presentation_time: u64,
+        subsegments: &[SegmentIndexReference],
+        references_per_index: usize,
+    ) -> Result<(Self, Vec<Self>)> {
+        let mut references = Vec::new();
+        let mut indexes = Vec::new();
+        let mut presentation_time = earliest_presentation_time;
+        for subsegments in subsegments.chunks(references_per_index.max(1)) {
+            let index = Self {
+                reference_id,
+                timescale,
+                earliest_presentation_time: presentation_time,
+                first_offset: 0,
+                references: subsegments.to_vec(),
+            };
+            let first_subsegment = &subsegments[0];
+            let subsegment_duration = checked_cast(
+                subsegments
+                    .iter()
+                    .map(|subsegment| subsegment.subsegment_duration as u64)
+                    .sum::<u64>(),
+                "subsegment_duration",
+            )?;
+            let referenced_size = index.size()
+                + subsegments
+                    .iter()
+                    .map(|subsegment| subsegment.referenced_size as u64)
+                    .sum::<u64>();
+            if referenced_size > 0x7FFFFFFF {
+                return Err(Error::ValueOutOfRange {
+                    field: "referenced_size",
+                });
+            }
+            references.push(SegmentIndexReference {
+                reference_type: SegmentIndexReferenceType::Index,
+                referenced_size: referenced_size as u32,
+                subsegment_duration,
+                starts_with_sap: first_subsegment.starts_with_sap,
+                sap_type: first_subsegment.sap_type,
+                sap_delta_time: first_subsegment.sap_delta_time,
+            });
+            presentation_time += subsegment_duration as u64;
+            indexes.push(index);
+        }
+        Ok((
+            Self {
+                reference_id,
+                timescale,
+                earliest_presentation_time,
+                first_offset: 0,
+                references,
+            },
+            indexes,
+        ))
+    }
+
+    pub fn size(&self) -> u64 {
+        let times_size = if self.version() == 0 { 4 + 4 } else { 8 + 8 };
+        4 + 4 + 1 + 3 + 4 + 4 + times_size + 2 + 2 + self.references.len() as u64 * (4 + 4 + 4)
+    }
+
+    fn version(&self) -> u8 {
+        if self.earliest_presentation_time > u32::MAX as u64 || self.first_offset > u32::MAX as u64
+        {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Encodes the sidx with a placeholder for the first_offset, which is
+    /// patched with the position of the first referenced box once it is
+    /// written.
+    pub fn encode_deferred(&self, output: &mut (impl Write + Seek)) -> Result<DeferredOffset> {
+        let begin = encode_box_header(output, *b"sidx")?;
+        let version = self.version();
+        output.write_u8(version)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        self.reference_id.encode(output)?;
+        self.timescale.encode(output)?;
+        if version == 0 {
+            (self.earliest_presentation_time as u32).encode(output)?;
+        } else {
+            self.earliest_presentation_time.encode(output)?;
+        }
+        let first_offset = DeferredOffset::encode(output, version == 1, begin + self.size())?;
+        0u16.encode(output)?; // reserved
+        checked_cast::<u16>(self.references.len(), "reference_count")?.encode(output)?;
+        for reference in &self.references {
+            if reference.referenced_size > 0x7FFFFFFF {
+                return Err(Error::ValueOutOfRange {
+                    field: "referenced_size",
+                });
+            }
+            if reference.sap_type > 0x7 {
+                return Err(Error::ValueOutOfRange { field: "sap_type" });
+            }
+            if reference.sap_delta_time > 0xFFFFFFF {
+                return Err(Error::ValueOutOfRange {
+                    field: "sap_delta_time",
+                });
+            }
+            let mut writer = BitWriter::new(&mut *output);
+            writer.write_bit(reference.reference_type == SegmentIndexReferenceType::Index)?;
+            writer.write_bits(reference.referenced_size as u64, 31)?;
+            writer.write_bits(reference.subsegment_duration as u64, 32)?;
+            writer.write_bit(reference.starts_with_sap)?;
+            writer.write_bits(reference.sap_type as u64, 3)?;
+            writer.write_bits(reference.sap_delta_time as u64, 28)?;
+            writer.into_inner()?;
+        }
+
+        update_box_header(output, begin)?;
+        Ok(first_offset)
+    }
+}
+
+impl Encode for SegmentIndexBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let first_offset = self.encode_deferred(output)?;
+        let target = first_offset.base + self.first_offset;
+        first_offset.update(output, target)
+    }
+}
+
+impl Decode for SegmentIndexBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        input.read_u24::<BigEndian>()?; // flags
+
+        let reference_id = Decode::decode(input)?;
+        let timescale = Decode::decode(input)?;
+        let earliest_presentation_time;
+        let first_offset;
+        match version {
+            0 => {
+                earliest_presentation_time = u32::decode(input)? as u64;
+                first_offset = u32::decode(input)? as u64;
+            }
+            1 => {
+                earliest_presentation_time = Decode::decode(input)?;
+                first_offset = Decode::decode(input)?;
+            }
+            _ => return Err(Error::ValueOutOfRange { field: "version" }),
+        }
+        u16::decode(input)?; // reserved
+        let reference_count = u16::decode(input)?;
+        let mut references = Vec::new();
+        let mut reader = BitReader::new(input);
+        for _ in 0..reference_count {
+            references.push(SegmentIndexReference {
+                reference_type: if reader.read_bit()? {
+                    SegmentIndexReferenceType::Index
+                } else {
+                    SegmentIndexReferenceType::Media
+                },
+                referenced_size: reader.read_bits(31)? as u32,
+                subsegment_duration: reader.read_bits(32)? as u32,
+                starts_with_sap: reader.read_bit()?,
+                sap_type: reader.read_bits(3)? as u8,
+                sap_delta_time: reader.read_bits(28)? as u32,
+            });
+        }
+        *input = reader.into_inner();
+        Ok(Self {
+            reference_id,
+            timescale,
+            earliest_presentation_time,
+            first_offset,
+            references,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// QuickTime File Format, Metadata Item List Atom
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+pub struct ItemListBox(pub Vec<MetadataItem>);
+
+impl ItemListBox {
+    pub fn get(&self, key: FourCC) -> Option<&MetadataItem> {
+        self.0.iter().find(|item| item.key == key)
+    }
+
+    /// Returns the first text value of an item.
+    pub fn text(&self, key: FourCC) -> Option<String> {
+        self.get(key)?.values.iter().find_map(DataBox::to_text)
+    }
+
+    /// Replaces an item with a single UTF-8 text value.
+    pub fn set_text(&mut self, key: FourCC, value: &str) {
+        self.0.retain(|item| item.key != key);
+        self.0.push(MetadataItem {
+            key,
+            values: vec![DataBox::text(value)],
+            children: Vec::new(),
+        });
+    }
+}
+
+#[derive(Debug)]
+pub struct MetadataItem {
+    pub key: FourCC,
+    pub values: Vec<DataBox>,
+    /// other boxes of the item, like mean and name of freeform items
+    pub children: Vec<RawBox>,
+}
+
+impl MetadataItem {
+    pub const TITLE: FourCC = FourCC(u32::from_be_bytes(*b"\xA9nam"));
+    pub const ARTIST: FourCC = FourCC(u32::from_be_bytes(*b"\xA9ART"));
+    pub const ALBUM: FourCC = FourCC(u32::from_be_bytes(*b"\xA9alb"));
+    pub const COMMENT: FourCC = FourCC(u32::from_be_bytes(*b"\xA9cmt"));
+    pub const ENCODER: FourCC = FourCC(u32::from_be_bytes(*b"\xA9too"));
+    pub const GENRE: FourCC = FourCC(u32::from_be_bytes(*b"\xA9gen"));
+    pub const DESCRIPTION: FourCC = FourCC(u32::from_be_bytes(*b"desc"));
+    pub const COPYRIGHT: FourCC = FourCC(u32::from_be_bytes(*b"cprt"));
+    pub const COVER_ART: FourCC = FourCC(u32::from_be_bytes(*b"covr"));
+}
+
+impl Encode for ItemListBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"ilst")?;
+
+        for item in &self.0 {
+            let item_begin = encode_box_header(output, item.key.to_bytes())?;
+            for child in &item.children {
+                child.encode(output)?;
+            }
+            for value in &item.values {
+                value.encode(output)?;
+            }
+            update_box_header(output, item_begin)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ItemListBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            let (key, data) = decode_box_header(input)?;
+            let mut values = Vec::new();
+            let mut children = Vec::new();
+            for child in Boxes::new(data) {
+                let child = child?;
+                if &child.r#type.to_bytes() == b"data" {
+                    values.push(DataBox::decode(&mut child.data.as_slice())?);
+                } else {
+                    children.push(child);
+                }
+            }
+            items.push(MetadataItem {
+                key: key.into(),
+                values,
+                children,
+            });
         }
         Ok(Self(items))
     }
 }
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct DataBox {
+    pub type_indicator: u32,
+    pub locale: u32,
+    #[derivative(Debug = "ignore")]
+    pub data: Vec<u8>,
+}
+
+impl DataBox {
+    pub const UTF8: u32 = 1;
+    pub const UTF16: u32 = 2;
+    pub const UTF8_SORT: u32 = 4;
+    pub const UTF16_SORT: u32 = 5;
+    pub const JPEG: u32 = 13;
+    pub const PNG: u32 = 14;
+
+    /// Text is always written as UTF-8.
+    pub fn text(value: &str) -> Self {
+        Self {
+            type_indicator: Self::UTF8,
+            locale: 0,
+            data: value.as_bytes().to_owned(),
+        }
+    }
+
+    /// Returns the value as text if it is UTF-8 or UTF-16, the latter being
+    /// big-endian unless there is a byte order mark.
+    pub fn to_text(&self) -> Option<String> {
+        let utf16 = |data: &[u8], little_endian: bool| {
+            if !data.len().is_multiple_of(2) {
+                return None;
+            }
+            char::decode_utf16(data.chunks_exact(2).map(|unit| {
+                if little_endian {
+                    u16::from_le_bytes([unit[0], unit[1]])
+                } else {
+                    u16::from_be_bytes([unit[0], unit[1]])
+                }
+            }))
+            .collect::<std::result::Result<String, _>>()
+            .ok()
+        };
+        // some writers use UTF-16 with a byte order mark regardless of the type
+        match (self.type_indicator, self.data.as_slice()) {
+            (
+                Self::UTF8 | Self::UTF16 | Self::UTF8_SORT | Self::UTF16_SORT,
+                [0xFE, 0xFF, data @ ..],
+            ) => utf16(data, false),
+            (
+                Self::UTF8 | Self::UTF16 | Self::UTF8_SORT | Self::UTF16_SORT,
+                [0xFF, 0xFE, data @ ..],
+            ) => utf16(data, true),
+            (Self::UTF8 | Self::UTF8_SORT, data) => String::from_utf8(data.to_owned()).ok(),
+            (Self::UTF16 | Self::UTF16_SORT, data) => utf16(data, false),
+            _ => None,
+        }
+    }
+}
+
+/// Removes the leading count of a QuickTime string, if it matches the length.
+fn uncount_string(value: &mut String) {
+    if value
+        .bytes()
+        .next()
+        .is_some_and(|count| count as usize == value.len() - 1)
+    {
+        value.remove(0);
+    }
+}
+
+/// Decodes the string of a 3GPP asset, behind the version, flags and
+/// language, which is UTF-8 or UTF-16 with a byte order mark.
+fn decode_asset_string(data: &[u8]) -> Option<String> {
+    let data = data.get(4 + 2..)?;
+    let data = if data.starts_with(&[0xFE, 0xFF]) || data.starts_with(&[0xFF, 0xFE]) {
+        let length = data
+            .chunks_exact(2)
+            .position(|unit| unit == [0, 0])
+            .map_or(data.len() & !1, |units| units * 2);
+        &data[..length]
+    } else {
+        &data[..data.iter().position(|&c| c == 0).unwrap_or(data.len())]
+    };
+    DataBox {
+        type_indicator: DataBox::UTF8,
+        locale: 0,
+        data: data.to_owned(),
+    }
+    .to_text()
+}
+
+impl Encode for DataBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"data")?;
+
+        self.type_indicator.encode(output)?;
+        self.locale.encode(output)?;
+        output.write_all(&self.data)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for DataBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let type_indicator = Decode::decode(input)?;
+        let locale = Decode::decode(input)?;
+        let data = input.to_owned();
+        *input = &input[input.len()..];
+        Ok(Self {
+            type_indicator,
+            locale,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use fixed_macro::types::U16F16;
+
+    use super::{
+        aac::{AACSampleEntry, AudioSpecificConfig},
+        heif::{ImageRotation, ItemPropertiesBox, ItemProperty},
+        mp3::MP3SampleEntry,
+        timed_metadata::{URIBox, URIMetaSampleEntry},
+        uncv::V210SampleEntry,
+        Decode, Encode, Error, File, FileTypeBox, FourCC, MediaDataBox, MediaTime, MovieBox,
+        MovieExtendsBox, MovieFragmentBox, MovieFragmentHeaderBox, MovieHeaderBox, Profile, RawBox,
+        SampleEntry, SegmentIndexBox, SegmentIndexReference, SegmentIndexReferenceType, TrackBox,
+        TrackExtendsBox, TrackFragmentBaseMediaDecodeTimeBox, TrackFragmentBox,
+        TrackFragmentHeaderBox, TrackRunBox, TrackRunEntry, VisualSampleEntry,
+    };
+
+    fn encode(value: &impl Encode) -> Vec<u8> {
+        let mut output = Cursor::new(Vec::new());
+        value.encode(&mut output).unwrap();
+        output.into_inner()
+    }
+
+    fn visual_sample_entry(width: u16, height: u16, extra_boxes: Vec<RawBox>) -> VisualSampleEntry {
+        VisualSampleEntry {
+            data_reference_index: 1,
+            width,
+            height,
+            horizresolution: U16F16!(72),
+            vertresolution: U16F16!(72),
+            frame_count: 1,
+            compressorname: Default::default(),
+            depth: 24,
+            colour: None,
+            field: None,
+            gamma: None,
+            coding_constraints: None,
+            bit_rate: None,
+            extra_boxes,
+        }
+    }
+
+    fn fractions_box(r#type: &[u8; 4], values: &[u32]) -> RawBox {
+        RawBox {
+            r#type: FourCC::from(*r#type),
+            data: values
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn sample_entry_extra_boxes() {
+        let entry = SampleEntry::V210(V210SampleEntry {
+            base: visual_sample_entry(720, 576, vec![fractions_box(b"pasp", &[16, 15])]),
+        });
+        let data = encode(&entry);
+
+        let decoded = SampleEntry::decode(&mut data.as_slice()).unwrap();
+        let extra_boxes = &decoded.visual().unwrap().extra_boxes;
+        assert_eq!(extra_boxes.len(), 1);
+        assert_eq!(extra_boxes[0].r#type, FourCC::from(*b"pasp"));
+        assert_eq!(encode(&decoded), data);
+    }
+
+    #[test]
+    fn display_dimensions() {
+        // HDV
+        let entry = visual_sample_entry(1440, 1080, vec![fractions_box(b"pasp", &[4, 3])]);
+        assert_eq!(entry.display_dimensions(), (1920.0, 1080.0));
+
+        // NTSC DV, with the clean aperture of SMPTE RP 187
+        let entry = visual_sample_entry(
+            720,
+            480,
+            vec![
+                fractions_box(b"pasp", &[10, 11]),
+                fractions_box(b"clap", &[704, 1, 480, 1, 0, 1, 0, 1]),
+            ],
+        );
+        assert_eq!(entry.display_dimensions(), (640.0, 480.0));
+
+        let entry = visual_sample_entry(1920, 1080, vec![]);
+        assert_eq!(entry.display_dimensions(), (1920.0, 1080.0));
+    }
+
+    #[test]
+    fn file_extra_boxes() {
+        let file = File {
+            file_type: FileTypeBox {
+                major_brand: FourCC::from(*b"isom"),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"isom")],
+            },
+            movie: None,
+            media_data: Vec::new(),
+            meta: None,
+            extra_boxes: vec![RawBox {
+                r#type: FourCC::from(*b"uuid"),
+                data: vec![0xAB; 20],
+            }],
+        };
+        let mut data = encode(&file);
+        // padding is dropped
+        data.extend_from_slice(&encode(&RawBox {
+            r#type: FourCC::from(*b"free"),
+            data: vec![0; 4],
+        }));
+
+        let decoded = File::decode(&mut data.as_slice()).unwrap();
+        assert_eq!(decoded.extra_boxes.len(), 1);
+        assert_eq!(decoded.extra_boxes[0].data, vec![0xAB; 20]);
+        assert_eq!(encode(&decoded), encode(&file));
+    }
+
+    fn cmaf_fragment(sequence_number: u32, decode_time: Option<u64>) -> RawBox {
+        let fragment = MovieFragmentBox {
+            header: MovieFragmentHeaderBox { sequence_number },
+            track_fragments: vec![TrackFragmentBox {
+                header: TrackFragmentHeaderBox {
+                    track_id: 1,
+                    base_data_offset: None,
+                    sample_description_index: None,
+                    default_sample_duration: None,
+                    default_sample_size: None,
+                    default_sample_flags: None,
+                    duration_is_empty: false,
+                    default_base_is_moof: true,
+                },
+                decode_time: decode_time.map(|time| TrackFragmentBaseMediaDecodeTimeBox {
+                    base_media_decode_time: MediaTime(time),
+                }),
+                runs: vec![TrackRunBox {
+                    data_offset: None,
+                    first_sample_flags: None,
+                    entries: vec![TrackRunEntry {
+                        sample_duration: Some(100),
+                        sample_size: Some(4),
+                        sample_flags: None,
+                        sample_composition_time_offset: None,
+                    }],
+                }],
+                sample_to_groups: Vec::new(),
+                sample_group_descriptions: Vec::new(),
+                auxiliary_information_sizes: Vec::new(),
+                auxiliary_information_offsets: Vec::new(),
+                sample_encryption: None,
+                extra_boxes: Vec::new(),
+            }],
+            protection_system_headers: Vec::new(),
+            extra_boxes: Vec::new(),
+        };
+        RawBox {
+            r#type: FourCC::from(*b"moof"),
+            data: encode(&fragment)[8..].to_vec(),
+        }
+    }
+
+    fn cmaf_file(fragments: Vec<RawBox>, media_data_count: usize) -> File {
+        let mut track = TrackBox::new_timed_metadata(
+            1,
+            1000,
+            SampleEntry::URIMeta(URIMetaSampleEntry {
+                data_reference_index: 1,
+                uri: URIBox {
+                    the_uri: "urn:example".to_owned(),
+                },
+                init: None,
+                bit_rate: None,
+            }),
+            None,
+        );
+        track.media.handler.r#type = FourCC::from(*b"text");
+        File {
+            file_type: FileTypeBox {
+                major_brand: FourCC::from(*b"cmf2"),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"iso6"), FourCC::from(*b"cmfc")],
+            },
+            movie: Some(MovieBox {
+                header: MovieHeaderBox {
+                    timescale: 1000,
+                    next_track_id: 2,
+                    ..Default::default()
+                },
+                tracks: vec![track],
+                extends: Some(MovieExtendsBox {
+                    header: None,
+                    track_extends: vec![TrackExtendsBox {
+                        track_id: 1,
+                        default_sample_description_index: 1,
+                        default_sample_duration: 0,
+                        default_sample_size: 0,
+                        default_sample_flags: 0,
+                    }],
+                    extra_boxes: Vec::new(),
+                }),
+                user_data: None,
+                extra_boxes: Vec::new(),
+            }),
+            media_data: (0..media_data_count)
+                .map(|_| MediaDataBox(vec![0; 4]))
+                .collect(),
+            meta: None,
+            extra_boxes: fragments,
+        }
+    }
+
+    #[test]
+    fn cmaf_fragments() {
+        let violation = |file: File, profile| match file.validate_with(profile) {
+            Err(Error::ProfileViolation { rule, .. }) => rule,
+            result => panic!("{result:?}"),
+        };
+
+        let file = cmaf_file(
+            vec![cmaf_fragment(1, Some(0)), cmaf_fragment(2, Some(100))],
+            2,
+        );
+        file.validate_with(Profile::Cmaf).unwrap();
+        assert_eq!(
+            violation(file, Profile::CmafHeader),
+            "no fragments in header"
+        );
+        cmaf_file(vec![], 0)
+            .validate_with(Profile::CmafHeader)
+            .unwrap();
+
+        let file = cmaf_file(vec![cmaf_fragment(1, Some(0)), cmaf_fragment(2, None)], 2);
+        assert_eq!(violation(file, Profile::Cmaf), "tfdt");
+        let file = cmaf_file(vec![cmaf_fragment(1, Some(0))], 2);
+        assert_eq!(violation(file, Profile::Cmaf), "moof and mdat pairs");
+    }
+
+    #[test]
+    fn add_track() {
+        let track = || cmaf_file(vec![], 0).movie.unwrap().tracks.pop().unwrap();
+        let mut movie = cmaf_file(vec![], 0).movie.unwrap();
+        assert_eq!(movie.add_track(track()).unwrap(), 2);
+        assert_eq!(movie.header.next_track_id, 3);
+
+        movie.header.next_track_id = u32::MAX - 1;
+        assert_eq!(movie.add_track(track()).unwrap(), u32::MAX - 1);
+        assert!(matches!(
+            movie.add_track(track()),
+            Err(Error::ValueOutOfRange { field: "track_id" })
+        ));
+        assert_eq!(movie.tracks.len(), 3);
+    }
+
+    #[test]
+    fn segment_index_hierarchy() {
+        let subsegment = SegmentIndexReference {
+            reference_type: SegmentIndexReferenceType::Media,
+            referenced_size: 1000,
+            // about 6.6 hours at 90 kHz
+            subsegment_duration: u32::MAX / 2 + 1,
+            starts_with_sap: true,
+            sap_type: 1,
+            sap_delta_time: 0,
+        };
+        let subsegments = vec![subsegment; 4];
+        let (index, child_indexes) =
+            SegmentIndexBox::hierarchy(1, 90000, 0, &subsegments, 1).unwrap();
+        assert_eq!(index.references.len(), 4);
+        assert_eq!(
+            child_indexes[3].earliest_presentation_time,
+            3 * (u32::MAX as u64 / 2 + 1)
+        );
+        assert!(matches!(
+            SegmentIndexBox::hierarchy(1, 90000, 0, &subsegments, 2),
+            Err(Error::ValueOutOfRange {
+                field: "subsegment_duration"
+            })
+        ));
+    }
+
+    #[test]
+    fn segment_index_size() {
+        let reference = SegmentIndexReference {
+            reference_type: SegmentIndexReferenceType::Media,
+            referenced_size: 1000,
+            subsegment_duration: 90000,
+            starts_with_sap: true,
+            sap_type: 1,
+            sap_delta_time: 0,
+        };
+        for earliest_presentation_time in [0, u32::MAX as u64 + 1] {
+            for references in [vec![], vec![reference.clone(), reference.clone()]] {
+                let index = SegmentIndexBox {
+                    reference_id: 1,
+                    timescale: 90000,
+                    earliest_presentation_time,
+                    first_offset: 0,
+                    references,
+                };
+                assert_eq!(index.size(), encode(&index).len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn item_property_range() {
+        let mut item_properties = ItemPropertiesBox::default();
+        for property_index in 1..=0x7FFF {
+            let property = ItemProperty::ImageRotation(ImageRotation { angle: 0 });
+            assert_eq!(item_properties.add(property).unwrap(), property_index);
+        }
+        let property = ItemProperty::ImageRotation(ImageRotation { angle: 0 });
+        assert!(matches!(
+            item_properties.add(property),
+            Err(Error::ValueOutOfRange {
+                field: "property_index"
+            })
+        ));
+
+        let rotation = ImageRotation { angle: 4 };
+        assert!(matches!(
+            rotation.encode(&mut Cursor::new(Vec::new())),
+            Err(Error::ValueOutOfRange { field: "angle" })
+        ));
+    }
+
+    #[test]
+    fn mp3_sample_entry_codec() {
+        let AACSampleEntry { base, es } =
+            AACSampleEntry::new(AudioSpecificConfig::AAC_LC, 44100, 2).unwrap();
+        // as written by some QuickTime versions, with an esds
+        let entry = SampleEntry::MP3(MP3SampleEntry {
+            codec: MP3SampleEntry::MP3,
+            base,
+            es: Some(es),
+        });
+        let data = encode(&entry);
+        assert_eq!(&data[4..8], b".mp3");
+        let entry = SampleEntry::decode(&mut data.as_slice()).unwrap();
+        assert!(matches!(&entry, SampleEntry::MP3(entry) if entry.es.is_some()));
+        assert_eq!(encode(&entry), data);
+    }
+
+    #[test]
+    fn avif_sequence_mime_type() {
+        let mut track = TrackBox::new_timed_metadata(
+            1,
+            1000,
+            SampleEntry::URIMeta(URIMetaSampleEntry {
+                data_reference_index: 1,
+                uri: URIBox {
+                    the_uri: "urn:example".to_owned(),
+                },
+                init: None,
+                bit_rate: None,
+            }),
+            None,
+        );
+        track.media.handler.r#type = FourCC::from(*b"pict");
+        let file = File {
+            file_type: FileTypeBox {
+                major_brand: FourCC::from(*b"avis"),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"avis"), FourCC::from(*b"msf1")],
+            },
+            movie: Some(MovieBox {
+                header: MovieHeaderBox {
+                    timescale: 1000,
+                    next_track_id: 2,
+                    ..Default::default()
+                },
+                tracks: vec![track],
+                extends: None,
+                user_data: None,
+                extra_boxes: Vec::new(),
+            }),
+            media_data: Vec::new(),
+            meta: None,
+            extra_boxes: Vec::new(),
+        };
+        assert_eq!(file.mime_type().unwrap(), "image/avif; codecs=\"urim\"");
+    }
+}
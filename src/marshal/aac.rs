@@ -1,12 +1,163 @@
-use std::io::{Seek, Write};
+use std::io::{Cursor, Seek, Write};
+
+use fixed::types::U16F16;
 
 use crate::marshal::{
-    encode_box_header, update_box_header, AudioSampleEntry, Decode, Encode, Result,
+    bits::{BitReader, BitWriter},
+    decode_boxes, encode_box_header,
+    esds::{DecoderConfigDescriptor, ESDescriptor, ESDescriptorBox},
+    update_box_header, AudioSampleEntry, Decode, Encode, Error, Result, SoundDescriptionVersion,
 };
 
 #[derive(Debug)]
 pub struct AACSampleEntry {
     pub base: AudioSampleEntry,
+    pub es: ESDescriptorBox,
+}
+
+impl AACSampleEntry {
+    /// Encoder delay of the common AAC-LC encoders, in samples.
+    pub const PRIMING: u64 = 2112;
+
+    /// Frames overlap with the previous one, which has to be decoded first.
+    pub const ROLL_DISTANCE: i16 = -1;
+
+    /// Creates an entry for the given audio object type, like
+    /// [AudioSpecificConfig::AAC_LC], output sample rate and channel count.
+    ///
+    /// For HE-AAC the core runs at half the sample rate with SBR, and for
+    /// HE-AACv2 additionally on a single channel with PS, which is signaled
+    /// hierarchically, see [Self::set_sbr_signaling]. The media timescale
+    /// should be the output sample rate, with
+    /// [AudioSpecificConfig::output_samples_per_frame] per sample.
+    pub fn new(audio_object_type: u8, sample_rate: u32, channels: u16) -> Result<Self> {
+        let channel_configuration = match channels {
+            1..=6 => channels as u8,
+            8 => 7,
+            _ => {
+                return Err(Error::ValueOutOfRange {
+                    field: "channelcount",
+                })
+            }
+        };
+        let config = match audio_object_type {
+            AudioSpecificConfig::SBR | AudioSpecificConfig::PS => AudioSpecificConfig {
+                audio_object_type: AudioSpecificConfig::AAC_LC,
+                sampling_frequency: sample_rate / 2,
+                channel_configuration: if audio_object_type == AudioSpecificConfig::PS {
+                    1
+                } else {
+                    channel_configuration
+                },
+                extension_sampling_frequency: Some(sample_rate),
+                sbr_present: true,
+                ps_present: audio_object_type == AudioSpecificConfig::PS,
+                ..AudioSpecificConfig::new(AudioSpecificConfig::AAC_LC, 0, 0)
+            },
+            _ => AudioSpecificConfig::new(audio_object_type, sample_rate, channel_configuration),
+        };
+
+        let mut entry = Self {
+            base: AudioSampleEntry {
+                data_reference_index: 1,
+                channelcount: channels,
+                samplesize: 16,
+                samplerate: U16F16::checked_from_num(sample_rate).ok_or(
+                    Error::ValueOutOfRange {
+                        field: "samplerate",
+                    },
+                )?,
+                version: SoundDescriptionVersion::V0,
+                bit_rate: None,
+                extra_boxes: Vec::new(),
+            },
+            es: ESDescriptorBox(ESDescriptor {
+                es_id: 0,
+                stream_priority: 0,
+                depends_on_es_id: None,
+                url: None,
+                ocr_es_id: None,
+                decoder_config: DecoderConfigDescriptor {
+                    object_type_indication: 0x40, // Audio ISO/IEC 14496-3
+                    stream_type: 0x05,            // AudioStream
+                    up_stream: false,
+                    buffer_size_db: 0,
+                    max_bitrate: 0,
+                    avg_bitrate: 0,
+                    decoder_specific_info: None,
+                },
+                sl_config: Default::default(),
+            }),
+        };
+        entry.set_audio_specific_config(&config)?;
+        Ok(entry)
+    }
+
+    /// Parses the decoder specific info, if present.
+    pub fn audio_specific_config(&self) -> Result<Option<AudioSpecificConfig>> {
+        self.es
+            .0
+            .decoder_config
+            .decoder_specific_info
+            .as_deref()
+            .map(|mut decoder_specific_info| {
+                AudioSpecificConfig::decode(&mut decoder_specific_info)
+            })
+            .transpose()
+    }
+
+    /// Returns the codecs parameter of RFC 6381, like mp4a.40.2, with the
+    /// audio object type of SBR or PS for HE-AAC.
+    pub fn codec_string(&self) -> Result<String> {
+        let object_type_indication = self.es.0.decoder_config.object_type_indication;
+        Ok(match self.audio_specific_config()? {
+            Some(config) => format!(
+                "mp4a.{:02X}.{}",
+                object_type_indication,
+                if config.ps_present {
+                    AudioSpecificConfig::PS
+                } else if config.sbr_present {
+                    AudioSpecificConfig::SBR
+                } else {
+                    config.audio_object_type
+                }
+            ),
+            None => format!("mp4a.{object_type_indication:02X}"),
+        })
+    }
+
+    /// Switches how SBR and PS are signaled, and sets the samplerate to the
+    /// one players expect with it: the output sample rate if signaled
+    /// explicitly, the one of the core for implicit signaling, as players
+    /// unaware of SBR would otherwise play at half the speed.
+    ///
+    /// As implicit signaling leaves no trace of SBR in the config, it can't
+    /// be switched back from.
+    pub fn set_sbr_signaling(&mut self, sbr_signaling: SbrSignaling) -> Result<()> {
+        let Some(mut config) = self.audio_specific_config()? else {
+            return Err(Error::InvalidBoxQuantity {
+                r#type: "DecoderSpecificInfo",
+                quantity: 0,
+                expected: 1,
+            });
+        };
+        config.sbr_signaling = sbr_signaling;
+        self.base.samplerate = U16F16::checked_from_num(match sbr_signaling {
+            SbrSignaling::Implicit => config.sampling_frequency,
+            _ => config.output_sampling_frequency(),
+        })
+        .ok_or(Error::ValueOutOfRange {
+            field: "samplerate",
+        })?;
+        self.set_audio_specific_config(&config)
+    }
+
+    pub fn set_audio_specific_config(&mut self, config: &AudioSpecificConfig) -> Result<()> {
+        let mut data = Cursor::new(Vec::new());
+        config.encode(&mut data)?;
+        self.es.0.decoder_config.decoder_specific_info = Some(data.into_inner());
+        Ok(())
+    }
 }
 
 impl Encode for AACSampleEntry {
@@ -14,6 +165,7 @@ impl Encode for AACSampleEntry {
         let begin = encode_box_header(output, *b"mp4a")?;
 
         self.base.encode(output)?;
+        self.es.encode(output)?;
 
         update_box_header(output, begin)
     }
@@ -21,8 +173,328 @@ impl Encode for AACSampleEntry {
 
 impl Decode for AACSampleEntry {
     fn decode(input: &mut &[u8]) -> Result<Self> {
-        Ok(Self {
-            base: Decode::decode(input)?,
+        let mut base = AudioSampleEntry::decode(input)?;
+
+        let mut es = None;
+        let mut wave: Option<SoundDescriptionExtensionBox> = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            optional esds es,
+            optional wave wave,
+        }
+        base.set_extra_boxes(extra_boxes);
+
+        let es = es
+            .or(wave.and_then(|wave| wave.es))
+            .ok_or(Error::InvalidBoxQuantity {
+                r#type: "esds",
+                quantity: 0,
+                expected: 1,
+            })?;
+        Ok(Self { base, es })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-3:2009 1.6.2.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Decoder specific info of MPEG-4 audio.
+///
+/// Only the GASpecificConfig of AAC Main, LC, SSR and LTP without a program
+/// config element is parsed, anything following it is dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioSpecificConfig {
+    /// of the core, without SBR and PS
+    pub audio_object_type: u8,
+    /// of the core, in Hz
+    pub sampling_frequency: u32,
+    pub channel_configuration: u8,
+    /// output sampling frequency of SBR, in Hz
+    pub extension_sampling_frequency: Option<u32>,
+    pub sbr_present: bool,
+    pub ps_present: bool,
+    /// how SBR and PS are written, detected when they are read
+    pub sbr_signaling: SbrSignaling,
+    /// 960 instead of 1024 samples per frame
+    pub frame_length_flag: bool,
+    pub core_coder_delay: Option<u16>,
+    pub extension_flag: bool,
+}
+
+impl AudioSpecificConfig {
+    pub const AAC_MAIN: u8 = 1;
+    pub const AAC_LC: u8 = 2;
+    pub const AAC_SSR: u8 = 3;
+    pub const AAC_LTP: u8 = 4;
+    pub const SBR: u8 = 5;
+    pub const PS: u8 = 29;
+
+    const SAMPLING_FREQUENCIES: [u32; 13] = [
+        96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+    ];
+
+    pub fn new(audio_object_type: u8, sampling_frequency: u32, channel_configuration: u8) -> Self {
+        Self {
+            audio_object_type,
+            sampling_frequency,
+            channel_configuration,
+            extension_sampling_frequency: None,
+            sbr_present: false,
+            ps_present: false,
+            sbr_signaling: SbrSignaling::Hierarchical,
+            frame_length_flag: false,
+            core_coder_delay: None,
+            extension_flag: false,
+        }
+    }
+
+    /// Index into the table of common sampling frequencies, or 0xF if it has
+    /// to be written explicitly.
+    pub fn sampling_frequency_index(sampling_frequency: u32) -> u8 {
+        Self::SAMPLING_FREQUENCIES
+            .iter()
+            .position(|&value| value == sampling_frequency)
+            .map_or(0xF, |index| index as u8)
+    }
+
+    /// Of the core.
+    pub fn samples_per_frame(&self) -> u32 {
+        if self.frame_length_flag {
+            960
+        } else {
+            1024
+        }
+    }
+
+    /// Sampling frequency after SBR, in Hz.
+    pub fn output_sampling_frequency(&self) -> u32 {
+        if self.sbr_present {
+            self.extension_sampling_frequency
+                .unwrap_or(self.sampling_frequency * 2)
+        } else {
+            self.sampling_frequency
+        }
+    }
+
+    /// Samples per frame after SBR, at the output sampling frequency.
+    pub fn output_samples_per_frame(&self) -> u32 {
+        (self.samples_per_frame() as u64 * self.output_sampling_frequency() as u64
+            / self.sampling_frequency.max(1) as u64) as u32
+    }
+
+    fn has_ga_specific_config(&self) -> bool {
+        (Self::AAC_MAIN..=Self::AAC_LTP).contains(&self.audio_object_type)
+            && self.channel_configuration != 0
+    }
+}
+
+impl Encode for AudioSpecificConfig {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        if self.channel_configuration > 0xF {
+            return Err(Error::ValueOutOfRange {
+                field: "channel_configuration",
+            });
+        }
+        if self.ps_present && !self.sbr_present {
+            return Err(Error::ValueOutOfRange {
+                field: "ps_present",
+            });
+        }
+        let hierarchical = self.sbr_present && self.sbr_signaling == SbrSignaling::Hierarchical;
+        let backward_compatible =
+            self.sbr_present && self.sbr_signaling == SbrSignaling::BackwardCompatible;
+        if backward_compatible && !self.has_ga_specific_config() {
+            return Err(Error::ValueOutOfRange {
+                field: "sbr_signaling",
+            });
+        }
+        let mut writer = BitWriter::new(&mut *output);
+        if hierarchical {
+            write_audio_object_type(
+                &mut writer,
+                if self.ps_present { Self::PS } else { Self::SBR },
+            )?;
+            write_sampling_frequency(&mut writer, self.sampling_frequency)?;
+            writer.write_bits(self.channel_configuration as u64, 4)?;
+            write_sampling_frequency(&mut writer, self.output_sampling_frequency())?;
+            write_audio_object_type(&mut writer, self.audio_object_type)?;
+        } else {
+            write_audio_object_type(&mut writer, self.audio_object_type)?;
+            write_sampling_frequency(&mut writer, self.sampling_frequency)?;
+            writer.write_bits(self.channel_configuration as u64, 4)?;
+        }
+        if self.has_ga_specific_config() {
+            writer.write_bit(self.frame_length_flag)?;
+            writer.write_bit(self.core_coder_delay.is_some())?;
+            if let Some(core_coder_delay) = self.core_coder_delay {
+                if core_coder_delay > 0x3FFF {
+                    return Err(Error::ValueOutOfRange {
+                        field: "core_coder_delay",
+                    });
+                }
+                writer.write_bits(core_coder_delay as u64, 14)?;
+            }
+            writer.write_bit(self.extension_flag)?;
+            if self.extension_flag {
+                writer.write_bit(false)?; // extensionFlag3
+            }
+        }
+        if backward_compatible {
+            writer.write_bits(0x2B7, 11)?; // syncExtensionType
+            write_audio_object_type(&mut writer, Self::SBR)?;
+            writer.write_bit(true)?; // sbrPresentFlag
+            write_sampling_frequency(&mut writer, self.output_sampling_frequency())?;
+            if self.ps_present {
+                writer.write_bits(0x548, 11)?; // syncExtensionType
+                writer.write_bit(true)?; // psPresentFlag
+            }
+        }
+        writer.into_inner()?;
+        Ok(())
+    }
+}
+
+impl Decode for AudioSpecificConfig {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut reader = BitReader::new(input);
+        let mut audio_object_type = read_audio_object_type(&mut reader)?;
+        let sampling_frequency = read_sampling_frequency(&mut reader)?;
+        let channel_configuration = reader.read_bits(4)? as u8;
+        let mut config = Self::new(audio_object_type, sampling_frequency, channel_configuration);
+
+        // explicit hierarchical signaling
+        if audio_object_type == Self::SBR || audio_object_type == Self::PS {
+            config.sbr_present = true;
+            config.ps_present = audio_object_type == Self::PS;
+            config.sbr_signaling = SbrSignaling::Hierarchical;
+            config.extension_sampling_frequency = Some(read_sampling_frequency(&mut reader)?);
+            audio_object_type = read_audio_object_type(&mut reader)?;
+            config.audio_object_type = audio_object_type;
+        }
+
+        if config.has_ga_specific_config() {
+            config.frame_length_flag = reader.read_bit()?;
+            if reader.read_bit()? {
+                config.core_coder_delay = Some(reader.read_bits(14)? as u16);
+            }
+            config.extension_flag = reader.read_bit()?;
+            if config.extension_flag {
+                reader.read_bit()?; // extensionFlag3
+            }
+
+            // explicit backward compatible signaling
+            if !config.sbr_present
+                && reader.remaining_bits() >= 16
+                && reader.read_bits(11)? == 0x2B7
+                && read_audio_object_type(&mut reader)? == Self::SBR
+            {
+                config.sbr_present = reader.read_bit()?;
+                if config.sbr_present {
+                    config.sbr_signaling = SbrSignaling::BackwardCompatible;
+                    config.extension_sampling_frequency =
+                        Some(read_sampling_frequency(&mut reader)?);
+                    if reader.remaining_bits() >= 12 && reader.read_bits(11)? == 0x548 {
+                        config.ps_present = reader.read_bit()?;
+                    }
+                }
+            }
+        }
+
+        reader.into_inner();
+        *input = &input[input.len()..];
+        Ok(config)
+    }
+}
+
+/// How the presence of SBR and PS is written into the AudioSpecificConfig,
+/// ISO/IEC 14496-3:2009 1.6.5.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SbrSignaling {
+    /// Not at all, decoders find SBR in the bitstream, and decoders unaware
+    /// of it only decode the core.
+    Implicit,
+    /// As audio object type of SBR or PS with the core behind it, which
+    /// decoders unaware of SBR can't decode.
+    #[default]
+    Hierarchical,
+    /// As extension behind the config of the core, which decoders unaware of
+    /// SBR skip.
+    BackwardCompatible,
+}
+
+fn read_audio_object_type(reader: &mut BitReader) -> Result<u8> {
+    let audio_object_type = reader.read_bits(5)? as u8;
+    Ok(if audio_object_type == 31 {
+        32 + reader.read_bits(6)? as u8
+    } else {
+        audio_object_type
+    })
+}
+
+fn write_audio_object_type(writer: &mut BitWriter<impl Write>, value: u8) -> Result<()> {
+    match value {
+        0..=30 => writer.write_bits(value as u64, 5),
+        32..=95 => {
+            writer.write_bits(31, 5)?; // escape
+            writer.write_bits(value as u64 - 32, 6)
+        }
+        _ => Err(Error::ValueOutOfRange {
+            field: "audio_object_type",
+        }),
+    }
+}
+
+fn read_sampling_frequency(reader: &mut BitReader) -> Result<u32> {
+    let sampling_frequency_index = reader.read_bits(4)? as usize;
+    if sampling_frequency_index == 0xF {
+        return Ok(reader.read_bits(24)? as u32);
+    }
+    AudioSpecificConfig::SAMPLING_FREQUENCIES
+        .get(sampling_frequency_index)
+        .copied()
+        .ok_or(Error::ValueOutOfRange {
+            field: "sampling_frequency_index",
         })
+}
+
+fn write_sampling_frequency(writer: &mut BitWriter<impl Write>, value: u32) -> Result<()> {
+    let sampling_frequency_index = AudioSpecificConfig::sampling_frequency_index(value);
+    writer.write_bits(sampling_frequency_index as u64, 4)?;
+    if sampling_frequency_index == 0xF {
+        if value > 0xFFFFFF {
+            return Err(Error::ValueOutOfRange {
+                field: "sampling_frequency",
+            });
+        }
+        writer.write_bits(value as u64, 24)?;
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// QuickTime File Format, Sound Sample Description Extensions
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Wraps the esds of mp4a entries with a version 1 sound description, and is
+/// only read to get to it.
+struct SoundDescriptionExtensionBox {
+    es: Option<ESDescriptorBox>,
+}
+
+impl Decode for SoundDescriptionExtensionBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut es = None;
+
+        decode_boxes! {
+            input,
+            optional esds es,
+        }
+
+        Ok(Self { es })
     }
 }
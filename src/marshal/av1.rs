@@ -1,12 +1,35 @@
 use std::io::{Seek, Write};
 
+use derivative::Derivative;
+
 use crate::marshal::{
-    encode_box_header, update_box_header, Decode, Encode, Result, VisualSampleEntry,
+    bits::{BitReader, BitWriter},
+    decode_boxes, encode_box_header, update_box_header, Decode, Encode, Error, Result,
+    VisualSampleEntry,
 };
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// AV1 Codec ISO Media File Format Binding 2.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
 #[derive(Debug)]
 pub struct AV1SampleEntry {
     pub base: VisualSampleEntry,
+    pub config: AV1CodecConfigurationRecord,
+}
+
+impl AV1SampleEntry {
+    /// Returns the short form of the codecs parameter of AV1-ISOBMFF Annex A,
+    /// like av01.0.08M.08.
+    pub fn codec_string(&self) -> String {
+        format!(
+            "av01.{}.{:02}{}.{:02}",
+            self.config.seq_profile,
+            self.config.seq_level_idx_0,
+            if self.config.seq_tier_0 { 'H' } else { 'M' },
+            self.config.bit_depth()
+        )
+    }
 }
 
 impl Encode for AV1SampleEntry {
@@ -14,6 +37,7 @@ impl Encode for AV1SampleEntry {
         let begin = encode_box_header(output, *b"av01")?;
 
         self.base.encode(output)?;
+        self.config.encode(output)?;
 
         update_box_header(output, begin)
     }
@@ -21,8 +45,213 @@ impl Encode for AV1SampleEntry {
 
 impl Decode for AV1SampleEntry {
     fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut base = VisualSampleEntry::decode(input)?;
+
+        let mut config = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            required av1C config,
+        }
+        base.set_extra_boxes(extra_boxes);
+
+        Ok(Self { base, config })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// AV1 Codec ISO Media File Format Binding 2.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct AV1CodecConfigurationRecord {
+    pub seq_profile: u8,
+    pub seq_level_idx_0: u8,
+    pub seq_tier_0: bool,
+    pub high_bitdepth: bool,
+    pub twelve_bit: bool,
+    pub monochrome: bool,
+    pub chroma_subsampling_x: bool,
+    pub chroma_subsampling_y: bool,
+    pub chroma_sample_position: u8,
+    pub initial_presentation_delay_minus_one: Option<u8>,
+    /// sequence header and metadata OBUs
+    #[derivative(Debug = "ignore")]
+    pub config_obus: Vec<u8>,
+}
+
+impl AV1CodecConfigurationRecord {
+    pub fn bit_depth(&self) -> u8 {
+        match (self.high_bitdepth, self.twelve_bit) {
+            (false, _) => 8,
+            (true, false) => 10,
+            (true, true) => 12,
+        }
+    }
+}
+
+impl Encode for AV1CodecConfigurationRecord {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"av1C")?;
+
+        if self.seq_profile > 0x7 {
+            return Err(Error::ValueOutOfRange {
+                field: "seq_profile",
+            });
+        }
+        if self.seq_level_idx_0 > 0x1F {
+            return Err(Error::ValueOutOfRange {
+                field: "seq_level_idx_0",
+            });
+        }
+        if self.chroma_sample_position > 0x3 {
+            return Err(Error::ValueOutOfRange {
+                field: "chroma_sample_position",
+            });
+        }
+        if self.initial_presentation_delay_minus_one.is_some_and(
+            |initial_presentation_delay_minus_one| initial_presentation_delay_minus_one > 0xF,
+        ) {
+            return Err(Error::ValueOutOfRange {
+                field: "initial_presentation_delay_minus_one",
+            });
+        }
+        let mut writer = BitWriter::new(&mut *output);
+        writer.write_bit(true)?; // marker
+        writer.write_bits(1, 7)?; // version
+        writer.write_bits(self.seq_profile as u64, 3)?;
+        writer.write_bits(self.seq_level_idx_0 as u64, 5)?;
+        writer.write_bit(self.seq_tier_0)?;
+        writer.write_bit(self.high_bitdepth)?;
+        writer.write_bit(self.twelve_bit)?;
+        writer.write_bit(self.monochrome)?;
+        writer.write_bit(self.chroma_subsampling_x)?;
+        writer.write_bit(self.chroma_subsampling_y)?;
+        writer.write_bits(self.chroma_sample_position as u64, 2)?;
+        writer.write_bits(0, 3)?; // reserved
+        writer.write_bit(self.initial_presentation_delay_minus_one.is_some())?;
+        writer.write_bits(
+            self.initial_presentation_delay_minus_one.unwrap_or(0) as u64,
+            4,
+        )?;
+        writer.into_inner()?;
+        output.write_all(&self.config_obus)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for AV1CodecConfigurationRecord {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut reader = BitReader::new(input);
+        let marker = reader.read_bit()?;
+        let version = reader.read_bits(7)?;
+        if !marker || version != 1 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        let seq_profile = reader.read_bits(3)? as u8;
+        let seq_level_idx_0 = reader.read_bits(5)? as u8;
+        let seq_tier_0 = reader.read_bit()?;
+        let high_bitdepth = reader.read_bit()?;
+        let twelve_bit = reader.read_bit()?;
+        let monochrome = reader.read_bit()?;
+        let chroma_subsampling_x = reader.read_bit()?;
+        let chroma_subsampling_y = reader.read_bit()?;
+        let chroma_sample_position = reader.read_bits(2)? as u8;
+        reader.read_bits(3)?; // reserved
+        let initial_presentation_delay_present = reader.read_bit()?;
+        let initial_presentation_delay_minus_one = reader.read_bits(4)? as u8;
+        *input = reader.into_inner();
+        let config_obus = input.to_owned();
+        *input = &input[input.len()..];
         Ok(Self {
-            base: Decode::decode(input)?,
+            seq_profile,
+            seq_level_idx_0,
+            seq_tier_0,
+            high_bitdepth,
+            twelve_bit,
+            monochrome,
+            chroma_subsampling_x,
+            chroma_subsampling_y,
+            chroma_sample_position,
+            initial_presentation_delay_minus_one: initial_presentation_delay_present
+                .then_some(initial_presentation_delay_minus_one),
+            config_obus,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{AV1CodecConfigurationRecord, AV1SampleEntry};
+    use crate::marshal::{Decode, Encode, VisualSampleEntry};
+
+    /// 8-bit 4:2:0 main profile, level 4.0, with the sequence header OBU
+    const MAIN: &[u8] = &[
+        0x81, 0x08, 0x0C, 0x00, // av1C
+        0x0A, 0x0B, 0x00, 0x00, 0x00, 0x24, 0xC6, 0x7F, 0xDF, 0xFF, 0xE0, 0x08, 0x00, // OBU
+    ];
+
+    /// 10-bit 4:2:0 main profile, level 5.1 high tier, with an initial
+    /// presentation delay of 4 and without OBUs
+    const HIGH_TIER: &[u8] = &[0x81, 0x0D, 0xCC, 0x13];
+
+    fn round_trip(data: &[u8]) -> AV1CodecConfigurationRecord {
+        let config = AV1CodecConfigurationRecord::decode(&mut &data[..]).unwrap();
+        let mut output = Cursor::new(Vec::new());
+        config.encode(&mut output).unwrap();
+        assert_eq!(output.get_ref()[8..], *data);
+        config
+    }
+
+    fn codec_string(config: AV1CodecConfigurationRecord) -> String {
+        AV1SampleEntry {
+            base: VisualSampleEntry {
+                data_reference_index: 1,
+                width: 1920,
+                height: 1080,
+                horizresolution: Default::default(),
+                vertresolution: Default::default(),
+                frame_count: 1,
+                compressorname: Default::default(),
+                depth: 24,
+                colour: None,
+                field: None,
+                gamma: None,
+                coding_constraints: None,
+                bit_rate: None,
+                extra_boxes: Vec::new(),
+            },
+            config,
+        }
+        .codec_string()
+    }
+
+    #[test]
+    fn codec_configuration_record() {
+        let config = round_trip(MAIN);
+        assert_eq!(config.seq_profile, 0);
+        assert_eq!(config.seq_level_idx_0, 8);
+        assert!(config.chroma_subsampling_x && config.chroma_subsampling_y);
+        assert_eq!(config.initial_presentation_delay_minus_one, None);
+        assert_eq!(config.config_obus, MAIN[4..]);
+        assert_eq!(codec_string(config), "av01.0.08M.08");
+
+        let config = round_trip(HIGH_TIER);
+        assert_eq!(config.initial_presentation_delay_minus_one, Some(3));
+        assert!(config.config_obus.is_empty());
+        assert_eq!(codec_string(config), "av01.0.13H.10");
+
+        let mut config = round_trip(MAIN);
+        config.seq_level_idx_0 = 32;
+        assert!(config.encode(&mut Cursor::new(Vec::new())).is_err());
+        // version 2
+        assert!(AV1CodecConfigurationRecord::decode(&mut &[0x82, 0x08, 0x0C, 0x00][..]).is_err());
+    }
+}
@@ -1,28 +1,259 @@
 use std::io::{Seek, Write};
 
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use derivative::Derivative;
+
 use crate::marshal::{
-    encode_box_header, update_box_header, Decode, Encode, Result, VisualSampleEntry,
+    checked_cast, decode_boxes, encode_box_header, update_box_header, Decode, Encode, Error,
+    FourCC, Result, VisualSampleEntry,
 };
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-15:2019 5.4.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
 #[derive(Debug)]
 pub struct AVCSampleEntry {
+    /// avc1 or avc2 if all parameter sets are in the configuration, avc3 or
+    /// avc4 if they may also be in the samples
+    pub codec: FourCC,
     pub base: VisualSampleEntry,
+    pub config: AVCConfigurationBox,
+}
+
+impl AVCSampleEntry {
+    pub const AVC1: FourCC = FourCC(u32::from_be_bytes(*b"avc1"));
+    pub const AVC2: FourCC = FourCC(u32::from_be_bytes(*b"avc2"));
+    pub const AVC3: FourCC = FourCC(u32::from_be_bytes(*b"avc3"));
+    pub const AVC4: FourCC = FourCC(u32::from_be_bytes(*b"avc4"));
+
+    pub fn has_in_band_parameter_sets(&self) -> bool {
+        self.codec == Self::AVC3 || self.codec == Self::AVC4
+    }
+
+    /// Switches between avc1 and avc3 (or avc2 and avc4), like for CMAF, which
+    /// prefers avc3 so that parameter sets can change between segments.
+    ///
+    /// Going out of band requires all parameter sets to be in the
+    /// configuration already, as they are not moved out of the samples.
+    pub fn set_in_band_parameter_sets(&mut self, in_band: bool) -> Result<()> {
+        if !in_band
+            && (self.config.sequence_parameter_sets.is_empty()
+                || self.config.picture_parameter_sets.is_empty())
+        {
+            return Err(Error::ValueOutOfRange {
+                field: "sequence_parameter_sets",
+            });
+        }
+        self.codec = match (
+            self.codec == Self::AVC2 || self.codec == Self::AVC4,
+            in_band,
+        ) {
+            (false, false) => Self::AVC1,
+            (true, false) => Self::AVC2,
+            (false, true) => Self::AVC3,
+            (true, true) => Self::AVC4,
+        };
+        Ok(())
+    }
+
+    /// Returns the codecs parameter of RFC 6381, like avc1.64001F.
+    pub fn codec_string(&self) -> String {
+        format!(
+            "{:?}.{:02X}{:02X}{:02X}",
+            self.codec,
+            self.config.profile_indication,
+            self.config.profile_compatibility,
+            self.config.level_indication
+        )
+    }
 }
 
 impl Encode for AVCSampleEntry {
     fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
-        let begin = encode_box_header(output, *b"avc1")?;
+        let begin = encode_box_header(output, self.codec.to_bytes())?;
 
         self.base.encode(output)?;
+        self.config.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl AVCSampleEntry {
+    pub(crate) fn decode(input: &mut &[u8], codec: FourCC) -> Result<Self> {
+        let mut base = VisualSampleEntry::decode(input)?;
+
+        let mut config = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            required avcC config,
+        }
+        base.set_extra_boxes(extra_boxes);
+
+        Ok(Self {
+            codec,
+            base,
+            config,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-15:2019 5.3.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct AVCConfigurationBox {
+    pub profile_indication: u8,
+    pub profile_compatibility: u8,
+    pub level_indication: u8,
+    pub length_size_minus_one: u8,
+    /// empty if only in band
+    #[derivative(Debug = "ignore")]
+    pub sequence_parameter_sets: Vec<Vec<u8>>,
+    /// empty if only in band
+    #[derivative(Debug = "ignore")]
+    pub picture_parameter_sets: Vec<Vec<u8>>,
+    /// only for the High profiles, but often left out
+    pub extension: Option<AVCConfigurationExtension>,
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct AVCConfigurationExtension {
+    pub chroma_format: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    #[derivative(Debug = "ignore")]
+    pub sequence_parameter_set_extensions: Vec<Vec<u8>>,
+}
+
+impl Encode for AVCConfigurationBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"avcC")?;
+
+        if self.length_size_minus_one > 0x3 || self.length_size_minus_one == 2 {
+            return Err(Error::ValueOutOfRange {
+                field: "length_size_minus_one",
+            });
+        }
+        output.write_u8(1)?; // configurationVersion
+        output.write_u8(self.profile_indication)?;
+        output.write_u8(self.profile_compatibility)?;
+        output.write_u8(self.level_indication)?;
+        output.write_u8(0b111111 << 2 | self.length_size_minus_one)?;
+        if self.sequence_parameter_sets.len() > 0x1F {
+            return Err(Error::ValueOutOfRange {
+                field: "sequence_parameter_sets",
+            });
+        }
+        output.write_u8(0b111 << 5 | self.sequence_parameter_sets.len() as u8)?;
+        encode_parameter_sets(output, &self.sequence_parameter_sets)?;
+        output.write_u8(checked_cast(
+            self.picture_parameter_sets.len(),
+            "picture_parameter_sets",
+        )?)?;
+        encode_parameter_sets(output, &self.picture_parameter_sets)?;
+        if let Some(extension) = &self.extension {
+            if extension.chroma_format > 0x3
+                || extension.bit_depth_luma_minus8 > 0x7
+                || extension.bit_depth_chroma_minus8 > 0x7
+            {
+                return Err(Error::ValueOutOfRange { field: "extension" });
+            }
+            output.write_u8(0b111111 << 2 | extension.chroma_format)?;
+            output.write_u8(0b11111 << 3 | extension.bit_depth_luma_minus8)?;
+            output.write_u8(0b11111 << 3 | extension.bit_depth_chroma_minus8)?;
+            output.write_u8(checked_cast(
+                extension.sequence_parameter_set_extensions.len(),
+                "sequence_parameter_set_extensions",
+            )?)?;
+            encode_parameter_sets(output, &extension.sequence_parameter_set_extensions)?;
+        }
 
         update_box_header(output, begin)
     }
 }
 
-impl Decode for AVCSampleEntry {
+impl Decode for AVCConfigurationBox {
     fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 1 {
+            return Err(Error::ValueOutOfRange {
+                field: "configurationVersion",
+            });
+        }
+        let profile_indication = input.read_u8()?;
+        let profile_compatibility = input.read_u8()?;
+        let level_indication = input.read_u8()?;
+        let length_size_minus_one = input.read_u8()? & 0x3;
+        let num_of_sequence_parameter_sets = input.read_u8()? & 0x1F;
+        let sequence_parameter_sets = decode_parameter_sets(input, num_of_sequence_parameter_sets)?;
+        let num_of_picture_parameter_sets = input.read_u8()?;
+        let picture_parameter_sets = decode_parameter_sets(input, num_of_picture_parameter_sets)?;
+        let extension = if input.is_empty() {
+            None
+        } else {
+            let chroma_format = input.read_u8()? & 0x3;
+            let bit_depth_luma_minus8 = input.read_u8()? & 0x7;
+            let bit_depth_chroma_minus8 = input.read_u8()? & 0x7;
+            let num_of_sequence_parameter_set_ext = input.read_u8()?;
+            Some(AVCConfigurationExtension {
+                chroma_format,
+                bit_depth_luma_minus8,
+                bit_depth_chroma_minus8,
+                sequence_parameter_set_extensions: decode_parameter_sets(
+                    input,
+                    num_of_sequence_parameter_set_ext,
+                )?,
+            })
+        };
+        *input = &input[input.len()..];
+
         Ok(Self {
-            base: Decode::decode(input)?,
+            profile_indication,
+            profile_compatibility,
+            level_indication,
+            length_size_minus_one,
+            sequence_parameter_sets,
+            picture_parameter_sets,
+            extension,
         })
     }
 }
+
+pub(crate) fn encode_parameter_sets(
+    output: &mut (impl Write + Seek),
+    parameter_sets: &[Vec<u8>],
+) -> Result<()> {
+    for parameter_set in parameter_sets {
+        output
+            .write_all(&checked_cast::<u16>(parameter_set.len(), "parameter_set")?.to_be_bytes())?;
+        output.write_all(parameter_set)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn decode_parameter_sets(
+    input: &mut &[u8],
+    count: impl Into<usize>,
+) -> Result<Vec<Vec<u8>>> {
+    let mut parameter_sets = Vec::new();
+    for _ in 0..count.into() {
+        let length = u16::decode(input)? as usize;
+        if length > input.len() {
+            return Err(Error::ValueOutOfRange {
+                field: "parameter_set",
+            });
+        }
+        let (parameter_set, remaining) = input.split_at(length);
+        parameter_sets.push(parameter_set.to_owned());
+        *input = remaining;
+    }
+    Ok(parameter_sets)
+}
@@ -0,0 +1,240 @@
+use std::io::{ErrorKind, Write};
+
+use crate::marshal::{checked_cast, Error, Result};
+
+pub struct BitReader<'a> {
+    input: &'a [u8],
+    position: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self { input, position: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool> {
+        let byte = *self
+            .input
+            .first()
+            .ok_or(Error::Io(ErrorKind::UnexpectedEof.into()))?;
+        let bit = byte >> (7 - self.position) & 1 != 0;
+        self.position += 1;
+        if self.position == 8 {
+            self.input = &self.input[1..];
+            self.position = 0;
+        }
+        Ok(bit)
+    }
+
+    pub fn read_bits(&mut self, count: u32) -> Result<u64> {
+        if count > 64 {
+            return Err(Error::ValueOutOfRange { field: "bits" });
+        }
+        let mut value = 0;
+        for _ in 0..count {
+            value = value << 1 | self.read_bit()? as u64;
+        }
+        Ok(value)
+    }
+
+    pub fn read_ue(&mut self) -> Result<u32> {
+        let mut leading_zero_bits = 0;
+        while !self.read_bit()? {
+            leading_zero_bits += 1;
+            // u32::MAX has 32
+            if leading_zero_bits > 32 {
+                return Err(Error::ValueOutOfRange { field: "ue(v)" });
+            }
+        }
+        checked_cast(
+            (1u64 << leading_zero_bits) - 1 + self.read_bits(leading_zero_bits)?,
+            "ue(v)",
+        )
+    }
+
+    pub fn read_se(&mut self) -> Result<i32> {
+        let value = self.read_ue()? as i64;
+        checked_cast(
+            if value & 1 != 0 {
+                (value + 1) / 2
+            } else {
+                -(value / 2)
+            },
+            "se(v)",
+        )
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.position == 0
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        self.input.len() * 8 - self.position as usize
+    }
+
+    pub fn byte_align(&mut self) {
+        if self.position != 0 {
+            self.input = &self.input[1..];
+            self.position = 0;
+        }
+    }
+
+    pub fn into_inner(mut self) -> &'a [u8] {
+        self.byte_align();
+        self.input
+    }
+}
+
+pub struct BitWriter<W: Write> {
+    output: W,
+    buffer: u8,
+    position: u32,
+}
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            buffer: 0,
+            position: 0,
+        }
+    }
+
+    pub fn write_bit(&mut self, value: bool) -> Result<()> {
+        self.buffer |= (value as u8) << (7 - self.position);
+        self.position += 1;
+        if self.position == 8 {
+            self.output.write_all(&[self.buffer])?;
+            self.buffer = 0;
+            self.position = 0;
+        }
+        Ok(())
+    }
+
+    pub fn write_bits(&mut self, value: u64, count: u32) -> Result<()> {
+        if count > 64 || count < 64 && value >> count != 0 {
+            return Err(Error::ValueOutOfRange { field: "bits" });
+        }
+        for index in (0..count).rev() {
+            self.write_bit(value >> index & 1 != 0)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_ue(&mut self, value: u32) -> Result<()> {
+        let value = value as u64 + 1;
+        let length = 64 - value.leading_zeros();
+        self.write_bits(0, length - 1)?;
+        self.write_bits(value, length)
+    }
+
+    pub fn write_se(&mut self, value: i32) -> Result<()> {
+        let value = value as i64;
+        self.write_ue(checked_cast(
+            if value > 0 { value * 2 - 1 } else { -value * 2 },
+            "se(v)",
+        )?)
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.position == 0
+    }
+
+    pub fn byte_align(&mut self) -> Result<()> {
+        while self.position != 0 {
+            self.write_bit(false)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(mut self) -> Result<W> {
+        self.byte_align()?;
+        Ok(self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitReader, BitWriter};
+
+    fn write(f: impl FnOnce(&mut BitWriter<&mut Vec<u8>>)) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut writer = BitWriter::new(&mut output);
+        f(&mut writer);
+        writer.into_inner().unwrap();
+        output
+    }
+
+    #[test]
+    fn bits() {
+        let data = write(|writer| {
+            writer.write_bit(true).unwrap();
+            writer.write_bits(0b010, 3).unwrap();
+            writer.write_bits(0xABCD, 16).unwrap();
+            assert!(!writer.is_aligned());
+            assert!(writer.write_bits(0b100, 2).is_err());
+        });
+        // padded with zero bits
+        assert_eq!(data, [0b1010_1010, 0b1011_1100, 0b1101_0000]);
+
+        let mut reader = BitReader::new(&data);
+        assert!(reader.read_bit().unwrap());
+        assert_eq!(reader.read_bits(3).unwrap(), 0b010);
+        assert_eq!(reader.read_bits(16).unwrap(), 0xABCD);
+        assert_eq!(reader.remaining_bits(), 4);
+        assert!(reader.into_inner().is_empty());
+
+        let data = u64::MAX.to_be_bytes();
+        assert_eq!(BitReader::new(&data).read_bits(64).unwrap(), u64::MAX);
+        assert!(BitReader::new(&data).read_bits(65).is_err());
+        assert!(BitReader::new(&data[..1]).read_bits(9).is_err());
+    }
+
+    #[test]
+    fn exp_golomb() {
+        // ITU-T H.264 9.1, 1, 010, 011, 00100, 00101, 00110, 00111, 0001000
+        let data = write(|writer| {
+            for value in 0..8 {
+                writer.write_ue(value).unwrap();
+            }
+        });
+        assert_eq!(
+            data,
+            [0b1010_0110, 0b0100_0010, 0b1001_1000, 0b1110_0010, 0]
+        );
+
+        // ITU-T H.264 9.1.1, 0, 1, -1, 2, -2
+        let data = write(|writer| {
+            for value in [0, 1, -1, 2, -2] {
+                writer.write_se(value).unwrap();
+            }
+        });
+        assert_eq!(data, [0b1010_0110, 0b0100_0010, 0b1000_0000]);
+
+        let ue = [0, 1, 254, 255, u32::MAX - 1, u32::MAX];
+        let se = [0, 1, -1, i32::MAX, -i32::MAX];
+        let data = write(|writer| {
+            for value in ue {
+                writer.write_ue(value).unwrap();
+            }
+            for value in se {
+                writer.write_se(value).unwrap();
+            }
+            assert!(writer.write_se(i32::MIN).is_err());
+        });
+        let mut reader = BitReader::new(&data);
+        for value in ue {
+            assert_eq!(reader.read_ue().unwrap(), value);
+        }
+        for value in se {
+            assert_eq!(reader.read_se().unwrap(), value);
+        }
+
+        // 33 leading zero bits
+        assert!(
+            BitReader::new(&[0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF])
+                .read_ue()
+                .is_err()
+        );
+    }
+}
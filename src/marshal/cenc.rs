@@ -0,0 +1,281 @@
+use std::io::{Read, Seek, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use derivative::Derivative;
+
+use crate::marshal::{
+    checked_cast, encode_box_header, update_box_header, Decode, Encode, Error, Result,
+    SampleAuxiliaryInformationSizesBox,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23001-7:2016 8.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Data for a DRM system to get the keys, in the moov or, when keys are
+/// rotated, in the moof of the fragments using them.
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct ProtectionSystemSpecificHeaderBox {
+    pub system_id: [u8; 16],
+    /// key ids the data applies to, only in version 1
+    pub kids: Vec<[u8; 16]>,
+    #[derivative(Debug = "ignore")]
+    pub data: Vec<u8>,
+}
+
+impl Encode for ProtectionSystemSpecificHeaderBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"pssh")?;
+        output.write_u8(if self.kids.is_empty() { 0 } else { 1 })?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        output.write_all(&self.system_id)?;
+        if !self.kids.is_empty() {
+            checked_cast::<u32>(self.kids.len(), "kid_count")?.encode(output)?;
+            for kid in &self.kids {
+                output.write_all(kid)?;
+            }
+        }
+        checked_cast::<u32>(self.data.len(), "data_size")?.encode(output)?;
+        output.write_all(&self.data)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ProtectionSystemSpecificHeaderBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        input.read_u24::<BigEndian>()?; // flags
+        if version > 1 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+
+        let mut system_id = [0; 16];
+        input.read_exact(&mut system_id)?;
+        let mut kids = Vec::new();
+        if version == 1 {
+            let kid_count = u32::decode(input)?;
+            if kid_count as u64 * 16 > input.len() as u64 {
+                return Err(Error::ValueOutOfRange { field: "kid_count" });
+            }
+            for _ in 0..kid_count {
+                let mut kid = [0; 16];
+                input.read_exact(&mut kid)?;
+                kids.push(kid);
+            }
+        }
+        let data_size = u32::decode(input)? as usize;
+        let Some((data, remaining_input)) = input.split_at_checked(data_size) else {
+            return Err(Error::ValueOutOfRange { field: "data_size" });
+        };
+        let data = data.to_owned();
+        *input = remaining_input;
+        Ok(Self {
+            system_id,
+            kids,
+            data,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23001-7:2016 6
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Entry of the seig sample group, which overrides the defaults of the tenc
+/// for the samples mapped to it, like a new key after a key rotation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CencSampleEncryptionInformation {
+    /// encrypted blocks of 16 bytes of the pattern, only for cens and cbcs
+    pub crypt_byte_block: u8,
+    /// clear blocks of 16 bytes of the pattern, only for cens and cbcs
+    pub skip_byte_block: u8,
+    pub is_protected: bool,
+    /// 0, 8 or 16, with 0 only for a constant IV
+    pub per_sample_iv_size: u8,
+    pub kid: [u8; 16],
+    /// IV of all samples if there is no IV per sample, 8 or 16 bytes
+    pub constant_iv: Vec<u8>,
+}
+
+impl CencSampleEncryptionInformation {
+    pub(crate) fn to_bytes(&self) -> Result<Vec<u8>> {
+        if self.crypt_byte_block > 0xF || self.skip_byte_block > 0xF {
+            return Err(Error::ValueOutOfRange {
+                field: "crypt_byte_block",
+            });
+        }
+        let mut data = vec![
+            0, // reserved
+            self.crypt_byte_block << 4 | self.skip_byte_block,
+            self.is_protected as u8,
+            self.per_sample_iv_size,
+        ];
+        data.extend_from_slice(&self.kid);
+        if self.is_protected && self.per_sample_iv_size == 0 {
+            data.push(checked_cast(self.constant_iv.len(), "constant_iv_size")?);
+            data.extend_from_slice(&self.constant_iv);
+        }
+        Ok(data)
+    }
+}
+
+impl Decode for CencSampleEncryptionInformation {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_u8()?; // reserved
+        let pattern = input.read_u8()?;
+        let is_protected = input.read_u8()? != 0;
+        let per_sample_iv_size = input.read_u8()?;
+        let mut kid = [0; 16];
+        input.read_exact(&mut kid)?;
+        let mut constant_iv = Vec::new();
+        if is_protected && per_sample_iv_size == 0 {
+            constant_iv = vec![0; input.read_u8()? as usize];
+            input.read_exact(&mut constant_iv)?;
+        }
+        Ok(Self {
+            crypt_byte_block: pattern >> 4,
+            skip_byte_block: pattern & 0xF,
+            is_protected,
+            per_sample_iv_size,
+            kid,
+            constant_iv,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23001-7:2016 7.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Sample auxiliary information of CENC in the traf, which the saio points
+/// to.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct SampleEncryptionBox {
+    pub use_subsample_encryption: bool,
+    pub sample_count: u32,
+    /// IV and subsamples of each sample, which can only be parsed with the
+    /// per_sample_iv_size of the tenc or seig, see [Self::entries]
+    #[derivative(Debug = "ignore")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleEncryptionEntry {
+    /// empty for a constant IV
+    pub iv: Vec<u8>,
+    pub subsamples: Vec<SubsampleEncryptionEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubsampleEncryptionEntry {
+    pub bytes_of_clear_data: u16,
+    pub bytes_of_protected_data: u32,
+}
+
+impl SampleEncryptionBox {
+    /// Offset of the auxiliary information of the first sample from the
+    /// beginning of the box.
+    pub(crate) const DATA_OFFSET: u64 = 4 + 4 + 1 + 3 + 4;
+
+    pub fn new(entries: &[SampleEncryptionEntry], use_subsample_encryption: bool) -> Result<Self> {
+        let mut data = Vec::new();
+        for entry in entries {
+            data.extend_from_slice(&entry.iv);
+            if use_subsample_encryption {
+                data.write_u16::<BigEndian>(checked_cast(
+                    entry.subsamples.len(),
+                    "subsample_count",
+                )?)?;
+                for subsample in &entry.subsamples {
+                    data.write_u16::<BigEndian>(subsample.bytes_of_clear_data)?;
+                    data.write_u32::<BigEndian>(subsample.bytes_of_protected_data)?;
+                }
+            } else if !entry.subsamples.is_empty() {
+                return Err(Error::ValueOutOfRange {
+                    field: "subsample_count",
+                });
+            }
+        }
+        Ok(Self {
+            use_subsample_encryption,
+            sample_count: checked_cast(entries.len(), "sample_count")?,
+            data,
+        })
+    }
+
+    pub fn entries(&self, per_sample_iv_size: u8) -> Result<Vec<SampleEncryptionEntry>> {
+        let mut input = self.data.as_slice();
+        let mut entries = Vec::new();
+        for _ in 0..self.sample_count {
+            let mut iv = vec![0; per_sample_iv_size as usize];
+            input.read_exact(&mut iv)?;
+            let mut subsamples = Vec::new();
+            if self.use_subsample_encryption {
+                let subsample_count = input.read_u16::<BigEndian>()?;
+                for _ in 0..subsample_count {
+                    subsamples.push(SubsampleEncryptionEntry {
+                        bytes_of_clear_data: input.read_u16::<BigEndian>()?,
+                        bytes_of_protected_data: input.read_u32::<BigEndian>()?,
+                    });
+                }
+            }
+            entries.push(SampleEncryptionEntry { iv, subsamples });
+        }
+        if !input.is_empty() {
+            return Err(Error::ValueOutOfRange {
+                field: "per_sample_iv_size",
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Returns the saiz for the auxiliary information of the samples.
+    pub fn sizes(&self, per_sample_iv_size: u8) -> Result<SampleAuxiliaryInformationSizesBox> {
+        let sizes = self
+            .entries(per_sample_iv_size)?
+            .iter()
+            .map(|entry| {
+                let subsamples_size = if self.use_subsample_encryption {
+                    2 + entry.subsamples.len() * (2 + 4)
+                } else {
+                    0
+                };
+                checked_cast(entry.iv.len() + subsamples_size, "sample_info_size")
+            })
+            .collect::<Result<_>>()?;
+        SampleAuxiliaryInformationSizesBox::new(None, sizes)
+    }
+}
+
+impl Encode for SampleEncryptionBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"senc")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>((self.use_subsample_encryption as u32) << 1)?; // flags
+
+        self.sample_count.encode(output)?;
+        output.write_all(&self.data)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for SampleEncryptionBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_u8()?; // version
+        let flags = input.read_u24::<BigEndian>()?;
+
+        let sample_count = u32::decode(input)?;
+        let data = input.to_owned();
+        *input = &[];
+        Ok(Self {
+            use_subsample_encryption: flags & 0x2 != 0,
+            sample_count,
+            data,
+        })
+    }
+}
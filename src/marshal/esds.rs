@@ -0,0 +1,375 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::marshal::{
+    bits::{BitReader, BitWriter},
+    checked_cast, encode_box_header, update_box_header, Decode, Encode, Error, Result,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-14:2003 5.6
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct ESDescriptorBox(pub ESDescriptor);
+
+impl Encode for ESDescriptorBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"esds")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        self.0.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ESDescriptorBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let (tag, mut data) = read_descriptor_header(input)?;
+        if tag != 0x03 {
+            return Err(Error::InvalidDescriptorQuantity {
+                tag: 0x03,
+                quantity: 0,
+                expected: 1,
+            });
+        }
+        Ok(Self(Decode::decode(&mut data)?))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-1:2010 7.2.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+pub fn write_descriptor_header(output: &mut (impl Write + Seek), tag: u8) -> Result<u64> {
+    let begin = output.stream_position()?;
+    output.write_u8(tag)?;
+    output.write_all(&[0x80, 0x80, 0x80, 0x00])?; // size
+    Ok(begin)
+}
+
+pub fn update_descriptor_header(output: &mut (impl Write + Seek), begin: u64) -> Result<()> {
+    let end = output.stream_position()?;
+    let size = end - begin - 1 - 4;
+    if size >= 1 << 28 {
+        return Err(Error::ValueOutOfRange { field: "size" });
+    }
+    output.seek(SeekFrom::Start(begin + 1))?;
+    output.write_all(&[
+        0x80 | (size >> 21 & 0x7F) as u8,
+        0x80 | (size >> 14 & 0x7F) as u8,
+        0x80 | (size >> 7 & 0x7F) as u8,
+        (size & 0x7F) as u8,
+    ])?;
+    output.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+pub fn read_descriptor_header<'a>(input: &mut &'a [u8]) -> Result<(u8, &'a [u8])> {
+    let tag = input.read_u8()?;
+    let mut size = 0;
+    for index in 0..4 {
+        let byte = input.read_u8()?;
+        size = size << 7 | (byte & 0x7F) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if index == 3 {
+            return Err(Error::InvalidDescriptorSize {
+                tag,
+                size,
+                remaining: input.len(),
+            });
+        }
+    }
+    if size > input.len() {
+        return Err(Error::InvalidDescriptorSize {
+            tag,
+            size,
+            remaining: input.len(),
+        });
+    }
+
+    let (data, remaining_data) = input.split_at(size);
+    *input = remaining_data;
+    Ok((tag, data))
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-1:2010 7.2.6.5
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct ESDescriptor {
+    pub es_id: u16,
+    pub stream_priority: u8,
+    pub depends_on_es_id: Option<u16>,
+    pub url: Option<String>,
+    pub ocr_es_id: Option<u16>,
+    pub decoder_config: DecoderConfigDescriptor,
+    pub sl_config: SLConfigDescriptor,
+}
+
+impl Encode for ESDescriptor {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = write_descriptor_header(output, 0x03)?;
+
+        self.es_id.encode(output)?;
+        let mut writer = BitWriter::new(&mut *output);
+        writer.write_bit(self.depends_on_es_id.is_some())?;
+        writer.write_bit(self.url.is_some())?;
+        writer.write_bit(self.ocr_es_id.is_some())?;
+        writer.write_bits(self.stream_priority as u64 & 0x1F, 5)?;
+        writer.into_inner()?;
+        self.depends_on_es_id.encode(output)?;
+        if let Some(url) = &self.url {
+            output.write_u8(checked_cast(url.len(), "url")?)?;
+            output.write_all(url.as_bytes())?;
+        }
+        self.ocr_es_id.encode(output)?;
+        self.decoder_config.encode(output)?;
+        self.sl_config.encode(output)?;
+
+        update_descriptor_header(output, begin)
+    }
+}
+
+impl Decode for ESDescriptor {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let es_id = Decode::decode(input)?;
+        let mut reader = BitReader::new(input);
+        let stream_dependence = reader.read_bit()?;
+        let url_flag = reader.read_bit()?;
+        let ocr_stream = reader.read_bit()?;
+        let stream_priority = reader.read_bits(5)? as u8;
+        *input = reader.into_inner();
+        let depends_on_es_id = if stream_dependence {
+            Some(Decode::decode(input)?)
+        } else {
+            None
+        };
+        let url = if url_flag {
+            let length = input.read_u8()? as usize;
+            if length > input.len() {
+                return Err(Error::InvalidDescriptorSize {
+                    tag: 0x03,
+                    size: length,
+                    remaining: input.len(),
+                });
+            }
+            let (data, remaining_data) = input.split_at(length);
+            *input = remaining_data;
+            Some(String::from_utf8_lossy(data).into_owned())
+        } else {
+            None
+        };
+        let ocr_es_id = if ocr_stream {
+            Some(Decode::decode(input)?)
+        } else {
+            None
+        };
+
+        let mut decoder_config = None;
+        let mut sl_config = None;
+        while !input.is_empty() {
+            let (tag, mut data) = read_descriptor_header(input)?;
+            match tag {
+                0x04 => decoder_config = Some(Decode::decode(&mut data)?),
+                0x06 => sl_config = Some(Decode::decode(&mut data)?),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            es_id,
+            stream_priority,
+            depends_on_es_id,
+            url,
+            ocr_es_id,
+            decoder_config: decoder_config.ok_or(Error::InvalidDescriptorQuantity {
+                tag: 0x04,
+                quantity: 0,
+                expected: 1,
+            })?,
+            sl_config: sl_config.unwrap_or_default(),
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-1:2010 7.2.6.6
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct DecoderConfigDescriptor {
+    pub object_type_indication: u8,
+    pub stream_type: u8,
+    pub up_stream: bool,
+    pub buffer_size_db: u32,
+    pub max_bitrate: u32,
+    pub avg_bitrate: u32,
+    pub decoder_specific_info: Option<Vec<u8>>,
+}
+
+impl Encode for DecoderConfigDescriptor {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = write_descriptor_header(output, 0x04)?;
+
+        output.write_u8(self.object_type_indication)?;
+        let mut writer = BitWriter::new(&mut *output);
+        writer.write_bits(self.stream_type as u64 & 0x3F, 6)?;
+        writer.write_bit(self.up_stream)?;
+        writer.write_bit(true)?; // reserved
+        writer.into_inner()?;
+        output.write_u24::<BigEndian>(self.buffer_size_db)?;
+        self.max_bitrate.encode(output)?;
+        self.avg_bitrate.encode(output)?;
+        if let Some(decoder_specific_info) = &self.decoder_specific_info {
+            let begin = write_descriptor_header(output, 0x05)?;
+            output.write_all(decoder_specific_info)?;
+            update_descriptor_header(output, begin)?;
+        }
+
+        update_descriptor_header(output, begin)
+    }
+}
+
+impl Decode for DecoderConfigDescriptor {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let object_type_indication = input.read_u8()?;
+        let mut reader = BitReader::new(input);
+        let stream_type = reader.read_bits(6)? as u8;
+        let up_stream = reader.read_bit()?;
+        reader.read_bit()?; // reserved
+        *input = reader.into_inner();
+        let buffer_size_db = input.read_u24::<BigEndian>()?;
+        let max_bitrate = Decode::decode(input)?;
+        let avg_bitrate = Decode::decode(input)?;
+
+        let mut decoder_specific_info = None;
+        while !input.is_empty() {
+            let (tag, data) = read_descriptor_header(input)?;
+            if tag == 0x05 {
+                decoder_specific_info = Some(data.to_owned());
+            }
+        }
+
+        Ok(Self {
+            object_type_indication,
+            stream_type,
+            up_stream,
+            buffer_size_db,
+            max_bitrate,
+            avg_bitrate,
+            decoder_specific_info,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-1:2010 7.3.2.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct SLConfigDescriptor(pub Vec<u8>);
+
+impl Default for SLConfigDescriptor {
+    fn default() -> Self {
+        // predefined: reserved for use in MP4 files
+        Self(vec![2])
+    }
+}
+
+impl Encode for SLConfigDescriptor {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = write_descriptor_header(output, 0x06)?;
+
+        output.write_all(&self.0)?;
+
+        update_descriptor_header(output, begin)
+    }
+}
+
+impl Decode for SLConfigDescriptor {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let data = input.to_owned();
+        *input = &input[input.len()..];
+        Ok(Self(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{read_descriptor_header, ESDescriptorBox};
+    use crate::marshal::{Decode, Encode};
+
+    /// AAC-LC, 44.1 kHz, stereo, with the compact sizes as written by FFmpeg
+    const COMPACT: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, // version and flags
+        0x03, 0x19, 0x00, 0x01, 0x00, // ES_Descriptor
+        0x04, 0x11, 0x40, 0x15, // DecoderConfigDescriptor
+        0x00, 0x00, 0x00, 0x00, 0x01, 0xF4, 0x00, 0x00, 0x01, 0xF4, 0x00, // rates
+        0x05, 0x02, 0x12, 0x10, // DecoderSpecificInfo
+        0x06, 0x01, 0x02, // SLConfigDescriptor
+    ];
+
+    /// the same, with the 4-byte sizes as written by QuickTime and this crate
+    const PADDED: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, // version and flags
+        0x03, 0x80, 0x80, 0x80, 0x22, 0x00, 0x01, 0x00, // ES_Descriptor
+        0x04, 0x80, 0x80, 0x80, 0x14, 0x40, 0x15, // DecoderConfigDescriptor
+        0x00, 0x00, 0x00, 0x00, 0x01, 0xF4, 0x00, 0x00, 0x01, 0xF4, 0x00, // rates
+        0x05, 0x80, 0x80, 0x80, 0x02, 0x12, 0x10, // DecoderSpecificInfo
+        0x06, 0x80, 0x80, 0x80, 0x01, 0x02, // SLConfigDescriptor
+    ];
+
+    #[test]
+    fn descriptor_header() {
+        let mut input = &COMPACT[4..];
+        let (tag, data) = read_descriptor_header(&mut input).unwrap();
+        assert_eq!((tag, data.len()), (0x03, 0x19));
+        assert!(input.is_empty());
+
+        let mut input = &PADDED[4..];
+        let (tag, data) = read_descriptor_header(&mut input).unwrap();
+        assert_eq!((tag, data.len()), (0x03, 0x22));
+        assert!(input.is_empty());
+
+        // more than 4 bytes of size, and more than the remaining data
+        assert!(
+            read_descriptor_header(&mut &[0x03, 0x80, 0x80, 0x80, 0x80, 0x01, 0x00][..]).is_err()
+        );
+        assert!(read_descriptor_header(&mut &[0x03, 0x02, 0x00][..]).is_err());
+    }
+
+    #[test]
+    fn es_descriptor() {
+        for sample in [COMPACT, PADDED] {
+            let esds = ESDescriptorBox::decode(&mut &sample[..]).unwrap();
+            let decoder_config = &esds.0.decoder_config;
+            assert_eq!(esds.0.es_id, 1);
+            assert_eq!(decoder_config.object_type_indication, 0x40);
+            assert_eq!(decoder_config.stream_type, 0x05);
+            assert_eq!(decoder_config.max_bitrate, 128000);
+            assert_eq!(
+                decoder_config.decoder_specific_info.as_deref(),
+                Some(&[0x12, 0x10][..])
+            );
+            assert_eq!(esds.0.sl_config.0, [0x02]);
+
+            let mut output = Cursor::new(Vec::new());
+            esds.encode(&mut output).unwrap();
+            assert_eq!(output.get_ref()[8..], *PADDED);
+        }
+    }
+}
@@ -0,0 +1,673 @@
+use std::io::{Seek, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use derivative::Derivative;
+
+use crate::marshal::{
+    bits::{BitReader, BitWriter},
+    checked_cast, decode_box_header, define_box, encode_box_header, update_box_header, Decode,
+    Encode, Error, FourCC, RawBox, Result,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 6.5.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+define_box! {
+    #[derive(Debug, Clone)]
+    pub struct ImageSpatialExtentsProperty: full_box b"ispe" {
+        pub image_width: u32,
+        pub image_height: u32,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 6.5.6
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+pub struct PixelInformationProperty(pub Vec<u8>);
+
+impl Encode for PixelInformationProperty {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"pixi")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        output.write_u8(checked_cast(self.0.len(), "num_channels")?)?;
+        output.write_all(&self.0)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for PixelInformationProperty {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let num_channels = input.read_u8()?;
+        let mut bits_per_channel = Vec::new();
+        for _ in 0..num_channels {
+            bits_per_channel.push(input.read_u8()?);
+        }
+        Ok(Self(bits_per_channel))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 6.5.8
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+pub struct AuxiliaryTypeProperty {
+    pub aux_type: String,
+    pub aux_subtype: Vec<u8>,
+}
+
+impl AuxiliaryTypeProperty {
+    pub const ALPHA: &'static str = "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha";
+    pub const DEPTH: &'static str = "urn:mpeg:mpegB:cicp:systems:auxiliary:depth";
+
+    pub fn is_alpha(&self) -> bool {
+        self.aux_type == Self::ALPHA || self.aux_type == "urn:mpeg:hevc:2015:auxid:1"
+    }
+
+    pub fn is_depth(&self) -> bool {
+        self.aux_type == Self::DEPTH || self.aux_type == "urn:mpeg:hevc:2015:auxid:2"
+    }
+}
+
+impl Encode for AuxiliaryTypeProperty {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"auxC")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        self.aux_type.encode(output)?;
+        output.write_all(&self.aux_subtype)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for AuxiliaryTypeProperty {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let aux_type = Decode::decode(input)?;
+        let aux_subtype = input.to_owned();
+        *input = &input[input.len()..];
+        Ok(Self {
+            aux_type,
+            aux_subtype,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 6.5.10
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+pub struct ImageRotation {
+    pub angle: u8,
+}
+
+impl ImageRotation {
+    pub fn degrees(&self) -> u32 {
+        self.angle as u32 * 90
+    }
+}
+
+impl Encode for ImageRotation {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        // in steps of 90 degrees anti-clockwise
+        if self.angle > 3 {
+            return Err(Error::ValueOutOfRange { field: "angle" });
+        }
+        let begin = encode_box_header(output, *b"irot")?;
+
+        let mut writer = BitWriter::new(&mut *output);
+        writer.write_bits(0, 6)?; // reserved
+        writer.write_bits(self.angle as u64, 2)?;
+        writer.into_inner()?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ImageRotation {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut reader = BitReader::new(input);
+        reader.read_bits(6)?; // reserved
+        let angle = reader.read_bits(2)? as u8;
+        *input = reader.into_inner();
+        Ok(Self { angle })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 6.5.12
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+pub struct ImageMirror {
+    pub axis: ImageMirrorAxis,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageMirrorAxis {
+    Vertical,
+    Horizontal,
+}
+
+impl Encode for ImageMirror {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"imir")?;
+
+        let mut writer = BitWriter::new(&mut *output);
+        writer.write_bits(0, 7)?; // reserved
+        writer.write_bit(self.axis == ImageMirrorAxis::Horizontal)?;
+        writer.into_inner()?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ImageMirror {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut reader = BitReader::new(input);
+        reader.read_bits(7)?; // reserved
+        let axis = if reader.read_bit()? {
+            ImageMirrorAxis::Horizontal
+        } else {
+            ImageMirrorAxis::Vertical
+        };
+        *input = reader.into_inner();
+        Ok(Self { axis })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 6.6.2.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct ImageOverlay {
+    pub canvas_fill_value: [u16; 4],
+    pub output_width: u32,
+    pub output_height: u32,
+    pub offsets: Vec<(i32, i32)>,
+}
+
+impl Encode for ImageOverlay {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let large = self.output_width > u16::MAX as u32
+            || self.output_height > u16::MAX as u32
+            || self.offsets.iter().any(|&(horizontal, vertical)| {
+                i16::try_from(horizontal).is_err() || i16::try_from(vertical).is_err()
+            });
+
+        output.write_u8(0)?; // version
+        output.write_u8(if large { 1 << 0 } else { 0 })?; // flags
+        for canvas_fill_value in self.canvas_fill_value {
+            canvas_fill_value.encode(output)?;
+        }
+        if large {
+            self.output_width.encode(output)?;
+            self.output_height.encode(output)?;
+            for &(horizontal_offset, vertical_offset) in &self.offsets {
+                output.write_i32::<BigEndian>(horizontal_offset)?;
+                output.write_i32::<BigEndian>(vertical_offset)?;
+            }
+        } else {
+            (self.output_width as u16).encode(output)?;
+            (self.output_height as u16).encode(output)?;
+            for &(horizontal_offset, vertical_offset) in &self.offsets {
+                output.write_i16::<BigEndian>(horizontal_offset as i16)?;
+                output.write_i16::<BigEndian>(vertical_offset as i16)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Decode for ImageOverlay {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        let large = input.read_u8()? & 1 << 0 != 0;
+
+        let mut canvas_fill_value = [0; 4];
+        for canvas_fill_value in &mut canvas_fill_value {
+            *canvas_fill_value = Decode::decode(input)?;
+        }
+        let output_width;
+        let output_height;
+        let mut offsets = Vec::new();
+        if large {
+            output_width = Decode::decode(input)?;
+            output_height = Decode::decode(input)?;
+            while !input.is_empty() {
+                offsets.push((
+                    input.read_i32::<BigEndian>()?,
+                    input.read_i32::<BigEndian>()?,
+                ));
+            }
+        } else {
+            output_width = u16::decode(input)? as u32;
+            output_height = u16::decode(input)? as u32;
+            while !input.is_empty() {
+                offsets.push((
+                    input.read_i16::<BigEndian>()? as i32,
+                    input.read_i16::<BigEndian>()? as i32,
+                ));
+            }
+        }
+        Ok(Self {
+            canvas_fill_value,
+            output_width,
+            output_height,
+            offsets,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 6.6.2.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct ImageGrid {
+    pub rows: u16,
+    pub columns: u16,
+    pub output_width: u32,
+    pub output_height: u32,
+}
+
+impl ImageGrid {
+    pub fn tile_count(&self) -> usize {
+        self.rows as usize * self.columns as usize
+    }
+}
+
+impl Encode for ImageGrid {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        if !(1..=256).contains(&self.rows) {
+            return Err(Error::ValueOutOfRange { field: "rows" });
+        }
+        if !(1..=256).contains(&self.columns) {
+            return Err(Error::ValueOutOfRange { field: "columns" });
+        }
+        let large = self.output_width > u16::MAX as u32 || self.output_height > u16::MAX as u32;
+
+        output.write_u8(0)?; // version
+        output.write_u8(if large { 1 << 0 } else { 0 })?; // flags
+        output.write_u8((self.rows - 1) as u8)?;
+        output.write_u8((self.columns - 1) as u8)?;
+        if large {
+            self.output_width.encode(output)?;
+            self.output_height.encode(output)?;
+        } else {
+            (self.output_width as u16).encode(output)?;
+            (self.output_height as u16).encode(output)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode for ImageGrid {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        let large = input.read_u8()? & 1 << 0 != 0;
+
+        let rows = input.read_u8()? as u16 + 1;
+        let columns = input.read_u8()? as u16 + 1;
+        let (output_width, output_height) = if large {
+            (Decode::decode(input)?, Decode::decode(input)?)
+        } else {
+            (u16::decode(input)? as u32, u16::decode(input)? as u32)
+        };
+        Ok(Self {
+            rows,
+            columns,
+            output_width,
+            output_height,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 7.2.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Constraints of the samples of an image sequence track, e.g. whether every
+/// sample can be decoded on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodingConstraintsBox {
+    pub all_ref_pics_intra: bool,
+    pub intra_pred_used: bool,
+    pub max_ref_per_pic: u8,
+}
+
+impl Encode for CodingConstraintsBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"ccst")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        let mut writer = BitWriter::new(&mut *output);
+        writer.write_bits(self.all_ref_pics_intra as u64, 1)?;
+        writer.write_bits(self.intra_pred_used as u64, 1)?;
+        writer.write_bits(self.max_ref_per_pic as u64 & 0xF, 4)?;
+        writer.write_bits(0, 26)?; // reserved
+        writer.into_inner()?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for CodingConstraintsBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let mut reader = BitReader::new(input);
+        let all_ref_pics_intra = reader.read_bits(1)? != 0;
+        let intra_pred_used = reader.read_bits(1)? != 0;
+        let max_ref_per_pic = reader.read_bits(4)? as u8;
+        reader.read_bits(26)?; // reserved
+        *input = reader.into_inner();
+        Ok(Self {
+            all_ref_pics_intra,
+            intra_pred_used,
+            max_ref_per_pic,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 9.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default)]
+pub struct ItemPropertiesBox {
+    pub properties: Vec<ItemProperty>,
+    pub associations: Vec<ItemPropertyAssociation>,
+}
+
+#[derive(Debug)]
+pub enum ItemProperty {
+    ImageSpatialExtents(ImageSpatialExtentsProperty),
+    PixelInformation(PixelInformationProperty),
+    AuxiliaryType(AuxiliaryTypeProperty),
+    ImageRotation(ImageRotation),
+    ImageMirror(ImageMirror),
+    Other(RawBox),
+}
+
+#[derive(Debug, Clone)]
+pub struct ItemPropertyAssociation {
+    pub item_id: u32,
+    pub properties: Vec<PropertyAssociation>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PropertyAssociation {
+    pub essential: bool,
+    pub property_index: u16,
+}
+
+impl ItemPropertiesBox {
+    const MAX_PROPERTY_INDEX: u16 = 0x7FFF;
+
+    /// Adds a property and returns its 1-based index for [Self::associate],
+    /// which has 15 bits in the ipma.
+    pub fn add(&mut self, property: ItemProperty) -> Result<u16> {
+        let property_index = self.properties.len() + 1;
+        if property_index > Self::MAX_PROPERTY_INDEX as usize {
+            return Err(Error::ValueOutOfRange {
+                field: "property_index",
+            });
+        }
+        self.properties.push(property);
+        Ok(property_index as u16)
+    }
+
+    pub fn associate(&mut self, item_id: u32, property_index: u16, essential: bool) {
+        let association = PropertyAssociation {
+            essential,
+            property_index,
+        };
+        match self
+            .associations
+            .iter_mut()
+            .find(|association| association.item_id == item_id)
+        {
+            Some(entry) => entry.properties.push(association),
+            None => self.associations.push(ItemPropertyAssociation {
+                item_id,
+                properties: vec![association],
+            }),
+        }
+    }
+
+    pub fn properties_of(&self, item_id: u32) -> impl Iterator<Item = &ItemProperty> {
+        self.associations
+            .iter()
+            .filter(move |association| association.item_id == item_id)
+            .flat_map(|association| &association.properties)
+            .filter_map(|association| {
+                self.properties
+                    .get((association.property_index as usize).checked_sub(1)?)
+            })
+    }
+}
+
+impl Encode for ItemProperty {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        match self {
+            ItemProperty::ImageSpatialExtents(property) => property.encode(output),
+            ItemProperty::PixelInformation(property) => property.encode(output),
+            ItemProperty::AuxiliaryType(property) => property.encode(output),
+            ItemProperty::ImageRotation(property) => property.encode(output),
+            ItemProperty::ImageMirror(property) => property.encode(output),
+            ItemProperty::Other(property) => property.encode(output),
+        }
+    }
+}
+
+impl Encode for ItemPropertiesBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"iprp")?;
+
+        let properties_begin = encode_box_header(output, *b"ipco")?;
+        for property in &self.properties {
+            property.encode(output)?;
+        }
+        update_box_header(output, properties_begin)?;
+
+        let version = if self
+            .associations
+            .iter()
+            .any(|association| association.item_id > u16::MAX as u32)
+        {
+            1
+        } else {
+            0
+        };
+        let large = self.associations.iter().any(|association| {
+            association
+                .properties
+                .iter()
+                .any(|property| property.property_index > 0x7F)
+        });
+        let associations_begin = encode_box_header(output, *b"ipma")?;
+        output.write_u8(version)?; // version
+        output.write_u24::<BigEndian>(if large { 1 << 0 } else { 0 })?; // flags
+        checked_cast::<u32>(self.associations.len(), "entry_count")?.encode(output)?;
+        for association in &self.associations {
+            if version == 0 {
+                (association.item_id as u16).encode(output)?;
+            } else {
+                association.item_id.encode(output)?;
+            }
+            output.write_u8(checked_cast(
+                association.properties.len(),
+                "association_count",
+            )?)?;
+            for property in &association.properties {
+                if property.property_index > Self::MAX_PROPERTY_INDEX {
+                    return Err(Error::ValueOutOfRange {
+                        field: "property_index",
+                    });
+                }
+                let mut writer = BitWriter::new(&mut *output);
+                writer.write_bit(property.essential)?;
+                if large {
+                    writer.write_bits(property.property_index as u64, 15)?;
+                } else {
+                    writer.write_bits(property.property_index as u64, 7)?;
+                }
+                writer.into_inner()?;
+            }
+        }
+        update_box_header(output, associations_begin)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ItemPropertiesBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut properties = None;
+        let mut associations = Vec::new();
+        while !input.is_empty() {
+            let (r#type, mut data) = decode_box_header(input)?;
+            match &r#type {
+                b"ipco" => properties = Some(decode_properties(&mut data)?),
+                b"ipma" => associations.extend(decode_associations(&mut data)?),
+                _ => {}
+            }
+        }
+        Ok(Self {
+            properties: properties.ok_or(Error::InvalidBoxQuantity {
+                r#type: "ipco",
+                quantity: 0,
+                expected: 1,
+            })?,
+            associations,
+        })
+    }
+}
+
+fn decode_properties(input: &mut &[u8]) -> Result<Vec<ItemProperty>> {
+    let mut properties = Vec::new();
+    while !input.is_empty() {
+        let (r#type, mut data) = decode_box_header(input)?;
+        properties.push(match &r#type {
+            b"ispe" => ItemProperty::ImageSpatialExtents(Decode::decode(&mut data)?),
+            b"pixi" => ItemProperty::PixelInformation(Decode::decode(&mut data)?),
+            b"auxC" => ItemProperty::AuxiliaryType(Decode::decode(&mut data)?),
+            b"irot" => ItemProperty::ImageRotation(Decode::decode(&mut data)?),
+            b"imir" => ItemProperty::ImageMirror(Decode::decode(&mut data)?),
+            _ => ItemProperty::Other(RawBox {
+                r#type: FourCC(u32::from_be_bytes(r#type)),
+                data: data.to_owned(),
+            }),
+        });
+    }
+    Ok(properties)
+}
+
+fn decode_associations(input: &mut &[u8]) -> Result<Vec<ItemPropertyAssociation>> {
+    let version = input.read_u8()?;
+    let large = input.read_u24::<BigEndian>()? & 1 << 0 != 0;
+
+    let entry_count = u32::decode(input)?;
+    let mut associations = Vec::new();
+    for _ in 0..entry_count {
+        let item_id = match version {
+            0 => u16::decode(input)? as u32,
+            1 => u32::decode(input)?,
+            _ => return Err(Error::ValueOutOfRange { field: "version" }),
+        };
+        let association_count = input.read_u8()?;
+        let mut properties = Vec::new();
+        let mut reader = BitReader::new(input);
+        for _ in 0..association_count {
+            properties.push(PropertyAssociation {
+                essential: reader.read_bit()?,
+                property_index: reader.read_bits(if large { 15 } else { 7 })? as u16,
+            });
+        }
+        *input = reader.into_inner();
+        associations.push(ItemPropertyAssociation {
+            item_id,
+            properties,
+        });
+    }
+    Ok(associations)
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23008-12:2017 A.2.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct ExifData {
+    pub exif_tiff_header_offset: u32,
+    #[derivative(Debug = "ignore")]
+    pub payload: Vec<u8>,
+}
+
+impl ExifData {
+    /// Wraps an Exif payload, like the contents of a JPEG APP1 segment,
+    /// locating the TIFF header.
+    pub fn new(payload: Vec<u8>) -> Self {
+        let exif_tiff_header_offset = payload
+            .windows(4)
+            .position(|magic| magic == b"II*\0" || magic == b"MM\0*")
+            .unwrap_or(0) as u32;
+        Self {
+            exif_tiff_header_offset,
+            payload,
+        }
+    }
+
+    pub fn tiff(&self) -> Option<&[u8]> {
+        self.payload.get(self.exif_tiff_header_offset as usize..)
+    }
+}
+
+impl Encode for ExifData {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        self.exif_tiff_header_offset.encode(output)?;
+        output.write_all(&self.payload)?;
+        Ok(())
+    }
+}
+
+impl Decode for ExifData {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let exif_tiff_header_offset = Decode::decode(input)?;
+        let payload = input.to_owned();
+        *input = &input[input.len()..];
+        Ok(Self {
+            exif_tiff_header_offset,
+            payload,
+        })
+    }
+}
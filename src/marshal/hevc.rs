@@ -0,0 +1,284 @@
+use std::io::{Seek, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use derivative::Derivative;
+
+use crate::marshal::{
+    avc::{decode_parameter_sets, encode_parameter_sets},
+    bits::{BitReader, BitWriter},
+    checked_cast, decode_boxes, encode_box_header, update_box_header, Decode, Encode, Error,
+    FourCC, Result, VisualSampleEntry,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-15:2019 8.4.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct HEVCSampleEntry {
+    /// hvc1 if all parameter sets are in the configuration, hev1 if they may
+    /// also be in the samples
+    pub codec: FourCC,
+    pub base: VisualSampleEntry,
+    pub config: HEVCConfigurationBox,
+}
+
+impl HEVCSampleEntry {
+    pub const HVC1: FourCC = FourCC(u32::from_be_bytes(*b"hvc1"));
+    pub const HEV1: FourCC = FourCC(u32::from_be_bytes(*b"hev1"));
+
+    pub fn has_in_band_parameter_sets(&self) -> bool {
+        self.codec == Self::HEV1
+    }
+
+    /// Returns the codecs parameter of ISO/IEC 14496-15 Annex E, like
+    /// hvc1.1.6.L93.B0.
+    pub fn codec_string(&self) -> String {
+        let config = &self.config;
+        let mut codec_string = format!(
+            "{:?}.{}{}.{:X}.{}{}",
+            self.codec,
+            ["", "A", "B", "C"][config.general_profile_space as usize & 0x3],
+            config.general_profile_idc,
+            config.general_profile_compatibility_flags.reverse_bits(),
+            if config.general_tier_flag { 'H' } else { 'L' },
+            config.general_level_idc
+        );
+        // the 6 bytes of constraint flags, without trailing zero bytes
+        let constraint_flags = &config.general_constraint_indicator_flags.to_be_bytes()[2..];
+        let length = constraint_flags
+            .iter()
+            .rposition(|&flags| flags != 0)
+            .map_or(0, |index| index + 1);
+        for flags in &constraint_flags[..length] {
+            codec_string.push_str(&format!(".{flags:X}"));
+        }
+        codec_string
+    }
+}
+
+impl Encode for HEVCSampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, self.codec.to_bytes())?;
+
+        self.base.encode(output)?;
+        self.config.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl HEVCSampleEntry {
+    pub(crate) fn decode(input: &mut &[u8], codec: FourCC) -> Result<Self> {
+        let mut base = VisualSampleEntry::decode(input)?;
+
+        let mut config = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            required hvcC config,
+        }
+        base.set_extra_boxes(extra_boxes);
+
+        Ok(Self {
+            codec,
+            base,
+            config,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-15:2019 8.3.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct HEVCConfigurationBox {
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    /// 48 bits
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+    pub min_spatial_segmentation_idc: u16,
+    pub parallelism_type: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub avg_frame_rate: u16,
+    pub constant_frame_rate: u8,
+    pub num_temporal_layers: u8,
+    pub temporal_id_nested: bool,
+    pub length_size_minus_one: u8,
+    pub arrays: Vec<HEVCNalUnitArray>,
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct HEVCNalUnitArray {
+    /// all NAL units of this type are in the array, and none in the samples
+    pub array_completeness: bool,
+    pub nal_unit_type: u8,
+    #[derivative(Debug = "ignore")]
+    pub nal_units: Vec<Vec<u8>>,
+}
+
+impl HEVCConfigurationBox {
+    /// ISO/IEC 23008-2 7.4.2.2
+    pub const VPS_NUT: u8 = 32;
+    pub const SPS_NUT: u8 = 33;
+    pub const PPS_NUT: u8 = 34;
+
+    /// Returns the NAL units of the given type.
+    pub fn nal_units(&self, nal_unit_type: u8) -> impl Iterator<Item = &Vec<u8>> {
+        self.arrays
+            .iter()
+            .filter(move |array| array.nal_unit_type == nal_unit_type)
+            .flat_map(|array| &array.nal_units)
+    }
+}
+
+impl Encode for HEVCConfigurationBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"hvcC")?;
+
+        if self.general_profile_space > 0x3 {
+            return Err(Error::ValueOutOfRange {
+                field: "general_profile_space",
+            });
+        }
+        if self.general_profile_idc > 0x1F {
+            return Err(Error::ValueOutOfRange {
+                field: "general_profile_idc",
+            });
+        }
+        if self.general_constraint_indicator_flags >> 48 != 0 {
+            return Err(Error::ValueOutOfRange {
+                field: "general_constraint_indicator_flags",
+            });
+        }
+        if self.min_spatial_segmentation_idc > 0xFFF {
+            return Err(Error::ValueOutOfRange {
+                field: "min_spatial_segmentation_idc",
+            });
+        }
+        if self.parallelism_type > 0x3
+            || self.chroma_format_idc > 0x3
+            || self.bit_depth_luma_minus8 > 0x7
+            || self.bit_depth_chroma_minus8 > 0x7
+            || self.constant_frame_rate > 0x3
+            || self.num_temporal_layers > 0x7
+            || self.length_size_minus_one > 0x3
+        {
+            return Err(Error::ValueOutOfRange { field: "hvcC" });
+        }
+        output.write_u8(1)?; // configurationVersion
+        let mut writer = BitWriter::new(&mut *output);
+        writer.write_bits(self.general_profile_space as u64, 2)?;
+        writer.write_bit(self.general_tier_flag)?;
+        writer.write_bits(self.general_profile_idc as u64, 5)?;
+        writer.write_bits(self.general_profile_compatibility_flags as u64, 32)?;
+        writer.write_bits(self.general_constraint_indicator_flags, 48)?;
+        writer.write_bits(self.general_level_idc as u64, 8)?;
+        writer.write_bits(0xF, 4)?; // reserved
+        writer.write_bits(self.min_spatial_segmentation_idc as u64, 12)?;
+        writer.write_bits(0x3F, 6)?; // reserved
+        writer.write_bits(self.parallelism_type as u64, 2)?;
+        writer.write_bits(0x3F, 6)?; // reserved
+        writer.write_bits(self.chroma_format_idc as u64, 2)?;
+        writer.write_bits(0x1F, 5)?; // reserved
+        writer.write_bits(self.bit_depth_luma_minus8 as u64, 3)?;
+        writer.write_bits(0x1F, 5)?; // reserved
+        writer.write_bits(self.bit_depth_chroma_minus8 as u64, 3)?;
+        writer.write_bits(self.avg_frame_rate as u64, 16)?;
+        writer.write_bits(self.constant_frame_rate as u64, 2)?;
+        writer.write_bits(self.num_temporal_layers as u64, 3)?;
+        writer.write_bit(self.temporal_id_nested)?;
+        writer.write_bits(self.length_size_minus_one as u64, 2)?;
+        writer.into_inner()?;
+        output.write_u8(checked_cast(self.arrays.len(), "arrays")?)?;
+        for array in &self.arrays {
+            if array.nal_unit_type > 0x3F {
+                return Err(Error::ValueOutOfRange {
+                    field: "nal_unit_type",
+                });
+            }
+            output.write_u8(
+                if array.array_completeness { 1 << 7 } else { 0 } | array.nal_unit_type,
+            )?;
+            checked_cast::<u16>(array.nal_units.len(), "nal_units")?.encode(output)?;
+            encode_parameter_sets(output, &array.nal_units)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for HEVCConfigurationBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 1 {
+            return Err(Error::ValueOutOfRange {
+                field: "configurationVersion",
+            });
+        }
+        let mut reader = BitReader::new(input);
+        let general_profile_space = reader.read_bits(2)? as u8;
+        let general_tier_flag = reader.read_bit()?;
+        let general_profile_idc = reader.read_bits(5)? as u8;
+        let general_profile_compatibility_flags = reader.read_bits(32)? as u32;
+        let general_constraint_indicator_flags = reader.read_bits(48)?;
+        let general_level_idc = reader.read_bits(8)? as u8;
+        reader.read_bits(4)?; // reserved
+        let min_spatial_segmentation_idc = reader.read_bits(12)? as u16;
+        reader.read_bits(6)?; // reserved
+        let parallelism_type = reader.read_bits(2)? as u8;
+        reader.read_bits(6)?; // reserved
+        let chroma_format_idc = reader.read_bits(2)? as u8;
+        reader.read_bits(5)?; // reserved
+        let bit_depth_luma_minus8 = reader.read_bits(3)? as u8;
+        reader.read_bits(5)?; // reserved
+        let bit_depth_chroma_minus8 = reader.read_bits(3)? as u8;
+        let avg_frame_rate = reader.read_bits(16)? as u16;
+        let constant_frame_rate = reader.read_bits(2)? as u8;
+        let num_temporal_layers = reader.read_bits(3)? as u8;
+        let temporal_id_nested = reader.read_bit()?;
+        let length_size_minus_one = reader.read_bits(2)? as u8;
+        *input = reader.into_inner();
+        let num_of_arrays = input.read_u8()?;
+        let mut arrays = Vec::new();
+        for _ in 0..num_of_arrays {
+            let header = input.read_u8()?;
+            let num_nalus = u16::decode(input)?;
+            arrays.push(HEVCNalUnitArray {
+                array_completeness: header & 1 << 7 != 0,
+                nal_unit_type: header & 0x3F,
+                nal_units: decode_parameter_sets(input, num_nalus)?,
+            });
+        }
+        *input = &input[input.len()..];
+
+        Ok(Self {
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags,
+            general_level_idc,
+            min_spatial_segmentation_idc,
+            parallelism_type,
+            chroma_format_idc,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            avg_frame_rate,
+            constant_frame_rate,
+            num_temporal_layers,
+            temporal_id_nested,
+            length_size_minus_one,
+            arrays,
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::{Seek, Write};
+
+use crate::{
+    marshal::{
+        encode_box_header, update_box_header, Boxes, Decode, Encode, FourCC, RawBox, Result,
+        VisualSampleEntry,
+    },
+    registry::VISUAL_SAMPLE_ENTRY_BOXES,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// QuickTime File Format, Video Sample Description
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Intermediate codecs like ProRes and DNxHD, whose boxes are kept as is.
+#[derive(Debug)]
+pub struct IntermediateVideoSampleEntry {
+    pub codec: FourCC,
+    pub base: VisualSampleEntry,
+    pub children: Vec<RawBox>,
+}
+
+impl IntermediateVideoSampleEntry {
+    pub const PRORES_422_PROXY: FourCC = FourCC(u32::from_be_bytes(*b"apco"));
+    pub const PRORES_422_LT: FourCC = FourCC(u32::from_be_bytes(*b"apcs"));
+    pub const PRORES_422: FourCC = FourCC(u32::from_be_bytes(*b"apcn"));
+    pub const PRORES_422_HQ: FourCC = FourCC(u32::from_be_bytes(*b"apch"));
+    pub const PRORES_4444: FourCC = FourCC(u32::from_be_bytes(*b"ap4h"));
+    pub const PRORES_4444_XQ: FourCC = FourCC(u32::from_be_bytes(*b"ap4x"));
+    pub const DNXHD: FourCC = FourCC(u32::from_be_bytes(*b"AVdn"));
+    pub const DNXHR: FourCC = FourCC(u32::from_be_bytes(*b"AVdh"));
+
+    pub fn is_prores(&self) -> bool {
+        self.codec.to_bytes().starts_with(b"ap")
+    }
+
+    pub fn is_dnxhd(&self) -> bool {
+        self.codec == Self::DNXHD || self.codec == Self::DNXHR
+    }
+}
+
+impl Encode for IntermediateVideoSampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, self.codec.to_bytes())?;
+
+        self.base.encode(output)?;
+        for child in &self.children {
+            child.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl IntermediateVideoSampleEntry {
+    pub(crate) fn decode(input: &mut &[u8], codec: FourCC) -> Result<Self> {
+        let base = VisualSampleEntry::decode(input)?;
+
+        // colr, fiel, gama, ccst and btrt are already part of the base
+        let mut children = Vec::new();
+        for child in Boxes::new(input) {
+            let child = child?;
+            if !VISUAL_SAMPLE_ENTRY_BOXES.contains(&child.r#type) {
+                children.push(child);
+            }
+        }
+        *input = &input[input.len()..];
+
+        Ok(Self {
+            codec,
+            base,
+            children,
+        })
+    }
+}
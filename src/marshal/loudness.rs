@@ -0,0 +1,356 @@
+use std::io::{Seek, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::marshal::{
+    bits::{BitReader, BitWriter},
+    checked_cast, decode_boxes, encode_box_header, update_box_header, Decode, Encode, Error,
+    RawBox, Result,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2015 8.18.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Loudness of the track, and of the album it is part of, in the udta of an
+/// audio track.
+#[derive(Debug, Default)]
+pub struct LoudnessBox {
+    pub track_loudness: Vec<TrackLoudnessInfoBox>,
+    pub album_loudness: Vec<AlbumLoudnessInfoBox>,
+    pub extra_boxes: Vec<RawBox>,
+}
+
+impl Encode for LoudnessBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"ludt")?;
+
+        for track_loudness in &self.track_loudness {
+            track_loudness.encode(output)?;
+        }
+        for album_loudness in &self.album_loudness {
+            album_loudness.encode(output)?;
+        }
+
+        for extra_box in &self.extra_boxes {
+            extra_box.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for LoudnessBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut track_loudness = Vec::new();
+        let mut album_loudness = Vec::new();
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            multiple tlou track_loudness,
+            multiple alou album_loudness,
+        }
+
+        Ok(Self {
+            track_loudness,
+            album_loudness,
+            extra_boxes,
+        })
+    }
+}
+
+/// Loudness of the track, one base per EQ, downmix and DRC set.
+#[derive(Debug, Clone, Default)]
+pub struct TrackLoudnessInfoBox(pub Vec<LoudnessBase>);
+
+impl Encode for TrackLoudnessInfoBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        encode_loudness_bases(output, *b"tlou", &self.0)
+    }
+}
+
+impl Decode for TrackLoudnessInfoBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        decode_loudness_bases(input).map(Self)
+    }
+}
+
+/// Loudness of the album, one base per EQ, downmix and DRC set.
+#[derive(Debug, Clone, Default)]
+pub struct AlbumLoudnessInfoBox(pub Vec<LoudnessBase>);
+
+impl Encode for AlbumLoudnessInfoBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        encode_loudness_bases(output, *b"alou", &self.0)
+    }
+}
+
+impl Decode for AlbumLoudnessInfoBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        decode_loudness_bases(input).map(Self)
+    }
+}
+
+/// Loudness info of ISO/IEC 23003-4, with the peak levels as coded there.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoudnessBase {
+    /// only in version 1
+    pub eq_set_id: u8,
+    pub downmix_id: u8,
+    pub drc_set_id: u8,
+    /// 12 bits, 0 if unknown, see [Self::sample_peak_level]
+    pub bs_sample_peak_level: u16,
+    /// 12 bits, 0 if unknown, see [Self::true_peak_level]
+    pub bs_true_peak_level: u16,
+    pub measurement_system_for_tp: u8,
+    pub reliability_for_tp: u8,
+    pub measurements: Vec<LoudnessMeasurement>,
+}
+
+impl LoudnessBase {
+    /// In dBFS.
+    pub fn sample_peak_level(&self) -> Option<f64> {
+        decode_peak_level(self.bs_sample_peak_level)
+    }
+
+    pub fn set_sample_peak_level(&mut self, level: Option<f64>) -> Result<()> {
+        self.bs_sample_peak_level = encode_peak_level(level, "bs_sample_peak_level")?;
+        Ok(())
+    }
+
+    /// In dBTP.
+    pub fn true_peak_level(&self) -> Option<f64> {
+        decode_peak_level(self.bs_true_peak_level)
+    }
+
+    pub fn set_true_peak_level(&mut self, level: Option<f64>) -> Result<()> {
+        self.bs_true_peak_level = encode_peak_level(level, "bs_true_peak_level")?;
+        Ok(())
+    }
+
+    /// Integrated loudness of the whole program, in LUFS.
+    pub fn program_loudness(&self) -> Option<f64> {
+        self.measurement(LoudnessMeasurement::PROGRAM_LOUDNESS)
+    }
+
+    /// In LU.
+    pub fn loudness_range(&self) -> Option<f64> {
+        self.measurement(LoudnessMeasurement::LOUDNESS_RANGE)
+    }
+
+    fn measurement(&self, method_definition: u8) -> Option<f64> {
+        self.measurements
+            .iter()
+            .find(|measurement| measurement.method_definition == method_definition)
+            .and_then(LoudnessMeasurement::value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoudnessMeasurement {
+    pub method_definition: u8,
+    pub method_value: u8,
+    pub measurement_system: u8,
+    pub reliability: u8,
+}
+
+impl LoudnessMeasurement {
+    pub const PROGRAM_LOUDNESS: u8 = 1;
+    pub const ANCHOR_LOUDNESS: u8 = 2;
+    pub const MAXIMUM_OF_THE_RANGE: u8 = 3;
+    pub const MAXIMUM_MOMENTARY_LOUDNESS: u8 = 4;
+    pub const MAXIMUM_SHORT_TERM_LOUDNESS: u8 = 5;
+    pub const LOUDNESS_RANGE: u8 = 6;
+
+    /// EBU R 128, as required for broadcast in Europe
+    pub const EBU_R128: u8 = 1;
+    /// ITU-R BS.1770, as referenced by ATSC A/85
+    pub const ITU_R_BS1770: u8 = 2;
+
+    /// Measured and accurate.
+    pub const RELIABILITY_MEASURED: u8 = 3;
+
+    /// Codes a measured loudness in LUFS, or a loudness range in LU.
+    pub fn new(method_definition: u8, value: f64, measurement_system: u8) -> Result<Self> {
+        let method_value = match method_definition {
+            Self::PROGRAM_LOUDNESS..=Self::MAXIMUM_SHORT_TERM_LOUDNESS => (value + 57.75) * 4.0,
+            Self::LOUDNESS_RANGE if value <= 32.0 => value * 4.0,
+            Self::LOUDNESS_RANGE if value <= 70.0 => (value - 32.0) * 2.0 + 128.0,
+            Self::LOUDNESS_RANGE => value - 70.0 + 204.0,
+            _ => {
+                return Err(Error::ValueOutOfRange {
+                    field: "method_definition",
+                })
+            }
+        }
+        .round();
+        if !(0.0..=255.0).contains(&method_value) {
+            return Err(Error::ValueOutOfRange {
+                field: "method_value",
+            });
+        }
+        if measurement_system > 0xF {
+            return Err(Error::ValueOutOfRange {
+                field: "measurement_system",
+            });
+        }
+        Ok(Self {
+            method_definition,
+            method_value: method_value as u8,
+            measurement_system,
+            reliability: Self::RELIABILITY_MEASURED,
+        })
+    }
+
+    /// Loudness in LUFS, or the loudness range in LU, if the method is one
+    /// of them.
+    pub fn value(&self) -> Option<f64> {
+        let value = self.method_value as f64;
+        match self.method_definition {
+            Self::PROGRAM_LOUDNESS..=Self::MAXIMUM_SHORT_TERM_LOUDNESS => Some(value / 4.0 - 57.75),
+            Self::LOUDNESS_RANGE => Some(match self.method_value {
+                0..=128 => value / 4.0,
+                129..=204 => (value - 128.0) / 2.0 + 32.0,
+                _ => value - 204.0 + 70.0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn decode_peak_level(bs_peak_level: u16) -> Option<f64> {
+    (bs_peak_level != 0).then(|| 20.0 - bs_peak_level as f64 / 32.0)
+}
+
+fn encode_peak_level(level: Option<f64>, field: &'static str) -> Result<u16> {
+    let Some(level) = level else {
+        return Ok(0);
+    };
+    let bs_peak_level = ((20.0 - level) * 32.0).round();
+    if !(1.0..=4095.0).contains(&bs_peak_level) {
+        return Err(Error::ValueOutOfRange { field });
+    }
+    Ok(bs_peak_level as u16)
+}
+
+fn encode_loudness_bases(
+    output: &mut (impl Write + Seek),
+    r#type: [u8; 4],
+    bases: &[LoudnessBase],
+) -> Result<()> {
+    let begin = encode_box_header(output, r#type)?;
+    let version = match bases {
+        [base] if base.eq_set_id == 0 => 0,
+        _ => 1,
+    };
+    output.write_u8(version)?; // version
+    output.write_u24::<BigEndian>(0)?; // flags
+
+    if version >= 1 {
+        if bases.len() > 0x3F {
+            return Err(Error::ValueOutOfRange {
+                field: "loudness_base_count",
+            });
+        }
+        output.write_u8(bases.len() as u8)?; // reserved bits are 0
+    }
+    for base in bases {
+        if base.eq_set_id > 0x3F
+            || base.downmix_id > 0x7F
+            || base.drc_set_id > 0x3F
+            || base.bs_sample_peak_level > 0xFFF
+            || base.bs_true_peak_level > 0xFFF
+            || base.measurement_system_for_tp > 0xF
+            || base.reliability_for_tp > 0xF
+        {
+            return Err(Error::ValueOutOfRange {
+                field: "loudness_base",
+            });
+        }
+        if version >= 1 {
+            output.write_u8(base.eq_set_id)?; // reserved bits are 0
+        }
+        let mut writer = BitWriter::new(&mut *output);
+        writer.write_bits(0, 3)?; // reserved
+        writer.write_bits(base.downmix_id as u64, 7)?;
+        writer.write_bits(base.drc_set_id as u64, 6)?;
+        writer.write_bits(base.bs_sample_peak_level as u64, 12)?;
+        writer.write_bits(base.bs_true_peak_level as u64, 12)?;
+        writer.write_bits(base.measurement_system_for_tp as u64, 4)?;
+        writer.write_bits(base.reliability_for_tp as u64, 4)?;
+        writer.into_inner()?;
+
+        output.write_u8(checked_cast(base.measurements.len(), "measurement_count")?)?;
+        for measurement in &base.measurements {
+            if measurement.measurement_system > 0xF || measurement.reliability > 0xF {
+                return Err(Error::ValueOutOfRange {
+                    field: "measurement_system",
+                });
+            }
+            output.write_u8(measurement.method_definition)?;
+            output.write_u8(measurement.method_value)?;
+            output.write_u8(measurement.measurement_system << 4 | measurement.reliability)?;
+        }
+    }
+
+    update_box_header(output, begin)
+}
+
+fn decode_loudness_bases(input: &mut &[u8]) -> Result<Vec<LoudnessBase>> {
+    let version = input.read_u8()?;
+    input.read_u24::<BigEndian>()?; // flags
+    if version > 1 {
+        return Err(Error::ValueOutOfRange { field: "version" });
+    }
+
+    let loudness_base_count = if version >= 1 {
+        input.read_u8()? & 0x3F
+    } else {
+        1
+    };
+    let mut bases = Vec::new();
+    for _ in 0..loudness_base_count {
+        let eq_set_id = if version >= 1 {
+            input.read_u8()? & 0x3F
+        } else {
+            0
+        };
+        let mut reader = BitReader::new(input);
+        reader.read_bits(3)?; // reserved
+        let downmix_id = reader.read_bits(7)? as u8;
+        let drc_set_id = reader.read_bits(6)? as u8;
+        let bs_sample_peak_level = reader.read_bits(12)? as u16;
+        let bs_true_peak_level = reader.read_bits(12)? as u16;
+        let measurement_system_for_tp = reader.read_bits(4)? as u8;
+        let reliability_for_tp = reader.read_bits(4)? as u8;
+        *input = reader.into_inner();
+
+        let measurement_count = input.read_u8()?;
+        let mut measurements = Vec::new();
+        for _ in 0..measurement_count {
+            let method_definition = input.read_u8()?;
+            let method_value = input.read_u8()?;
+            let measurement = input.read_u8()?;
+            measurements.push(LoudnessMeasurement {
+                method_definition,
+                method_value,
+                measurement_system: measurement >> 4,
+                reliability: measurement & 0xF,
+            });
+        }
+        bases.push(LoudnessBase {
+            eq_set_id,
+            downmix_id,
+            drc_set_id,
+            bs_sample_peak_level,
+            bs_true_peak_level,
+            measurement_system_for_tp,
+            reliability_for_tp,
+            measurements,
+        });
+    }
+    Ok(bases)
+}
@@ -0,0 +1,49 @@
+use std::io::{Seek, Write};
+
+use crate::marshal::{
+    decode_boxes, encode_box_header, esds::ESDescriptorBox, update_box_header, AudioSampleEntry,
+    Decode, Encode, FourCC, Result,
+};
+
+#[derive(Debug)]
+pub struct MP3SampleEntry {
+    /// mp4a with an esds, or .mp3 of QuickTime, which usually has none
+    pub codec: FourCC,
+    pub base: AudioSampleEntry,
+    pub es: Option<ESDescriptorBox>,
+}
+
+impl MP3SampleEntry {
+    pub const MP4A: FourCC = FourCC(u32::from_be_bytes(*b"mp4a"));
+    pub const MP3: FourCC = FourCC(u32::from_be_bytes(*b".mp3"));
+}
+
+impl Encode for MP3SampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, self.codec.to_bytes())?;
+
+        self.base.encode(output)?;
+        self.es.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl MP3SampleEntry {
+    pub(crate) fn decode(input: &mut &[u8], codec: FourCC) -> Result<Self> {
+        let mut base = AudioSampleEntry::decode(input)?;
+
+        let mut es = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            optional esds es,
+        }
+        base.set_extra_boxes(extra_boxes);
+
+        Ok(Self { codec, base, es })
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{Read, Seek, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use derivative::Derivative;
+
+use crate::marshal::{
+    decode_boxes, encode_box_header, update_box_header, AudioSampleEntry, Decode, Encode, Error,
+    Result,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23003-5:2020 5.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct PCMSampleEntry {
+    pub format: PCMFormat,
+    pub base: AudioSampleEntry,
+    pub config: PCMConfigurationBox,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PCMFormat {
+    Integer,
+    FloatingPoint,
+}
+
+impl Encode for PCMSampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(
+            output,
+            match self.format {
+                PCMFormat::Integer => *b"ipcm",
+                PCMFormat::FloatingPoint => *b"fpcm",
+            },
+        )?;
+
+        self.base.encode(output)?;
+        self.config.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl PCMSampleEntry {
+    pub(crate) fn decode(input: &mut &[u8], format: PCMFormat) -> Result<Self> {
+        let mut base = AudioSampleEntry::decode(input)?;
+
+        let mut config = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            required pcmC config,
+        }
+        base.set_extra_boxes(extra_boxes);
+
+        Ok(Self {
+            format,
+            base,
+            config,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23003-5:2020 5.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct PCMConfigurationBox {
+    pub little_endian: bool,
+    pub sample_size: u8,
+}
+
+impl Encode for PCMConfigurationBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"pcmC")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        output.write_u8(if self.little_endian { 1 << 0 } else { 0 })?;
+        output.write_u8(self.sample_size)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for PCMConfigurationBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        if input.read_u8()? != 0 {
+            return Err(Error::ValueOutOfRange { field: "version" });
+        }
+        input.read_u24::<BigEndian>()?; // flags
+
+        let format_flags = input.read_u8()?;
+        let sample_size = input.read_u8()?;
+        Ok(Self {
+            little_endian: format_flags & 1 << 0 != 0,
+            sample_size,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// QuickTime File Format, Sound Sample Description (Version 2)
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct LPCMSampleEntry {
+    pub data_reference_index: u16,
+    #[derivative(Debug = "ignore")]
+    pub data: Vec<u8>,
+}
+
+impl Encode for LPCMSampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"lpcm")?;
+
+        output.write_all(&[0; 6])?; // reserved
+        self.data_reference_index.encode(output)?;
+        output.write_all(&self.data)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for LPCMSampleEntry {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_exact(&mut [0; 6])?; // reserved
+        let data_reference_index = Decode::decode(input)?;
+        let data = input.to_owned();
+        *input = &input[input.len()..];
+        Ok(Self {
+            data_reference_index,
+            data,
+        })
+    }
+}
@@ -0,0 +1,210 @@
+use std::io::{Read, Seek, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use derivative::Derivative;
+
+use crate::marshal::{
+    decode_box_header, define_box, encode_box_header, update_box_header, BitRateBox, Decode,
+    Encode, Error, Result,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 14496-12:2015 12.3.3
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Samples are XML documents, like sensor readings with a schema.
+#[derive(Debug)]
+pub struct XMLMetaDataSampleEntry {
+    pub data_reference_index: u16,
+    /// MIME type of the content encoding, like "application/zip", or empty
+    pub content_encoding: String,
+    /// space-separated list of XML namespaces of the samples
+    pub namespace: String,
+    /// space-separated list of URLs of the schemas, or empty
+    pub schema_location: String,
+    pub bit_rate: Option<BitRateBox>,
+}
+
+/// Samples are text, like JSON telemetry or GPS positions.
+#[derive(Debug)]
+pub struct TextMetaDataSampleEntry {
+    pub data_reference_index: u16,
+    /// MIME type of the content encoding, like "application/zip", or empty
+    pub content_encoding: String,
+    /// MIME type of the samples, like "application/json"
+    pub mime_format: String,
+    pub bit_rate: Option<BitRateBox>,
+}
+
+/// Samples are in a format identified by an URI, like KLV per SMPTE.
+#[derive(Debug)]
+pub struct URIMetaSampleEntry {
+    pub data_reference_index: u16,
+    pub uri: URIBox,
+    pub init: Option<URIInitBox>,
+    pub bit_rate: Option<BitRateBox>,
+}
+
+define_box! {
+    #[derive(Debug, Clone)]
+    pub struct URIBox: full_box b"uri " {
+        pub the_uri: String,
+    }
+}
+
+/// Initialization data of the format identified by the [URIBox].
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct URIInitBox {
+    #[derivative(Debug = "ignore")]
+    pub uri_initialization_data: Vec<u8>,
+}
+
+impl Encode for XMLMetaDataSampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"metx")?;
+
+        output.write_all(&[0; 6])?; // reserved
+        self.data_reference_index.encode(output)?;
+        self.content_encoding.encode(output)?;
+        self.namespace.encode(output)?;
+        self.schema_location.encode(output)?;
+        self.bit_rate.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for XMLMetaDataSampleEntry {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_exact(&mut [0; 6])?; // reserved
+        let data_reference_index = Decode::decode(input)?;
+        let content_encoding = Decode::decode(input)?;
+        let namespace = Decode::decode(input)?;
+        let schema_location = Decode::decode(input)?;
+        let bit_rate = decode_bit_rate(input)?;
+
+        Ok(Self {
+            data_reference_index,
+            content_encoding,
+            namespace,
+            schema_location,
+            bit_rate,
+        })
+    }
+}
+
+impl Encode for TextMetaDataSampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"mett")?;
+
+        output.write_all(&[0; 6])?; // reserved
+        self.data_reference_index.encode(output)?;
+        self.content_encoding.encode(output)?;
+        self.mime_format.encode(output)?;
+        self.bit_rate.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for TextMetaDataSampleEntry {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_exact(&mut [0; 6])?; // reserved
+        let data_reference_index = Decode::decode(input)?;
+        let content_encoding = Decode::decode(input)?;
+        let mime_format = Decode::decode(input)?;
+        let bit_rate = decode_bit_rate(input)?;
+
+        Ok(Self {
+            data_reference_index,
+            content_encoding,
+            mime_format,
+            bit_rate,
+        })
+    }
+}
+
+impl Encode for URIMetaSampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"urim")?;
+
+        output.write_all(&[0; 6])?; // reserved
+        self.data_reference_index.encode(output)?;
+        self.uri.encode(output)?;
+        self.init.encode(output)?;
+        self.bit_rate.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for URIMetaSampleEntry {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_exact(&mut [0; 6])?; // reserved
+        let data_reference_index = Decode::decode(input)?;
+
+        // the type of the URIBox is not a valid identifier for decode_boxes
+        let mut uri = None;
+        let mut init = None;
+        let mut bit_rate = None;
+        while !input.is_empty() {
+            let (r#type, mut data) = decode_box_header(input)?;
+            match &r#type {
+                b"uri " if uri.is_none() => uri = Some(Decode::decode(&mut data)?),
+                b"uriI" if init.is_none() => init = Some(Decode::decode(&mut data)?),
+                b"btrt" if bit_rate.is_none() => bit_rate = Some(Decode::decode(&mut data)?),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            data_reference_index,
+            uri: uri.ok_or(Error::InvalidBoxQuantity {
+                r#type: "uri ",
+                quantity: 0,
+                expected: 1,
+            })?,
+            init,
+            bit_rate,
+        })
+    }
+}
+
+impl Encode for URIInitBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"uriI")?;
+        output.write_u8(0)?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        output.write_all(&self.uri_initialization_data)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for URIInitBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        input.read_u8()?; // version
+        input.read_u24::<BigEndian>()?; // flags
+
+        let uri_initialization_data = input.to_owned();
+        *input = &input[input.len()..];
+        Ok(Self {
+            uri_initialization_data,
+        })
+    }
+}
+
+/// Returns the btrt following the strings, skipping any other boxes, like the
+/// txtC of ISO/IEC 14496-12:2015.
+fn decode_bit_rate(input: &mut &[u8]) -> Result<Option<BitRateBox>> {
+    let mut bit_rate = None;
+    while !input.is_empty() {
+        let (r#type, mut data) = decode_box_header(input)?;
+        if &r#type == b"btrt" && bit_rate.is_none() {
+            bit_rate = Some(Decode::decode(&mut data)?);
+        }
+    }
+    Ok(bit_rate)
+}
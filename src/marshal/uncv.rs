@@ -0,0 +1,275 @@
+use std::io::{Seek, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::marshal::{
+    bits::{BitReader, BitWriter},
+    checked_cast, decode_boxes, encode_box_header, update_box_header, Boxes, Decode, Encode, Error,
+    FourCC, Result, VisualSampleEntry,
+};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23001-17:2024 5.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct UncompressedVideoSampleEntry {
+    pub base: VisualSampleEntry,
+    pub config: UncompressedFrameConfigBox,
+    pub components: Option<ComponentDefinitionBox>,
+}
+
+impl Encode for UncompressedVideoSampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"uncv")?;
+
+        self.base.encode(output)?;
+        self.components.encode(output)?;
+        self.config.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for UncompressedVideoSampleEntry {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut base = VisualSampleEntry::decode(input)?;
+
+        let mut config = None;
+        let mut components = None;
+
+        let mut extra_boxes = Vec::new();
+
+        decode_boxes! {
+            input,
+            unknown extra_boxes,
+            required uncC config,
+            optional cmpd components,
+        }
+        base.set_extra_boxes(extra_boxes);
+
+        Ok(Self {
+            base,
+            config,
+            components,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23001-17:2024 5.2.1
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct ComponentDefinitionBox(pub Vec<ComponentDefinition>);
+
+#[derive(Debug)]
+pub struct ComponentDefinition {
+    pub component_type: u16,
+    pub component_type_uri: Option<String>,
+}
+
+impl Encode for ComponentDefinitionBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"cmpd")?;
+
+        checked_cast::<u32>(self.0.len(), "component_count")?.encode(output)?;
+        for entry in &self.0 {
+            entry.component_type.encode(output)?;
+            if entry.component_type >= 0x8000 {
+                entry
+                    .component_type_uri
+                    .as_deref()
+                    .unwrap_or_default()
+                    .to_owned()
+                    .encode(output)?;
+            }
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for ComponentDefinitionBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let component_count = u32::decode(input)?;
+        let mut entries = Vec::new();
+        for _ in 0..component_count {
+            let component_type = u16::decode(input)?;
+            let component_type_uri = if component_type >= 0x8000 {
+                Some(Decode::decode(input)?)
+            } else {
+                None
+            };
+            entries.push(ComponentDefinition {
+                component_type,
+                component_type_uri,
+            });
+        }
+        Ok(Self(entries))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// ISO/IEC 23001-17:2024 5.2.2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct UncompressedFrameConfigBox {
+    pub profile: FourCC,
+    pub layout: Option<UncompressedFrameLayout>,
+}
+
+#[derive(Debug)]
+pub struct UncompressedFrameLayout {
+    pub components: Vec<UncompressedFrameComponent>,
+    pub sampling_type: u8,
+    pub interleave_type: u8,
+    pub block_size: u8,
+    pub components_little_endian: bool,
+    pub block_pad_lsb: bool,
+    pub block_little_endian: bool,
+    pub block_reversed: bool,
+    pub pad_unknown: bool,
+    pub pixel_size: u32,
+    pub row_align_size: u32,
+    pub tile_align_size: u32,
+    pub num_tile_cols_minus_one: u32,
+    pub num_tile_rows_minus_one: u32,
+}
+
+#[derive(Debug)]
+pub struct UncompressedFrameComponent {
+    pub component_index: u16,
+    pub component_bit_depth_minus_one: u8,
+    pub component_format: u8,
+    pub component_align_size: u8,
+}
+
+impl Encode for UncompressedFrameConfigBox {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"uncC")?;
+        output.write_u8(if self.layout.is_some() { 0 } else { 1 })?; // version
+        output.write_u24::<BigEndian>(0)?; // flags
+
+        self.profile.0.encode(output)?;
+        if let Some(layout) = &self.layout {
+            checked_cast::<u32>(layout.components.len(), "component_count")?.encode(output)?;
+            for component in &layout.components {
+                component.component_index.encode(output)?;
+                output.write_u8(component.component_bit_depth_minus_one)?;
+                output.write_u8(component.component_format)?;
+                output.write_u8(component.component_align_size)?;
+            }
+            output.write_u8(layout.sampling_type)?;
+            output.write_u8(layout.interleave_type)?;
+            output.write_u8(layout.block_size)?;
+            let mut writer = BitWriter::new(&mut *output);
+            writer.write_bit(layout.components_little_endian)?;
+            writer.write_bit(layout.block_pad_lsb)?;
+            writer.write_bit(layout.block_little_endian)?;
+            writer.write_bit(layout.block_reversed)?;
+            writer.write_bit(layout.pad_unknown)?;
+            writer.write_bits(0, 3)?; // reserved
+            writer.into_inner()?;
+            layout.pixel_size.encode(output)?;
+            layout.row_align_size.encode(output)?;
+            layout.tile_align_size.encode(output)?;
+            layout.num_tile_cols_minus_one.encode(output)?;
+            layout.num_tile_rows_minus_one.encode(output)?;
+        }
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for UncompressedFrameConfigBox {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let version = input.read_u8()?;
+        input.read_u24::<BigEndian>()?; // flags
+
+        let profile = FourCC(Decode::decode(input)?);
+        let layout = match version {
+            0 => {
+                let component_count = u32::decode(input)?;
+                let mut components = Vec::new();
+                for _ in 0..component_count {
+                    let component_index = Decode::decode(input)?;
+                    let component_bit_depth_minus_one = input.read_u8()?;
+                    let component_format = input.read_u8()?;
+                    let component_align_size = input.read_u8()?;
+                    components.push(UncompressedFrameComponent {
+                        component_index,
+                        component_bit_depth_minus_one,
+                        component_format,
+                        component_align_size,
+                    });
+                }
+                let sampling_type = input.read_u8()?;
+                let interleave_type = input.read_u8()?;
+                let block_size = input.read_u8()?;
+                let mut reader = BitReader::new(input);
+                let components_little_endian = reader.read_bit()?;
+                let block_pad_lsb = reader.read_bit()?;
+                let block_little_endian = reader.read_bit()?;
+                let block_reversed = reader.read_bit()?;
+                let pad_unknown = reader.read_bit()?;
+                reader.read_bits(3)?; // reserved
+                *input = reader.into_inner();
+                let pixel_size = Decode::decode(input)?;
+                let row_align_size = Decode::decode(input)?;
+                let tile_align_size = Decode::decode(input)?;
+                let num_tile_cols_minus_one = Decode::decode(input)?;
+                let num_tile_rows_minus_one = Decode::decode(input)?;
+                Some(UncompressedFrameLayout {
+                    components,
+                    sampling_type,
+                    interleave_type,
+                    block_size,
+                    components_little_endian,
+                    block_pad_lsb,
+                    block_little_endian,
+                    block_reversed,
+                    pad_unknown,
+                    pixel_size,
+                    row_align_size,
+                    tile_align_size,
+                    num_tile_cols_minus_one,
+                    num_tile_rows_minus_one,
+                })
+            }
+            1 => None,
+            _ => return Err(Error::ValueOutOfRange { field: "version" }),
+        };
+        Ok(Self { profile, layout })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// QuickTime File Format, Uncompressed Y'CbCr 10-bit 4:2:2
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct V210SampleEntry {
+    pub base: VisualSampleEntry,
+}
+
+impl Encode for V210SampleEntry {
+    fn encode(&self, output: &mut (impl Write + Seek)) -> Result<()> {
+        let begin = encode_box_header(output, *b"v210")?;
+
+        self.base.encode(output)?;
+
+        update_box_header(output, begin)
+    }
+}
+
+impl Decode for V210SampleEntry {
+    fn decode(input: &mut &[u8]) -> Result<Self> {
+        let mut base = VisualSampleEntry::decode(input)?;
+        base.set_extra_boxes(Boxes::new(input).collect::<Result<_>>()?);
+        *input = &input[input.len()..];
+
+        Ok(Self { base })
+    }
+}
@@ -0,0 +1,478 @@
+use std::io::Cursor;
+
+use crate::marshal::{
+    decode_box_header, Boxes, Decode, Encode, Error, File, FourCC, MediaDataBox, RawBox, Result,
+    SampleToChunkEntry, TrackBox,
+};
+
+/// Track reference of a track to its chapter track, a text track whose
+/// samples are the chapter titles.
+const CHAPTER: [u8; 4] = *b"chap";
+/// Track reference of a track to its timecode track.
+const TIMECODE: [u8; 4] = *b"tmcd";
+
+/// Transfers the metadata of a file to another file, e.g. the output of a
+/// transcode: the file-level meta, the user data of the movie, including
+/// iTunes metadata, and of each track, the QuickTime metadata of the movie, and
+/// the chapter and timecode tracks.
+///
+/// Tracks are matched up by their handler type in order of appearance, as
+/// track ids are not necessarily kept. Existing metadata, chapter and
+/// timecode tracks of `to` are replaced. The samples of the transferred
+/// tracks are appended in a new mdat.
+///
+/// The meta of image files is not transferred, as its items are stored in the
+/// mdat of `from`.
+pub fn copy_metadata(from: &File, to: &mut File) -> Result<()> {
+    let previous_offset = to.media_data_offsets()?.first().copied();
+    if let Some(meta) = &from.meta {
+        if &meta.handler.r#type.to_bytes() != b"pict" {
+            to.meta = Some(duplicate(meta)?);
+        }
+    }
+
+    let Some(from_movie) = &from.movie else {
+        return Ok(());
+    };
+    let to_movie = to.movie.as_mut().ok_or(Error::InvalidBoxQuantity {
+        r#type: "moov",
+        quantity: 0,
+        expected: 1,
+    })?;
+
+    if let Some(user_data) = &from_movie.user_data {
+        to_movie.user_data = Some(duplicate(user_data)?);
+    }
+    // the keys and ilst of QuickTime metadata
+    if from_movie
+        .extra_boxes
+        .iter()
+        .any(|extra_box| &extra_box.r#type.to_bytes() == b"meta")
+    {
+        to_movie
+            .extra_boxes
+            .retain(|extra_box| &extra_box.r#type.to_bytes() != b"meta");
+        for extra_box in &from_movie.extra_boxes {
+            if &extra_box.r#type.to_bytes() == b"meta" {
+                to_movie.extra_boxes.push(RawBox {
+                    r#type: extra_box.r#type,
+                    data: extra_box.data.clone(),
+                });
+            }
+        }
+    }
+
+    let copied_tracks = referenced_tracks(&from_movie.tracks)?;
+    if !copied_tracks.is_empty() {
+        let replaced_tracks = referenced_tracks(&to_movie.tracks)?;
+        to_movie.filter_tracks(|track| !replaced_tracks.contains(&track.header.track_id));
+    }
+
+    let mut from_tracks: Vec<_> = from_movie
+        .tracks
+        .iter()
+        .filter(|track| !copied_tracks.contains(&track.header.track_id))
+        .map(Some)
+        .collect();
+    // track id in `from` and index in `to` of the matched tracks
+    let mut matched_tracks = Vec::new();
+    for (index, to_track) in to_movie.tracks.iter_mut().enumerate() {
+        let Some(from_track) = from_tracks.iter_mut().find(|from_track| {
+            from_track.is_some_and(|from_track| {
+                from_track.media.handler.r#type == to_track.media.handler.r#type
+            })
+        }) else {
+            continue;
+        };
+        let from_track = from_track.take().unwrap();
+        if let Some(user_data) = &from_track.user_data {
+            to_track.user_data = Some(duplicate(user_data)?);
+        }
+        matched_tracks.push((from_track.header.track_id, index));
+    }
+
+    let track_count = to_movie.tracks.len();
+    if !copied_tracks.is_empty() {
+        let copied_track_ids = copy_tracks(from, &copied_tracks, to)?;
+        update_track_references(from, to, &matched_tracks, &copied_tracks, &copied_track_ids)?;
+    }
+    relocate(to, previous_offset, track_count)
+}
+
+/// Points the references of the matched and transferred tracks to the ids
+/// of the transferred tracks in `to`.
+fn update_track_references(
+    from: &File,
+    to: &mut File,
+    matched_tracks: &[(u32, usize)],
+    copied_tracks: &[u32],
+    copied_track_ids: &[u32],
+) -> Result<()> {
+    let from_movie = from.movie.as_ref().unwrap();
+    let to_movie = to.movie.as_mut().unwrap();
+    let mut track_ids: Vec<_> = copied_tracks
+        .iter()
+        .copied()
+        .zip(copied_track_ids.iter().copied())
+        .collect();
+    for &(from_track_id, index) in matched_tracks {
+        track_ids.push((from_track_id, to_movie.tracks[index].header.track_id));
+    }
+    let map_track_id = |track_id: &u32| {
+        track_ids
+            .iter()
+            .find(|(from_track_id, _)| from_track_id == track_id)
+            .map(|(_, to_track_id)| *to_track_id)
+    };
+    for &(from_track_id, index) in matched_tracks {
+        let from_track = from_movie.track(from_track_id).unwrap();
+        let to_track = &mut to_movie.tracks[index];
+        let mut references: Vec<_> = track_references(to_track)?
+            .into_iter()
+            .filter(|(r#type, _)| ![CHAPTER, TIMECODE].contains(&r#type.to_bytes()))
+            .collect();
+        for (r#type, track_ids) in track_references(from_track)? {
+            if ![CHAPTER, TIMECODE].contains(&r#type.to_bytes()) {
+                continue;
+            }
+            let track_ids: Vec<_> = track_ids.iter().filter_map(map_track_id).collect();
+            if !track_ids.is_empty() {
+                references.push((r#type, track_ids));
+            }
+        }
+        set_track_references(to_track, &references)?;
+    }
+    for (&from_track_id, &to_track_id) in copied_tracks.iter().zip(copied_track_ids) {
+        let from_track = from_movie.track(from_track_id).unwrap();
+        let references = track_references(from_track)?
+            .into_iter()
+            .filter_map(|(r#type, track_ids)| {
+                let track_ids: Vec<_> = track_ids.iter().filter_map(map_track_id).collect();
+                (!track_ids.is_empty()).then_some((r#type, track_ids))
+            })
+            .collect::<Vec<_>>();
+        let to_track = to_movie
+            .tracks
+            .iter_mut()
+            .find(|track| track.header.track_id == to_track_id)
+            .unwrap();
+        set_track_references(to_track, &references)?;
+    }
+    Ok(())
+}
+
+/// Returns the ids of the chapter and timecode tracks.
+fn referenced_tracks(tracks: &[TrackBox]) -> Result<Vec<u32>> {
+    let mut track_ids = Vec::new();
+    for track in tracks {
+        if &track.media.handler.r#type.to_bytes() == b"tmcd" {
+            track_ids.push(track.header.track_id);
+        }
+        for (r#type, referenced_track_ids) in track_references(track)? {
+            if r#type.to_bytes() == CHAPTER {
+                track_ids.extend(referenced_track_ids);
+            }
+        }
+    }
+    track_ids.sort_unstable();
+    track_ids.dedup();
+    track_ids.retain(|track_id| {
+        tracks
+            .iter()
+            .any(|track| track.header.track_id == *track_id)
+    });
+    Ok(track_ids)
+}
+
+/// Adds the tracks of `from` to `to` with new track ids, which are returned,
+/// and their samples in a new mdat, which their chunk offsets are relative
+/// to.
+fn copy_tracks(from: &File, track_ids: &[u32], to: &mut File) -> Result<Vec<u32>> {
+    let mut input = Cursor::new(Vec::new());
+    from.encode(&mut input)?;
+    let from_movie = from.movie.as_ref().unwrap();
+    let to_movie = to.movie.as_mut().unwrap();
+    let mut data = Vec::new();
+    let mut copied_track_ids = Vec::new();
+    for &track_id in track_ids {
+        let from_track = from_movie.track(track_id).unwrap();
+        let from_sample_table = &from_track.media.information.sample_table;
+        let mut track = duplicate(from_track)?;
+        let sample_table = &mut track.media.information.sample_table;
+
+        // a chunk per sample, with offsets relative to the new mdat
+        sample_table.chunk_offset.0.clear();
+        for sample_data in from_sample_table.sample_data(input.get_ref()) {
+            sample_table.chunk_offset.0.push(data.len() as u64);
+            data.extend_from_slice(sample_data?);
+        }
+        sample_table.sample_to_chunk.0.clear();
+        for (chunk, sample_description_index) in
+            (1..).zip(from_sample_table.sample_description_indices())
+        {
+            if sample_table
+                .sample_to_chunk
+                .0
+                .last()
+                .is_none_or(|entry| entry.sample_description_index != sample_description_index)
+            {
+                sample_table.sample_to_chunk.0.push(SampleToChunkEntry {
+                    first_chunk: chunk,
+                    samples_per_chunk: 1,
+                    sample_description_index,
+                });
+            }
+        }
+
+        // edits are in the timescale of the movie
+        let media_timescale = track.media.header.timescale;
+        if let Some(edit_list) = track.edit.as_mut().and_then(|edit| edit.edit_list.as_mut()) {
+            for entry in &mut edit_list.0 {
+                entry.segment_duration = entry
+                    .segment_duration
+                    .to_media(from_movie.header.timescale, media_timescale)
+                    .to_movie(media_timescale, to_movie.header.timescale);
+            }
+        }
+        track.recompute_duration(to_movie.header.timescale);
+        copied_track_ids.push(to_movie.add_track(track)?);
+    }
+    to.media_data.push(MediaDataBox(data));
+    Ok(copied_track_ids)
+}
+
+/// Moves the chunk offsets along with the mdats, as the moov changed in size:
+/// the ones of the first `track_count` tracks from `previous_offset`, where
+/// the first mdat started before, and the ones of the tracks added after them
+/// from the start of the last mdat.
+fn relocate(to: &mut File, previous_offset: Option<u64>, track_count: usize) -> Result<()> {
+    let mut previous_offsets = (previous_offset, 0);
+    loop {
+        let media_data_offsets = to.media_data_offsets()?;
+        let offsets = (
+            previous_offset.and(media_data_offsets.first().copied()),
+            media_data_offsets.last().copied().unwrap_or(0),
+        );
+        if offsets == previous_offsets {
+            break;
+        }
+        let to_movie = to.movie.as_mut().unwrap();
+        for (index, track) in to_movie.tracks.iter_mut().enumerate() {
+            let (previous_offset, offset) = if index < track_count {
+                match (previous_offsets.0, offsets.0) {
+                    (Some(previous_offset), Some(offset)) => (previous_offset, offset),
+                    _ => continue,
+                }
+            } else {
+                (previous_offsets.1, offsets.1)
+            };
+            for chunk_offset in &mut track.media.information.sample_table.chunk_offset.0 {
+                *chunk_offset = *chunk_offset - previous_offset + offset;
+            }
+        }
+        previous_offsets = offsets;
+    }
+    Ok(())
+}
+
+/// Returns the entries of the tref, with their type and referenced track ids.
+fn track_references(track: &TrackBox) -> Result<Vec<(FourCC, Vec<u32>)>> {
+    let mut references = Vec::new();
+    for extra_box in &track.extra_boxes {
+        if &extra_box.r#type.to_bytes() != b"tref" {
+            continue;
+        }
+        for reference in Boxes::new(&extra_box.data) {
+            let reference = reference?;
+            let mut data = reference.data.as_slice();
+            let mut track_ids = Vec::new();
+            while !data.is_empty() {
+                track_ids.push(u32::decode(&mut data)?);
+            }
+            references.push((reference.r#type, track_ids));
+        }
+    }
+    Ok(references)
+}
+
+/// Replaces the tref, which is removed if there are no references.
+fn set_track_references(track: &mut TrackBox, references: &[(FourCC, Vec<u32>)]) -> Result<()> {
+    track
+        .extra_boxes
+        .retain(|extra_box| &extra_box.r#type.to_bytes() != b"tref");
+    if references.is_empty() {
+        return Ok(());
+    }
+    let mut data = Cursor::new(Vec::new());
+    for (r#type, track_ids) in references {
+        RawBox {
+            r#type: *r#type,
+            data: track_ids
+                .iter()
+                .flat_map(|track_id| track_id.to_be_bytes())
+                .collect(),
+        }
+        .encode(&mut data)?;
+    }
+    track.extra_boxes.push(RawBox {
+        r#type: FourCC::from(*b"tref"),
+        data: data.into_inner(),
+    });
+    Ok(())
+}
+
+fn duplicate<T: Encode + Decode>(value: &T) -> Result<T> {
+    let mut data = Cursor::new(Vec::new());
+    value.encode(&mut data)?;
+    let (_, mut data) = decode_box_header(&mut data.get_ref().as_slice())?;
+    T::decode(&mut data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{copy_metadata, set_track_references, track_references, CHAPTER, TIMECODE};
+    use crate::marshal::{
+        timed_metadata::{URIBox, URIMetaSampleEntry},
+        Decode, Encode, File, FileTypeBox, FourCC, HandlerBox, MediaDataBox, MetaBox, MovieBox,
+        MovieHeaderBox, SampleEntry, SampleSizeBox, SampleToChunkEntry, TimeToSampleEntry,
+        TrackBox,
+    };
+
+    /// Track with a sample of 4 bytes each at `offset` of the mdat.
+    fn track(track_id: u32, handler: &[u8; 4], sample_count: u32, offset: u64) -> TrackBox {
+        let mut track = TrackBox::new_timed_metadata(
+            track_id,
+            1000,
+            SampleEntry::URIMeta(URIMetaSampleEntry {
+                data_reference_index: 1,
+                uri: URIBox {
+                    the_uri: "urn:example".to_owned(),
+                },
+                init: None,
+                bit_rate: None,
+            }),
+            None,
+        );
+        track.media.handler.r#type = FourCC::from(*handler);
+        let sample_table = &mut track.media.information.sample_table;
+        sample_table.time_to_sample.0.push(TimeToSampleEntry {
+            sample_count,
+            sample_delta: 100,
+        });
+        sample_table.sample_size = SampleSizeBox::Value {
+            sample_size: 4,
+            sample_count,
+        };
+        sample_table.sample_to_chunk.0.push(SampleToChunkEntry {
+            first_chunk: 1,
+            samples_per_chunk: 1,
+            sample_description_index: 1,
+        });
+        sample_table.chunk_offset.0 = (0..sample_count as u64)
+            .map(|sample| offset + sample * 4)
+            .collect();
+        track.recompute_duration(1000);
+        track
+    }
+
+    fn file(tracks: Vec<TrackBox>, data: Vec<u8>) -> File {
+        let mut file = File {
+            file_type: FileTypeBox {
+                major_brand: FourCC::from(*b"qt  "),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"qt  ")],
+            },
+            movie: Some(MovieBox {
+                header: MovieHeaderBox {
+                    timescale: 1000,
+                    next_track_id: tracks.len() as u32 + 1,
+                    ..Default::default()
+                },
+                tracks,
+                extends: None,
+                user_data: None,
+                extra_boxes: Vec::new(),
+            }),
+            media_data: vec![MediaDataBox(data)],
+            meta: None,
+            extra_boxes: Vec::new(),
+        };
+        let offset = file.media_data_offsets().unwrap()[0];
+        for track in &mut file.movie.as_mut().unwrap().tracks {
+            for chunk_offset in &mut track.media.information.sample_table.chunk_offset.0 {
+                *chunk_offset += offset;
+            }
+        }
+        file
+    }
+
+    #[test]
+    fn chapter_and_timecode_tracks() {
+        let mut video_track = track(1, b"vide", 1, 0);
+        set_track_references(
+            &mut video_track,
+            &[
+                (FourCC::from(CHAPTER), vec![2]),
+                (FourCC::from(TIMECODE), vec![3]),
+            ],
+        )
+        .unwrap();
+        let mut from = file(
+            vec![
+                video_track,
+                track(2, b"text", 2, 4),
+                track(3, b"tmcd", 1, 12),
+            ],
+            [[1; 4], [2; 4], [3; 4], [4; 4]].concat(),
+        );
+        from.meta = Some(MetaBox {
+            handler: HandlerBox {
+                r#type: FourCC::from(*b"mdir"),
+                name: String::new(),
+            },
+            item_location: None,
+            item_info: None,
+            item_reference: None,
+            item_properties: None,
+            item_list: None,
+            extra_boxes: Vec::new(),
+        });
+        let mut to = file(vec![track(1, b"vide", 1, 0)], vec![7; 4]);
+
+        copy_metadata(&from, &mut to).unwrap();
+        let mut data = Cursor::new(Vec::new());
+        to.encode(&mut data).unwrap();
+        let data = data.into_inner();
+        let to = File::decode(&mut data.as_slice()).unwrap();
+        assert!(to.meta.is_some());
+
+        let movie = to.movie.unwrap();
+        let samples = |track_id| -> Vec<_> {
+            movie
+                .track(track_id)
+                .unwrap()
+                .media
+                .information
+                .sample_table
+                .sample_data(&data)
+                .map(Result::unwrap)
+                .collect()
+        };
+        assert_eq!(samples(1), [[7; 4]]);
+        assert_eq!(samples(2), [[2; 4], [3; 4]]);
+        assert_eq!(samples(3), [[4; 4]]);
+        assert_eq!(
+            &movie.track(3).unwrap().media.handler.r#type.to_bytes(),
+            b"tmcd"
+        );
+        assert_eq!(
+            track_references(movie.track(1).unwrap()).unwrap(),
+            [
+                (FourCC::from(CHAPTER), vec![2]),
+                (FourCC::from(TIMECODE), vec![3]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,700 @@
+use std::io::{Read, Seek, Write};
+
+use crate::{
+    append::AppendSession,
+    fragment::encode_fragment,
+    marshal::{
+        aac::AACSampleEntry, checked_cast, Encode, Error, FileTypeBox, FourCC, MediaTime,
+        MetadataItem, MovieBox, MovieExtendsBox, MovieFragmentBox, MovieFragmentHeaderBox,
+        MovieTime, Result, SampleEntry, SampleSizeBox, TrackBox, TrackExtendsBox,
+        TrackFragmentBaseMediaDecodeTimeBox, TrackFragmentBox, TrackFragmentHeaderBox, TrackRunBox,
+        TrackRunEntry,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub data: Vec<u8>,
+    pub dts: MediaTime,
+    pub duration: u32,
+    pub sync: bool,
+    pub track: u32,
+}
+
+/// Produces the samples of one or more tracks in decode order, e.g. an
+/// adapter around an encoder.
+pub trait SampleSource {
+    fn next_sample(&mut self) -> Option<Sample>;
+}
+
+/// Describes the file to mux, with tracks that have their sample descriptions
+/// but no samples yet.
+#[derive(Debug)]
+pub struct MuxConfig {
+    pub file_type: FileTypeBox,
+    pub movie: MovieBox,
+    /// see [AppendSession::set_media_data_limit]
+    pub media_data_limit: Option<u64>,
+    /// encoder delay of the AAC tracks without an edit list in samples, which
+    /// is converted to the timescale of each track and hidden behind an edit
+    /// list, see [AppendSession::set_priming]
+    pub priming: Option<u64>,
+    pub writer: WriterConfig,
+    /// writes movie fragments of about this duration, starting at sync
+    /// samples, instead of a single moov at the end, so that only the samples
+    /// of one fragment are kept in memory, like for recordings which run for
+    /// days
+    pub fragment_duration: Option<MovieTime>,
+}
+
+impl MuxConfig {
+    /// Creates a config without a media data limit, and with the priming of
+    /// the common AAC encoders.
+    pub fn new(file_type: FileTypeBox, movie: MovieBox) -> Self {
+        Self {
+            file_type,
+            movie,
+            media_data_limit: None,
+            priming: Some(AACSampleEntry::PRIMING),
+            writer: Default::default(),
+            fragment_duration: None,
+        }
+    }
+}
+
+/// What the writers put into a file besides the samples, which players and
+/// tools show, like the name of the encoder.
+///
+/// Only fills in what the file doesn't have already, so that e.g. the
+/// handler name of a track is kept if it has one.
+#[derive(Debug, Clone)]
+pub struct WriterConfig {
+    /// replaces the ftyp of the writer, if set
+    pub file_type: Option<FileTypeBox>,
+    /// compressorname of the visual sample entries
+    pub compressor_name: Option<String>,
+    /// name of the hdlr of the tracks, by handler type
+    pub handler_names: Vec<(FourCC, String)>,
+    /// [MetadataItem::ENCODER] tag in the udta of the movie, like
+    /// "Lavf60.16.100"
+    pub encoder: Option<String>,
+}
+
+impl Default for WriterConfig {
+    /// Uses the handler names of FFmpeg, which most tools show.
+    fn default() -> Self {
+        Self {
+            file_type: None,
+            compressor_name: None,
+            handler_names: vec![
+                (FourCC::from(*b"vide"), "VideoHandler".to_owned()),
+                (FourCC::from(*b"soun"), "SoundHandler".to_owned()),
+            ],
+            encoder: None,
+        }
+    }
+}
+
+impl WriterConfig {
+    /// Applies the config to the ftyp and moov before they are written.
+    pub(crate) fn apply(&self, file_type: &mut FileTypeBox, movie: &mut MovieBox) {
+        if let Some(writer_file_type) = &self.file_type {
+            *file_type = writer_file_type.clone();
+        }
+        for track in &mut movie.tracks {
+            let handler = &mut track.media.handler;
+            if handler.name.is_empty() {
+                if let Some((_, name)) = self
+                    .handler_names
+                    .iter()
+                    .find(|(r#type, _)| *r#type == handler.r#type)
+                {
+                    handler.name.clone_from(name);
+                }
+            }
+
+            let Some(compressor_name) = &self.compressor_name else {
+                continue;
+            };
+            for entry in &mut track.media.information.sample_table.description.0 {
+                if let Some(entry) = entry
+                    .visual_mut()
+                    .filter(|entry| entry.compressorname.as_str().is_empty())
+                {
+                    entry.compressorname = compressor_name.as_str().into();
+                }
+            }
+        }
+        if let Some(encoder) = &self.encoder {
+            movie
+                .item_list_mut()
+                .set_text(MetadataItem::ENCODER, encoder);
+        }
+    }
+}
+
+/// Applies the media data limit and priming of a [MuxConfig] to a session.
+pub(crate) fn configure<F: Read + Write + Seek>(
+    session: &mut AppendSession<F>,
+    media_data_limit: Option<u64>,
+    priming: Option<u64>,
+) -> Result<()> {
+    session.set_media_data_limit(media_data_limit);
+    let Some(priming) = priming else {
+        return Ok(());
+    };
+    let primings: Vec<_> = session
+        .movie()
+        .tracks
+        .iter()
+        .filter_map(|track| Some((track.header.track_id, track_priming(track, priming)?)))
+        .collect();
+    for (track_id, priming) in primings {
+        session.set_priming(track_id, Some(priming))?;
+    }
+    Ok(())
+}
+
+/// Converts the priming in samples to the media timescale of an AAC track
+/// without an edit list, None for other tracks.
+fn track_priming(track: &TrackBox, priming: u64) -> Option<MediaTime> {
+    if track.edit.is_some() {
+        return None;
+    }
+    let Some(SampleEntry::AAC(entry)) = track.media.information.sample_table.description.0.first()
+    else {
+        return None;
+    };
+    let sample_rate = entry.base.sample_rate();
+    if sample_rate <= 0.0 {
+        return None;
+    }
+    let timescale = track.media.header.timescale as f64;
+    Some(MediaTime(
+        (priming as f64 * timescale / sample_rate).round() as u64,
+    ))
+}
+
+/// Outcome of writing a file, to log or display without parsing it again.
+#[derive(Debug, Clone)]
+pub struct MuxSummary {
+    pub duration: MovieTime,
+    pub tracks: Vec<TrackSummary>,
+    /// size of the whole file
+    pub file_size: u64,
+    pub movie_size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackSummary {
+    pub track_id: u32,
+    pub sample_count: u32,
+    /// sum of the sample sizes
+    pub size: u64,
+    /// in bits per second, over the duration of the media
+    pub average_bitrate: u64,
+}
+
+impl TrackSummary {
+    fn new(
+        track_id: u32,
+        sample_count: u32,
+        size: u64,
+        duration: MediaTime,
+        timescale: u32,
+    ) -> Self {
+        Self {
+            track_id,
+            sample_count,
+            size,
+            average_bitrate: if duration.0 == 0 {
+                0
+            } else {
+                (size as u128 * 8 * timescale as u128 / duration.0 as u128) as u64
+            },
+        }
+    }
+}
+
+impl MuxSummary {
+    pub(crate) fn new(movie: &MovieBox, file_size: u64, movie_size: u64) -> Self {
+        Self {
+            duration: movie.header.duration,
+            tracks: movie
+                .tracks
+                .iter()
+                .map(|track| {
+                    let sample_size = &track.media.information.sample_table.sample_size;
+                    let size = match sample_size {
+                        SampleSizeBox::Value {
+                            sample_size,
+                            sample_count,
+                        } => *sample_size as u64 * *sample_count as u64,
+                        SampleSizeBox::PerSample(sizes) => {
+                            sizes.iter().map(|&size| size as u64).sum()
+                        }
+                    };
+                    TrackSummary::new(
+                        track.header.track_id,
+                        sample_size.sample_count(),
+                        size,
+                        track.media.header.duration,
+                        track.media.header.timescale,
+                    )
+                })
+                .collect(),
+            file_size,
+            movie_size,
+        }
+    }
+}
+
+/// Pulls samples from all sources until they are exhausted, interleaving them
+/// by decode time, and writes the finished file into `sink`.
+pub fn mux<F: Read + Write + Seek>(
+    sources: Vec<Box<dyn SampleSource + '_>>,
+    mut sink: F,
+    mut config: MuxConfig,
+) -> Result<(F, MuxSummary)> {
+    config
+        .writer
+        .apply(&mut config.file_type, &mut config.movie);
+    let mut samples = Interleaver::new(sources);
+    if let Some(fragment_duration) = config.fragment_duration {
+        return mux_fragments(samples, sink, config, fragment_duration);
+    }
+
+    config.file_type.encode(&mut sink)?;
+    config.movie.encode(&mut sink)?;
+    let mut session = AppendSession::open(sink)?;
+    configure(&mut session, config.media_data_limit, config.priming)?;
+    while let Some(sample) = samples.next(session.movie())? {
+        session.append_sample(sample.track, &sample.data, sample.duration, sample.sync)?;
+    }
+    session.close()
+}
+
+/// Takes the samples of all sources in the order of their decode times.
+struct Interleaver<'a> {
+    sources: Vec<Box<dyn SampleSource + 'a>>,
+    pending: Vec<Option<Sample>>,
+}
+
+impl<'a> Interleaver<'a> {
+    fn new(mut sources: Vec<Box<dyn SampleSource + 'a>>) -> Self {
+        let pending = sources
+            .iter_mut()
+            .map(|source| source.next_sample())
+            .collect();
+        Self { sources, pending }
+    }
+
+    fn next(&mut self, movie: &MovieBox) -> Result<Option<Sample>> {
+        // compare decode times in the movie timescale, as tracks can differ
+        let mut next = None;
+        for (index, sample) in self.pending.iter().enumerate() {
+            let Some(sample) = sample else {
+                continue;
+            };
+            let track = movie
+                .tracks
+                .iter()
+                .find(|track| track.header.track_id == sample.track)
+                .ok_or(Error::InvalidTrackId {
+                    track_id: sample.track,
+                })?;
+            let time = sample
+                .dts
+                .to_movie(track.media.header.timescale, movie.header.timescale);
+            if next.is_none_or(|(_, next_time)| time < next_time) {
+                next = Some((index, time));
+            }
+        }
+        let Some((index, _)) = next else {
+            return Ok(None);
+        };
+        Ok(std::mem::replace(
+            &mut self.pending[index],
+            self.sources[index].next_sample(),
+        ))
+    }
+}
+
+/// Writes the moov without samples, followed by a moof and mdat for each
+/// fragment, which start at sync samples of the first video track, or the
+/// first track if there is none.
+fn mux_fragments<F: Write + Seek>(
+    mut samples: Interleaver,
+    mut sink: F,
+    mut config: MuxConfig,
+    fragment_duration: MovieTime,
+) -> Result<(F, MuxSummary)> {
+    let movie = &mut config.movie;
+    movie.extends = Some(MovieExtendsBox {
+        header: None,
+        track_extends: movie
+            .tracks
+            .iter()
+            .map(|track| TrackExtendsBox {
+                track_id: track.header.track_id,
+                default_sample_description_index: 1,
+                default_sample_duration: 0,
+                default_sample_size: 0,
+                default_sample_flags: 0,
+            })
+            .collect(),
+        extra_boxes: Vec::new(),
+    });
+    // the edit has no duration, and therefore covers all fragments
+    if let Some(priming) = config.priming {
+        let movie_timescale = movie.header.timescale;
+        for track in &mut movie.tracks {
+            if let Some(priming) = track_priming(track, priming) {
+                track.set_priming(priming, movie_timescale);
+            }
+        }
+    }
+    config.file_type.encode(&mut sink)?;
+    let movie_begin = sink.stream_position()?;
+    movie.encode(&mut sink)?;
+    let movie_size = sink.stream_position()? - movie_begin;
+
+    let movie_timescale = movie.header.timescale;
+    let lead = movie
+        .tracks
+        .iter()
+        .position(|track| &track.media.handler.r#type.to_bytes() == b"vide")
+        .unwrap_or(0);
+    let mut tracks: Vec<_> = movie
+        .tracks
+        .iter()
+        .map(|track| FragmentedTrack {
+            track_id: track.header.track_id,
+            timescale: track.media.header.timescale,
+            fragment_decode_time: 0,
+            decode_time: 0,
+            sample_count: 0,
+            size: 0,
+        })
+        .collect();
+    let mut fragment = Vec::new();
+    let mut sequence_number = 0;
+    let mut next_boundary = fragment_duration.0;
+    while let Some(sample) = samples.next(movie)? {
+        let index = tracks
+            .iter()
+            .position(|track| track.track_id == sample.track)
+            .ok_or(Error::InvalidTrackId {
+                track_id: sample.track,
+            })?;
+        if index == lead && sample.sync && !fragment.is_empty() {
+            let time = tracks[lead].decode_time(movie_timescale).0;
+            if fragment_duration.0 != 0 && time >= next_boundary {
+                while next_boundary <= time {
+                    next_boundary += fragment_duration.0;
+                }
+                sequence_number += 1;
+                write_fragment(&mut sink, sequence_number, &mut tracks, &fragment)?;
+                fragment.clear();
+            }
+        }
+        let track = &mut tracks[index];
+        track.decode_time += sample.duration as u64;
+        track.sample_count += 1;
+        track.size += sample.data.len() as u64;
+        fragment.push(sample);
+    }
+    if !fragment.is_empty() {
+        sequence_number += 1;
+        write_fragment(&mut sink, sequence_number, &mut tracks, &fragment)?;
+    }
+
+    let file_size = sink.stream_position()?;
+    let summary = MuxSummary {
+        duration: tracks
+            .iter()
+            .map(|track| track.decode_time(movie_timescale))
+            .max()
+            .unwrap_or_default(),
+        tracks: tracks
+            .iter()
+            .map(|track| {
+                TrackSummary::new(
+                    track.track_id,
+                    track.sample_count,
+                    track.size,
+                    MediaTime(track.decode_time),
+                    track.timescale,
+                )
+            })
+            .collect(),
+        file_size,
+        movie_size,
+    };
+    Ok((sink, summary))
+}
+
+/// Samples written so far of a track of [mux_fragments].
+struct FragmentedTrack {
+    track_id: u32,
+    timescale: u32,
+    /// of the first sample of the current fragment
+    fragment_decode_time: u64,
+    /// of the next sample
+    decode_time: u64,
+    sample_count: u32,
+    size: u64,
+}
+
+impl FragmentedTrack {
+    fn decode_time(&self, movie_timescale: u32) -> MovieTime {
+        MediaTime(self.decode_time).to_movie(self.timescale, movie_timescale)
+    }
+}
+
+/// Writes a moof with a track fragment for each track with samples, and the
+/// mdat.
+fn write_fragment(
+    output: &mut (impl Write + Seek),
+    sequence_number: u32,
+    tracks: &mut [FragmentedTrack],
+    samples: &[Sample],
+) -> Result<()> {
+    let mut fragment = MovieFragmentBox {
+        header: MovieFragmentHeaderBox { sequence_number },
+        track_fragments: Vec::new(),
+        protection_system_headers: Vec::new(),
+        extra_boxes: Vec::new(),
+    };
+    let mut data = Vec::new();
+    let mut data_offsets = Vec::new();
+    for track in tracks {
+        let mut entries = Vec::new();
+        let base_media_decode_time = MediaTime(track.fragment_decode_time);
+        track.fragment_decode_time = track.decode_time;
+        let data_offset = data.len();
+        for sample in samples
+            .iter()
+            .filter(|sample| sample.track == track.track_id)
+        {
+            entries.push(TrackRunEntry {
+                sample_duration: Some(sample.duration),
+                sample_size: Some(checked_cast(sample.data.len(), "sample_size")?),
+                sample_flags: Some(if sample.sync {
+                    0
+                } else {
+                    TrackRunEntry::NON_SYNC_SAMPLE
+                }),
+                sample_composition_time_offset: None,
+            });
+            data.extend_from_slice(&sample.data);
+        }
+        if entries.is_empty() {
+            continue;
+        }
+        data_offsets.push(data_offset);
+        fragment.track_fragments.push(TrackFragmentBox {
+            header: TrackFragmentHeaderBox {
+                track_id: track.track_id,
+                default_base_is_moof: true,
+                ..Default::default()
+            },
+            decode_time: Some(TrackFragmentBaseMediaDecodeTimeBox {
+                base_media_decode_time,
+            }),
+            runs: vec![TrackRunBox {
+                data_offset: Some(0),
+                first_sample_flags: None,
+                entries,
+            }],
+            sample_to_groups: Vec::new(),
+            sample_group_descriptions: Vec::new(),
+            auxiliary_information_sizes: Vec::new(),
+            auxiliary_information_offsets: Vec::new(),
+            sample_encryption: None,
+            extra_boxes: Vec::new(),
+        });
+    }
+    encode_fragment(output, fragment, data, &data_offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{mux, MuxConfig, Sample, SampleSource};
+    use crate::{
+        defragment::defragment,
+        demux::Reader,
+        marshal::{
+            aac::{AACSampleEntry, AudioSpecificConfig},
+            timed_metadata::{URIBox, URIMetaSampleEntry},
+            Decode, Encode, File, FileTypeBox, FourCC, MediaTime, MovieBox, MovieHeaderBox,
+            MovieTime, SampleEntry, TrackBox,
+        },
+    };
+
+    struct Samples {
+        track: u32,
+        duration: u32,
+        index: u32,
+    }
+
+    impl SampleSource for Samples {
+        fn next_sample(&mut self) -> Option<Sample> {
+            if self.index == 20 {
+                return None;
+            }
+            let sample = Sample {
+                data: vec![self.track as u8; 1 + self.index as usize],
+                dts: MediaTime((self.index * self.duration) as u64),
+                duration: self.duration,
+                sync: self.index.is_multiple_of(3),
+                track: self.track,
+            };
+            self.index += 1;
+            Some(sample)
+        }
+    }
+
+    fn config(fragment_duration: Option<MovieTime>) -> MuxConfig {
+        let track = |track_id| {
+            TrackBox::new_timed_metadata(
+                track_id,
+                100,
+                SampleEntry::URIMeta(URIMetaSampleEntry {
+                    data_reference_index: 1,
+                    uri: URIBox {
+                        the_uri: "urn:example".to_owned(),
+                    },
+                    init: None,
+                    bit_rate: None,
+                }),
+                None,
+            )
+        };
+        let mut config = MuxConfig::new(
+            FileTypeBox {
+                major_brand: FourCC::from(*b"isom"),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"isom"), FourCC::from(*b"iso6")],
+            },
+            MovieBox {
+                header: MovieHeaderBox {
+                    timescale: 1000,
+                    next_track_id: 3,
+                    ..Default::default()
+                },
+                tracks: vec![track(1), track(2)],
+                extends: None,
+                user_data: None,
+                extra_boxes: Vec::new(),
+            },
+        );
+        config.fragment_duration = fragment_duration;
+        config
+    }
+
+    /// Data of the samples of a track, and their decode time, size and sync
+    /// flag.
+    type TrackSamples = (Vec<u8>, Vec<(MediaTime, u32, bool)>);
+
+    fn samples(file: &[u8]) -> Vec<TrackSamples> {
+        let mut reader = Reader::new(file).unwrap();
+        let mut tracks: Vec<_> = reader
+            .movie()
+            .tracks
+            .iter()
+            .map(|track| {
+                (
+                    Vec::new(),
+                    track
+                        .samples()
+                        .map(|sample| (sample.decode_time, sample.size, sample.is_sync))
+                        .collect(),
+                )
+            })
+            .collect();
+        while let Some(chunk) = reader.next_chunk() {
+            let (chunk, data) = chunk.unwrap();
+            tracks[chunk.track_id as usize - 1].0.extend(data);
+        }
+        tracks
+    }
+
+    #[test]
+    fn fragments() {
+        let sources = || -> Vec<Box<dyn SampleSource>> {
+            vec![
+                Box::new(Samples {
+                    track: 1,
+                    duration: 4,
+                    index: 0,
+                }),
+                Box::new(Samples {
+                    track: 2,
+                    duration: 5,
+                    index: 0,
+                }),
+            ]
+        };
+        let (file, summary) = mux(sources(), Cursor::new(Vec::new()), config(None)).unwrap();
+        let file = file.into_inner();
+
+        // fragments start at every third sample of the first track, 120 ms apart
+        let (fragmented, fragmented_summary) = mux(
+            sources(),
+            Cursor::new(Vec::new()),
+            config(Some(MovieTime(100))),
+        )
+        .unwrap();
+        let fragmented = fragmented.into_inner();
+        let fragments = File::decode(&mut fragmented.as_slice())
+            .unwrap()
+            .extra_boxes
+            .iter()
+            .filter(|raw_box| &raw_box.r#type.to_bytes() == b"moof")
+            .count();
+        assert_eq!(fragments, 7);
+        assert_eq!(fragmented_summary.duration, summary.duration);
+        assert_eq!(fragmented_summary.file_size, fragmented.len() as u64);
+        for (track, fragmented_track) in summary.tracks.iter().zip(&fragmented_summary.tracks) {
+            assert_eq!(fragmented_track.sample_count, track.sample_count);
+            assert_eq!(fragmented_track.size, track.size);
+        }
+
+        let mut defragmented = Cursor::new(Vec::new());
+        defragment(&fragmented)
+            .unwrap()
+            .encode(&mut defragmented)
+            .unwrap();
+        assert_eq!(samples(defragmented.get_ref()), samples(&file));
+    }
+
+    #[test]
+    fn priming() {
+        // 48 kHz audio in a track with the timescale of video
+        let entry = AACSampleEntry::new(AudioSpecificConfig::AAC_LC, 48000, 2).unwrap();
+        let mut config = config(None);
+        config.movie.tracks = vec![TrackBox::new_timed_metadata(
+            1,
+            90000,
+            SampleEntry::AAC(entry),
+            None,
+        )];
+        config.movie.header.next_track_id = 2;
+        let sources: Vec<Box<dyn SampleSource>> = vec![Box::new(Samples {
+            track: 1,
+            duration: 1920,
+            index: 0,
+        })];
+        let (file, _) = mux(sources, Cursor::new(Vec::new()), config).unwrap();
+        let file = File::decode(&mut file.get_ref().as_slice()).unwrap();
+        let track = &file.movie.unwrap().tracks[0];
+        let edit_list = track.edit.as_ref().unwrap().edit_list.as_ref().unwrap();
+        assert_eq!(
+            edit_list.0[0].media_time,
+            (AACSampleEntry::PRIMING * 90000 / 48000) as i64
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use crate::{
+    defragment::{decode_movie, defragment_with},
+    filter::{nal_units, strip_nal_units},
+    io::RandomAccess,
+    marshal::{
+        bits::BitReader,
+        hevc::{HEVCConfigurationBox, HEVCNalUnitArray, HEVCSampleEntry},
+        Error, File, FourCC, MovieBox, Result, SampleEntry, TrackBox,
+    },
+};
+
+/// Converts hev1 tracks to hvc1, as required by Apple players, by moving the
+/// VPS, SPS and PPS out of the samples into the hvcC. Like [defragment], the
+/// result is progressive.
+///
+/// Fails if a parameter set changes within a track, which hvc1 can't express.
+///
+/// [defragment]: crate::defragment::defragment
+pub fn hev1_to_hvc1(input: &(impl RandomAccess + ?Sized)) -> Result<File> {
+    let movie = decode_movie(input)?;
+    let length_sizes = length_sizes(&movie, HEVCSampleEntry::HEV1);
+
+    let mut parameter_sets = HashMap::<u32, Vec<Vec<u8>>>::new();
+    let mut file = defragment_with(input, |track_id, sample| {
+        let Some(&length_size) = length_sizes.get(&track_id) else {
+            return Ok(());
+        };
+        let parameter_sets = parameter_sets.entry(track_id).or_default();
+        for nal_unit in nal_units(sample, length_size) {
+            let nal_unit = nal_unit?;
+            if !is_parameter_set(nal_unit) || parameter_sets.iter().any(|set| set == nal_unit) {
+                continue;
+            }
+            let key = parameter_set_key(nal_unit)?;
+            if parameter_sets
+                .iter()
+                .any(|set| parameter_set_key(set).is_ok_and(|other| other == key))
+            {
+                return Err(Error::ValueOutOfRange {
+                    field: "parameter_set",
+                });
+            }
+            parameter_sets.push(nal_unit.to_owned());
+        }
+        strip_nal_units(sample, length_size, is_parameter_set)
+    })?;
+
+    for track in &mut file.movie.as_mut().unwrap().tracks {
+        let Some(track_parameter_sets) = parameter_sets.remove(&track.header.track_id) else {
+            continue;
+        };
+        for entry in hevc_entries(track) {
+            if entry.codec != HEVCSampleEntry::HEV1 {
+                continue;
+            }
+            for nal_unit in &track_parameter_sets {
+                let key = parameter_set_key(nal_unit)?;
+                let array = array(&mut entry.config, key.0);
+                // the in-band one is the one used for decoding
+                array
+                    .nal_units
+                    .retain(|set| parameter_set_key(set).map_or(true, |other| other != key));
+                array.nal_units.push(nal_unit.clone());
+            }
+            for nal_unit_type in [
+                HEVCConfigurationBox::VPS_NUT,
+                HEVCConfigurationBox::SPS_NUT,
+                HEVCConfigurationBox::PPS_NUT,
+            ] {
+                let array = array(&mut entry.config, nal_unit_type);
+                if array.nal_units.is_empty() {
+                    return Err(Error::ValueOutOfRange {
+                        field: "parameter_set",
+                    });
+                }
+                array.array_completeness = true;
+            }
+            entry.codec = HEVCSampleEntry::HVC1;
+        }
+    }
+    Ok(file)
+}
+
+/// Converts hvc1 tracks to hev1 by repeating the VPS, SPS and PPS of the hvcC
+/// in front of every IRAP picture, so that decoding can start at any of them
+/// without the sample entry. Like [defragment], the result is progressive.
+///
+/// [defragment]: crate::defragment::defragment
+pub fn hvc1_to_hev1(input: &(impl RandomAccess + ?Sized)) -> Result<File> {
+    let movie = decode_movie(input)?;
+    let mut prefixes = HashMap::new();
+    for track in &movie.tracks {
+        let Some(entry) = track
+            .media
+            .information
+            .sample_table
+            .description
+            .0
+            .iter()
+            .find_map(|entry| match entry {
+                SampleEntry::HEVC(entry) if entry.codec == HEVCSampleEntry::HVC1 => Some(entry),
+                _ => None,
+            })
+        else {
+            continue;
+        };
+        let length_size = entry.config.length_size_minus_one + 1;
+        let mut prefix = Vec::new();
+        for nal_unit_type in [
+            HEVCConfigurationBox::VPS_NUT,
+            HEVCConfigurationBox::SPS_NUT,
+            HEVCConfigurationBox::PPS_NUT,
+        ] {
+            for nal_unit in entry.config.nal_units(nal_unit_type) {
+                prefix.extend_from_slice(
+                    &u32::try_from(nal_unit.len())
+                        .map_err(|_| Error::ValueOutOfRange {
+                            field: "parameter_set",
+                        })?
+                        .to_be_bytes()[4 - length_size as usize..],
+                );
+                prefix.extend_from_slice(nal_unit);
+            }
+        }
+        prefixes.insert(track.header.track_id, (length_size, prefix));
+    }
+
+    let mut file = defragment_with(input, |track_id, sample| {
+        let Some((length_size, prefix)) = prefixes.get(&track_id) else {
+            return Ok(());
+        };
+        let mut irap = false;
+        for nal_unit in nal_units(sample, *length_size) {
+            let nal_unit = nal_unit?;
+            if is_parameter_set(nal_unit) {
+                return Ok(());
+            }
+            // ISO/IEC 23008-2 7.4.2.2, BLA, IDR and CRA
+            irap |= nal_unit
+                .first()
+                .is_some_and(|&header| (16..=23).contains(&(header >> 1 & 0x3F)));
+        }
+        if irap {
+            sample.splice(0..0, prefix.iter().copied());
+        }
+        Ok(())
+    })?;
+
+    for track in &mut file.movie.as_mut().unwrap().tracks {
+        if !prefixes.contains_key(&track.header.track_id) {
+            continue;
+        }
+        for entry in hevc_entries(track) {
+            if entry.codec != HEVCSampleEntry::HVC1 {
+                continue;
+            }
+            for array in &mut entry.config.arrays {
+                if is_parameter_set_type(array.nal_unit_type) {
+                    array.array_completeness = false;
+                }
+            }
+            entry.codec = HEVCSampleEntry::HEV1;
+        }
+    }
+    Ok(file)
+}
+
+/// Returns the NAL unit length size of each track with a sample entry of the
+/// given codec.
+fn length_sizes(movie: &MovieBox, codec: FourCC) -> HashMap<u32, u8> {
+    movie
+        .tracks
+        .iter()
+        .filter_map(|track| {
+            track
+                .media
+                .information
+                .sample_table
+                .description
+                .0
+                .iter()
+                .find_map(|entry| match entry {
+                    SampleEntry::HEVC(entry) if entry.codec == codec => Some((
+                        track.header.track_id,
+                        entry.config.length_size_minus_one + 1,
+                    )),
+                    _ => None,
+                })
+        })
+        .collect()
+}
+
+fn hevc_entries(track: &mut TrackBox) -> impl Iterator<Item = &mut HEVCSampleEntry> {
+    track
+        .media
+        .information
+        .sample_table
+        .description
+        .0
+        .iter_mut()
+        .filter_map(|entry| match entry {
+            SampleEntry::HEVC(entry) => Some(entry),
+            _ => None,
+        })
+}
+
+fn array(config: &mut HEVCConfigurationBox, nal_unit_type: u8) -> &mut HEVCNalUnitArray {
+    let index = match config
+        .arrays
+        .iter()
+        .position(|array| array.nal_unit_type == nal_unit_type)
+    {
+        Some(index) => index,
+        None => {
+            config.arrays.push(HEVCNalUnitArray {
+                array_completeness: false,
+                nal_unit_type,
+                nal_units: Vec::new(),
+            });
+            config.arrays.len() - 1
+        }
+    };
+    &mut config.arrays[index]
+}
+
+fn is_parameter_set_type(nal_unit_type: u8) -> bool {
+    matches!(
+        nal_unit_type,
+        HEVCConfigurationBox::VPS_NUT
+            | HEVCConfigurationBox::SPS_NUT
+            | HEVCConfigurationBox::PPS_NUT
+    )
+}
+
+fn is_parameter_set(nal_unit: &[u8]) -> bool {
+    nal_unit
+        .first()
+        .is_some_and(|&header| is_parameter_set_type(header >> 1 & 0x3F))
+}
+
+/// Returns the NAL unit type and the id of a VPS, SPS or PPS, which a later
+/// parameter set of the same type and id replaces.
+fn parameter_set_key(nal_unit: &[u8]) -> Result<(u8, u32)> {
+    let nal_unit_type = nal_unit.first().map_or(0, |&header| header >> 1 & 0x3F);
+
+    // ISO/IEC 23008-2 7.3.1.1, without emulation_prevention_three_byte
+    let mut rbsp = Vec::with_capacity(nal_unit.len());
+    for &byte in nal_unit.get(2..).unwrap_or_default() {
+        if byte == 3 && rbsp.ends_with(&[0, 0]) {
+            continue;
+        }
+        rbsp.push(byte);
+    }
+    let mut reader = BitReader::new(&rbsp);
+
+    let id = match nal_unit_type {
+        // ISO/IEC 23008-2 7.3.2.1
+        HEVCConfigurationBox::VPS_NUT => reader.read_bits(4)? as u32,
+        // ISO/IEC 23008-2 7.3.2.2
+        HEVCConfigurationBox::SPS_NUT => {
+            reader.read_bits(4)?; // sps_video_parameter_set_id
+            let sps_max_sub_layers_minus1 = reader.read_bits(3)? as u32;
+            reader.read_bit()?; // sps_temporal_id_nesting_flag
+
+            // ISO/IEC 23008-2 7.3.3
+            // general profile and level
+            reader.read_bits(48)?;
+            reader.read_bits(48)?;
+            let mut sub_layers = Vec::new();
+            for _ in 0..sps_max_sub_layers_minus1 {
+                sub_layers.push((reader.read_bit()?, reader.read_bit()?));
+            }
+            if sps_max_sub_layers_minus1 > 0 {
+                for _ in sps_max_sub_layers_minus1..8 {
+                    reader.read_bits(2)?; // reserved_zero_2bits
+                }
+            }
+            for (sub_layer_profile_present, sub_layer_level_present) in sub_layers {
+                if sub_layer_profile_present {
+                    reader.read_bits(44)?;
+                    reader.read_bits(44)?;
+                }
+                if sub_layer_level_present {
+                    reader.read_bits(8)?;
+                }
+            }
+
+            reader.read_ue()?
+        }
+        // ISO/IEC 23008-2 7.3.2.3
+        HEVCConfigurationBox::PPS_NUT => reader.read_ue()?,
+        _ => {
+            return Err(Error::ValueOutOfRange {
+                field: "nal_unit_type",
+            })
+        }
+    };
+    Ok((nal_unit_type, id))
+}
@@ -0,0 +1,303 @@
+use crate::marshal::{Boxes, Error, FourCC, Result};
+
+/// How the payload of a box is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxKind {
+    /// only fields, or children which can't be told apart from fields
+    Leaf,
+    /// child boxes, after the fields in front of them, like the version and
+    /// flags of meta or the fixed part of a sample entry
+    Container { children_offset: usize },
+}
+
+#[derive(Debug)]
+pub struct BoxInfo {
+    pub r#type: FourCC,
+    pub kind: BoxKind,
+    /// starts with version and flags
+    pub full_box: bool,
+    /// the boxes it may be in, with [FILE] for the top level, empty if it may
+    /// be anywhere
+    pub parents: &'static [FourCC],
+}
+
+impl BoxInfo {
+    pub fn children_offset(&self) -> Option<usize> {
+        match self.kind {
+            BoxKind::Leaf => None,
+            BoxKind::Container { children_offset } => Some(children_offset),
+        }
+    }
+
+    pub fn is_allowed_in(&self, parent: FourCC) -> bool {
+        self.parents.is_empty() || self.parents.contains(&parent)
+    }
+}
+
+/// Stands in for the parent of top-level boxes.
+pub const FILE: FourCC = FourCC(0);
+
+/// Returns what is known about a box type, None if it is unknown.
+pub fn lookup(r#type: FourCC) -> Option<&'static BoxInfo> {
+    BOXES.iter().find(|info| info.r#type == r#type)
+}
+
+/// Checks that all known boxes of an encoded file are in one of their allowed
+/// parents, unknown boxes and their children are skipped.
+pub fn validate_hierarchy(input: &[u8]) -> Result<()> {
+    validate_children(input, FILE)
+}
+
+fn validate_children(input: &[u8], parent: FourCC) -> Result<()> {
+    for r#box in Boxes::new(input) {
+        let r#box = r#box?;
+        let Some(info) = lookup(r#box.r#type) else {
+            continue;
+        };
+        if !info.is_allowed_in(parent) {
+            return Err(Error::InvalidBoxParent {
+                r#type: r#box.r#type,
+                parent,
+            });
+        }
+        if let Some(children_offset) = info.children_offset() {
+            validate_children(
+                r#box.data.get(children_offset..).unwrap_or_default(),
+                r#box.r#type,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+const fn fourcc(r#type: &[u8; 4]) -> FourCC {
+    FourCC(u32::from_be_bytes(*r#type))
+}
+
+const fn leaf(r#type: &[u8; 4], full_box: bool, parents: &'static [FourCC]) -> BoxInfo {
+    BoxInfo {
+        r#type: fourcc(r#type),
+        kind: BoxKind::Leaf,
+        full_box,
+        parents,
+    }
+}
+
+const fn container(
+    r#type: &[u8; 4],
+    full_box: bool,
+    children_offset: usize,
+    parents: &'static [FourCC],
+) -> BoxInfo {
+    BoxInfo {
+        r#type: fourcc(r#type),
+        kind: BoxKind::Container { children_offset },
+        full_box,
+        parents,
+    }
+}
+
+const MOOV: FourCC = fourcc(b"moov");
+const TRAK: FourCC = fourcc(b"trak");
+const EDTS: FourCC = fourcc(b"edts");
+const MDIA: FourCC = fourcc(b"mdia");
+const MINF: FourCC = fourcc(b"minf");
+const DINF: FourCC = fourcc(b"dinf");
+const DREF: FourCC = fourcc(b"dref");
+const STBL: FourCC = fourcc(b"stbl");
+const STSD: FourCC = fourcc(b"stsd");
+const MVEX: FourCC = fourcc(b"mvex");
+const UDTA: FourCC = fourcc(b"udta");
+const LUDT: FourCC = fourcc(b"ludt");
+const META: FourCC = fourcc(b"meta");
+const MOOF: FourCC = fourcc(b"moof");
+const TRAF: FourCC = fourcc(b"traf");
+const MFRA: FourCC = fourcc(b"mfra");
+const IPRP: FourCC = fourcc(b"iprp");
+const IPCO: FourCC = fourcc(b"ipco");
+const UNCV: FourCC = fourcc(b"uncv");
+
+const VISUAL_SAMPLE_ENTRIES: &[FourCC] = &[
+    fourcc(b"av01"),
+    fourcc(b"avc1"),
+    fourcc(b"avc2"),
+    fourcc(b"avc3"),
+    fourcc(b"avc4"),
+    fourcc(b"hvc1"),
+    fourcc(b"hev1"),
+    UNCV,
+    fourcc(b"v210"),
+    fourcc(b"apco"),
+    fourcc(b"apcs"),
+    fourcc(b"apcn"),
+    fourcc(b"apch"),
+    fourcc(b"ap4h"),
+    fourcc(b"ap4x"),
+    fourcc(b"AVdn"),
+    fourcc(b"AVdh"),
+];
+
+/// Boxes that every visual sample entry decodes itself, see
+/// [VisualSampleEntry](crate::marshal::VisualSampleEntry).
+pub(crate) const VISUAL_SAMPLE_ENTRY_BOXES: &[FourCC] = &[
+    fourcc(b"colr"),
+    fourcc(b"fiel"),
+    fourcc(b"gama"),
+    fourcc(b"ccst"),
+    fourcc(b"btrt"),
+];
+
+/// The visual sample entries, and the item properties of HEIF.
+const VISUAL_SAMPLE_ENTRIES_AND_IPCO: &[FourCC] = &[
+    fourcc(b"av01"),
+    fourcc(b"avc1"),
+    fourcc(b"avc2"),
+    fourcc(b"avc3"),
+    fourcc(b"avc4"),
+    fourcc(b"hvc1"),
+    fourcc(b"hev1"),
+    UNCV,
+    fourcc(b"v210"),
+    fourcc(b"apco"),
+    fourcc(b"apcs"),
+    fourcc(b"apcn"),
+    fourcc(b"apch"),
+    fourcc(b"ap4h"),
+    fourcc(b"ap4x"),
+    fourcc(b"AVdn"),
+    fourcc(b"AVdh"),
+    IPCO,
+];
+
+/// All box types known to this crate.
+pub static BOXES: &[BoxInfo] = &[
+    // ISO/IEC 14496-12
+    leaf(b"ftyp", false, &[FILE]),
+    leaf(b"mdat", false, &[FILE]),
+    leaf(b"free", false, &[]),
+    leaf(b"skip", false, &[]),
+    leaf(b"uuid", false, &[]),
+    container(b"moov", false, 0, &[FILE]),
+    leaf(b"mvhd", true, &[MOOV]),
+    container(b"trak", false, 0, &[MOOV]),
+    leaf(b"tkhd", true, &[TRAK]),
+    container(b"edts", false, 0, &[TRAK]),
+    leaf(b"elst", true, &[EDTS]),
+    container(b"mdia", false, 0, &[TRAK]),
+    leaf(b"mdhd", true, &[MDIA]),
+    leaf(b"hdlr", true, &[MDIA, META]),
+    container(b"minf", false, 0, &[MDIA]),
+    leaf(b"vmhd", true, &[MINF]),
+    leaf(b"smhd", true, &[MINF]),
+    leaf(b"nmhd", true, &[MINF]),
+    container(b"dinf", false, 0, &[MINF, META]),
+    // entry_count
+    container(b"dref", true, 1 + 3 + 4, &[DINF]),
+    leaf(b"url ", true, &[DREF]),
+    leaf(b"urn ", true, &[DREF]),
+    container(b"stbl", false, 0, &[MINF]),
+    // entry_count
+    container(b"stsd", true, 1 + 3 + 4, &[STBL]),
+    leaf(b"stts", true, &[STBL]),
+    leaf(b"ctts", true, &[STBL]),
+    leaf(b"stss", true, &[STBL]),
+    leaf(b"stsz", true, &[STBL]),
+    leaf(b"stsc", true, &[STBL]),
+    leaf(b"stco", true, &[STBL]),
+    leaf(b"co64", true, &[STBL]),
+    leaf(b"sdtp", true, &[STBL, TRAF]),
+    leaf(b"padb", true, &[STBL]),
+    leaf(b"sbgp", true, &[STBL, TRAF]),
+    leaf(b"sgpd", true, &[STBL, TRAF]),
+    leaf(b"saiz", true, &[STBL, TRAF]),
+    leaf(b"saio", true, &[STBL, TRAF]),
+    container(b"mvex", false, 0, &[MOOV]),
+    leaf(b"mehd", true, &[MVEX]),
+    leaf(b"trex", true, &[MVEX]),
+    container(b"moof", false, 0, &[FILE]),
+    leaf(b"mfhd", true, &[MOOF]),
+    container(b"traf", false, 0, &[MOOF]),
+    leaf(b"tfhd", true, &[TRAF]),
+    leaf(b"tfdt", true, &[TRAF]),
+    leaf(b"trun", true, &[TRAF]),
+    leaf(b"sidx", true, &[FILE]),
+    leaf(b"pssh", true, &[MOOV, MOOF]),
+    leaf(b"senc", true, &[TRAF]),
+    container(b"mfra", false, 0, &[FILE]),
+    leaf(b"tfra", true, &[MFRA]),
+    leaf(b"mfro", true, &[MFRA]),
+    container(b"udta", false, 0, &[MOOV, TRAK]),
+    leaf(b"kind", true, &[UDTA]),
+    container(b"ludt", false, 0, &[UDTA]),
+    leaf(b"tlou", true, &[LUDT]),
+    leaf(b"alou", true, &[LUDT]),
+    container(b"meta", true, 1 + 3, &[FILE, MOOV, TRAK, UDTA]),
+    leaf(b"ilst", false, &[META]),
+    leaf(b"pitm", true, &[META]),
+    leaf(b"iloc", true, &[META]),
+    leaf(b"iinf", true, &[META]),
+    leaf(b"iref", true, &[META]),
+    container(b"iprp", false, 0, &[META]),
+    container(b"ipco", false, 0, &[IPRP]),
+    leaf(b"ipma", true, &[IPRP]),
+    leaf(b"btrt", false, VISUAL_SAMPLE_ENTRIES),
+    leaf(b"colr", false, VISUAL_SAMPLE_ENTRIES_AND_IPCO),
+    // ISO/IEC 14496-12 sample entries, with the fields of VisualSampleEntry
+    // and AudioSampleEntry in front of the children
+    container(b"av01", false, 78, &[STSD]),
+    container(b"avc1", false, 78, &[STSD]),
+    container(b"avc2", false, 78, &[STSD]),
+    container(b"avc3", false, 78, &[STSD]),
+    container(b"avc4", false, 78, &[STSD]),
+    container(b"hvc1", false, 78, &[STSD]),
+    container(b"hev1", false, 78, &[STSD]),
+    container(b"uncv", false, 78, &[STSD]),
+    container(b"v210", false, 78, &[STSD]),
+    container(b"apco", false, 78, &[STSD]),
+    container(b"apcs", false, 78, &[STSD]),
+    container(b"apcn", false, 78, &[STSD]),
+    container(b"apch", false, 78, &[STSD]),
+    container(b"ap4h", false, 78, &[STSD]),
+    container(b"ap4x", false, 78, &[STSD]),
+    container(b"AVdn", false, 78, &[STSD]),
+    container(b"AVdh", false, 78, &[STSD]),
+    container(b"mp4a", false, 28, &[STSD]),
+    container(b".mp3", false, 28, &[STSD]),
+    container(b"ipcm", false, 28, &[STSD]),
+    container(b"fpcm", false, 28, &[STSD]),
+    leaf(b"lpcm", false, &[STSD]),
+    // the strings in front of the children have no fixed size
+    leaf(b"metx", false, &[STSD]),
+    leaf(b"mett", false, &[STSD]),
+    container(b"urim", false, 8, &[STSD]),
+    leaf(b"uri ", true, &[fourcc(b"urim")]),
+    leaf(b"uriI", true, &[fourcc(b"urim")]),
+    // ISO/IEC 14496-14, ISO/IEC 14496-15, AV1-ISOBMFF, ISO/IEC 23003-5
+    leaf(b"esds", true, &[fourcc(b"mp4a"), fourcc(b".mp3")]),
+    leaf(b"av1C", false, &[fourcc(b"av01"), IPCO]),
+    leaf(
+        b"avcC",
+        false,
+        &[
+            fourcc(b"avc1"),
+            fourcc(b"avc2"),
+            fourcc(b"avc3"),
+            fourcc(b"avc4"),
+        ],
+    ),
+    leaf(b"hvcC", false, &[fourcc(b"hvc1"), fourcc(b"hev1"), IPCO]),
+    leaf(b"pcmC", true, &[fourcc(b"ipcm"), fourcc(b"fpcm")]),
+    // QuickTime File Format
+    leaf(b"fiel", false, VISUAL_SAMPLE_ENTRIES),
+    leaf(b"gama", false, VISUAL_SAMPLE_ENTRIES),
+    // ISO/IEC 23008-12
+    leaf(b"ispe", true, &[IPCO]),
+    leaf(b"pixi", true, &[IPCO]),
+    leaf(b"auxC", true, &[IPCO]),
+    leaf(b"irot", false, &[IPCO]),
+    leaf(b"imir", false, &[IPCO]),
+    leaf(b"ccst", true, VISUAL_SAMPLE_ENTRIES),
+    // ISO/IEC 23001-17
+    leaf(b"uncC", true, &[UNCV, IPCO]),
+    leaf(b"cmpd", false, &[UNCV, IPCO]),
+];
@@ -0,0 +1,268 @@
+use std::io::{Cursor, Read, Seek, Write};
+
+use crate::{
+    append::AppendSession,
+    marshal::{Decode, Encode, Error, MediaTime, MovieBox, MovieTime, Result},
+    mux::{configure, MuxConfig, MuxSummary},
+};
+
+/// When [RolloverWriter] finishes a file and continues in the next one,
+/// whichever is reached first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RolloverLimit {
+    pub duration: Option<MovieTime>,
+    /// of the samples in a file
+    pub size: Option<u64>,
+}
+
+/// Creates the file with the index, starting at 0, and the creation time, in
+/// seconds since 1904, e.g. to name it after the time of the recording.
+pub type OpenFile<F> = Box<dyn FnMut(usize, u64) -> Result<F> + Send>;
+
+/// Writes samples into a sequence of files, each finished with its own moov
+/// once it reaches the limit, like the recordings of a camera.
+///
+/// A new file starts at a sync sample of the first track, so that each of
+/// them plays on its own. All files have the tracks, track IDs and sample
+/// descriptions of the [MuxConfig], and creation times which continue where
+/// the previous file ended.
+pub struct RolloverWriter<F: Read + Write + Seek> {
+    /// encoded ftyp
+    file_type: Vec<u8>,
+    /// encoded moov without samples, as the template of each file
+    movie: Vec<u8>,
+    media_data_limit: Option<u64>,
+    limit: RolloverLimit,
+    open: OpenFile<F>,
+    session: AppendSession<F>,
+    index: usize,
+    /// of the first file
+    creation_time: u64,
+    /// duration of the previous files
+    elapsed: MovieTime,
+    movie_timescale: u32,
+    /// track ID and timescale of each track
+    timescales: Vec<(u32, u32)>,
+    /// media time of each track in the current file
+    durations: Vec<u64>,
+    /// of the samples in the current file
+    size: u64,
+}
+
+impl<F: Read + Write + Seek> RolloverWriter<F> {
+    pub fn new(
+        mut config: MuxConfig,
+        limit: RolloverLimit,
+        open: impl FnMut(usize, u64) -> Result<F> + Send + 'static,
+    ) -> Result<Self> {
+        // each file is already bounded by the limit
+        if config.fragment_duration.is_some() {
+            return Err(Error::ValueOutOfRange {
+                field: "fragment_duration",
+            });
+        }
+        config
+            .writer
+            .apply(&mut config.file_type, &mut config.movie);
+        let mut file_type = Cursor::new(Vec::new());
+        config.file_type.encode(&mut file_type)?;
+        let mut movie = Cursor::new(Vec::new());
+        config.movie.encode(&mut movie)?;
+
+        let timescales: Vec<_> = config
+            .movie
+            .tracks
+            .iter()
+            .map(|track| (track.header.track_id, track.media.header.timescale))
+            .collect();
+        let mut open: OpenFile<F> = Box::new(open);
+        let creation_time = config.movie.header.creation_time;
+        let session = begin_file(
+            file_type.get_ref(),
+            movie.get_ref(),
+            config.media_data_limit,
+            config.priming,
+            &mut open,
+            0,
+            creation_time,
+        )?;
+        Ok(Self {
+            file_type: file_type.into_inner(),
+            movie: movie.into_inner(),
+            media_data_limit: config.media_data_limit,
+            limit,
+            open,
+            session,
+            index: 0,
+            creation_time,
+            elapsed: MovieTime(0),
+            movie_timescale: config.movie.header.timescale,
+            durations: vec![0; timescales.len()],
+            timescales,
+            size: 0,
+        })
+    }
+
+    /// Appends a sample, and returns the previous file with its summary if
+    /// the sample started the next one.
+    pub fn append_sample(
+        &mut self,
+        track_id: u32,
+        data: &[u8],
+        duration: u32,
+        sync: bool,
+    ) -> Result<Option<(F, MuxSummary)>> {
+        let index = self
+            .timescales
+            .iter()
+            .position(|&(other_track_id, _)| other_track_id == track_id)
+            .ok_or(Error::InvalidTrackId { track_id })?;
+
+        let mut finished = None;
+        if index == 0 && sync && self.size > 0 && self.limit_reached() {
+            let elapsed = MovieTime(self.elapsed.0 + self.duration().0);
+            let session = begin_file(
+                &self.file_type,
+                &self.movie,
+                self.media_data_limit,
+                // the encoder delay is only at the start of the stream, which
+                // continues in the following files
+                None,
+                &mut self.open,
+                self.index + 1,
+                self.creation_time + elapsed.0 / self.movie_timescale.max(1) as u64,
+            )?;
+            finished = Some(std::mem::replace(&mut self.session, session).close()?);
+            self.index += 1;
+            self.elapsed = elapsed;
+            self.durations.fill(0);
+            self.size = 0;
+        }
+
+        self.session.append_sample(track_id, data, duration, sync)?;
+        self.durations[index] += duration as u64;
+        self.size += data.len() as u64;
+        Ok(finished)
+    }
+
+    /// Finishes the current file.
+    pub fn close(self) -> Result<(F, MuxSummary)> {
+        self.session.close()
+    }
+
+    /// Duration of the current file, of its longest track.
+    fn duration(&self) -> MovieTime {
+        self.timescales
+            .iter()
+            .zip(&self.durations)
+            .map(|(&(_, timescale), &duration)| {
+                MediaTime(duration).to_movie(timescale, self.movie_timescale)
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn limit_reached(&self) -> bool {
+        self.limit
+            .duration
+            .is_some_and(|duration| self.duration() >= duration)
+            || self.limit.size.is_some_and(|size| self.size >= size)
+    }
+}
+
+/// Opens the next file, and writes the template with the creation time into
+/// it.
+fn begin_file<F: Read + Write + Seek>(
+    file_type: &[u8],
+    movie: &[u8],
+    media_data_limit: Option<u64>,
+    priming: Option<u64>,
+    open: &mut OpenFile<F>,
+    index: usize,
+    creation_time: u64,
+) -> Result<AppendSession<F>> {
+    let mut movie = MovieBox::decode(&mut &movie[4 + 4..])?;
+    movie.header.creation_time = creation_time;
+    movie.header.modification_time = creation_time;
+    for track in &mut movie.tracks {
+        track.header.creation_time = creation_time;
+        track.header.modification_time = creation_time;
+        track.media.header.creation_time = creation_time;
+        track.media.header.modification_time = creation_time;
+    }
+
+    let mut file = open(index, creation_time)?;
+    file.write_all(file_type)?;
+    movie.encode(&mut file)?;
+    let mut session = AppendSession::open(file)?;
+    configure(&mut session, media_data_limit, priming)?;
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{RolloverLimit, RolloverWriter};
+    use crate::{
+        marshal::{
+            aac::{AACSampleEntry, AudioSpecificConfig},
+            Decode, File, FileTypeBox, FourCC, MovieBox, MovieHeaderBox, MovieTime, SampleEntry,
+            TrackBox,
+        },
+        mux::MuxConfig,
+    };
+
+    #[test]
+    fn priming_of_first_file() {
+        let entry = AACSampleEntry::new(AudioSpecificConfig::AAC_LC, 48000, 2).unwrap();
+        let track = TrackBox::new_timed_metadata(1, 48000, SampleEntry::AAC(entry), None);
+        let config = MuxConfig::new(
+            FileTypeBox {
+                major_brand: FourCC::from(*b"isom"),
+                minor_version: 0,
+                compatible_brands: vec![FourCC::from(*b"isom")],
+            },
+            MovieBox {
+                header: MovieHeaderBox {
+                    timescale: 1000,
+                    next_track_id: 2,
+                    ..Default::default()
+                },
+                tracks: vec![track],
+                extends: None,
+                user_data: None,
+                extra_boxes: Vec::new(),
+            },
+        );
+        let limit = RolloverLimit {
+            duration: Some(MovieTime(1000)),
+            size: None,
+        };
+        let mut writer =
+            RolloverWriter::new(config, limit, |_, _| Ok(Cursor::new(Vec::new()))).unwrap();
+        let mut files = Vec::new();
+        // about 1.5 s of audio
+        for _ in 0..70 {
+            if let Some((file, _)) = writer.append_sample(1, &[0; 4], 1024, true).unwrap() {
+                files.push(file.into_inner());
+            }
+        }
+        files.push(writer.close().unwrap().0.into_inner());
+        assert_eq!(files.len(), 2);
+
+        let media_times: Vec<_> = files
+            .iter()
+            .map(|file| {
+                let file = File::decode(&mut file.as_slice()).unwrap();
+                let track = &file.movie.unwrap().tracks[0];
+                track
+                    .edit
+                    .as_ref()
+                    .and_then(|edit| edit.edit_list.as_ref())
+                    .map(|edit_list| edit_list.0[0].media_time)
+            })
+            .collect();
+        assert_eq!(media_times, [Some(AACSampleEntry::PRIMING as i64), None]);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::marshal::{File, ItemInfoEntry, ItemLocationEntry, MetaBox, RawBox, Result};
+
+/// Types of boxes which carry metadata, like the keys and ilst of Apple's
+/// moov-level meta with its ISO 6709 location, XMP, and vendor uuid boxes of
+/// camera telemetry.
+const METADATA_BOXES: [&[u8; 4]; 4] = [b"meta", b"udta", b"uuid", b"XMP_"];
+
+/// Content type of XMP items.
+const XMP_CONTENT_TYPE: &str = "application/rdf+xml";
+
+/// Removes identifying information before sharing a file: creation and
+/// modification times, and user data with its metadata, like GPS location,
+/// XMP and vendor uuid boxes.
+///
+/// The meta box of image files is kept, as it contains the images, but its
+/// Exif and XMP items are removed, and their data is overwritten with zeros.
+pub fn scrub(file: &mut File) -> Result<()> {
+    strip(&mut file.extra_boxes);
+    if let Some(movie) = &mut file.movie {
+        movie.header.creation_time = 0;
+        movie.header.modification_time = 0;
+        movie.user_data = None;
+        strip(&mut movie.extra_boxes);
+        if let Some(extends) = &mut movie.extends {
+            strip(&mut extends.extra_boxes);
+        }
+        for track in &mut movie.tracks {
+            track.header.creation_time = 0;
+            track.header.modification_time = 0;
+            track.media.header.creation_time = 0;
+            track.media.header.modification_time = 0;
+            track.user_data = None;
+            strip(&mut track.extra_boxes);
+            if let Some(edit) = &mut track.edit {
+                strip(&mut edit.extra_boxes);
+            }
+            strip(&mut track.media.extra_boxes);
+            strip(&mut track.media.information.extra_boxes);
+            strip(&mut track.media.information.sample_table.extra_boxes);
+        }
+    }
+
+    let Some(meta) = &mut file.meta else {
+        return Ok(());
+    };
+    if &meta.handler.r#type.to_bytes() != b"pict" {
+        file.meta = None;
+        return Ok(());
+    }
+    meta.item_list = None;
+    strip(&mut meta.extra_boxes);
+    let items = remove_metadata_items(meta);
+    erase_items(file, &items)
+}
+
+fn strip(extra_boxes: &mut Vec<RawBox>) {
+    extra_boxes.retain(|extra_box| !METADATA_BOXES.contains(&&extra_box.r#type.to_bytes()));
+}
+
+/// Removes the Exif and XMP items, with their locations, references and
+/// property associations, and returns their locations.
+fn remove_metadata_items(meta: &mut MetaBox) -> Vec<ItemLocationEntry> {
+    let Some(item_info) = &mut meta.item_info else {
+        return Vec::new();
+    };
+    let mut item_ids = Vec::new();
+    item_info.0.retain(|item| {
+        let metadata = item.item_type == ItemInfoEntry::EXIF
+            || (item.item_type == ItemInfoEntry::MIME && item.content_type == XMP_CONTENT_TYPE);
+        if metadata {
+            item_ids.push(item.item_id);
+        }
+        !metadata
+    });
+
+    if let Some(item_reference) = &mut meta.item_reference {
+        for reference in &mut item_reference.0 {
+            reference
+                .to_item_ids
+                .retain(|item_id| !item_ids.contains(item_id));
+        }
+        item_reference.0.retain(|reference| {
+            !item_ids.contains(&reference.from_item_id) && !reference.to_item_ids.is_empty()
+        });
+    }
+    if let Some(item_properties) = &mut meta.item_properties {
+        item_properties
+            .associations
+            .retain(|association| !item_ids.contains(&association.item_id));
+    }
+    let Some(item_location) = &mut meta.item_location else {
+        return Vec::new();
+    };
+    item_ids
+        .into_iter()
+        .filter_map(|item_id| item_location.remove(item_id))
+        .collect()
+}
+
+/// Overwrites the data of items stored in the file itself with zeros, at the
+/// positions the mdats have once the file is encoded.
+fn erase_items(file: &mut File, items: &[ItemLocationEntry]) -> Result<()> {
+    let mut extents = Vec::new();
+    for item in items {
+        if item.construction_method != 0 || item.data_reference_index != 0 {
+            continue;
+        }
+        for extent in &item.extents {
+            let begin = item.base_offset.saturating_add(extent.extent_offset);
+            // a length of 0 denotes the entire remaining file
+            let end = if extent.extent_length == 0 {
+                u64::MAX
+            } else {
+                begin.saturating_add(extent.extent_length)
+            };
+            extents.push(begin..end);
+        }
+    }
+    if extents.is_empty() {
+        return Ok(());
+    }
+
+    let media_data_offsets = file.media_data_offsets()?;
+    for (media_data, offset) in file.media_data.iter_mut().zip(media_data_offsets) {
+        let length = media_data.0.len() as u64;
+        for extent in &extents {
+            let begin = extent.start.clamp(offset, offset + length) - offset;
+            let end = extent.end.clamp(offset, offset + length) - offset;
+            media_data.0[begin as usize..end as usize].fill(0);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::scrub;
+    use crate::marshal::{
+        File, FileTypeBox, FourCC, HandlerBox, ItemInfoBox, ItemInfoEntry, ItemLocationBox,
+        ItemLocationEntry, ItemLocationEntryExtent, ItemReferenceBox, MediaDataBox, MetaBox,
+        MovieBox, MovieHeaderBox, RawBox, SingleItemTypeReference,
+    };
+
+    fn file_type() -> FileTypeBox {
+        FileTypeBox {
+            major_brand: FourCC::from(*b"qt  "),
+            minor_version: 0,
+            compatible_brands: vec![FourCC::from(*b"qt  ")],
+        }
+    }
+
+    fn raw_box(r#type: &[u8; 4]) -> RawBox {
+        RawBox {
+            r#type: FourCC::from(*r#type),
+            data: vec![0; 8],
+        }
+    }
+
+    #[test]
+    fn extra_boxes() {
+        let mut file = File {
+            file_type: file_type(),
+            movie: Some(MovieBox {
+                header: MovieHeaderBox {
+                    timescale: 1000,
+                    next_track_id: 1,
+                    creation_time: 1,
+                    ..Default::default()
+                },
+                tracks: Vec::new(),
+                extends: None,
+                user_data: None,
+                // the keys and ilst of Apple, with the location
+                extra_boxes: vec![raw_box(b"meta"), raw_box(b"free")],
+            }),
+            media_data: Vec::new(),
+            meta: None,
+            // GPMF or XMP of cameras
+            extra_boxes: vec![raw_box(b"uuid"), raw_box(b"skip")],
+        };
+        scrub(&mut file).unwrap();
+
+        let movie = file.movie.unwrap();
+        assert_eq!(movie.header.creation_time, 0);
+        let types = |extra_boxes: &[RawBox]| -> Vec<_> {
+            extra_boxes
+                .iter()
+                .map(|extra_box| extra_box.r#type)
+                .collect()
+        };
+        assert_eq!(types(&movie.extra_boxes), [FourCC::from(*b"free")]);
+        assert_eq!(types(&file.extra_boxes), [FourCC::from(*b"skip")]);
+    }
+
+    #[test]
+    fn exif_items() {
+        let mut item_location = ItemLocationBox::default();
+        // the image and the Exif item, both stored in the mdat, which starts
+        // after the ftyp of 20 bytes and its own header
+        for (item_id, extent_offset) in [(1, 28), (2, 32)] {
+            item_location.push(ItemLocationEntry {
+                item_id,
+                construction_method: 0,
+                data_reference_index: 0,
+                base_offset: 0,
+                extents: vec![ItemLocationEntryExtent {
+                    extent_offset,
+                    extent_length: 4,
+                }],
+            });
+        }
+        let mut file = File {
+            file_type: file_type(),
+            movie: None,
+            media_data: vec![MediaDataBox(vec![1; 8])],
+            meta: Some(MetaBox {
+                handler: HandlerBox {
+                    r#type: FourCC::from(*b"pict"),
+                    name: String::new(),
+                },
+                item_location: Some(item_location),
+                item_info: Some(ItemInfoBox(vec![
+                    ItemInfoEntry::new(1, FourCC::from(*b"hvc1")),
+                    ItemInfoEntry::new(2, ItemInfoEntry::EXIF),
+                ])),
+                item_reference: Some(ItemReferenceBox(vec![SingleItemTypeReference {
+                    r#type: SingleItemTypeReference::CONTENT_DESCRIBES,
+                    from_item_id: 2,
+                    to_item_ids: vec![1],
+                }])),
+                item_properties: None,
+                item_list: None,
+                extra_boxes: Vec::new(),
+            }),
+            extra_boxes: Vec::new(),
+        };
+        scrub(&mut file).unwrap();
+
+        assert_eq!(file.media_data[0].0, [1, 1, 1, 1, 0, 0, 0, 0]);
+        let meta = file.meta.unwrap();
+        assert_eq!(meta.item_info.unwrap().0.len(), 1);
+        assert!(meta.item_reference.unwrap().0.is_empty());
+        let item_location = meta.item_location.unwrap();
+        assert!(item_location.get(1).is_some());
+        assert!(item_location.get(2).is_none());
+    }
+}
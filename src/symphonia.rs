@@ -0,0 +1,136 @@
+use symphonia_core::{
+    audio::Channels,
+    codecs::{
+        CodecParameters, CODEC_TYPE_AAC, CODEC_TYPE_MP3, CODEC_TYPE_PCM_F32BE,
+        CODEC_TYPE_PCM_F32LE, CODEC_TYPE_PCM_F64BE, CODEC_TYPE_PCM_F64LE, CODEC_TYPE_PCM_S16BE,
+        CODEC_TYPE_PCM_S16LE, CODEC_TYPE_PCM_S24BE, CODEC_TYPE_PCM_S24LE, CODEC_TYPE_PCM_S32BE,
+        CODEC_TYPE_PCM_S32LE,
+    },
+    formats::Packet,
+    units::TimeBase,
+};
+
+use crate::marshal::{pcm::PCMFormat, Error, Result, SampleEntry, TrackBox};
+
+/// Returns the parameters to create a symphonia decoder for an audio track,
+/// or none if its codec has no equivalent.
+pub fn codec_parameters(track: &TrackBox) -> Option<CodecParameters> {
+    let sample_table = &track.media.information.sample_table;
+    let mut parameters = CodecParameters::new();
+    let base = match sample_table.description.get(1)? {
+        SampleEntry::AAC(entry) => {
+            parameters.for_codec(CODEC_TYPE_AAC);
+            if let Some(decoder_specific_info) = &entry.es.0.decoder_config.decoder_specific_info {
+                parameters.with_extra_data(decoder_specific_info.clone().into_boxed_slice());
+            }
+            &entry.base
+        }
+        SampleEntry::MP3(entry) => {
+            parameters.for_codec(CODEC_TYPE_MP3);
+            &entry.base
+        }
+        SampleEntry::PCM(entry) => {
+            let codec = match (
+                entry.format,
+                entry.config.sample_size,
+                entry.config.little_endian,
+            ) {
+                (PCMFormat::Integer, 16, false) => CODEC_TYPE_PCM_S16BE,
+                (PCMFormat::Integer, 16, true) => CODEC_TYPE_PCM_S16LE,
+                (PCMFormat::Integer, 24, false) => CODEC_TYPE_PCM_S24BE,
+                (PCMFormat::Integer, 24, true) => CODEC_TYPE_PCM_S24LE,
+                (PCMFormat::Integer, 32, false) => CODEC_TYPE_PCM_S32BE,
+                (PCMFormat::Integer, 32, true) => CODEC_TYPE_PCM_S32LE,
+                (PCMFormat::FloatingPoint, 32, false) => CODEC_TYPE_PCM_F32BE,
+                (PCMFormat::FloatingPoint, 32, true) => CODEC_TYPE_PCM_F32LE,
+                (PCMFormat::FloatingPoint, 64, false) => CODEC_TYPE_PCM_F64BE,
+                (PCMFormat::FloatingPoint, 64, true) => CODEC_TYPE_PCM_F64LE,
+                _ => return None,
+            };
+            parameters
+                .for_codec(codec)
+                .with_bits_per_sample(entry.config.sample_size as u32)
+                .with_bits_per_coded_sample(entry.config.sample_size as u32);
+            &entry.base
+        }
+        _ => return None,
+    };
+
+    let channel_count = base.channel_count();
+    if let Some(channels) = 1u32
+        .checked_shl(channel_count)
+        .and_then(|channels| Channels::from_bits(channels - 1))
+    {
+        parameters.with_channels(channels);
+    }
+    parameters
+        .with_sample_rate(base.sample_rate() as u32)
+        .with_time_base(TimeBase::new(1, track.media.header.timescale))
+        .with_n_frames(track.media.header.duration.0);
+
+    // samples skipped by the edit list are the encoder delay
+    if let Some(entry) = track
+        .edit
+        .as_ref()
+        .and_then(|edit| edit.edit_list.as_ref())
+        .and_then(|edit_list| edit_list.0.first())
+    {
+        if let Ok(delay) = u32::try_from(entry.media_time) {
+            parameters.with_delay(delay);
+        }
+    }
+    Some(parameters)
+}
+
+/// Yields the samples of a track as symphonia packets, timestamped in the
+/// media timescale.
+pub struct TrackPackets<'a> {
+    input: &'a [u8],
+    track_id: u32,
+    samples: std::vec::IntoIter<(u64, u32, u64, u32)>,
+}
+
+impl<'a> TrackPackets<'a> {
+    pub fn new(input: &'a [u8], track: &TrackBox) -> Self {
+        let sample_table = &track.media.information.sample_table;
+        let durations =
+            sample_table.time_to_sample.0.iter().flat_map(|entry| {
+                std::iter::repeat_n(entry.sample_delta, entry.sample_count as usize)
+            });
+        let samples = (0..)
+            .zip(sample_table.sample_times())
+            .zip(durations)
+            .zip(sample_table.sample_offsets())
+            .map(|(((sample, time), duration), offset)| {
+                (
+                    time.0,
+                    duration,
+                    offset,
+                    sample_table.sample_size.sample_size(sample).unwrap_or(0),
+                )
+            })
+            .collect::<Vec<_>>();
+        Self {
+            input,
+            track_id: track.header.track_id,
+            samples: samples.into_iter(),
+        }
+    }
+}
+
+impl Iterator for TrackPackets<'_> {
+    type Item = Result<Packet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (time, duration, offset, size) = self.samples.next()?;
+        Some(
+            usize::try_from(offset)
+                .ok()
+                .and_then(|offset| self.input.get(offset..offset + size as usize))
+                .map(|data| Packet::new_from_slice(self.track_id, time, duration as u64, data))
+                .ok_or(Error::ValueOutOfRange {
+                    field: "chunk_offset",
+                }),
+        )
+    }
+}
@@ -0,0 +1,61 @@
+use crate::marshal::{FourCC, MovieBox, MovieTime};
+
+#[derive(Debug)]
+pub struct SyncReport {
+    pub tracks: Vec<TrackSync>,
+    /// pairs of video and audio tracks whose start differs by more than the
+    /// threshold
+    pub offsets: Vec<SyncOffset>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackSync {
+    pub track_id: u32,
+    pub handler: FourCC,
+    pub first_presentation_time: MovieTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncOffset {
+    pub video_track_id: u32,
+    pub audio_track_id: u32,
+    /// how much later audio starts than video, in the movie timescale
+    pub offset: i64,
+}
+
+impl SyncReport {
+    pub fn new(movie: &MovieBox, threshold: MovieTime) -> Self {
+        let tracks: Vec<_> = movie
+            .tracks
+            .iter()
+            .map(|track| TrackSync {
+                track_id: track.header.track_id,
+                handler: track.media.handler.r#type,
+                first_presentation_time: track.first_presentation_time(movie.header.timescale),
+            })
+            .collect();
+
+        let mut offsets = Vec::new();
+        for video in tracks
+            .iter()
+            .filter(|track| &track.handler.to_bytes() == b"vide")
+        {
+            for audio in tracks
+                .iter()
+                .filter(|track| &track.handler.to_bytes() == b"soun")
+            {
+                let offset =
+                    audio.first_presentation_time.0 as i64 - video.first_presentation_time.0 as i64;
+                if offset.unsigned_abs() > threshold.0 {
+                    offsets.push(SyncOffset {
+                        video_track_id: video.track_id,
+                        audio_track_id: audio.track_id,
+                        offset,
+                    });
+                }
+            }
+        }
+
+        Self { tracks, offsets }
+    }
+}
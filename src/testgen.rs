@@ -0,0 +1,303 @@
+use std::io::Cursor;
+
+use fixed::types::U16F16;
+use fixed_macro::types::{U16F16, U8F8};
+
+use crate::{
+    defragment::defragment,
+    fragment::fragment,
+    marshal::{
+        pcm::{PCMConfigurationBox, PCMFormat, PCMSampleEntry},
+        uncv::{UncompressedFrameConfigBox, UncompressedVideoSampleEntry},
+        AudioSampleEntry, ChunkOffsetBox, EditBox, EditListBox, EditListEntry, Encode, FileTypeBox,
+        FourCC, HandlerBox, MediaBox, MediaHeaderBox, MediaInformationBox, MediaInformationHeader,
+        MediaTime, MovieBox, MovieHeaderBox, MovieTime, Result, SampleDescriptionBox, SampleEntry,
+        SampleSizeBox, SampleTableBox, SampleToChunkBox, SoundDescriptionVersion,
+        SoundMediaHeaderBox, TimeToSampleBox, TrackBox, TrackHeaderBox, VideoMediaHeaderBox,
+        VisualSampleEntry, Volume,
+    },
+    mux::{mux, MuxConfig, Sample, SampleSource},
+};
+
+/// A tiny file of black uncompressed frames and silent PCM audio.
+#[derive(Debug)]
+pub struct TestVector {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tracks {
+    Video,
+    Audio,
+    AudioVideo,
+}
+
+const MOVIE_TIMESCALE: u32 = 1000;
+const SAMPLE_COUNT: u64 = 5;
+const VIDEO_TIMESCALE: u32 = 25;
+const VIDEO_WIDTH: u16 = 16;
+const VIDEO_HEIGHT: u16 = 16;
+const AUDIO_TIMESCALE: u32 = 48000;
+const AUDIO_FRAMES_PER_SAMPLE: u32 = 1024;
+const AUDIO_CHANNELS: u16 = 2;
+/// in movie time
+const EMPTY_EDIT_DURATION: u64 = 40;
+
+/// Generates every combination of tracks (video, audio, both), layout
+/// (progressive, moov behind a 64-bit mdat, fragmented) and edit list (none,
+/// starting with an empty edit).
+pub fn generate() -> Result<Vec<TestVector>> {
+    let mut vectors = Vec::new();
+    for tracks in [Tracks::Video, Tracks::Audio, Tracks::AudioVideo] {
+        for empty_edit in [false, true] {
+            let name = format!(
+                "{}{}",
+                match tracks {
+                    Tracks::Video => "video",
+                    Tracks::Audio => "audio",
+                    Tracks::AudioVideo => "audio-video",
+                },
+                if empty_edit { "-empty-edit" } else { "" }
+            );
+
+            let large_size = muxed(tracks, empty_edit)?;
+
+            let mut progressive = Cursor::new(Vec::new());
+            defragment(&large_size)?.encode(&mut progressive)?;
+            let progressive = progressive.into_inner();
+
+            let mut fragmented = Cursor::new(Vec::new());
+            fragment(
+                &progressive,
+                MovieTime(MOVIE_TIMESCALE as u64 / 10),
+                &mut fragmented,
+            )?;
+
+            vectors.push(TestVector {
+                name: format!("{name}-progressive.mp4"),
+                data: progressive,
+            });
+            vectors.push(TestVector {
+                name: format!("{name}-large-size.mp4"),
+                data: large_size,
+            });
+            vectors.push(TestVector {
+                name: format!("{name}-fragmented.mp4"),
+                data: fragmented.into_inner(),
+            });
+        }
+    }
+    Ok(vectors)
+}
+
+/// Writes the samples with [mux], resulting in the previous moov being freed,
+/// followed by a mdat with a 64-bit size and the final moov.
+fn muxed(tracks: Tracks, empty_edit: bool) -> Result<Vec<u8>> {
+    let mut movie = MovieBox {
+        header: MovieHeaderBox {
+            timescale: MOVIE_TIMESCALE,
+            next_track_id: 1,
+            ..Default::default()
+        },
+        tracks: Vec::new(),
+        extends: None,
+        user_data: None,
+        extra_boxes: Vec::new(),
+    };
+    let mut sources: Vec<Box<dyn SampleSource>> = Vec::new();
+    if matches!(tracks, Tracks::Video | Tracks::AudioVideo) {
+        let track_id = movie.add_track(video_track(empty_edit)?)?;
+        sources.push(Box::new(Samples {
+            track_id,
+            duration: 1,
+            size: VIDEO_WIDTH as usize * VIDEO_HEIGHT as usize * 3,
+            index: 0,
+        }));
+    }
+    if matches!(tracks, Tracks::Audio | Tracks::AudioVideo) {
+        let track_id = movie.add_track(audio_track(empty_edit))?;
+        sources.push(Box::new(Samples {
+            track_id,
+            duration: AUDIO_FRAMES_PER_SAMPLE,
+            size: AUDIO_FRAMES_PER_SAMPLE as usize * AUDIO_CHANNELS as usize * 2,
+            index: 0,
+        }));
+    }
+
+    let config = MuxConfig {
+        file_type: FileTypeBox {
+            major_brand: FourCC::from(*b"isom"),
+            minor_version: 0,
+            compatible_brands: vec![FourCC::from(*b"isom"), FourCC::from(*b"iso2")],
+        },
+        movie,
+        media_data_limit: None,
+        priming: None,
+        writer: Default::default(),
+        fragment_duration: None,
+    };
+    Ok(mux(sources, Cursor::new(Vec::new()), config)?
+        .0
+        .into_inner())
+}
+
+struct Samples {
+    track_id: u32,
+    duration: u32,
+    size: usize,
+    index: u64,
+}
+
+impl SampleSource for Samples {
+    fn next_sample(&mut self) -> Option<Sample> {
+        if self.index == SAMPLE_COUNT {
+            return None;
+        }
+        let sample = Sample {
+            // black and silent
+            data: vec![0; self.size],
+            dts: MediaTime(self.index * self.duration as u64),
+            duration: self.duration,
+            sync: true,
+            track: self.track_id,
+        };
+        self.index += 1;
+        Some(sample)
+    }
+}
+
+fn video_track(empty_edit: bool) -> Result<TrackBox> {
+    let mut header = TrackHeaderBox {
+        volume: Volume::MUTED,
+        ..Default::default()
+    };
+    header.set_pixel_dimensions(VIDEO_WIDTH as u32, VIDEO_HEIGHT as u32)?;
+    Ok(TrackBox {
+        header,
+        media: MediaBox {
+            header: MediaHeaderBox {
+                timescale: VIDEO_TIMESCALE,
+                ..Default::default()
+            },
+            handler: HandlerBox {
+                r#type: FourCC::from(*b"vide"),
+                name: String::new(),
+            },
+            information: MediaInformationBox {
+                header: MediaInformationHeader::Video(VideoMediaHeaderBox {
+                    graphicsmode: 0,
+                    opcolor: [0; 3],
+                }),
+                data_information: Default::default(),
+                sample_table: sample_table(SampleEntry::Uncompressed(
+                    UncompressedVideoSampleEntry {
+                        base: VisualSampleEntry {
+                            data_reference_index: 1,
+                            width: VIDEO_WIDTH,
+                            height: VIDEO_HEIGHT,
+                            horizresolution: U16F16!(72),
+                            vertresolution: U16F16!(72),
+                            frame_count: 1,
+                            compressorname: Default::default(),
+                            depth: 24,
+                            colour: None,
+                            field: None,
+                            gamma: None,
+                            coding_constraints: None,
+                            bit_rate: None,
+                            extra_boxes: Vec::new(),
+                        },
+                        config: UncompressedFrameConfigBox {
+                            profile: FourCC::from(*b"rgb3"),
+                            layout: None,
+                        },
+                        components: None,
+                    },
+                )),
+                extra_boxes: Vec::new(),
+            },
+            extra_boxes: Vec::new(),
+        },
+        edit: edit(empty_edit),
+        user_data: None,
+        extra_boxes: Vec::new(),
+    })
+}
+
+fn audio_track(empty_edit: bool) -> TrackBox {
+    TrackBox {
+        header: Default::default(),
+        media: MediaBox {
+            header: MediaHeaderBox {
+                timescale: AUDIO_TIMESCALE,
+                ..Default::default()
+            },
+            handler: HandlerBox {
+                r#type: FourCC::from(*b"soun"),
+                name: String::new(),
+            },
+            information: MediaInformationBox {
+                header: MediaInformationHeader::Sound(SoundMediaHeaderBox { balance: U8F8!(0) }),
+                data_information: Default::default(),
+                sample_table: sample_table(SampleEntry::PCM(PCMSampleEntry {
+                    format: PCMFormat::Integer,
+                    base: AudioSampleEntry {
+                        data_reference_index: 1,
+                        channelcount: AUDIO_CHANNELS,
+                        samplesize: 16,
+                        samplerate: U16F16::from_num(AUDIO_TIMESCALE),
+                        bit_rate: None,
+                        version: SoundDescriptionVersion::V0,
+                        extra_boxes: Vec::new(),
+                    },
+                    config: PCMConfigurationBox {
+                        little_endian: false,
+                        sample_size: 16,
+                    },
+                })),
+                extra_boxes: Vec::new(),
+            },
+            extra_boxes: Vec::new(),
+        },
+        edit: edit(empty_edit),
+        user_data: None,
+        extra_boxes: Vec::new(),
+    }
+}
+
+fn sample_table(entry: SampleEntry) -> SampleTableBox {
+    SampleTableBox {
+        description: SampleDescriptionBox(vec![entry]),
+        time_to_sample: TimeToSampleBox(vec![]),
+        composition_offset: None,
+        sync_sample: None,
+        sample_size: SampleSizeBox::PerSample(vec![]),
+        sample_to_chunk: SampleToChunkBox(vec![]),
+        chunk_offset: ChunkOffsetBox(vec![]),
+        sample_dependency_type: None,
+        padding_bits: None,
+        sample_to_group: None,
+        sample_group_description: None,
+        extra_boxes: Vec::new(),
+    }
+}
+
+/// The edit of the media is extended to the samples when the file is closed.
+fn edit(empty_edit: bool) -> Option<EditBox> {
+    empty_edit.then(|| EditBox {
+        edit_list: Some(EditListBox(vec![
+            EditListEntry {
+                segment_duration: MovieTime(EMPTY_EDIT_DURATION),
+                media_time: -1,
+                media_rate: U16F16!(1),
+            },
+            EditListEntry {
+                segment_duration: MovieTime(0),
+                media_time: 0,
+                media_rate: U16F16!(1),
+            },
+        ])),
+        extra_boxes: Vec::new(),
+    })
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    io::{Seek, Write},
+};
+
+use crate::{
+    io::{read_box_data, read_box_headers, BoxHeader, RandomAccess},
+    marshal::{
+        Decode, Encode, Error, MediaTime, MovieBox, MovieFragmentBox, MovieFragmentRandomAccessBox,
+        MovieTime, Result, SegmentIndexBox, SegmentIndexReferenceType, TrackRunEntry,
+    },
+};
+
+/// Result of [trim_fragments].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrimSummary {
+    pub dropped_fragments: usize,
+    pub dropped_duration: MovieTime,
+    pub retained_duration: MovieTime,
+}
+
+/// Drops the oldest movie fragments of a fragmented file, like a rolling DVR
+/// recording, keeping at least `window` of the newest ones.
+///
+/// The retained fragments start with a sync sample in all tracks, and if the
+/// file has a sidx, at one of its subsegments. The sidx and mfra are
+/// rewritten for the retained fragments, explicit base data offsets are
+/// moved along and the mehd is shortened. The tfdt are kept, so that the
+/// retained fragments are still presented at the same times.
+pub fn trim_fragments(
+    input: &(impl RandomAccess + ?Sized),
+    window: MovieTime,
+    output: &mut (impl Write + Seek),
+) -> Result<TrimSummary> {
+    let headers = read_box_headers(input)?;
+    let first_fragment = headers
+        .iter()
+        .position(|header| &header.r#type.to_bytes() == b"moof")
+        .unwrap_or(headers.len());
+    let (prefix, rest) = headers.split_at(first_fragment);
+    let movie_header = prefix
+        .iter()
+        .find(|header| &header.r#type.to_bytes() == b"moov")
+        .ok_or(Error::InvalidBoxQuantity {
+            r#type: "moov",
+            quantity: 0,
+            expected: 1,
+        })?;
+    let movie = MovieBox::decode(&mut read_box_data(input, movie_header)?.as_slice())?;
+
+    // boxes from each moof up to the next one, and the mfra
+    let mut fragments: Vec<Fragment> = Vec::new();
+    let mut random_access = None;
+    for header in rest {
+        match &header.r#type.to_bytes() {
+            b"moof" => {
+                let fragment =
+                    MovieFragmentBox::decode(&mut read_box_data(input, header)?.as_slice())?;
+                let (duration, sync) = fragment_timing(&movie, &fragment)?;
+                fragments.push(Fragment {
+                    headers: vec![header],
+                    duration,
+                    sync,
+                });
+            }
+            b"mfra" => random_access = Some(header),
+            _ => {
+                if let Some(fragment) = fragments.last_mut() {
+                    fragment.headers.push(header);
+                }
+            }
+        }
+    }
+
+    // offsets at which subsegments of the sidx begin
+    let mut segment_index = prefix
+        .iter()
+        .find(|header| &header.r#type.to_bytes() == b"sidx")
+        .map(|header| -> Result<_> {
+            let segment_index =
+                SegmentIndexBox::decode(&mut read_box_data(input, header)?.as_slice())?;
+            if segment_index
+                .references
+                .iter()
+                .any(|reference| reference.reference_type == SegmentIndexReferenceType::Index)
+            {
+                return Err(Error::ValueOutOfRange {
+                    field: "reference_type",
+                });
+            }
+            let mut begin = header.offset + header.size + segment_index.first_offset;
+            let begins: Vec<_> = segment_index
+                .references
+                .iter()
+                .map(|reference| {
+                    let reference_begin = begin;
+                    begin += reference.referenced_size as u64;
+                    reference_begin
+                })
+                .collect();
+            Ok((segment_index, begins))
+        })
+        .transpose()?;
+
+    // the latest fragment to start at, from which on there is at least the
+    // window left
+    let mut cut = 0;
+    let mut retained_duration = MovieTime(0);
+    for (index, fragment) in fragments.iter().enumerate().rev() {
+        retained_duration.0 += fragment.duration.0;
+        let begin = fragment.begin();
+        if index > 0
+            && fragment.sync
+            && segment_index
+                .as_ref()
+                .is_none_or(|(_, begins)| begins.contains(&begin))
+            && retained_duration >= window
+        {
+            cut = index;
+            break;
+        }
+    }
+    let dropped_duration = MovieTime(
+        fragments[..cut]
+            .iter()
+            .map(|fragment| fragment.duration.0)
+            .sum(),
+    );
+    let retained_duration = MovieTime(
+        fragments[cut..]
+            .iter()
+            .map(|fragment| fragment.duration.0)
+            .sum(),
+    );
+    let cut_offset = fragments
+        .get(cut)
+        .map_or(u64::MAX, |fragment| fragment.begin());
+
+    let start = output.stream_position()?;
+    for header in prefix {
+        if cut > 0 && &header.r#type.to_bytes() == b"sidx" {
+            if let Some((mut segment_index, begins)) = segment_index.take() {
+                let dropped = begins.partition_point(|&begin| begin < cut_offset);
+                segment_index.earliest_presentation_time += segment_index.references[..dropped]
+                    .iter()
+                    .map(|reference| reference.subsegment_duration as u64)
+                    .sum::<u64>();
+                segment_index.references.drain(..dropped);
+                segment_index.encode(output)?;
+                continue;
+            }
+        }
+        match &header.r#type.to_bytes() {
+            b"moov" if cut > 0 => {
+                let mut movie = MovieBox::decode(&mut read_box_data(input, header)?.as_slice())?;
+                if let Some(header) = movie
+                    .extends
+                    .as_mut()
+                    .and_then(|extends| extends.header.as_mut())
+                {
+                    header.fragment_duration.0 = header
+                        .fragment_duration
+                        .0
+                        .saturating_sub(dropped_duration.0);
+                }
+                movie.encode(output)?;
+            }
+            _ => copy(input, header, output)?,
+        }
+    }
+
+    let mut moof_offsets = HashMap::new();
+    for fragment in &fragments[cut..] {
+        for header in &fragment.headers {
+            let offset = output.stream_position()? - start;
+            if &header.r#type.to_bytes() != b"moof" {
+                copy(input, header, output)?;
+                continue;
+            }
+            moof_offsets.insert(header.offset, offset);
+            let mut fragment =
+                MovieFragmentBox::decode(&mut read_box_data(input, header)?.as_slice())?;
+            let mut moved = false;
+            for track_fragment in &mut fragment.track_fragments {
+                if let Some(base_data_offset) = &mut track_fragment.header.base_data_offset {
+                    *base_data_offset = base_data_offset
+                        .checked_add(offset)
+                        .and_then(|base_data_offset| base_data_offset.checked_sub(header.offset))
+                        .ok_or(Error::ValueOutOfRange {
+                            field: "base_data_offset",
+                        })?;
+                    moved = true;
+                }
+            }
+            if moved {
+                fragment.encode(output)?;
+            } else {
+                copy(input, header, output)?;
+            }
+        }
+    }
+
+    if let Some(header) = random_access {
+        let mut random_access =
+            MovieFragmentRandomAccessBox::decode(&mut read_box_data(input, header)?.as_slice())?;
+        for track_fragment_random_access in &mut random_access.0 {
+            track_fragment_random_access.entries.retain_mut(|entry| {
+                match moof_offsets.get(&entry.moof_offset) {
+                    Some(&offset) => {
+                        entry.moof_offset = offset;
+                        true
+                    }
+                    None => false,
+                }
+            });
+        }
+        random_access.encode(output)?;
+    }
+
+    Ok(TrimSummary {
+        dropped_fragments: cut,
+        dropped_duration,
+        retained_duration,
+    })
+}
+
+struct Fragment<'a> {
+    headers: Vec<&'a BoxHeader>,
+    duration: MovieTime,
+    sync: bool,
+}
+
+impl Fragment<'_> {
+    fn begin(&self) -> u64 {
+        self.headers[0].offset
+    }
+}
+
+/// Returns the duration of the longest track fragment, and whether all track
+/// fragments start with a sync sample.
+fn fragment_timing(movie: &MovieBox, fragment: &MovieFragmentBox) -> Result<(MovieTime, bool)> {
+    let mut duration = MovieTime(0);
+    let mut sync = true;
+    for track_fragment in &fragment.track_fragments {
+        let header = &track_fragment.header;
+        let track_id = header.track_id;
+        let track = movie
+            .tracks
+            .iter()
+            .find(|track| track.header.track_id == track_id)
+            .ok_or(Error::InvalidTrackId { track_id })?;
+        let track_extends = movie
+            .extends
+            .as_ref()
+            .and_then(|extends| extends.get(track_id));
+
+        let default_sample_duration = header
+            .default_sample_duration
+            .or(track_extends.map(|track_extends| track_extends.default_sample_duration))
+            .unwrap_or_default();
+        let track_duration = MediaTime(
+            track_fragment
+                .runs
+                .iter()
+                .flat_map(|run| &run.entries)
+                .map(|entry| entry.sample_duration.unwrap_or(default_sample_duration) as u64)
+                .sum(),
+        )
+        .to_movie(track.media.header.timescale, movie.header.timescale);
+        duration = duration.max(track_duration);
+
+        if let Some(run) = track_fragment
+            .runs
+            .iter()
+            .find(|run| !run.entries.is_empty())
+        {
+            let flags = run
+                .first_sample_flags
+                .or(run.entries[0].sample_flags)
+                .or(header.default_sample_flags)
+                .or(track_extends.map(|track_extends| track_extends.default_sample_flags))
+                .unwrap_or_default();
+            sync &= flags & TrackRunEntry::NON_SYNC_SAMPLE == 0;
+        }
+    }
+    Ok((duration, sync))
+}
+
+fn copy(
+    input: &(impl RandomAccess + ?Sized),
+    header: &BoxHeader,
+    output: &mut impl Write,
+) -> Result<()> {
+    output.write_all(&input.read_range(header.offset..header.offset + header.size)?)?;
+    Ok(())
+}
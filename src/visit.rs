@@ -0,0 +1,125 @@
+use std::io::Cursor;
+
+use crate::{
+    marshal::{
+        Boxes, ChunkLargeOffsetBox, ChunkOffsetBox, CompositionOffsetBox, DataReferenceBox, Decode,
+        EditListBox, Encode, File, FileTypeBox, FourCC, HandlerBox, ItemListBox, KindBox,
+        MediaHeaderBox, MovieExtendsHeaderBox, MovieHeaderBox, NullMediaHeaderBox, Result,
+        SampleDescriptionBox, SampleSizeBox, SampleToChunkBox, SoundMediaHeaderBox, SyncSampleBox,
+        TimeToSampleBox, TrackExtendsBox, TrackHeaderBox, UuidBox, VideoMediaHeaderBox,
+    },
+    registry::{lookup, BoxInfo},
+};
+
+/// Receives the boxes of a file in order, with their offset from the start of
+/// the file.
+pub trait BoxVisitor {
+    fn enter_box(&mut self, _type: FourCC, _offset: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn leave_box(&mut self, _type: FourCC, _offset: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn leaf_box(&mut self, _type: FourCC, _offset: u64, _payload: LeafBox) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Payload of a box without children, decoded if the type is known.
+#[derive(Debug)]
+pub enum LeafBox<'a> {
+    FileType(FileTypeBox),
+    MovieHeader(MovieHeaderBox),
+    TrackHeader(TrackHeaderBox),
+    EditList(EditListBox),
+    MediaHeader(MediaHeaderBox),
+    Handler(HandlerBox),
+    VideoMediaHeader(VideoMediaHeaderBox),
+    SoundMediaHeader(SoundMediaHeaderBox),
+    NullMediaHeader(NullMediaHeaderBox),
+    DataReference(DataReferenceBox),
+    SampleDescription(SampleDescriptionBox),
+    TimeToSample(TimeToSampleBox),
+    CompositionOffset(CompositionOffsetBox),
+    SyncSample(SyncSampleBox),
+    SampleSize(SampleSizeBox),
+    SampleToChunk(SampleToChunkBox),
+    /// stco or co64
+    ChunkOffset(ChunkOffsetBox),
+    MovieExtendsHeader(MovieExtendsHeaderBox),
+    TrackExtends(TrackExtendsBox),
+    Kind(KindBox),
+    Uuid(UuidBox),
+    ItemList(ItemListBox),
+    Unknown(&'a [u8]),
+}
+
+pub fn visit(file: &File, visitor: &mut impl BoxVisitor) -> Result<()> {
+    let mut data = Cursor::new(Vec::new());
+    file.encode(&mut data)?;
+    visit_data(data.get_ref(), visitor)
+}
+
+/// Visits the boxes of an already encoded file.
+pub fn visit_data(input: &[u8], visitor: &mut impl BoxVisitor) -> Result<()> {
+    visit_boxes(input, 0, visitor)
+}
+
+fn visit_boxes(input: &[u8], offset: u64, visitor: &mut impl BoxVisitor) -> Result<()> {
+    let mut boxes = Boxes::new(input);
+    loop {
+        let begin = input.len() - boxes.remaining().len();
+        let Some(r#box) = boxes.next().transpose()? else {
+            break;
+        };
+        let end = input.len() - boxes.remaining().len();
+        let data = &input[end - r#box.data.len()..end];
+        let data_offset = offset + (end - data.len()) as u64;
+        let begin = offset + begin as u64;
+
+        let r#type = r#box.r#type;
+        let mut data = data;
+        let payload = match &r#type.to_bytes() {
+            b"ftyp" => LeafBox::FileType(Decode::decode(&mut data)?),
+            b"mvhd" => LeafBox::MovieHeader(Decode::decode(&mut data)?),
+            b"tkhd" => LeafBox::TrackHeader(Decode::decode(&mut data)?),
+            b"elst" => LeafBox::EditList(Decode::decode(&mut data)?),
+            b"mdhd" => LeafBox::MediaHeader(Decode::decode(&mut data)?),
+            b"hdlr" => LeafBox::Handler(Decode::decode(&mut data)?),
+            b"vmhd" => LeafBox::VideoMediaHeader(Decode::decode(&mut data)?),
+            b"smhd" => LeafBox::SoundMediaHeader(Decode::decode(&mut data)?),
+            b"nmhd" => LeafBox::NullMediaHeader(Decode::decode(&mut data)?),
+            b"dref" => LeafBox::DataReference(Decode::decode(&mut data)?),
+            b"stsd" => LeafBox::SampleDescription(Decode::decode(&mut data)?),
+            b"stts" => LeafBox::TimeToSample(Decode::decode(&mut data)?),
+            b"ctts" => LeafBox::CompositionOffset(Decode::decode(&mut data)?),
+            b"stss" => LeafBox::SyncSample(Decode::decode(&mut data)?),
+            b"stsz" => LeafBox::SampleSize(Decode::decode(&mut data)?),
+            b"stsc" => LeafBox::SampleToChunk(Decode::decode(&mut data)?),
+            b"stco" => LeafBox::ChunkOffset(Decode::decode(&mut data)?),
+            b"co64" => LeafBox::ChunkOffset(ChunkLargeOffsetBox::decode(&mut data)?.0),
+            b"mehd" => LeafBox::MovieExtendsHeader(Decode::decode(&mut data)?),
+            b"trex" => LeafBox::TrackExtends(Decode::decode(&mut data)?),
+            b"kind" => LeafBox::Kind(Decode::decode(&mut data)?),
+            b"uuid" => LeafBox::Uuid(Decode::decode(&mut data)?),
+            b"ilst" => LeafBox::ItemList(Decode::decode(&mut data)?),
+            _ => match lookup(r#type).and_then(BoxInfo::children_offset) {
+                Some(children_offset) => {
+                    visitor.enter_box(r#type, begin)?;
+                    visit_boxes(
+                        data.get(children_offset..).unwrap_or_default(),
+                        data_offset + children_offset as u64,
+                        visitor,
+                    )?;
+                    visitor.leave_box(r#type, begin)?;
+                    continue;
+                }
+                None => LeafBox::Unknown(data),
+            },
+        };
+        visitor.leaf_box(r#type, begin, payload)?;
+    }
+    Ok(())
+}
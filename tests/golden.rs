@@ -0,0 +1,121 @@
+//! Compares the test vectors with the files in `tests/fixtures`, which catches
+//! any change in the encoded bytes, like a field or box order, or the clock
+//! leaking into creation times.
+//!
+//! Run with `ISOBMFF_BLESS=1` to rewrite the fixtures after an intended change.
+
+use std::{fs, io::Cursor, path::PathBuf};
+
+use isobmff::{
+    edit::EditableFile,
+    m4a::M4aWriter,
+    marshal::{
+        aac::{AACSampleEntry, AudioSpecificConfig},
+        Boxes, Decode, Encode, File, SampleEntry,
+    },
+    registry::lookup,
+    testgen::generate,
+};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn test_vectors() {
+    let bless = std::env::var_os("ISOBMFF_BLESS").is_some();
+    for vector in generate().unwrap() {
+        let path = fixture(&vector.name);
+        if bless {
+            fs::write(&path, &vector.data).unwrap();
+            continue;
+        }
+        let expected = fs::read(&path).unwrap();
+        assert_eq!(vector.data, expected, "{}", vector.name);
+    }
+}
+
+#[test]
+fn decode_encode() {
+    for vector in generate().unwrap() {
+        // the moov is encoded first, and the fragments aren't part of the File
+        if !vector.name.ends_with("-progressive.mp4") {
+            continue;
+        }
+        let file = File::decode(&mut vector.data.as_slice()).unwrap();
+        let mut output = Cursor::new(Vec::new());
+        file.encode(&mut output).unwrap();
+        assert_eq!(output.into_inner(), vector.data, "{}", vector.name);
+    }
+}
+
+#[test]
+fn editable_file() {
+    for vector in generate().unwrap() {
+        let file = EditableFile::decode(&mut vector.data.as_slice()).unwrap();
+        let mut output = Cursor::new(Vec::new());
+        file.encode(&mut output).unwrap();
+        assert_eq!(output.into_inner(), vector.data, "{}", vector.name);
+    }
+}
+
+#[test]
+fn encode_parallel() {
+    for vector in generate().unwrap() {
+        let Some(movie) = File::decode(&mut vector.data.as_slice()).unwrap().movie else {
+            continue;
+        };
+        let mut expected = Cursor::new(Vec::new());
+        movie.encode(&mut expected).unwrap();
+        let mut output = Cursor::new(Vec::new());
+        movie.encode_parallel(&mut output).unwrap();
+        assert_eq!(
+            output.into_inner(),
+            expected.into_inner(),
+            "{}",
+            vector.name
+        );
+    }
+}
+
+#[test]
+fn writers_keep_creation_times() {
+    let entry = AACSampleEntry::new(AudioSpecificConfig::AAC_LC, 48000, 2).unwrap();
+    let mut writer = M4aWriter::new(Cursor::new(Vec::new()), SampleEntry::AAC(entry)).unwrap();
+    writer.append_sample(&[0; 8], 1024).unwrap();
+    let file = writer.close().unwrap().0.into_inner();
+
+    let movie = File::decode(&mut file.as_slice()).unwrap().movie.unwrap();
+    assert_eq!(movie.header.creation_time, 0);
+    assert_eq!(movie.header.modification_time, 0);
+    for track in &movie.tracks {
+        assert_eq!(track.header.creation_time, 0);
+        assert_eq!(track.header.modification_time, 0);
+        assert_eq!(track.media.header.creation_time, 0);
+        assert_eq!(track.media.header.modification_time, 0);
+    }
+}
+
+/// Checks that the children of every container fill it exactly, which fails
+/// if a box header was written with a size other than the encoded length.
+fn check_box_sizes(input: &[u8], count: &mut usize) {
+    for r#box in Boxes::new(input) {
+        let r#box = r#box.unwrap();
+        *count += 1;
+        if let Some(children_offset) = lookup(r#box.r#type).and_then(|info| info.children_offset())
+        {
+            check_box_sizes(&r#box.data[children_offset..], count);
+        }
+    }
+}
+
+#[test]
+fn box_sizes() {
+    for vector in generate().unwrap() {
+        let mut count = 0;
+        check_box_sizes(&vector.data, &mut count);
+        assert!(count > 0, "{}", vector.name);
+    }
+}